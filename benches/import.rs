@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use emergence::data::{
+    notes::{NoteBuilder, NoteData},
+    ConnectionType, Store,
+};
+
+const NOTE_COUNT: usize = 1_000;
+
+fn test_notes() -> Vec<NoteData> {
+    (0..NOTE_COUNT)
+        .map(|i| {
+            NoteBuilder::new()
+                .text(lipsum::lipsum_words(5 + i % 50))
+                .build()
+        })
+        .collect()
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Import");
+    group.sample_size(10);
+
+    let notes = test_notes();
+
+    group.bench_function("Index each note", |b| {
+        b.iter_batched(
+            || Store::new(ConnectionType::InMemory),
+            |store| {
+                let conn = store.conn.clone();
+                let conn = conn.borrow();
+                let tx = conn.unchecked_transaction().unwrap();
+                for note in &notes {
+                    store.import_note(note).unwrap();
+                }
+                tx.commit().unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("Rebuild index once", |b| {
+        b.iter_batched(
+            || Store::new(ConnectionType::InMemory),
+            |store| {
+                let conn = store.conn.clone();
+                let conn = conn.borrow();
+                let tx = conn.unchecked_transaction().unwrap();
+                for note in &notes {
+                    store.import_note_rows(note).unwrap();
+                }
+                tx.commit().unwrap();
+                drop(conn);
+                store.rebuild_search_index().unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(30));
+    targets = criterion_benchmark
+}
+
+criterion_main!(benches);
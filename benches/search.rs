@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use emergence::data::{
+    benchmark::{sample_texts, search_query},
+    shove_test_data,
+    similarity::SimilarityParams,
+    ConnectionType, Store,
+};
+
+const VAULT_SIZES: [usize; 2] = [1_000, 10_000];
+const QUERY_COUNT: usize = 50;
+
+/// A sample vault of `notes` notes with its search index built.
+/// The store is opened again so that the search thread sees the index.
+fn sample_vault(dir: &std::path::Path, notes: usize) -> Store {
+    let store = Store::new(ConnectionType::File(dir.to_path_buf()));
+    shove_test_data(&mut store.conn.borrow_mut(), notes).unwrap();
+    store.rebuild_search_index().unwrap();
+    drop(store);
+    Store::new(ConnectionType::File(dir.to_path_buf()))
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Search");
+    group.sample_size(50);
+
+    for size in VAULT_SIZES {
+        let dir = tempfile::tempdir().unwrap();
+        let store = sample_vault(dir.path(), size);
+        let texts = sample_texts(&store, QUERY_COUNT).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("Full text", size), &texts, |b, texts| {
+            let mut queries = texts.iter().map(|text| search_query(text)).cycle();
+            b.iter(|| {
+                let query = queries.next().unwrap();
+                store.search.perform_search_blocking(query).unwrap()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("Similar", size), &texts, |b, texts| {
+            let mut texts = texts.iter().cycle();
+            b.iter(|| {
+                let text = texts.next().unwrap().clone();
+                store
+                    .search
+                    .find_similar_blocking(text, SimilarityParams::default())
+                    .unwrap()
+            })
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(10));
+    targets = criterion_benchmark
+}
+
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use emergence::data::{
+    subjects::{filter_flat_tree, subjects_in_tree_order},
+    ConnectionType, Store,
+};
+
+const SUBJECT_COUNT: usize = 400;
+
+/// A store with a few root subjects and the rest nested a few levels under them.
+fn test_store() -> Store {
+    let store = Store::new(ConnectionType::InMemory);
+    let mut ids = Vec::new();
+    for i in 0..SUBJECT_COUNT {
+        let subject = store.add_subject(format!("Subject {i}")).unwrap();
+        if i >= 10 {
+            store
+                .set_subject_parent(subject.id, Some(ids[i / 3]))
+                .unwrap();
+        }
+        ids.push(subject.id);
+    }
+    store
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Subject tree");
+    let store = test_store();
+
+    group.bench_function("Order in memory", |b| {
+        b.iter(|| {
+            let subjects: BTreeMap<_, _> = store
+                .get_subjects()
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.id, s))
+                .collect();
+            subjects_in_tree_order(&subjects)
+        })
+    });
+
+    group.bench_function("Recursive query", |b| {
+        b.iter(|| store.get_subjects_flat_with_depth().unwrap())
+    });
+
+    let flat = store.get_subjects_flat_with_depth().unwrap();
+    group.bench_function("Filter flat list", |b| {
+        b.iter(|| filter_flat_tree(&flat, |s| s.name.contains("Subject 1")))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,430 @@
+//! A local HTTP API for scripting Emergence from other tools.
+//!
+//! Started with `--serve <port>` when built with the `api` feature. The server
+//! only listens on localhost and every request must carry the token from the
+//! global settings file as `Authorization: Bearer <token>`.
+//!
+//! Endpoints, all speaking JSON:
+//!
+//! - `GET /subjects` lists all subjects.
+//! - `POST /subjects` with `{"name": "..."}` creates a subject.
+//! - `GET /notes` lists the newest notes. Takes the optional query parameters
+//!   `subject` (a subject id), `tasks=true` and `limit`.
+//! - `GET /notes/search?q=...` searches the text of all notes.
+//! - `POST /notes` with `{"text": "...", "subjects": [...], "task": false}`
+//!   creates a note. `subjects` and `task` are optional.
+//!
+//! Notes and subjects are returned in the same format as in exports.
+//! Errors are returned as `{"error": "..."}`.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use crate::data::{
+    notes::{NoteBuilder, NoteData, NoteSearch},
+    search::SearchError,
+    subjects::{SubjectData, SubjectId},
+    ParseIdError, Store,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::{info, warn};
+
+/// Requests with a larger body are rejected.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the API on `port` until the process is stopped.
+/// Requests are handled one at a time, since the store is single threaded.
+pub fn serve(store: &Store, port: u16, token: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    info!("Serving the API on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| handle_connection(store, token, stream));
+        if let Err(e) = result {
+            warn!("API connection failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(store: &Store, token: &str, stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader) {
+        Ok(request) => handle(store, token, &request),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            Response::error(400, &e.to_string())
+        }
+        Err(e) => return Err(e),
+    };
+    response.write_to(&stream)
+}
+
+#[derive(Debug, Default)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid body: {e}")))
+    }
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid_data("Malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: parse_query(query),
+        ..Default::default()
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Unexpected end of headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else { continue };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| invalid_data("Invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            request.authorization = value.strip_prefix("Bearer ").map(str::to_string);
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(invalid_data("Request body is too large"));
+    }
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let decoded = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match decoded {
+                    Some(decoded) => {
+                        bytes.push(decoded);
+                        rest = &rest[2..];
+                    }
+                    None => bytes.push(b'%'),
+                }
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: &impl serde::Serialize) -> Self {
+        let body = serde_json::to_string(value).expect("API responses are serializable");
+        Self { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.body.len(),
+            self.body
+        )?;
+        stream.flush()
+    }
+}
+
+#[derive(Debug)]
+enum ApiError {
+    BadRequest(String),
+    NotFound,
+    Store(rusqlite::Error),
+    Search(SearchError),
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        ApiError::Store(e)
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(e: SearchError) -> Self {
+        ApiError::Search(e)
+    }
+}
+
+impl ApiError {
+    fn to_response(&self) -> Response {
+        match self {
+            ApiError::BadRequest(message) => Response::error(400, message),
+            ApiError::NotFound => Response::error(404, "Not found"),
+            ApiError::Store(rusqlite::Error::QueryReturnedNoRows) => {
+                Response::error(404, "Not found")
+            }
+            ApiError::Store(e) => Response::error(500, &format!("Database error: {e}")),
+            ApiError::Search(e) => Response::error(500, &e.to_string()),
+        }
+    }
+}
+
+fn handle(store: &Store, token: &str, request: &Request) -> Response {
+    if request.authorization.as_deref() != Some(token) {
+        return Response::error(401, "Missing or invalid token");
+    }
+    route(store, request).unwrap_or_else(|e| {
+        warn!(
+            "API request {} {} failed: {:?}",
+            request.method, request.path, e
+        );
+        e.to_response()
+    })
+}
+
+#[derive(Deserialize)]
+struct NewSubject {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct NewNote {
+    text: String,
+    #[serde(default)]
+    subjects: Vec<SubjectId>,
+    #[serde(default)]
+    task: bool,
+}
+
+fn route(store: &Store, request: &Request) -> Result<Response, ApiError> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/subjects") => {
+            let subjects = store.get_subjects()?;
+            let subjects = subjects.iter().map(|s| &**s).collect::<Vec<&SubjectData>>();
+            Ok(Response::json(200, &subjects))
+        }
+        ("POST", "/subjects") => {
+            let new: NewSubject = request.json()?;
+            let subject = store.add_subject(new.name)?;
+            Ok(Response::json(201, &*subject))
+        }
+        ("GET", "/notes") => {
+            let mut search = NoteSearch::new();
+            if let Some(subject) = request.param("subject") {
+                let subject = subject
+                    .parse()
+                    .map_err(|e: ParseIdError| ApiError::BadRequest(e.to_string()))?;
+                if !store.subject_exists(subject)? {
+                    return Err(ApiError::NotFound);
+                }
+                search = search.subject(subject);
+            }
+            if let Some(limit) = request.param("limit") {
+                let limit = limit
+                    .parse()
+                    .map_err(|e| ApiError::BadRequest(format!("Invalid limit: {e}")))?;
+                search = search.page_size(limit);
+            }
+            search = search.task_only(request.param("tasks") == Some("true"));
+
+            let notes = store.get_notes(&store.find_notes(search)?)?;
+            let notes = notes.iter().map(|n| &**n).collect::<Vec<&NoteData>>();
+            Ok(Response::json(200, &notes))
+        }
+        ("GET", "/notes/search") => {
+            let Some(text) = request.param("q") else {
+                return Err(ApiError::BadRequest("Missing q".to_string()));
+            };
+            let notes = store.search.perform_search_blocking(text.to_string())?;
+            let notes = notes.iter().map(|n| &**n).collect::<Vec<&NoteData>>();
+            Ok(Response::json(200, &notes))
+        }
+        ("POST", "/notes") => {
+            let new: NewNote = request.json()?;
+            for &subject in &new.subjects {
+                if !store.subject_exists(subject)? {
+                    return Err(ApiError::BadRequest(format!("Unknown subject {subject}")));
+                }
+            }
+            let mut note = NoteBuilder::new().text(new.text).subjects(new.subjects);
+            if new.task {
+                note = note.as_task();
+            }
+            let note = store.add_note(note)?;
+            Ok(Response::json(201, &*note))
+        }
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::ConnectionType;
+
+    use super::*;
+
+    const TOKEN: &str = "secret";
+
+    fn request(method: &str, target: &str, body: &str) -> Request {
+        let raw = format!(
+            "{method} {target} HTTP/1.1\r\n\
+            Authorization: Bearer {TOKEN}\r\n\
+            Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        read_request(&mut raw.as_bytes()).unwrap()
+    }
+
+    fn call(store: &Store, request: &Request) -> (u16, serde_json::Value) {
+        let response = handle(store, TOKEN, request);
+        (
+            response.status,
+            serde_json::from_str(&response.body).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parses_requests() {
+        let request = request("GET", "/notes/search?q=hello+w%C3%B6rld&limit=5", "");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/notes/search");
+        assert_eq!(request.param("q"), Some("hello wörld"));
+        assert_eq!(request.param("limit"), Some("5"));
+        assert_eq!(request.authorization.as_deref(), Some(TOKEN));
+    }
+
+    #[test]
+    fn rejects_missing_token() {
+        let store = Store::new(ConnectionType::InMemory);
+        let mut request = request("GET", "/subjects", "");
+        request.authorization = None;
+        assert_eq!(call(&store, &request).0, 401);
+
+        request.authorization = Some("wrong".to_string());
+        assert_eq!(call(&store, &request).0, 401);
+    }
+
+    #[test]
+    fn creates_and_lists_notes() {
+        let store = Store::new(ConnectionType::InMemory);
+
+        let (status, subject) = call(&store, &request("POST", "/subjects", r#"{"name": "Work"}"#));
+        assert_eq!(status, 201);
+        assert_eq!(subject["name"], "Work");
+        let subject_id = subject["id"].as_str().unwrap();
+
+        let body = format!(r#"{{"text": "Ship it", "subjects": ["{subject_id}"], "task": true}}"#);
+        let (status, note) = call(&store, &request("POST", "/notes", &body));
+        assert_eq!(status, 201);
+        assert_eq!(note["text"], "Ship it");
+        assert_eq!(note["task_state"], "Todo");
+        call(
+            &store,
+            &request("POST", "/notes", r#"{"text": "Unrelated"}"#),
+        );
+
+        let (status, notes) = call(&store, &request("GET", "/notes", ""));
+        assert_eq!(status, 200);
+        assert_eq!(notes.as_array().unwrap().len(), 2);
+
+        let target = format!("/notes?subject={subject_id}&tasks=true");
+        let (_, notes) = call(&store, &request("GET", &target, ""));
+        assert_eq!(notes.as_array().unwrap().len(), 1);
+        assert_eq!(notes[0]["text"], "Ship it");
+
+        let (_, subjects) = call(&store, &request("GET", "/subjects", ""));
+        assert_eq!(subjects.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reports_bad_requests() {
+        let store = Store::new(ConnectionType::InMemory);
+        assert_eq!(call(&store, &request("POST", "/notes", "{")).0, 400);
+        assert_eq!(call(&store, &request("GET", "/notes?limit=x", "")).0, 400);
+        assert_eq!(call(&store, &request("GET", "/notes/search", "")).0, 400);
+        assert_eq!(call(&store, &request("DELETE", "/notes", "")).0, 404);
+
+        let unknown = SubjectId(uuid::Uuid::new_v4());
+        let body = format!(r#"{{"text": "Note", "subjects": ["{unknown}"]}}"#);
+        assert_eq!(call(&store, &request("POST", "/notes", &body)).0, 400);
+        let target = format!("/notes?subject={unknown}");
+        assert_eq!(call(&store, &request("GET", &target, "")).0, 404);
+    }
+}
@@ -1,27 +1,78 @@
+pub mod backup;
+pub mod benchmark;
+pub mod drafts;
 pub mod explain;
 pub mod export;
+pub mod focus;
 mod functions;
+mod history;
 pub mod layer;
+pub mod links;
 pub mod notes;
+pub mod paste;
+pub mod replace;
+mod retry;
 pub mod search;
+pub mod settings;
 mod setup;
+pub mod similarity;
 pub mod subjects;
 pub mod tfidf;
 
 use rusqlite::{params, Connection, Result};
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::{cell::RefCell, sync::Arc};
-use tracing::{debug, info, instrument};
+use std::{
+    cell::{Cell, RefCell},
+    sync::Arc,
+};
+use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
+use retry::{begin_write, commit_write};
 use subjects::SubjectId;
 
 pub struct Store {
     pub conn: Rc<RefCell<rusqlite::Connection>>,
     pub search: search::SearchWorker,
 
-    index_writer: RefCell<tantivy::IndexWriter>,
+    index_writer: search::SearchIndexWriter,
+    /// See [`settings::VaultSettings::index_subject_names`].
+    index_subject_names: Cell<bool>,
+    /// Where the store was opened from.
+    dir: ConnectionType,
+}
+
+/// Why a store couldn't be opened, see [`Store::open`].
+#[derive(Debug)]
+pub enum OpenError {
+    Database(rusqlite::Error),
+    /// The search index couldn't be opened, e.g. because another store
+    /// still has it open.
+    Index(tantivy::TantivyError),
+}
+
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenError::Database(e) => write!(f, "Database error: {e}"),
+            OpenError::Index(e) => write!(f, "Search index error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+impl From<rusqlite::Error> for OpenError {
+    fn from(e: rusqlite::Error) -> Self {
+        OpenError::Database(e)
+    }
+}
+
+impl From<tantivy::TantivyError> for OpenError {
+    fn from(e: tantivy::TantivyError) -> Self {
+        OpenError::Index(e)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -32,9 +83,39 @@ pub enum ConnectionType {
 }
 
 impl Store {
-    #[instrument()]
+    /// Opens the store, panicking if it can't be opened.
     pub fn new(dir: ConnectionType) -> Self {
+        Self::with_similarity(dir, Box::new(similarity::TfIdfBackend))
+    }
+
+    /// Like [`Store::new`], but returns an error if the store can't be opened.
+    pub fn open(dir: ConnectionType) -> std::result::Result<Self, OpenError> {
+        Self::open_with_similarity(dir, Box::new(similarity::TfIdfBackend))
+    }
+
+    /// Opens the store, finding similar notes with `similarity`.
+    pub fn with_similarity(
+        dir: ConnectionType,
+        similarity: Box<dyn similarity::SimilarityBackend>,
+    ) -> Self {
+        Self::open_with_similarity(dir, similarity)
+            .unwrap_or_else(|e| panic!("Failed to open the store: {e}"))
+    }
+
+    #[instrument(skip(similarity))]
+    fn open_with_similarity(
+        dir: ConnectionType,
+        similarity: Box<dyn similarity::SimilarityBackend>,
+    ) -> std::result::Result<Self, OpenError> {
         debug!("Begin");
+        let (index_subject_names, commit_interval) = match &dir {
+            ConnectionType::InMemory => (false, None),
+            ConnectionType::File(path) => {
+                let settings = settings::VaultSettings::load(path);
+                let interval = settings.index_commit_interval();
+                (settings.index_subject_names, Some(interval))
+            }
+        };
         let file = match dir.clone() {
             ConnectionType::InMemory => ConnectionType::InMemory,
             ConnectionType::File(path) => {
@@ -45,31 +126,158 @@ impl Store {
         };
 
         let mut conn = match &file {
-            ConnectionType::InMemory => Connection::open_in_memory().unwrap(),
-            ConnectionType::File(path) => Connection::open(path).unwrap(),
+            ConnectionType::InMemory => Connection::open_in_memory()?,
+            ConnectionType::File(path) => Connection::open(path)?,
         };
 
-        functions::add_functions(&conn).unwrap();
-        setup::setup_tables(&mut conn).unwrap();
+        functions::add_functions(&conn)?;
+        setup::setup_tables(&mut conn)?;
 
-        let index = search::construct_tantivy_index(dir);
+        let index = search::construct_tantivy_index(dir.clone())?;
         let index = Arc::new(index);
-        let index_writer = index.writer(5_000_000).unwrap();
-        let index_writer = RefCell::new(index_writer);
+        let index_writer = search::SearchIndexWriter::new(index.clone(), commit_interval)?;
+        let reader = index_writer.reader();
 
         let store = Self {
             conn: Rc::new(RefCell::new(conn)),
-            search: search::SearchWorker::start_search_thread(file, index.clone()),
+            search: search::SearchWorker::start_search_thread(file, index, reader, similarity),
             index_writer,
+            index_subject_names: Cell::new(index_subject_names),
+            dir,
         };
 
         debug!("Finished");
-        store
+        Ok(store)
+    }
+
+    /// Where the store was opened from.
+    pub fn connection(&self) -> &ConnectionType {
+        &self.dir
+    }
+
+    /// Whether notes are indexed with the names of their subjects.
+    pub fn index_subject_names(&self) -> bool {
+        self.index_subject_names.get()
+    }
+
+    /// Starts or stops indexing notes with the names of their subjects.
+    /// Notes already in the index keep what they were indexed with until
+    /// they change or the index is rebuilt.
+    pub fn set_index_subject_names(&self, enabled: bool) {
+        self.index_subject_names.set(enabled);
+    }
+}
+
+/// Error for parsing a [`notes::NoteId`] or [`SubjectId`] that isn't a valid UUID.
+#[derive(Debug)]
+pub struct ParseIdError {
+    kind: &'static str,
+    input: String,
+    source: uuid::Error,
+}
+
+impl ParseIdError {
+    pub(crate) fn new(kind: &'static str, input: &str, source: uuid::Error) -> Self {
+        Self {
+            kind,
+            input: input.to_string(),
+            source,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid {} id \"{}\": {}",
+            self.kind, self.input, self.source
+        )
+    }
+}
+
+impl std::error::Error for ParseIdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl Store {
+    /// Drops unused terms and rebuilds the database file to reclaim space.
+    pub fn vacuum(&self) -> Result<()> {
+        let pruned = self.prune_term_occurrences()?;
+        info!("Pruned {} unused terms", pruned);
+        self.conn.borrow().execute_batch("VACUUM;")
+    }
+
+    /// Problems found by SQLite's integrity check, empty if there are none.
+    pub fn integrity_errors(&self) -> Result<Vec<String>> {
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let errors = rows.collect::<Result<Vec<_>>>()?;
+        Ok(errors.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    /// Writes out everything still pending, so that the data folder can be
+    /// copied as is: commits the search index and moves the database's
+    /// write-ahead log into the database file.
+    #[instrument(skip(self))]
+    pub fn flush(&self) -> Result<()> {
+        debug!("Flushing");
+        self.flush_index()?;
+        // Returns a row of log page counts, which aren't needed.
+        self.conn
+            .borrow()
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// Commits the changes to the search index now, instead of when the
+    /// index is next committed in the background. Bulk changes call this
+    /// once at the end, so that searches see them right away.
+    pub fn flush_index(&self) -> Result<()> {
+        self.index_writer
+            .commit()
+            .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))
+    }
+
+    /// How many rows of `notes_search` are missing, extra or duplicated
+    /// compared to the note subjects it's built from.
+    pub fn notes_search_drift(&self) -> Result<usize> {
+        let expected = setup::EXPECTED_NOTES_SEARCH;
+        let actual = "SELECT note_id, subject_id, task_state, created_at FROM notes_search";
+        let query = format!(
+            "SELECT
+                (SELECT COUNT(*) FROM ({expected} EXCEPT {actual}))
+                + (SELECT COUNT(*) FROM ({actual} EXCEPT {expected}))
+                + (SELECT COUNT(*) FROM notes_search)
+                - (SELECT COUNT(*) FROM (SELECT DISTINCT * FROM ({actual})))"
+        );
+        self.conn
+            .borrow()
+            .query_row(&query, [], |row| row.get::<_, i64>(0))
+            .map(|drift| drift as usize)
+    }
+
+    /// Rebuilds `notes_search` from the note subjects, in case it drifted
+    /// through edits that bypassed its triggers. Returns how many rows it has.
+    #[instrument(skip(self))]
+    pub fn repair_notes_search(&self) -> Result<usize> {
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        tx.execute("DELETE FROM notes_search", [])?;
+        let rows = setup::fill_notes_search(&tx)?;
+        commit_write(tx)?;
+        Ok(rows)
     }
 }
 
 impl Drop for Store {
     fn drop(&mut self) {
+        if let Err(e) = self.prune_term_occurrences() {
+            warn!("Pruning unused terms failed: {}", e);
+        }
         info!("Optimize database");
         self.conn
             .borrow()
@@ -79,7 +287,7 @@ impl Drop for Store {
             "#,
             )
             .unwrap();
-        // TODO: Gracefully close the search thread.
+        self.search.shutdown();
     }
 }
 
@@ -230,6 +438,201 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_note_query_by_all_subjects() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+        let c = store.add_subject("C".to_string())?.id;
+
+        store.add_note(NoteBuilder::new().text("A").subject(a))?;
+        store.add_note(NoteBuilder::new().text("B").subject(b))?;
+        let ab = store.add_note(NoteBuilder::new().text("AB").subjects(vec![a, b]))?;
+        let abc = store.add_note(
+            NoteBuilder::new()
+                .text("ABC")
+                .subjects(vec![a, b, c])
+                .as_task(),
+        )?;
+
+        let mut found = store.find_notes(NoteSearch::new().all_subjects(&[a, b]))?;
+        found.sort();
+        let mut expected = vec![ab.id, abc.id];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        let found = store.find_notes(NoteSearch::new().all_subjects(&[c, a, b]))?;
+        assert_eq!(found, vec![abc.id]);
+
+        let search = NoteSearch::new().all_subjects(&[b, a]).task_only(true);
+        assert_eq!(store.find_notes(search.clone())?, vec![abc.id]);
+        let search = search.hide_done_before(Some(chrono::Local::now()));
+        assert_eq!(store.find_notes(search.clone())?, vec![abc.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_query_by_any_subject() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+        let c = store.add_subject("C".to_string())?.id;
+
+        let note_a = store.add_note(NoteBuilder::new().text("A").subject(a))?;
+        store.add_note(NoteBuilder::new().text("C").subject(c))?;
+        let note_ab = store.add_note(NoteBuilder::new().text("AB").subjects(vec![a, b]))?;
+        let task_b = store.add_note(NoteBuilder::new().text("B").subject(b).as_task())?;
+
+        // Newest first, and notes with both subjects only once.
+        let found = store.find_notes(NoteSearch::new().any_subjects(&[a, b]))?;
+        assert_eq!(found, vec![task_b.id, note_ab.id, note_a.id]);
+
+        let search = NoteSearch::new().any_subjects(&[a, b]).task_only(true);
+        assert_eq!(store.find_notes(search.clone())?, vec![task_b.id]);
+        let search = search.hide_done_before(Some(chrono::Local::now()));
+        assert_eq!(store.find_notes(search)?, vec![task_b.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_query_excluding_subjects() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+
+        let untagged = store.add_note(NoteBuilder::new().text("untagged"))?;
+        let note_a = store.add_note(NoteBuilder::new().text("A").subject(a))?;
+        store.add_note(NoteBuilder::new().text("AB").subjects(vec![a, b]))?;
+        let task_a = store.add_note(NoteBuilder::new().text("task A").subject(a).as_task())?;
+        let done_a = store.add_note(
+            NoteBuilder::new()
+                .text("done A")
+                .subject(a)
+                .task_state(TaskState::Done),
+        )?;
+
+        let search = NoteSearch::new().exclude_subjects(vec![b]);
+        assert_eq!(
+            store.find_notes(search.clone())?,
+            vec![done_a.id, task_a.id, note_a.id, untagged.id]
+        );
+        assert_eq!(
+            store.find_notes(search.clone().subject(a))?,
+            vec![done_a.id, task_a.id, note_a.id]
+        );
+        assert_eq!(
+            store.find_notes(search.clone().task_only(true).exclude_done(true))?,
+            vec![task_a.id]
+        );
+        assert_eq!(
+            store.find_notes(search.exclude_tasks(true).exclude_untagged(true))?,
+            vec![note_a.id]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_date_range() -> Result<()> {
+        use chrono::TimeZone;
+
+        let store = Store::new(ConnectionType::InMemory);
+        assert_eq!(store.note_date_range(None)?, None);
+
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+        let day = |d| {
+            chrono::Local
+                .with_ymd_and_hms(2023, 5, d, 12, 0, 0)
+                .unwrap()
+        };
+        let first = store.add_note(NoteBuilder::new().text("1").created_at(day(1)))?;
+        let second = store.add_note(NoteBuilder::new().subject(a).created_at(day(10)))?;
+        let third = store.add_note(NoteBuilder::new().subject(a).created_at(day(20)))?;
+
+        assert_eq!(store.note_date_range(None)?, Some((day(1), day(20))));
+        assert_eq!(store.note_date_range(Some(a))?, Some((day(10), day(20))));
+        assert_eq!(store.note_date_range(Some(b))?, None);
+
+        let search = NoteSearch::new().created_before(Some(day(20)));
+        assert_eq!(store.find_notes(search.clone())?, vec![second.id, first.id]);
+        let search = search.page_size(1);
+        assert_eq!(store.find_notes(search.clone())?, vec![second.id]);
+        assert_eq!(store.find_notes(search.subject(a))?, vec![second.id]);
+        let search = NoteSearch::new()
+            .subject(a)
+            .exclude_subjects(vec![b])
+            .created_before(Some(day(21)));
+        assert_eq!(store.find_notes(search)?, vec![third.id, second.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_per_day() -> Result<()> {
+        use chrono::TimeZone;
+
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+        let at = |d, h| chrono::Local.with_ymd_and_hms(2023, 5, d, h, 0, 0).unwrap();
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2023, 5, d).unwrap();
+        store.add_note(NoteBuilder::new().text("1").created_at(at(1, 23)))?;
+        store.add_note(NoteBuilder::new().text("2").created_at(at(2, 0)))?;
+        // Counted once despite its two subjects.
+        let both = NoteBuilder::new().text("3").subject(a).subject(b);
+        store.add_note(both.created_at(at(2, 12)))?;
+        store.add_note(NoteBuilder::new().text("4").created_at(at(5, 8)))?;
+
+        let counts = store.notes_per_day(date(1), date(2))?;
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            vec![(date(1), 1), (date(2), 2)]
+        );
+        assert_eq!(store.notes_per_day(date(3), date(4))?.len(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_note_dedup() -> Result<()> {
+        use chrono::Duration;
+
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_subject("A".to_string())?.id;
+        let b = store.add_subject("B".to_string())?.id;
+        let window = Duration::minutes(10);
+        let now = chrono::Local::now();
+        let note = || NoteBuilder::new().text("Buy milk").created_at(now);
+
+        let (first, created) = store.add_note_dedup(note().subjects(vec![a, b]), window)?;
+        assert!(created);
+        let later = now + Duration::minutes(5);
+        let (second, created) =
+            store.add_note_dedup(note().subjects(vec![b, a]).created_at(later), window)?;
+        assert!(!created);
+        assert_eq!(second.id, first.id);
+
+        // Different subjects, text or a time outside the window make a new note.
+        assert!(store.add_note_dedup(note().subject(a), window)?.1);
+        assert!(store.add_note_dedup(note().text("Buy oat milk"), window)?.1);
+        let much_later = now + Duration::hours(1);
+        assert!(
+            store
+                .add_note_dedup(note().subjects(vec![a, b]).created_at(much_later), window)?
+                .1
+        );
+        assert!(!store.add_note_dedup(note().subject(a), window)?.1);
+
+        // The plain version always creates.
+        store.add_note(note().subject(a))?;
+        assert_eq!(store.subject_note_count(a)?, 4);
+
+        Ok(())
+    }
+
     #[test]
     fn test_delete_note() -> Result<()> {
         let store = Store::new(ConnectionType::InMemory);
@@ -270,6 +673,155 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_delete_notes() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+
+        let mut doomed = Vec::new();
+        for i in 0..100 {
+            let note = NoteBuilder::new()
+                .text(format!("Doomed note number{i}"))
+                .subject(subject.id);
+            doomed.push(store.add_note(note)?.id);
+        }
+        let kept = store.add_note(NoteBuilder::new().text("Kept note"))?;
+
+        store.delete_notes(&doomed)?;
+
+        assert_eq!(store.find_notes(NoteSearch::new())?, vec![kept.id]);
+        assert!(store
+            .find_notes(NoteSearch::new().subject(subject.id))?
+            .is_empty());
+
+        let searcher = store.index_writer.index().reader().unwrap().searcher();
+        assert_eq!(searcher.num_docs(), 1);
+
+        // The decremented counts match a full recount.
+        let term_counts = || -> Result<Vec<(String, i64)>> {
+            let conn = store.conn.borrow();
+            let mut stmt = conn.prepare(
+                "SELECT term, count FROM term_occurrences WHERE count > 0 ORDER BY term",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        };
+        let decremented = term_counts()?;
+        tfidf::fill_word_occurence_table(&store.conn.borrow())?;
+        assert_eq!(decremented, term_counts()?);
+        assert!(store.term_suggestions("doo", 5)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unindexed_notes() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let num_docs = || {
+            let index = store.index_writer.index();
+            index.reader().unwrap().searcher().num_docs()
+        };
+
+        let note = store.add_note(NoteBuilder::new().text("Scratchpad").indexed(false))?;
+        assert!(!store.get_note(note.id)?.indexed);
+        assert_eq!(num_docs(), 0);
+        assert!(store.term_suggestions("scr", 5)?.is_empty());
+
+        let indexed = NoteBuilder::new().indexed(true).apply_to_note(&note);
+        store.update_note(indexed.clone())?;
+        assert_eq!(num_docs(), 1);
+        assert_eq!(store.term_suggestions("scr", 5)?, vec!["scratchpad"]);
+
+        store.update_note(NoteBuilder::new().indexed(false).apply_to_note(&indexed))?;
+        assert_eq!(num_docs(), 0);
+        assert!(store.term_suggestions("scr", 5)?.is_empty());
+
+        // Deleting a note that was never indexed leaves the counts alone.
+        store.add_note(NoteBuilder::new().text("Scratchpad"))?;
+        store.delete_note(note.id)?;
+        assert_eq!(store.term_suggestions("scr", 5)?, vec!["scratchpad"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_subtree() -> Result<()> {
+        use subjects::{InvalidMove, MAX_SUBJECT_DEPTH};
+
+        let store = Store::new(ConnectionType::InMemory);
+        let top = store.add_subject("Top".to_string())?.id;
+        let middle = store.add_subject("Middle".to_string())?.id;
+        let bottom = store.add_subject("Bottom".to_string())?.id;
+        let target = store.add_subject("Target".to_string())?.id;
+        store.move_subtree(middle, Some(top))?;
+        store.move_subtree(bottom, Some(middle))?;
+
+        assert_eq!(store.move_subtree(top, Some(target))?, 3);
+        assert_eq!(store.get_subject(top)?.parent_id, Some(target));
+        assert_eq!(store.get_subject(middle)?.parent_id, Some(top));
+        assert_eq!(store.get_subject(bottom)?.parent_id, Some(middle));
+        let ancestors = store.get_subject_ancestors(bottom)?;
+        let ancestors = ancestors.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ancestors, vec![target, top, middle]);
+
+        let invalid = |result: Result<usize>| match result {
+            Err(rusqlite::Error::UserFunctionError(e)) => e.downcast::<InvalidMove>().ok(),
+            _ => None,
+        };
+        assert_eq!(
+            invalid(store.move_subtree(top, Some(top))),
+            Some(Box::new(InvalidMove::OwnParent(top)))
+        );
+        assert_eq!(
+            invalid(store.move_subtree(top, Some(bottom))),
+            Some(Box::new(InvalidMove::IntoOwnSubtree(top)))
+        );
+        assert_eq!(store.get_subject(top)?.parent_id, Some(target));
+
+        // Hang the four level subtree below a chain that leaves no room for it.
+        let mut parent = None;
+        for i in 0..MAX_SUBJECT_DEPTH - 3 {
+            let link = store.add_subject(format!("Link {i}"))?.id;
+            store.move_subtree(link, parent)?;
+            parent = Some(link);
+        }
+        assert_eq!(
+            invalid(store.move_subtree(target, parent)),
+            Some(Box::new(InvalidMove::TooDeep(target)))
+        );
+        assert_eq!(store.move_subtree(top, parent)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_losing_all_subjects() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        let no_subject = SubjectId(Uuid::nil());
+
+        let note = store.add_note(NoteBuilder::new().text("Tagged").subject(subject.id))?;
+        assert!(store
+            .find_notes(NoteSearch::new().subject(no_subject))?
+            .is_empty());
+
+        let untagged = note.modify_with(|b| b.subjects(Vec::new()));
+        assert!(untagged.subjects.is_empty());
+        store.update_note(untagged)?;
+
+        assert!(store.get_note(note.id)?.subjects.is_empty());
+        assert_eq!(store.find_notes(NoteSearch::new())?, vec![note.id]);
+        assert_eq!(
+            store.find_notes(NoteSearch::new().subject(no_subject))?,
+            vec![note.id]
+        );
+        assert!(store
+            .find_notes(NoteSearch::new().subject(subject.id))?
+            .is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_tasks() -> Result<()> {
         let store = Store::new(ConnectionType::InMemory);
@@ -285,33 +837,41 @@ mod test {
 
         let search = NoteSearch::new().task_only(true);
 
-        let notes = store.find_notes(search).unwrap();
+        let notes = store.find_notes(search.clone()).unwrap();
         assert_eq!(notes.len(), 0);
 
         store.update_note(note1.modify_with(|b| b.task_state(TaskState::Todo)))?;
 
-        let notes = store.find_notes(search).unwrap();
+        let notes = store.find_notes(search.clone()).unwrap();
         assert_eq!(notes.len(), 1);
 
-        let notes = store.find_notes(search.subject(subject1.id)).unwrap();
+        let notes = store
+            .find_notes(search.clone().subject(subject1.id))
+            .unwrap();
         assert_eq!(notes.len(), 1);
-        let notes = store.find_notes(search.subject(subject2.id)).unwrap();
+        let notes = store
+            .find_notes(search.clone().subject(subject2.id))
+            .unwrap();
         assert_eq!(notes.len(), 0);
 
         store.update_note(note1.modify_with(|b| b.task_state(TaskState::Done)))?;
-        let notes = store.find_notes(search).unwrap();
+        let notes = store.find_notes(search.clone()).unwrap();
         assert_eq!(notes.len(), 1);
 
         store.update_note(
             note1.modify_with(|b| b.task_state(TaskState::Todo).subject(subject2.id)),
         )?;
 
-        let notes = store.find_notes(search).unwrap();
+        let notes = store.find_notes(search.clone()).unwrap();
         assert_eq!(notes.len(), 1);
 
-        let notes = store.find_notes(search.subject(subject1.id)).unwrap();
+        let notes = store
+            .find_notes(search.clone().subject(subject1.id))
+            .unwrap();
         assert_eq!(notes.len(), 0);
-        let notes = store.find_notes(search.subject(subject2.id)).unwrap();
+        let notes = store
+            .find_notes(search.clone().subject(subject2.id))
+            .unwrap();
         assert_eq!(notes.len(), 1);
 
         Ok(())
@@ -335,4 +895,706 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_reparent_orphans() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        let parent = store.add_subject("Parent".to_string())?;
+        let child = store.add_subject("Child".to_string())?;
+        let grandchild = store.add_subject("Grandchild".to_string())?;
+        store.set_subject_parent(child.id, Some(parent.id))?;
+        store.set_subject_parent(grandchild.id, Some(child.id))?;
+        // Leaves the child pointing at a subject that doesn't exist.
+        store.delete_subject(parent.id)?;
+
+        let all = store
+            .get_subjects()?
+            .into_iter()
+            .map(|s| (s.id, s))
+            .collect();
+        assert_eq!(
+            subjects::subjects_in_tree_order(&all),
+            vec![child.id, grandchild.id]
+        );
+
+        assert_eq!(store.reparent_orphans()?, 1);
+        assert_eq!(store.get_subject(child.id)?.parent_id, None);
+        assert_eq!(store.get_subject(grandchild.id)?.parent_id, Some(child.id));
+        assert_eq!(store.reparent_orphans()?, 0);
+
+        // Opening the database repairs it, too.
+        let other = store.add_subject("Other".to_string())?;
+        store.conn.borrow().execute(
+            "UPDATE subjects SET parent_id = ?1 WHERE id = ?2",
+            params![parent.id, other.id],
+        )?;
+        drop(store);
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        assert_eq!(store.get_subject(other.id)?.parent_id, None);
+        assert_eq!(store.get_subject_ancestors(other.id)?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_within_results() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        let both = store.add_note(NoteBuilder::new().text("Apple and banana"))?;
+        store.add_note(NoteBuilder::new().text("Apple and cherry"))?;
+        store.add_note(NoteBuilder::new().text("Banana bread"))?;
+        // Makes the notes searchable right away.
+        store.flush()?;
+
+        let results = store
+            .search
+            .perform_search_blocking("apple cherry".to_string())
+            .unwrap();
+        assert_eq!(results.len(), 2);
+
+        let rowids = results.iter().map(|note| note.rowid).collect();
+        let narrowed = store
+            .search
+            .search_within_results_blocking("banana".to_string(), rowids)
+            .unwrap();
+        let narrowed = narrowed.iter().map(|note| note.id).collect::<Vec<_>>();
+        assert_eq!(narrowed, vec![both.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_fails_while_the_store_is_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = || ConnectionType::File(dir.path().to_path_buf());
+        let store = Store::new(conn());
+        assert!(matches!(Store::open(conn()), Err(OpenError::Index(_))));
+        drop(store);
+        assert!(Store::open(conn()).is_ok());
+    }
+
+    #[test]
+    fn test_search_in_date_range() -> Result<()> {
+        use crate::data::search::DateRange;
+        use chrono::TimeZone;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        let day = |day| chrono::NaiveDate::from_ymd_opt(2023, 8, day).unwrap();
+        let add = |text: &str, on: u32| {
+            let created_at = day(on).and_hms_opt(12, 0, 0).unwrap();
+            let created_at = chrono::Local.from_local_datetime(&created_at).unwrap();
+            store.add_note(NoteBuilder::new().text(text).created_at(created_at))
+        };
+        add("Apple pie", 1)?;
+        let second = add("Apple tart", 5)?;
+        let third = add("Plum jam", 9)?;
+        store.flush()?;
+
+        let search = |text: &str, range| {
+            let notes = store
+                .search
+                .search_in_range_blocking(text.to_string(), range)
+                .unwrap();
+            notes.iter().map(|note| note.id).collect::<Vec<_>>()
+        };
+        let week = DateRange::days(Some(day(3)), Some(day(9)));
+        assert_eq!(search("apple", week), vec![second.id]);
+        // Without text, the notes of the range are listed newest first.
+        assert_eq!(search("", week), vec![third.id, second.id]);
+        assert_eq!(search("", DateRange::days(None, Some(day(4)))).len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_subject_names() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_path_buf();
+        let store = Store::new(ConnectionType::File(path.clone()));
+        let subject = store.add_subject("Gardening".to_string())?;
+        let before = store.add_note(NoteBuilder::new().text("Before").subject(subject.id))?;
+        store.set_index_subject_names(true);
+        let after = store.add_note(NoteBuilder::new().text("After").subject(subject.id))?;
+        let tagged = store.add_note(NoteBuilder::new().text("Tagged later"))?;
+        store.add_subject_to_notes(subject.id, &[tagged.id])?;
+        drop(store);
+
+        // A new store has a fresh view of the index.
+        let found = |store: &Store| {
+            let mut ids = store
+                .search
+                .perform_search_blocking("Gardening".to_string())
+                .unwrap()
+                .into_iter()
+                .map(|note| note.id)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        let sorted = |mut ids: Vec<notes::NoteId>| {
+            ids.sort();
+            ids
+        };
+        let store = Store::new(ConnectionType::File(path.clone()));
+        assert_eq!(found(&store), sorted(vec![after.id, tagged.id]));
+
+        // Rebuilding the index picks up the notes from before.
+        store.set_index_subject_names(true);
+        store.rebuild_search_index()?;
+        drop(store);
+        let store = Store::new(ConnectionType::File(path));
+        assert_eq!(found(&store), sorted(vec![before.id, after.id, tagged.id]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_due_reminders() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let now = chrono::Local::now();
+        let missed = store.add_note(
+            NoteBuilder::new()
+                .text("Missed")
+                .reminder_at(Some(now - chrono::Duration::hours(1))),
+        )?;
+        let later = store.add_note(
+            NoteBuilder::new()
+                .text("Later")
+                .reminder_at(Some(now + chrono::Duration::hours(1))),
+        )?;
+        store.add_note(NoteBuilder::new().text("No reminder"))?;
+
+        let due = store.get_due_reminders(now)?;
+        assert_eq!(
+            due.iter().map(|n| n.id).collect::<Vec<_>>(),
+            vec![missed.id]
+        );
+        // Reminders stay due until they're cleared.
+        assert_eq!(store.get_due_reminders(now)?.len(), 1);
+        assert!(store.clear_reminder(missed.id, missed.reminder_at.unwrap())?);
+        assert_eq!(store.get_note(missed.id)?.reminder_at, None);
+        assert!(store.get_due_reminders(now)?.is_empty());
+
+        // Edits keep the reminder.
+        store.update_note(later.modify_with(|n| n.text("Later, edited")))?;
+        assert_eq!(store.get_note(later.id)?.reminder_at, later.reminder_at);
+        let due = store.get_due_reminders(now + chrono::Duration::hours(2))?;
+        assert_eq!(due.iter().map(|n| n.id).collect::<Vec<_>>(), vec![later.id]);
+
+        // A reminder moved after it came due isn't cleared.
+        let moved = now + chrono::Duration::hours(3);
+        store.update_note(later.modify_with(|n| n.reminder_at(Some(moved))))?;
+        assert!(!store.clear_reminder(later.id, later.reminder_at.unwrap())?);
+        assert_eq!(store.get_note(later.id)?.reminder_at, Some(moved));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exists() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        let note = store.add_note(NoteBuilder::new().text("Test note").subject(subject.id))?;
+        assert!(store.note_exists(note.id)?);
+        assert!(store.subject_exists(subject.id)?);
+
+        store.delete_note(note.id)?;
+        store.delete_subject(subject.id)?;
+        assert!(!store.note_exists(note.id)?);
+        assert!(!store.subject_exists(subject.id)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subjects_with_counts() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let parent = store.add_subject("Parent".to_string())?;
+        let child = store.add_subject("Child".to_string())?;
+        store.set_subject_parent(child.id, Some(parent.id))?;
+        store.add_note(NoteBuilder::new().text("One").subject(child.id))?;
+        store.add_note(NoteBuilder::new().text("Two").subject(child.id))?;
+
+        let counts = store.get_all_subjects_with_counts()?;
+        let of = |id| counts.iter().find(|c| c.subject == id).unwrap();
+        assert_eq!((of(parent.id).notes, of(parent.id).children), (0, 1));
+        assert_eq!((of(child.id).notes, of(child.id).children), (2, 0));
+        assert!(counts.iter().all(|c| !c.is_empty()));
+
+        store.rename_subject(child.id, "Renamed")?;
+        assert_eq!(store.get_subject(child.id)?.name, "Renamed");
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        store
+            .conn
+            .borrow()
+            .query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        store.add_note(NoteBuilder::new().text("Test note"))?;
+        store.flush()?;
+
+        let wal = dir.path().join("data.db-wal");
+        assert_eq!(std::fs::metadata(wal).map(|m| m.len()).unwrap_or(0), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_notes_search() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        let note1 = store.add_note(NoteBuilder::new().text("Test note 1").subject(subject.id))?;
+        let note2 = store.add_note(
+            NoteBuilder::new()
+                .text("Test note 2")
+                .subject(subject.id)
+                .task_state(TaskState::Todo),
+        )?;
+        assert_eq!(store.notes_search_drift()?, 0);
+
+        // Edit the tables past their triggers.
+        store.conn.borrow().execute_batch(&format!(
+            "DELETE FROM notes_search WHERE note_id = X'{}';
+            UPDATE notes SET task_state = 2 WHERE id = X'{}';
+            INSERT INTO notes_search (note_id, subject_id, created_at)
+                SELECT note_id, subject_id, created_at FROM notes_search;",
+            note1.id.0.simple(),
+            note2.id.0.simple(),
+        ))?;
+        assert_eq!(store.notes_search_drift()?, 4);
+
+        assert_eq!(store.repair_notes_search()?, 2);
+        assert_eq!(store.notes_search_drift()?, 0);
+        let search = NoteSearch::new().subject(subject.id);
+        assert_eq!(store.find_notes(search.clone())?.len(), 2);
+        let tasks = store.find_notes(search.task_only(true))?;
+        assert_eq!(tasks.len(), 1);
+        assert!(store.get_note(tasks[0])?.is_done());
+
+        Ok(())
+    }
+
+    /// Sets the parent directly, as a vault edited outside the app could have it.
+    fn make_cycle(store: &Store, subject: SubjectId, parent: SubjectId) -> Result<()> {
+        store.conn.borrow().execute(
+            "UPDATE subjects SET parent_id = ?1 WHERE id = ?2",
+            params![parent, subject],
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_subject_cycles_are_refused() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let first = store.add_subject("First".to_string())?;
+        let second = store.add_subject("Second".to_string())?;
+        let third = store.add_subject("Third".to_string())?;
+
+        let is_invalid_move = |result: Result<()>| matches!(result, Err(rusqlite::Error::UserFunctionError(e)) if e.is::<subjects::InvalidMove>());
+        assert!(is_invalid_move(
+            store.set_subject_parent(first.id, Some(first.id))
+        ));
+
+        store.set_subject_parent(second.id, Some(first.id))?;
+        assert!(is_invalid_move(
+            store.set_subject_parent(first.id, Some(second.id))
+        ));
+
+        store.set_subject_parent(third.id, Some(second.id))?;
+        assert!(is_invalid_move(
+            store.set_subject_parent(first.id, Some(third.id))
+        ));
+
+        assert_eq!(store.get_subject(first.id)?.parent_id, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_subject_parent_depth() -> Result<()> {
+        use subjects::{InvalidMove, MAX_SUBJECT_DEPTH};
+
+        let store = Store::new(ConnectionType::InMemory);
+        let mut parent = None;
+        for i in 0..MAX_SUBJECT_DEPTH - 1 {
+            let link = store.add_subject(format!("Link {i}"))?.id;
+            store.set_subject_parent(link, parent)?;
+            parent = Some(link);
+        }
+        let moved = store.add_subject("Moved".to_string())?.id;
+        let child = store.add_subject("Child".to_string())?.id;
+        store.set_subject_parent(child, Some(moved))?;
+
+        // The child would end up one level too deep.
+        let result = store.set_subject_parent(moved, parent);
+        assert!(matches!(
+            result,
+            Err(rusqlite::Error::UserFunctionError(e))
+                if e.downcast_ref() == Some(&InvalidMove::TooDeep(moved))
+        ));
+        assert_eq!(store.get_subject(moved)?.parent_id, None);
+
+        store.set_subject_parent(child, None)?;
+        store.set_subject_parent(moved, parent)?;
+        assert_eq!(store.get_subject(moved)?.parent_id, parent);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_subject_parent_missing() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Subject".to_string())?.id;
+        let missing = SubjectId(uuid::Uuid::new_v4());
+
+        assert!(matches!(
+            store.set_subject_parent(subject, Some(missing)),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+        assert_eq!(store.get_subject(subject)?.parent_id, None);
+        assert!(matches!(
+            store.set_subject_parent(missing, Some(subject)),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_subject_ancestors() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let root = store.add_subject("Root".to_string())?;
+        let project = store.add_subject("Project".to_string())?;
+        let subproject = store.add_subject("Subproject".to_string())?;
+        store.set_subject_parent(project.id, Some(root.id))?;
+        store.set_subject_parent(subproject.id, Some(project.id))?;
+
+        let names = |ancestors: Vec<subjects::Subject>| {
+            ancestors.iter().map(|s| s.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(store.get_subject_ancestors(subproject.id)?),
+            vec!["Root", "Project"]
+        );
+        assert!(store.get_subject_ancestors(root.id)?.is_empty());
+
+        // A cycle must not hang.
+        make_cycle(&store, root.id, subproject.id)?;
+        assert_eq!(
+            names(store.get_subject_ancestors(subproject.id)?),
+            vec!["Root", "Project"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_subjects_by_name() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        store.add_subject("Garden".to_string())?;
+        store.add_subject("Gardening tools".to_string())?;
+        store.add_subject("Work".to_string())?;
+
+        let names = |query: &str| -> Result<Vec<String>> {
+            Ok(store
+                .find_subjects_by_name(query)?
+                .into_iter()
+                .map(|s| s.name.clone())
+                .collect())
+        };
+        assert_eq!(names("gard")?, vec!["Garden", "Gardening tools"]);
+        assert_eq!(names("W")?, vec!["Work"]);
+        assert_eq!(names("TOOLS")?, vec!["Gardening tools"]);
+        assert_eq!(names("nothing")?, Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subjects_flat_with_depth() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let work = store.add_subject("Work".to_string())?;
+        let home = store.add_subject("Home".to_string())?;
+        let garden = store.add_subject("Garden".to_string())?;
+        let beta = store.add_subject("Beta".to_string())?;
+        let alpha = store.add_subject("Alpha".to_string())?;
+        let tests = store.add_subject("Tests".to_string())?;
+        store.set_subject_parent(garden.id, Some(home.id))?;
+        store.set_subject_parent(beta.id, Some(work.id))?;
+        store.set_subject_parent(alpha.id, Some(work.id))?;
+        store.set_subject_parent(tests.id, Some(beta.id))?;
+
+        let flat = store.get_subjects_flat_with_depth()?;
+        let names = |flat: &[(subjects::Subject, usize)]| {
+            flat.iter()
+                .map(|(s, depth)| (s.name.clone(), *depth))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&flat),
+            vec![
+                ("Home".to_string(), 0),
+                ("Garden".to_string(), 1),
+                ("Work".to_string(), 0),
+                ("Alpha".to_string(), 1),
+                ("Beta".to_string(), 1),
+                ("Tests".to_string(), 2),
+            ]
+        );
+        let all = store
+            .get_subjects()?
+            .into_iter()
+            .map(|s| (s.id, s))
+            .collect();
+        assert_eq!(
+            flat.iter().map(|(s, _)| s.id).collect::<Vec<_>>(),
+            subjects::subjects_in_tree_order(&all)
+        );
+
+        let filtered = subjects::filter_flat_tree(&flat, |s| s.name == "Beta");
+        assert_eq!(
+            names(&filtered),
+            vec![
+                ("Work".to_string(), 0),
+                ("Beta".to_string(), 1),
+                ("Tests".to_string(), 2),
+            ]
+        );
+
+        // Subjects in a cycle can't be reached from a root.
+        make_cycle(&store, home.id, garden.id)?;
+        let flat = store.get_subjects_flat_with_depth()?;
+        assert_eq!(flat.len(), 4);
+        assert_eq!(flat[0].0.id, work.id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subject_favorites() -> Result<()> {
+        use crate::data::{settings::FavoriteOrder, subjects::favorites};
+
+        let store = Store::new(ConnectionType::InMemory);
+        let banana = store.add_subject("Banana".to_string())?;
+        let apple = store.add_subject("Apple".to_string())?;
+        store.add_subject("Cherry".to_string())?;
+
+        store.set_subject_favorite(banana.id, true, 0)?;
+        store.set_subject_favorite(apple.id, true, 1)?;
+
+        let subjects = store.get_subjects()?;
+        let names = |order| {
+            favorites(&subjects, order)
+                .iter()
+                .map(|s| s.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(FavoriteOrder::Manual), vec!["Banana", "Apple"]);
+        assert_eq!(names(FavoriteOrder::Alphabetical), vec!["Apple", "Banana"]);
+
+        store.set_subject_favorite(banana.id, false, 0)?;
+        assert!(!store.get_subject(banana.id)?.favorite);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_all_tasks() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let first = store.add_subject("First".to_string())?;
+        let second = store.add_subject("Second".to_string())?;
+
+        store.add_note(NoteBuilder::new().text("Plain note").subject(first.id))?;
+        store.add_note(
+            NoteBuilder::new()
+                .text("Done task")
+                .subject(first.id)
+                .task_state(TaskState::Done),
+        )?;
+        store.add_note(
+            NoteBuilder::new()
+                .text("Shared task")
+                .subjects(vec![first.id, second.id])
+                .as_task(),
+        )?;
+        store.add_note(NoteBuilder::new().text("Loose task").as_task())?;
+
+        let tasks = store.get_all_tasks()?;
+        let texts = tasks.iter().map(|n| n.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Loose task", "Shared task", "Done task"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hide_old_done_tasks() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        let now = chrono::Local::now();
+        let long_ago = now - chrono::Duration::days(30);
+
+        store.add_note(
+            NoteBuilder::new()
+                .text("Todo")
+                .subject(subject.id)
+                .as_task(),
+        )?;
+        store.add_note(
+            NoteBuilder::new()
+                .text("Done recently")
+                .subject(subject.id)
+                .as_done_task(now),
+        )?;
+        let old = store.add_note(
+            NoteBuilder::new()
+                .text("Done long ago")
+                .subject(subject.id)
+                .as_done_task(long_ago),
+        )?;
+
+        let cutoff = Some(now - chrono::Duration::days(7));
+        let searches = [
+            NoteSearch::new().task_only(true),
+            NoteSearch::new().subject(subject.id).task_only(true),
+        ];
+        for search in searches {
+            assert_eq!(store.find_notes(search.clone())?.len(), 3);
+
+            let note_ids = store.find_notes(search.clone().hide_done_before(cutoff))?;
+            let notes = store.get_notes(&note_ids)?;
+            let texts = notes.iter().map(|n| n.text.as_str()).collect::<Vec<_>>();
+            assert_eq!(texts, vec!["Todo", "Done recently"]);
+        }
+
+        // Reopening the task brings it back.
+        store.update_note(old.modify_with(|b| b.as_task()))?;
+        let search = NoteSearch::new().task_only(true).hide_done_before(cutoff);
+        assert_eq!(store.find_notes(search.clone())?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_done_tasks_stay() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let long_ago = chrono::Local::now() - chrono::Duration::days(30);
+        let pinned = store.add_note(
+            NoteBuilder::new()
+                .text("Pinned")
+                .as_done_task(long_ago)
+                .pinned(true),
+        )?;
+        store.add_note(NoteBuilder::new().text("Unpinned").as_done_task(long_ago))?;
+
+        let cutoff = Some(long_ago + chrono::Duration::days(7));
+        let search = NoteSearch::new().task_only(true).hide_done_before(cutoff);
+        assert_eq!(store.find_notes(search)?, vec![pinned.id]);
+        assert!(store.get_note(pinned.id)?.pinned);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_notes_page_size() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        for i in 0..5 {
+            store.add_note(
+                NoteBuilder::new()
+                    .text(format!("Test note {i}"))
+                    .subject(subject.id)
+                    .task_state(TaskState::Todo),
+            )?;
+        }
+
+        let searches = [
+            NoteSearch::new(),
+            NoteSearch::new().subject(subject.id),
+            NoteSearch::new().task_only(true),
+            NoteSearch::new().subject(subject.id).task_only(true),
+        ];
+        for search in searches {
+            assert_eq!(store.find_notes(search.clone())?.len(), 5);
+            // Run twice so the second query comes from the statement cache.
+            for _ in 0..2 {
+                let note_ids = store.find_notes(search.clone().page_size(2))?;
+                let notes = store.get_notes(&note_ids)?;
+                let texts = notes.iter().map(|n| n.text.as_str()).collect::<Vec<_>>();
+                assert_eq!(texts, vec!["Test note 4", "Test note 3"]);
+            }
+        }
+
+        Ok(())
+    }
+    #[test]
+    fn test_tasks_by_due_date() -> Result<()> {
+        use chrono::TimeZone;
+
+        let store = Store::new(ConnectionType::InMemory);
+        let due = |day| {
+            Some(
+                chrono::Local
+                    .with_ymd_and_hms(2023, 8, day, 12, 0, 0)
+                    .unwrap(),
+            )
+        };
+        let later = store.add_note(NoteBuilder::new().text("Later").as_task().due_at(due(20)))?;
+        let undated = store.add_note(NoteBuilder::new().text("Undated").as_task())?;
+        let sooner = store.add_note(NoteBuilder::new().text("Sooner").as_task().due_at(due(10)))?;
+        assert_eq!(store.get_note(later.id)?.due_at, due(20));
+
+        let search = NoteSearch::new().task_only(true);
+        assert_eq!(
+            store.find_notes(search.clone())?,
+            vec![sooner.id, undated.id, later.id]
+        );
+        assert_eq!(
+            store.find_notes(search.sort_by_due(true))?,
+            vec![sooner.id, later.id, undated.id]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_notes_page() -> Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Test subject".to_string())?;
+        for i in 0..5 {
+            store.add_note(
+                NoteBuilder::new()
+                    .text(format!("Test note {i}"))
+                    .subject(subject.id)
+                    .task_state(TaskState::Todo),
+            )?;
+        }
+
+        let searches = [
+            NoteSearch::new(),
+            NoteSearch::new().subject(subject.id),
+            NoteSearch::new().task_only(true),
+        ];
+        for search in searches {
+            let mut texts = Vec::new();
+            let mut pages = 0;
+            loop {
+                let search = search.clone().page_size(2).page_offset(pages * 2);
+                let page = store.find_notes_page(search)?;
+                texts.extend(store.get_notes(&page.notes)?.iter().map(|n| n.text.clone()));
+                pages += 1;
+                if !page.has_more {
+                    break;
+                }
+            }
+            assert_eq!(pages, 3);
+            assert_eq!(
+                texts,
+                (0..5)
+                    .rev()
+                    .map(|i| format!("Test note {i}"))
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        Ok(())
+    }
 }
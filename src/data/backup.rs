@@ -0,0 +1,155 @@
+//! Automatic JSON backups of a vault, kept in its `backups` folder.
+
+use std::{
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone};
+use tracing::{debug, info};
+
+use super::{
+    export::{export_to_writer, ExportFormat},
+    ConnectionType, Store,
+};
+
+/// The timestamp in backup file names, which sorts them oldest first.
+const FILE_NAME_FORMAT: &str = "backup-%Y-%m-%d-%H%M%S.json";
+
+/// How often a vault is backed up and how many backups are kept.
+/// See [`GlobalSettings::backup_config`](super::settings::GlobalSettings::backup_config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupConfig {
+    pub interval: Duration,
+    pub keep: usize,
+}
+
+fn backup_dir(data_path: &Path) -> PathBuf {
+    data_path.join("backups")
+}
+
+/// The backups in the backup folder of `data_path` with when they were made,
+/// oldest first. Other files in the folder are ignored.
+pub fn list_backups(data_path: &Path) -> std::io::Result<Vec<(DateTime<Local>, PathBuf)>> {
+    let dir = backup_dir(data_path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let made_at = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| NaiveDateTime::parse_from_str(name, FILE_NAME_FORMAT).ok())
+            .and_then(|time| Local.from_local_datetime(&time).earliest());
+        if let Some(made_at) = made_at {
+            backups.push((made_at, path));
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Exports the vault at `data_path` to its backup folder if the latest backup
+/// is older than the interval of `config`, then removes all but the newest
+/// [`BackupConfig::keep`] backups.
+///
+/// Opens a store of its own, so call this before the app opens the vault.
+/// Returns the new backup, or `None` if none was due.
+pub fn maybe_backup(data_path: &Path, config: &BackupConfig) -> std::io::Result<Option<PathBuf>> {
+    maybe_backup_at(data_path, config, Local::now())
+}
+
+fn maybe_backup_at(
+    data_path: &Path,
+    config: &BackupConfig,
+    now: DateTime<Local>,
+) -> std::io::Result<Option<PathBuf>> {
+    let latest = list_backups(data_path)?.last().map(|(made_at, _)| *made_at);
+    if let Some(latest) = latest.filter(|latest| now - *latest < config.interval) {
+        debug!("Latest backup is from {}, not backing up", latest);
+        return Ok(None);
+    }
+
+    let dir = backup_dir(data_path);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(now.format(FILE_NAME_FORMAT).to_string());
+    write_backup(data_path, &path)?;
+    info!("Backed up the vault to {}", path.display());
+
+    let backups = list_backups(data_path)?;
+    let old = backups.len().saturating_sub(config.keep.max(1));
+    for (_, old_path) in &backups[..old] {
+        std::fs::remove_file(old_path)?;
+        info!("Removed old backup {}", old_path.display());
+    }
+
+    Ok(Some(path))
+}
+
+fn write_backup(data_path: &Path, path: &Path) -> std::io::Result<()> {
+    let to_io = |e: rusqlite::Error| std::io::Error::other(e);
+
+    let store = Store::new(ConnectionType::File(data_path.to_path_buf()));
+    let subjects = store
+        .get_subjects()
+        .map_err(to_io)?
+        .into_iter()
+        .map(|subject| (subject.id, subject))
+        .collect();
+    let notes = store.get_all_notes().map_err(to_io)?;
+
+    // Written under a temporary name, so that a failed backup isn't mistaken
+    // for the latest one.
+    let partial = path.with_extension("partial");
+    let file = BufWriter::new(std::fs::File::create(&partial)?);
+    export_to_writer(
+        file,
+        notes.iter().map(|note| &**note),
+        &subjects,
+        ExportFormat::Json,
+        None,
+    )?;
+    std::fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::notes::NoteBuilder;
+
+    #[test]
+    fn backups_are_made_when_due_and_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().to_path_buf();
+        let store = Store::new(ConnectionType::File(data_path.clone()));
+        store
+            .add_note(NoteBuilder::new().text("Backed up"))
+            .unwrap();
+        drop(store);
+
+        let config = BackupConfig {
+            interval: Duration::hours(24),
+            keep: 2,
+        };
+        let start = Local.with_ymd_and_hms(2023, 8, 10, 12, 0, 0).unwrap();
+        let backup_at =
+            |hours| maybe_backup_at(&data_path, &config, start + Duration::hours(hours));
+
+        let first = backup_at(0).unwrap().unwrap();
+        let text = std::fs::read_to_string(&first).unwrap();
+        assert!(text.contains("Backed up"));
+
+        assert_eq!(backup_at(23).unwrap(), None);
+        let second = backup_at(24).unwrap().unwrap();
+        let third = backup_at(48).unwrap().unwrap();
+
+        let kept = list_backups(&data_path)
+            .unwrap()
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![second, third]);
+    }
+}
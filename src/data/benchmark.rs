@@ -0,0 +1,137 @@
+//! Latency of the searches users wait on, measured on a vault as it is.
+//! Run with `--benchmark search`; `benches/search.rs` covers sample vaults.
+//!
+//! Baseline of a release build on the sample vaults (`--sample`), with
+//! queries from 200 of their notes:
+//!
+//! | Notes  | Full text p50 | Full text p95 | Similar p50 | Similar p95 |
+//! |--------|---------------|---------------|-------------|-------------|
+//! | 1 000  | 2.0 ms        | 2.3 ms        | 0.95 ms     | 1.2 ms      |
+//! | 10 000 | 3.2 ms        | 4.4 ms        | 1.9 ms      | 2.7 ms      |
+
+use std::time::{Duration, Instant};
+
+use super::{search::SearchResult, similarity::SimilarityParams, Store};
+
+/// How many words of a note make up a full text query.
+const QUERY_WORDS: usize = 3;
+
+/// The spread of a set of timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latencies {
+    pub runs: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+}
+
+impl Latencies {
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        let percentile = |p: usize| {
+            let index = (samples.len().saturating_sub(1) * p + 50) / 100;
+            samples.get(index).copied().unwrap_or_default()
+        };
+        Latencies {
+            runs: samples.len(),
+            p50: percentile(50),
+            p95: percentile(95),
+        }
+    }
+}
+
+impl std::fmt::Display for Latencies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "p50 {:.1?}, p95 {:.1?} over {} runs",
+            self.p50, self.p95, self.runs
+        )
+    }
+}
+
+/// See [`benchmark_search`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchBenchmark {
+    pub notes: usize,
+    pub search: Latencies,
+    pub similar: Latencies,
+}
+
+/// Texts to search for, taken from up to `count` notes spread over the vault,
+/// so that the queries look like what's actually in it.
+pub fn sample_texts(store: &Store, count: usize) -> rusqlite::Result<Vec<String>> {
+    let notes = store.get_all_notes()?;
+    let step = (notes.len() / count.max(1)).max(1);
+    Ok(notes
+        .iter()
+        .step_by(step)
+        .take(count)
+        .map(|note| note.text.clone())
+        .filter(|text| !search_query(text).is_empty())
+        .collect())
+}
+
+/// The first few words of `text` long enough to be searched for.
+pub fn search_query(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .take(QUERY_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Times full text search and similar note search for each of `texts`,
+/// going through the search thread like the app does.
+pub fn benchmark_search(
+    store: &Store,
+    texts: &[String],
+    params: SimilarityParams,
+) -> SearchResult<SearchBenchmark> {
+    let time = |run: &dyn Fn() -> SearchResult<()>| -> SearchResult<Duration> {
+        let start = Instant::now();
+        run()?;
+        Ok(start.elapsed())
+    };
+
+    let mut search = Vec::new();
+    let mut similar = Vec::new();
+    for text in texts {
+        let query = search_query(text);
+        search.push(time(&|| {
+            store.search.perform_search_blocking(query.clone())?;
+            Ok(())
+        })?);
+        similar.push(time(&|| {
+            store.search.find_similar_blocking(text.clone(), params)?;
+            Ok(())
+        })?);
+    }
+
+    Ok(SearchBenchmark {
+        notes: store.get_all_notes()?.len(),
+        search: Latencies::from_samples(search),
+        similar: Latencies::from_samples(similar),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).rev().collect();
+        let latencies = Latencies::from_samples(samples);
+        assert_eq!(latencies.runs, 100);
+        assert_eq!(latencies.p50, Duration::from_millis(51));
+        assert_eq!(latencies.p95, Duration::from_millis(95));
+
+        assert_eq!(Latencies::from_samples(Vec::new()).p95, Duration::ZERO);
+    }
+
+    #[test]
+    fn queries_skip_short_words() {
+        assert_eq!(search_query("A to-do: buy milk and eggs"), "buy milk and");
+        assert_eq!(search_query("a b c"), "");
+    }
+}
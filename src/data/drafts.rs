@@ -0,0 +1,100 @@
+//! Unsaved note input, kept in the vault's data folder so it survives a crash.
+
+use std::{collections::BTreeMap, path::Path};
+
+use super::{
+    notes::NoteId,
+    settings::{load_json, save_json},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Drafts {
+    /// Text of the note being created.
+    pub new_note: Option<String>,
+    /// Text of the notes being edited.
+    pub edits: BTreeMap<NoteId, String>,
+}
+
+impl Drafts {
+    fn path(vault: &Path) -> std::path::PathBuf {
+        vault.join("drafts.json")
+    }
+
+    pub fn load(vault: &Path) -> Self {
+        load_json(&Self::path(vault))
+    }
+
+    /// Saves the drafts, removing the file once there are none left.
+    pub fn save(&self, vault: &Path) {
+        let path = Self::path(vault);
+        if self.is_empty() {
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::warn!("Failed to remove {}: {}", path.display(), e);
+                }
+            }
+        } else {
+            save_json(&path, self);
+        }
+    }
+
+    /// Stores the draft of `vault` for `note`, or for a new note with `None`.
+    /// Reads the other drafts from disk first, so they are kept.
+    pub fn update(vault: &Path, note: Option<NoteId>, text: &str) {
+        let mut drafts = Self::load(vault);
+        if drafts.get(note).unwrap_or_default() != text {
+            drafts.set(note, text);
+            drafts.save(vault);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new_note.is_none() && self.edits.is_empty()
+    }
+
+    pub fn get(&self, note: Option<NoteId>) -> Option<&str> {
+        match note {
+            Some(id) => self.edits.get(&id).map(String::as_str),
+            None => self.new_note.as_deref(),
+        }
+    }
+
+    /// Sets the draft for `note`. Empty text removes the draft.
+    pub fn set(&mut self, note: Option<NoteId>, text: &str) {
+        let text = Some(text.to_string()).filter(|t| !t.is_empty());
+        match (note, text) {
+            (Some(id), Some(text)) => {
+                self.edits.insert(id, text);
+            }
+            (Some(id), None) => {
+                self.edits.remove(&id);
+            }
+            (None, text) => self.new_note = text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drafts_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path();
+        let note = NoteId(uuid::Uuid::new_v4());
+
+        Drafts::update(vault, None, "New note");
+        Drafts::update(vault, Some(note), "Edited note");
+
+        let drafts = Drafts::load(vault);
+        assert_eq!(drafts.get(None), Some("New note"));
+        assert_eq!(drafts.get(Some(note)), Some("Edited note"));
+
+        Drafts::update(vault, None, "");
+        Drafts::update(vault, Some(note), "");
+        assert!(Drafts::load(vault).is_empty());
+        assert!(!Drafts::path(vault).exists());
+    }
+}
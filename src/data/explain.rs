@@ -1,7 +1,15 @@
-use rusqlite::Connection;
+//! Query plans and timings of the database queries, for `--explain`.
+//!
+//! Run it on a sample database to see how the queries behave with data in it.
+
+use std::{collections::BTreeMap, time::Instant};
+
+use chrono::Local;
+use regex::Regex;
+use rusqlite::{types::Value, Connection, OptionalExtension};
 use uuid::Uuid;
 
-use crate::data::notes;
+use crate::data::{notes, replace, subjects, tfidf};
 
 use super::{notes::NoteSearch, subjects::SubjectId, ConnectionType};
 
@@ -9,7 +17,20 @@ pub fn explain_all(ctype: ConnectionType) -> rusqlite::Result<()> {
     let store = super::Store::new(ctype);
     let conn = store.conn.borrow();
 
-    let subject = SubjectId(Uuid::nil());
+    // A subject with notes makes the timings meaningful.
+    let subject = conn
+        .query_row(
+            "SELECT subject_id FROM notes_search GROUP BY subject_id ORDER BY COUNT(*) DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(SubjectId(Uuid::nil()));
+    let now = Local::now().naive_utc().timestamp_nanos();
+    let week_ago = now
+        - chrono::Duration::days(7)
+            .num_nanoseconds()
+            .unwrap_or_default();
 
     let cases = [
         ("all notes", NoteSearch::new()),
@@ -19,31 +40,169 @@ pub fn explain_all(ctype: ConnectionType) -> rusqlite::Result<()> {
             "notes with subject and task state",
             NoteSearch::new().subject(subject).task_only(true),
         ),
+        (
+            "notes with all of two subjects",
+            NoteSearch::new().all_subjects(&[subject, subject]),
+        ),
+        (
+            "tasks with all of two subjects",
+            NoteSearch::new()
+                .all_subjects(&[subject, subject])
+                .task_only(true),
+        ),
+        (
+            "notes with any of two subjects",
+            NoteSearch::new().any_subjects(&[subject, subject]),
+        ),
+        (
+            "tasks with any of two subjects",
+            NoteSearch::new()
+                .any_subjects(&[subject, subject])
+                .task_only(true),
+        ),
+        (
+            "notes excluding a subject",
+            NoteSearch::new().exclude_subjects(vec![subject]),
+        ),
+        (
+            "notes with subject excluding a subject",
+            NoteSearch::new()
+                .subject(subject)
+                .exclude_subjects(vec![subject]),
+        ),
+        (
+            "tasks excluding a subject and done tasks",
+            NoteSearch::new()
+                .task_only(true)
+                .exclude_subjects(vec![subject])
+                .exclude_done(true),
+        ),
+        (
+            "notes excluding tasks and untagged notes",
+            NoteSearch::new().exclude_tasks(true).exclude_untagged(true),
+        ),
+        (
+            "notes before a time",
+            NoteSearch::new().created_before(Some(chrono::Local::now())),
+        ),
+        (
+            "notes with subject before a time",
+            NoteSearch::new()
+                .subject(subject)
+                .created_before(Some(chrono::Local::now())),
+        ),
+        (
+            "tasks hiding those done before a time",
+            NoteSearch::new()
+                .task_only(true)
+                .hide_done_before(Some(chrono::Local::now())),
+        ),
+        (
+            "tasks with subject hiding those done before a time",
+            NoteSearch::new()
+                .subject(subject)
+                .task_only(true)
+                .hide_done_before(Some(chrono::Local::now())),
+        ),
     ];
 
+    let mut scans = Vec::new();
     for (name, search) in cases.iter() {
         println!("Explain query plan for: {}", name);
-        let query = notes::query_for_search(*search);
-        print_query_plan(&conn, &query)?;
+        let query = notes::query_for_search(search);
+        scans.extend(
+            print_query_plan(&conn, &query)?
+                .into_iter()
+                .map(|t| (*name, t)),
+        );
+        let start = Instant::now();
+        let rows = store.find_notes(search.clone())?.len();
+        print_timing(start, rows);
         println!();
     }
 
+    let subject_param = Value::Blob(subject.0.as_bytes().to_vec());
+    // Full text search goes through tantivy, which has no query plan.
+    let queries = [
+        ("all tasks", notes::ALL_TASKS, vec![]),
+        (
+            "duplicate candidates",
+            notes::DUPLICATE_CANDIDATES,
+            vec![Value::Text("Note".to_string()), week_ago.into(), now.into()],
+        ),
+        ("date range of all notes", notes::NOTE_DATE_RANGE, vec![]),
+        (
+            "date range of notes with subject",
+            notes::SUBJECT_NOTE_DATE_RANGE,
+            vec![subject_param.clone()],
+        ),
+        (
+            "notes to find and replace in",
+            replace::NOTES_WITH_SUBJECT,
+            vec![subject_param],
+        ),
+        (
+            "subjects by name",
+            subjects::SUBJECTS_BY_NAME,
+            vec![Value::Text("subject".to_string())],
+        ),
+        (
+            "subject tree with depths",
+            subjects::SUBJECTS_FLAT_WITH_DEPTH,
+            vec![(subjects::MAX_SUBJECT_DEPTH as i64).into()],
+        ),
+        (
+            "search term suggestions",
+            tfidf::TERM_SUGGESTIONS,
+            vec![
+                Value::Text("the".to_string()),
+                Value::Text(format!("the{}", char::MAX)),
+                8.into(),
+            ],
+        ),
+    ];
+
+    for (name, query, params) in queries {
+        println!("Explain query plan for: {}", name);
+        scans.extend(
+            print_query_plan(&conn, query)?
+                .into_iter()
+                .map(|t| (name, t)),
+        );
+        let start = Instant::now();
+        let rows = conn
+            .prepare(query)?
+            .query_map(rusqlite::params_from_iter(params), |_| Ok(()))?
+            .count();
+        print_timing(start, rows);
+        println!();
+    }
+
+    if scans.is_empty() {
+        println!("No query scans an indexed table");
+    }
+    for (name, table) in scans {
+        println!("Warning: {name} scans all of {table}");
+    }
+
     Ok(())
 }
 
-fn print_query_plan(conn: &Connection, query: &str) -> rusqlite::Result<()> {
-    use std::collections::BTreeMap;
+fn print_timing(start: Instant, rows: usize) {
+    let plural = if rows == 1 { "row" } else { "rows" };
+    println!("  took {:?} for {rows} {plural}", start.elapsed());
+}
 
+/// Prints the plan of `query` and returns the indexed tables it scans in full.
+fn print_query_plan(conn: &Connection, query: &str) -> rusqlite::Result<Vec<String>> {
     let mut levels = BTreeMap::new();
     levels.insert(0, 0);
 
-    let mut parameters = Vec::new();
-    if query.contains('?') {
-        parameters.push("?");
-    }
-
     let mut plan = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", query))?;
+    // The plan doesn't depend on the bound values, only on which are bound.
+    let parameters = vec!["?"; plan.parameter_count()];
     let mut rows = plan.query(rusqlite::params_from_iter(parameters))?;
+    let mut details = Vec::new();
 
     while let Some(row) = rows.next()? {
         let id = row.get_unwrap::<_, u32>(0);
@@ -54,7 +213,39 @@ fn print_query_plan(conn: &Connection, query: &str) -> rusqlite::Result<()> {
 
         let indent = "  ".repeat(level);
         println!("{}{}", indent, detail);
+        details.push(detail);
     }
 
-    Ok(())
+    let mut scans = Vec::new();
+    for detail in details {
+        // Scans through an index are there to walk it in order.
+        let Some(name) = detail.strip_prefix("SCAN ") else { continue };
+        if name.contains(" USING ") {
+            continue;
+        }
+        let table = table_of_alias(query, name);
+        let indexes: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_index_list(?1)",
+            [&table],
+            |row| row.get(0),
+        )?;
+        if indexes > 0 {
+            println!("  Full scan of the indexed table {table}");
+            scans.push(table);
+        }
+    }
+
+    Ok(scans)
+}
+
+/// The table `name` refers to in `query`, as plans show the alias of a table.
+fn table_of_alias(query: &str, name: &str) -> String {
+    let pattern = format!(
+        r"(?i)\b(?:FROM|JOIN)\s+(\w+)\s+(?:AS\s+)?{}\b",
+        regex::escape(name)
+    );
+    Regex::new(&pattern)
+        .ok()
+        .and_then(|regex| regex.captures(query))
+        .map_or_else(|| name.to_string(), |captures| captures[1].to_string())
 }
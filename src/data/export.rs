@@ -1,36 +1,196 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::PathBuf,
+};
 
-use super::{notes::NoteData, subjects::SubjectData, ConnectionType, Store};
+use chrono::{DateTime, Local};
+use tracing::warn;
+
+use super::{
+    links,
+    notes::{NoteData, TaskState},
+    subjects::{Subject, SubjectData, SubjectId},
+    ConnectionType, Store,
+};
+
+/// How often the import progress callback is invoked, in records.
+const PROGRESS_INTERVAL: usize = 100;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct SerializedStore {
     subjects: Vec<SubjectData>,
     notes: Vec<NoteData>,
+    /// Only notes changed after this were exported. `None` for a full export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<DateTime<Local>>,
+    /// The latest change in the export. Exporting again since then picks up
+    /// where this export left off.
+    #[serde(default)]
+    until: Option<DateTime<Local>>,
+    /// The search the notes were exported from, for a partial export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+}
+
+/// What [`export_to_writer`] writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The format of full exports, which can be imported.
+    #[default]
+    Json,
+    /// Readable notes, as [`notes_to_markdown`] formats them.
+    Markdown,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Json, ExportFormat::Markdown];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "JSON",
+            ExportFormat::Markdown => "Markdown",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Progress of an ongoing import, passed to the progress callback.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub subjects: usize,
+    pub total_subjects: usize,
+    pub notes: usize,
+    pub total_notes: usize,
+}
+
+/// Exports all subjects and notes to a JSON file.
+/// Returns the time of the latest change, to pass to [`export_since`] next time.
+pub fn export(db_path: PathBuf, export_path: PathBuf) -> Option<DateTime<Local>> {
+    write_export(db_path, None, export_path)
 }
 
-pub fn export(db_path: PathBuf, export_path: PathBuf) {
+/// Exports the notes created, modified or done after `since`, along with all
+/// subjects so that the notes' subjects can be found. Deletions aren't exported.
+///
+/// Returns the time of the latest change, or `since` if nothing changed.
+/// Apply the export to a backup with [`import_merge`].
+pub fn export_since(
+    db_path: PathBuf,
+    since: DateTime<Local>,
+    export_path: PathBuf,
+) -> Option<DateTime<Local>> {
+    write_export(db_path, Some(since), export_path)
+}
+
+/// When the note last changed in a way that an incremental export picks up.
+fn last_change(note: &NoteData) -> DateTime<Local> {
+    [Some(note.created_at), Some(note.modified_at), note.done_at]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap()
+}
+
+fn write_export(
+    db_path: PathBuf,
+    since: Option<DateTime<Local>>,
+    export_path: PathBuf,
+) -> Option<DateTime<Local>> {
     let store = Store::new(ConnectionType::File(db_path));
+    if let Err(e) = store.flush() {
+        warn!("Flushing before export failed: {}", e);
+    }
     let subjects = store
         .get_subjects()
         .unwrap()
         .into_iter()
         .map(|s| (*s).clone())
         .collect();
-    let notes = store
+    let notes: Vec<NoteData> = store
         .get_all_notes()
         .unwrap()
         .into_iter()
+        .filter(|n| since.iter().all(|since| last_change(n) > *since))
         .map(|n| (*n).clone())
         .collect();
+    let until = notes.iter().map(last_change).max().or(since);
 
-    let serialized = SerializedStore { subjects, notes };
+    let serialized = SerializedStore {
+        subjects,
+        notes,
+        since,
+        until,
+        query: None,
+    };
 
     // write to file
     let file = std::fs::File::create(export_path).unwrap();
     serde_json::to_writer_pretty(file, &serialized).unwrap();
+    until
+}
+
+/// Writes `notes` to `writer`, along with the subjects they refer to and the
+/// ancestors of those. `query` records the search the notes were found with.
+pub fn export_to_writer<'a>(
+    mut writer: impl Write,
+    notes: impl IntoIterator<Item = &'a NoteData>,
+    subjects: &BTreeMap<SubjectId, Subject>,
+    format: ExportFormat,
+    query: Option<&str>,
+) -> std::io::Result<()> {
+    let notes = notes.into_iter().collect::<Vec<_>>();
+    match format {
+        ExportFormat::Json => {
+            let mut referenced = BTreeSet::new();
+            for &id in notes.iter().flat_map(|note| &note.subjects) {
+                let mut next = Some(id);
+                while let Some(subject) = next.and_then(|id| subjects.get(&id)) {
+                    if !referenced.insert(subject.id) {
+                        break;
+                    }
+                    next = subject.parent_id;
+                }
+            }
+            let serialized = SerializedStore {
+                subjects: referenced
+                    .iter()
+                    .map(|id| (*subjects[id]).clone())
+                    .collect(),
+                notes: notes.into_iter().cloned().collect(),
+                since: None,
+                until: None,
+                query: query.map(str::to_owned),
+            };
+            serde_json::to_writer_pretty(&mut writer, &serialized)?;
+        }
+        ExportFormat::Markdown => {
+            if let Some(query) = query {
+                write!(writer, "*Search: {query}*{MARKDOWN_SEPARATOR}")?;
+            }
+            writer.write_all(notes_to_markdown(notes, subjects, true).as_bytes())?;
+        }
+    }
+    writer.flush()
 }
 
-pub fn import(db_path: PathBuf, import_path: PathBuf) {
+/// Imports a JSON export into the database at `db_path`.
+/// The search index is built once after all notes have been inserted.
+///
+/// `on_progress` is called every [`PROGRESS_INTERVAL`] records and once more
+/// when the import finishes. Returns the final counts, or `None` if the user
+/// declined to overwrite an existing database.
+pub fn import(
+    db_path: PathBuf,
+    import_path: PathBuf,
+    on_progress: impl FnMut(ImportProgress),
+) -> Option<ImportProgress> {
     // if db exists, confirm overwrite and delete
     if db_path.exists() {
         println!("Database already exists. Overwrite? (y/n)");
@@ -38,29 +198,430 @@ pub fn import(db_path: PathBuf, import_path: PathBuf) {
         std::io::stdin().read_line(&mut input).unwrap();
         if input.trim() != "y" {
             println!("Aborting");
-            return;
+            return None;
         }
         std::fs::remove_file(&db_path).unwrap();
     }
 
     let store = Store::new(ConnectionType::File(db_path));
+    Some(import_into(&store, import_path, false, on_progress))
+}
+
+/// Applies a JSON export, typically from [`export_since`], on top of the
+/// database at `db_path`. Subjects and notes already in the database are
+/// replaced by their exported versions, the rest are left as they are.
+pub fn import_merge(
+    db_path: PathBuf,
+    import_path: PathBuf,
+    on_progress: impl FnMut(ImportProgress),
+) -> ImportProgress {
+    let store = Store::new(ConnectionType::File(db_path));
+    import_into(&store, import_path, true, on_progress)
+}
+
+fn import_into(
+    store: &Store,
+    import_path: PathBuf,
+    merge: bool,
+    mut on_progress: impl FnMut(ImportProgress),
+) -> ImportProgress {
+    let conn = store.conn.borrow();
+    let tx = conn.unchecked_transaction().unwrap();
 
     // read from file
     let file = std::fs::File::open(import_path).unwrap();
     let serialized: SerializedStore = serde_json::from_reader(file).unwrap();
 
+    let mut progress = ImportProgress {
+        total_subjects: serialized.subjects.len(),
+        total_notes: serialized.notes.len(),
+        ..Default::default()
+    };
+
     // add subjects
     for subject in serialized.subjects {
-        store.import_subject(&subject).unwrap();
+        if merge {
+            store.merge_subject(&subject).unwrap();
+        } else {
+            store.import_subject(&subject).unwrap();
+        }
+        progress.subjects += 1;
+        if progress.subjects % PROGRESS_INTERVAL == 0 {
+            on_progress(progress);
+        }
     }
 
-    let mut conn = store.conn.borrow_mut();
-    let tx = conn.transaction().unwrap();
-
-    // add notes
+    // add notes, along with their links to subjects
     for note in serialized.notes {
-        store.add_note_with_tx(&tx, note).unwrap();
+        if merge {
+            store.merge_note_rows(&note).unwrap();
+        } else {
+            store.import_note_rows(&note).unwrap();
+        }
+        progress.notes += 1;
+        if progress.notes % PROGRESS_INTERVAL == 0 {
+            on_progress(progress);
+        }
     }
 
+    // Titles can refer to notes imported after the note referring to them.
+    links::rebuild_text_links(&tx).unwrap();
+
     tx.commit().unwrap();
+    drop(conn);
+
+    // Indexing every note as it's inserted is slow, so it's done in one go
+    // and committed once.
+    store.rebuild_search_index().unwrap();
+    store.flush_index().unwrap();
+
+    on_progress(progress);
+    progress
+}
+
+/// Separates notes in [`notes_to_markdown`].
+const MARKDOWN_SEPARATOR: &str = "\n\n---\n\n";
+
+/// Formats `notes` as Markdown for pasting elsewhere, separated by rules.
+/// With `header`, each note starts with its creation time, task state and subjects.
+pub fn notes_to_markdown<'a>(
+    notes: impl IntoIterator<Item = &'a NoteData>,
+    subjects: &BTreeMap<SubjectId, Subject>,
+    header: bool,
+) -> String {
+    let notes = notes.into_iter().map(|note| {
+        if !header {
+            return note.text.clone();
+        }
+        let mut meta = vec![note.created_at.format("%Y-%m-%d %H:%M").to_string()];
+        match note.task_state {
+            TaskState::NotATask => {}
+            TaskState::Todo => meta.push("TODO".to_string()),
+            TaskState::Done => meta.push("DONE".to_string()),
+        }
+        let names = note
+            .subjects
+            .iter()
+            .filter_map(|id| subjects.get(id))
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        if !names.is_empty() {
+            meta.push(names.join(", "));
+        }
+        format!("*{}*\n\n{}", meta.join(" · "), note.text)
+    });
+    notes.collect::<Vec<_>>().join(MARKDOWN_SEPARATOR)
+}
+
+/// Exports every note to a Markdown file of its own in `out_dir`, for use in
+/// other note apps. Notes are put in folders following the parents of their
+/// first subject, and untagged notes directly in `out_dir`.
+///
+/// Each file starts with YAML frontmatter holding the subjects, task state
+/// and times of the note. Returns how many notes were exported.
+pub fn export_markdown(db_path: PathBuf, out_dir: PathBuf) -> std::io::Result<usize> {
+    let to_io = |e: rusqlite::Error| std::io::Error::other(e);
+
+    let store = Store::new(ConnectionType::File(db_path));
+    let subjects = store
+        .get_subjects()
+        .map_err(to_io)?
+        .into_iter()
+        .map(|subject| (subject.id, subject))
+        .collect::<BTreeMap<_, _>>();
+    let notes = store.get_all_notes().map_err(to_io)?;
+
+    for note in &notes {
+        let dir = note
+            .subjects
+            .first()
+            .map(|&id| out_dir.join(subject_folder(id, &subjects)))
+            .unwrap_or_else(|| out_dir.clone());
+        std::fs::create_dir_all(&dir)?;
+        let name = format!("{}-{}.md", note.created_at.format("%Y-%m-%d"), note.id);
+        std::fs::write(dir.join(name), note_to_markdown_file(note, &subjects))?;
+    }
+    Ok(notes.len())
+}
+
+/// The folder of `id` relative to the export folder, one level per ancestor.
+fn subject_folder(id: SubjectId, subjects: &BTreeMap<SubjectId, Subject>) -> PathBuf {
+    let mut names = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut next = Some(id);
+    while let Some(subject) = next.and_then(|id| subjects.get(&id)) {
+        // Cycles were possible in older databases.
+        if !seen.insert(subject.id) {
+            break;
+        }
+        names.push(file_name_safe(&subject.name));
+        next = subject.parent_id;
+    }
+    names.iter().rev().collect()
+}
+
+/// `name` with the characters that aren't allowed in file names replaced.
+fn file_name_safe(name: &str) -> String {
+    let safe = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let safe = safe.trim().trim_matches('.');
+    if safe.is_empty() {
+        "_".to_string()
+    } else {
+        safe.to_string()
+    }
+}
+
+/// The contents of a note's file in [`export_markdown`].
+fn note_to_markdown_file(note: &NoteData, subjects: &BTreeMap<SubjectId, Subject>) -> String {
+    // JSON strings are valid YAML, and take care of quoting.
+    let quote = |s: &str| serde_json::to_string(s).unwrap();
+    let names = note
+        .subjects
+        .iter()
+        .filter_map(|id| subjects.get(id))
+        .map(|s| quote(&s.name))
+        .collect::<Vec<_>>();
+    let task_state = match note.task_state {
+        TaskState::NotATask => "none",
+        TaskState::Todo => "todo",
+        TaskState::Done => "done",
+    };
+
+    let mut file = String::from("---\n");
+    file += &format!("subjects: [{}]\n", names.join(", "));
+    file += &format!("task_state: {task_state}\n");
+    file += &format!("created_at: {}\n", note.created_at.to_rfc3339());
+    if let Some(done_at) = note.done_at {
+        file += &format!("done_at: {}\n", done_at.to_rfc3339());
+    }
+    file += "---\n\n";
+
+    let checkbox = match note.task_state {
+        TaskState::NotATask => None,
+        TaskState::Todo => Some("- [ ] "),
+        TaskState::Done => Some("- [x] "),
+    };
+    match checkbox {
+        // The rest of the lines are indented to keep them in the list item.
+        Some(checkbox) => {
+            file += checkbox;
+            file += &note.text.lines().collect::<Vec<_>>().join("\n  ");
+        }
+        None => file += &note.text,
+    }
+    file.push('\n');
+    file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::{notes::NoteBuilder, notes::NoteSearch, ConnectionType, Store};
+
+    use super::*;
+
+    #[test]
+    fn export_import_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let target = dir.path().join("target");
+        let export_file = dir.path().join("export.json");
+
+        let (subject, note) = {
+            let store = Store::new(ConnectionType::File(source.clone()));
+            let subject = store.add_subject("Test subject".to_string()).unwrap();
+            store
+                .set_subject_color(subject.id, Some("#ff8000"))
+                .unwrap();
+            let subject = store.get_subject(subject.id).unwrap();
+            let note = store
+                .add_note(NoteBuilder::new().text("Test note").subject(subject.id))
+                .unwrap();
+            store
+                .add_note(NoteBuilder::new().text("No subject"))
+                .unwrap();
+            (subject, note)
+        };
+
+        export(source, export_file.clone());
+        let summary = import(target.clone(), export_file, |_| {}).unwrap();
+        assert_eq!(summary.subjects, 1);
+        assert_eq!(summary.notes, 2);
+
+        let store = Store::new(ConnectionType::File(target));
+        assert_eq!(store.get_subjects().unwrap(), vec![subject.clone()]);
+
+        // Row ids are assigned on insert and aren't preserved.
+        let mut imported = store.get_note(note.id).unwrap();
+        std::rc::Rc::make_mut(&mut imported).rowid = note.rowid;
+        assert_eq!(imported, note);
+
+        let note_ids = store
+            .find_notes(NoteSearch::new().subject(subject.id))
+            .unwrap();
+        assert_eq!(note_ids, vec![note.id]);
+        assert_eq!(store.find_notes(NoteSearch::new()).unwrap().len(), 2);
+
+        // The search index is rebuilt after the import.
+        assert_eq!(store.term_suggestions("subj", 5).unwrap(), vec!["subject"]);
+        let found = store
+            .search
+            .perform_search_blocking("Test note".to_string())
+            .unwrap();
+        assert_eq!(found.first().map(|n| n.id), Some(note.id));
+    }
+
+    #[test]
+    fn incremental_export_merges_into_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let backup = dir.path().join("backup");
+        let full_file = dir.path().join("full.json");
+        let delta_file = dir.path().join("delta.json");
+
+        let store = Store::new(ConnectionType::File(source.clone()));
+        let old = store.add_note(NoteBuilder::new().text("Old")).unwrap();
+        let kept = store.add_note(NoteBuilder::new().text("Kept")).unwrap();
+        drop(store);
+
+        let until = export(source.clone(), full_file.clone()).unwrap();
+        assert_eq!(until, kept.created_at);
+        import(backup.clone(), full_file, |_| {}).unwrap();
+
+        let store = Store::new(ConnectionType::File(source.clone()));
+        let subject = store.add_subject("New subject".to_string()).unwrap();
+        let edited = old.modify_with(|n| n.text("Old, edited").subject(subject.id).modified_now());
+        store.update_note(edited.clone()).unwrap();
+        let new = store.add_note(NoteBuilder::new().text("New")).unwrap();
+        drop(store);
+
+        let next = export_since(source.clone(), until, delta_file.clone()).unwrap();
+        assert_eq!(next, new.created_at);
+        let summary = import_merge(backup.clone(), delta_file.clone(), |_| {});
+        assert_eq!(summary.notes, 2);
+
+        let store = Store::new(ConnectionType::File(backup));
+        assert_eq!(store.get_note(old.id).unwrap().text, "Old, edited");
+        assert_eq!(store.get_note(old.id).unwrap().subjects, vec![subject.id]);
+        assert_eq!(store.get_note(kept.id).unwrap().text, "Kept");
+        assert_eq!(store.get_note(new.id).unwrap().text, "New");
+        assert_eq!(store.find_notes(NoteSearch::new()).unwrap().len(), 3);
+        assert_eq!(store.get_subjects().unwrap(), vec![subject]);
+
+        // Nothing changed since, so the next export is empty and resumes from the same time.
+        assert_eq!(export_since(source, next, delta_file).unwrap(), next);
+    }
+
+    #[test]
+    fn export_search_results() {
+        let store = Store::new(ConnectionType::InMemory);
+        let parent = store.add_subject("Projects".to_string()).unwrap();
+        let child = store.add_subject("Garden".to_string()).unwrap();
+        store.set_subject_parent(child.id, Some(parent.id)).unwrap();
+        store.add_subject("Unrelated".to_string()).unwrap();
+        let note = store
+            .add_note(NoteBuilder::new().text("Plant tulips").subject(child.id))
+            .unwrap();
+        let subjects = store
+            .get_subjects()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.id, s))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut json = Vec::new();
+        let query = Some("tulips");
+        export_to_writer(&mut json, [&*note], &subjects, ExportFormat::Json, query).unwrap();
+        let exported: SerializedStore = serde_json::from_slice(&json).unwrap();
+        assert_eq!(exported.query.as_deref(), Some("tulips"));
+        assert_eq!(exported.notes, vec![(*note).clone()]);
+        let names = exported
+            .subjects
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(names, BTreeSet::from(["Garden", "Projects"]));
+
+        let mut markdown = Vec::new();
+        export_to_writer(
+            &mut markdown,
+            [&*note],
+            &subjects,
+            ExportFormat::Markdown,
+            query,
+        )
+        .unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.starts_with("*Search: tulips*"));
+        assert!(markdown.ends_with("Plant tulips"));
+    }
+
+    #[test]
+    fn notes_to_markdown_with_header() {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Work".to_string()).unwrap();
+        let created_at = chrono::Local::now();
+        let task = store
+            .add_note(
+                NoteBuilder::new()
+                    .text("Ship it")
+                    .subject(subject.id)
+                    .as_task()
+                    .created_at(created_at),
+            )
+            .unwrap();
+        let note = store.add_note(NoteBuilder::new().text("Plain")).unwrap();
+        let subjects = BTreeMap::from([(subject.id, subject)]);
+
+        let plain = notes_to_markdown([&*task, &*note], &subjects, false);
+        assert_eq!(plain, "Ship it\n\n---\n\nPlain");
+
+        let with_header = notes_to_markdown([&*task], &subjects, true);
+        let time = created_at.format("%Y-%m-%d %H:%M");
+        assert_eq!(with_header, format!("*{time} · TODO · Work*\n\nShip it"));
+    }
+
+    #[test]
+    fn markdown_export_writes_a_file_per_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let out = dir.path().join("markdown");
+
+        let store = Store::new(ConnectionType::File(source.clone()));
+        let work = store.add_subject("Work".to_string()).unwrap();
+        let plans = store.add_subject("Plans: 2024".to_string()).unwrap();
+        store.set_subject_parent(plans.id, Some(work.id)).unwrap();
+        let task = store
+            .add_note(
+                NoteBuilder::new()
+                    .text("Ship it\nBefore Friday")
+                    .subject(plans.id)
+                    .task_state(TaskState::Todo),
+            )
+            .unwrap();
+        let untagged = store.add_note(NoteBuilder::new().text("Loose")).unwrap();
+        drop(store);
+
+        assert_eq!(export_markdown(source, out.clone()).unwrap(), 2);
+
+        let file_name =
+            |note: &NoteData| format!("{}-{}.md", note.created_at.format("%Y-%m-%d"), note.id);
+        let task_file = out.join("Work").join("Plans_ 2024").join(file_name(&task));
+        let expected = format!(
+            "---\nsubjects: [\"Plans: 2024\"]\ntask_state: todo\ncreated_at: {}\n---\n\n\
+            - [ ] Ship it\n  Before Friday\n",
+            task.created_at.to_rfc3339()
+        );
+        assert_eq!(std::fs::read_to_string(task_file).unwrap(), expected);
+
+        let untagged_file = std::fs::read_to_string(out.join(file_name(&untagged))).unwrap();
+        assert!(untagged_file.starts_with("---\nsubjects: []\ntask_state: none\n"));
+        assert!(untagged_file.ends_with("---\n\nLoose\n"));
+    }
 }
@@ -0,0 +1,96 @@
+//! Time spent focusing on notes.
+//!
+//! Focus sessions are recorded outside the undo history and are kept when
+//! their note is deleted, so undoing the deletion brings the total back.
+
+use chrono::{DateTime, Local};
+use rusqlite::params;
+use tracing::{debug, instrument};
+
+use super::{
+    notes::NoteId,
+    retry::{begin_write, commit_write},
+    Store,
+};
+
+impl Store {
+    /// Records a focus session on `note`. Sessions that didn't last are ignored.
+    #[instrument(skip(self))]
+    pub fn add_focus_session(
+        &self,
+        note: NoteId,
+        started_at: DateTime<Local>,
+        ended_at: DateTime<Local>,
+    ) -> rusqlite::Result<()> {
+        if ended_at <= started_at {
+            return Ok(());
+        }
+        debug!("Recording focus session");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        tx.prepare_cached(
+            "INSERT INTO focus_sessions (note_id, started_at, ended_at) VALUES (?1, ?2, ?3)",
+        )?
+        .execute(params![
+            note,
+            started_at.naive_utc().timestamp_nanos(),
+            ended_at.naive_utc().timestamp_nanos(),
+        ])?;
+        commit_write(tx)
+    }
+
+    /// Total time spent focusing on `note`, in whole seconds.
+    pub fn focus_seconds(&self, note: NoteId) -> rusqlite::Result<i64> {
+        let nanos: i64 = self
+            .conn
+            .borrow()
+            .prepare_cached(
+                "SELECT COALESCE(SUM(ended_at - started_at), 0)
+                FROM focus_sessions WHERE note_id = ?1",
+            )?
+            .query_row(params![note], |row| row.get(0))?;
+        Ok(nanos / 1_000_000_000)
+    }
+}
+
+/// Formats a duration in seconds as hours and minutes, e.g. "1h 5m".
+pub fn format_focus_time(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::data::{notes::NoteBuilder, ConnectionType};
+
+    #[test]
+    fn focus_sessions_add_up() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let task = store.add_note(NoteBuilder::new().text("Task").as_task())?;
+        let other = store.add_note(NoteBuilder::new().text("Other").as_task())?;
+        assert_eq!(store.focus_seconds(task.id)?, 0);
+
+        let start = Local::now();
+        store.add_focus_session(task.id, start, start + Duration::minutes(25))?;
+        store.add_focus_session(task.id, start, start + Duration::seconds(90))?;
+        store.add_focus_session(task.id, start, start - Duration::minutes(5))?;
+        assert_eq!(store.focus_seconds(task.id)?, 25 * 60 + 90);
+        assert_eq!(store.focus_seconds(other.id)?, 0);
+
+        // Sessions outlive the note for undo.
+        store.delete_note(task.id)?;
+        assert_eq!(store.focus_seconds(task.id)?, 25 * 60 + 90);
+
+        assert_eq!(format_focus_time(25 * 60 + 90), "26m");
+        assert_eq!(format_focus_time(2 * 3600), "2h");
+        assert_eq!(format_focus_time(3600 + 5 * 60), "1h 5m");
+        Ok(())
+    }
+}
@@ -16,6 +16,7 @@ impl ToSql for Blob {
 pub fn add_functions(conn: &Connection) -> Result<()> {
     add_concat_blobs(conn)?;
     add_case_insensitive_includes(conn)?;
+    add_local_day(conn)?;
     Ok(())
 }
 
@@ -64,3 +65,19 @@ pub fn add_case_insensitive_includes(conn: &Connection) -> Result<()> {
         },
     )
 }
+
+/// `local_day(nanos)` is the local date of a timestamp as days from the common era,
+/// the same date the views get with `naive_local()`.
+fn add_local_day(conn: &Connection) -> Result<()> {
+    use chrono::{Datelike, Local, TimeZone};
+
+    conn.create_scalar_function(
+        "local_day",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let nanos = ctx.get::<i64>(0)?;
+            Ok(Local.timestamp_nanos(nanos).date_naive().num_days_from_ce())
+        },
+    )
+}
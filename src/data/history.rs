@@ -0,0 +1,73 @@
+//! The undo and redo queues of the layer, kept in the database so they
+//! survive a restart.
+
+use std::collections::VecDeque;
+
+use rusqlite::params;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, instrument, warn};
+
+use super::{
+    retry::{begin_write, commit_write},
+    Store,
+};
+
+const UNDO: &str = "undo";
+const REDO: &str = "redo";
+
+impl Store {
+    /// Replaces the saved undo history with `undo` and `redo`, oldest first.
+    #[instrument(skip_all)]
+    pub fn save_undo_history<T: Serialize>(
+        &self,
+        undo: &VecDeque<T>,
+        redo: &VecDeque<T>,
+    ) -> rusqlite::Result<()> {
+        debug!("Saving {} undo and {} redo actions", undo.len(), redo.len());
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        tx.execute("DELETE FROM undo_history", [])?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO undo_history (queue, position, action) VALUES (?1, ?2, ?3)",
+            )?;
+            for (queue, actions) in [(UNDO, undo), (REDO, redo)] {
+                for (position, action) in actions.iter().enumerate() {
+                    let json = serde_json::to_string(action)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                    insert.execute(params![queue, position, json])?;
+                }
+            }
+        }
+        commit_write(tx)
+    }
+
+    /// The saved undo and redo queues, oldest first.
+    ///
+    /// History saved by a version with different actions can't be read,
+    /// and is left out as a whole rather than replayed in part.
+    pub fn load_undo_history<T: DeserializeOwned>(
+        &self,
+    ) -> rusqlite::Result<(VecDeque<T>, VecDeque<T>)> {
+        let conn = self.conn.borrow();
+        let mut query = conn
+            .prepare_cached("SELECT action FROM undo_history WHERE queue = ?1 ORDER BY position")?;
+        let mut load = |queue: &str| -> rusqlite::Result<Option<VecDeque<T>>> {
+            let mut actions = VecDeque::new();
+            for json in query.query_map([queue], |row| row.get::<_, String>(0))? {
+                match serde_json::from_str(&json?) {
+                    Ok(action) => actions.push_back(action),
+                    Err(e) => {
+                        warn!("Ignoring the saved undo history: {}", e);
+                        return Ok(None);
+                    }
+                }
+            }
+            Ok(Some(actions))
+        };
+        match (load(UNDO)?, load(REDO)?) {
+            (Some(undo), Some(redo)) => Ok((undo, redo)),
+            _ => Ok((VecDeque::new(), VecDeque::new())),
+        }
+    }
+}
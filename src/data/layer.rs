@@ -1,23 +1,30 @@
 mod test;
 
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::hash::Hash;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use dioxus::prelude::{use_context, use_context_provider, ScopeState};
+use dioxus::prelude::{use_context, use_context_provider, use_future, ScopeState};
 use dioxus_signals::*;
 use uuid::Uuid;
 
-use super::notes::{NoteBuilder, NoteSearch};
+use super::links::LinkChange;
+use super::notes::{NoteBuilder, NotePage, NoteSearch, SubjectMatch, TaskState};
+use super::replace::FindPattern;
 use super::search::SearchWorker;
-use super::subjects::{Subject, SubjectId};
-use super::ConnectionType;
+use super::settings::{ClearDonePolicy, EmptyEditPolicy, FavoriteOrder};
+use super::subjects::{
+    favorites, neighbor_subject, parse_subject_outline, InvalidMove, Subject, SubjectCounts,
+    SubjectData, SubjectHasNotes, SubjectId, SubjectNameTaken,
+};
 use super::{
-    notes::{Note, NoteId},
+    notes::{Note, NoteData, NoteId},
     Store,
 };
+use super::{ConnectionType, OpenError};
 
 #[derive(Debug, Clone)]
 struct Cache<K, V> {
@@ -86,6 +93,11 @@ where
         }
     }
 
+    fn retain(&mut self, mut f: impl FnMut(&K) -> bool) {
+        self.map.retain(|k, _| f(k));
+        self.drop_order.retain(|k| f(k));
+    }
+
     fn clear(&mut self) {
         self.map.clear();
         self.drop_order.clear();
@@ -101,49 +113,417 @@ pub(crate) enum ApplyDirection {
 pub struct DbActions {
     store: Rc<Store>,
     note_cache: Cache<NoteId, Note>,
-    query_cache: Cache<NoteSearch, Vec<NoteId>>,
+    query_cache: Cache<NoteSearch, NotePage>,
     subject_cache: Option<Rc<BTreeMap<SubjectId, Subject>>>,
+    ancestor_cache: RefCell<Cache<SubjectId, Rc<Vec<Subject>>>>,
     last_added_subject: Option<Subject>,
     undo_queue: VecDeque<LayerAction>,
     redo_queue: VecDeque<LayerAction>,
+    /// Whether the queues changed since they were saved to the store.
+    history_changed: Cell<bool>,
     direction: ApplyDirection,
+    /// Collects the inverse actions while a group is being performed.
+    group_backwards: Option<Vec<LayerAction>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LayerEffect {
     InvalidateQuery,
-    InvalidateNote(NoteId),
+    /// The note changed. Its cached queries are invalidated by `subjects`,
+    /// the subjects it had before, and the ones it has now.
+    InvalidateNote {
+        note: NoteId,
+        subjects: Vec<SubjectId>,
+    },
+    InvalidateNotes(Vec<NoteId>),
     InvalidateSubjects,
+    InvalidateAll,
+    Nothing,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// An edit was made against an outdated version of a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditConflict {
+    /// The note was modified after the edit started.
+    Modified(NoteId),
+    /// The note was deleted after the edit started.
+    Deleted(NoteId),
+}
+
+impl std::fmt::Display for EditConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditConflict::Modified(_) => write!(f, "the note was changed elsewhere"),
+            EditConflict::Deleted(_) => write!(f, "the note was deleted"),
+        }
+    }
+}
+
+impl std::error::Error for EditConflict {}
+
+/// Actions fail with rusqlite errors, so conflicts are wrapped in one.
+impl From<EditConflict> for rusqlite::Error {
+    fn from(conflict: EditConflict) -> Self {
+        rusqlite::Error::UserFunctionError(Box::new(conflict))
+    }
+}
+
+/// How many of the subjects in an outline were created and how many already existed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutlineSummary {
+    pub created: usize,
+    pub matched: usize,
+}
+
+/// What happens to the notes of a subject being deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RemoveSubjectPolicy {
+    /// Refuses to delete a subject that still has notes.
+    Refuse,
+    /// Tags the notes with the parent of the subject, or untags them if it has none.
+    MoveToParent,
+    /// Untags the notes, keeping them otherwise as they are.
+    Untag,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum LayerAction {
     CreateNote(NoteBuilder),
     DeleteNote(NoteId),
     EditNote(NoteId, NoteBuilder),
+    /// Moves a note to another date without touching anything else,
+    /// including its task state and modification time.
+    BumpNote {
+        note: NoteId,
+        to: chrono::DateTime<chrono::Local>,
+    },
+    /// Pins or unpins a note, also without touching its modification time.
+    SetPinned {
+        note: NoteId,
+        pinned: bool,
+    },
+    /// Deletes the notes in one transaction, see [`Store::delete_notes`].
+    DeleteNotes(Vec<NoteId>),
+    /// Edits the notes in one transaction, see [`Store::update_notes`].
+    EditNotes(Vec<(NoteId, NoteBuilder)>),
     AddSubject(Option<SubjectId>, String),
-    RemoveSubject(SubjectId),
+    /// Deletes a subject, moving its children up to its parent and handling
+    /// its notes by the policy.
+    RemoveSubject(SubjectId, RemoveSubjectPolicy),
     SetSubjectParent {
         subject: SubjectId,
         parent: Option<SubjectId>,
     },
+    /// Re-parents a subject after checking the hierarchy stays valid,
+    /// see [`Store::move_subtree`].
+    MoveSubtree {
+        subject: SubjectId,
+        parent: Option<SubjectId>,
+    },
+    SetSubjectFavorite {
+        subject: SubjectId,
+        favorite: bool,
+        order: i64,
+    },
+    SetSubjectColor {
+        subject: SubjectId,
+        color: Option<String>,
+    },
+    RenameSubject {
+        subject: SubjectId,
+        name: String,
+    },
+    AddSubjectToNotes {
+        subject: SubjectId,
+        notes: Vec<NoteId>,
+    },
+    RemoveSubjectFromNotes {
+        subject: SubjectId,
+        notes: Vec<NoteId>,
+    },
+    LinkNotes {
+        from: NoteId,
+        to: NoteId,
+    },
+    UnlinkNotes {
+        from: NoteId,
+        to: NoteId,
+    },
+    /// Undoes linking notes that were already linked in the text of `from`,
+    /// see [`Store::make_link_text_only`].
+    MakeLinkTextOnly {
+        from: NoteId,
+        to: NoteId,
+    },
+    /// Performs the actions in order as a single undo step.
+    Group(Vec<LayerAction>),
+    /// A marker in the undo history that changes nothing.
+    /// Its label names the action performed right after it.
+    Checkpoint(Option<String>),
+}
+
+impl LayerAction {
+    /// The action that saves `text` and `subjects` from the note editor,
+    /// as an edit of `note`. Returns `None` if `policy` says the edit
+    /// shouldn't be saved.
+    pub fn save_edit(
+        note: &NoteData,
+        text: String,
+        subjects: Vec<SubjectId>,
+        policy: EmptyEditPolicy,
+    ) -> Option<LayerAction> {
+        if !text.trim().is_empty() {
+            let builder = NoteBuilder::new()
+                .text(text)
+                .subjects(subjects)
+                .based_on(note.modified_at);
+            return Some(LayerAction::EditNote(note.id, builder));
+        }
+        match policy {
+            EmptyEditPolicy::KeepOldText => None,
+            EmptyEditPolicy::DeleteNote => Some(LayerAction::DeleteNote(note.id)),
+        }
+    }
+
+    /// An edit adding `text` to the end of `note`, on a line of its own.
+    pub fn append_to_note(note: &NoteData, text: &str) -> LayerAction {
+        let builder = NoteBuilder::new()
+            .text(join_lines(&note.text, text))
+            .based_on(note.modified_at);
+        LayerAction::EditNote(note.id, builder)
+    }
+
+    /// An edit adding `text` to the start of `note`, on a line of its own.
+    pub fn prepend_to_note(note: &NoteData, text: &str) -> LayerAction {
+        let builder = NoteBuilder::new()
+            .text(join_lines(text, &note.text))
+            .based_on(note.modified_at);
+        LayerAction::EditNote(note.id, builder)
+    }
+
+    /// Creates the subjects of an outline that don't exist yet, see
+    /// [`parse_subject_outline`]. Subjects with the same name under the same
+    /// parent are reused. Performing the returned group is a single undo step.
+    pub fn create_subjects_from_outline(
+        text: &str,
+        existing: &BTreeMap<SubjectId, Subject>,
+    ) -> (LayerAction, OutlineSummary) {
+        let mut known = existing
+            .values()
+            .map(|s| ((s.parent_id, s.name.clone()), s.id))
+            .collect::<HashMap<_, _>>();
+        let mut matched = BTreeSet::new();
+        let mut summary = OutlineSummary::default();
+        let mut actions = Vec::new();
+
+        for path in parse_subject_outline(text) {
+            let mut parent = None;
+            for (depth, name) in path.iter().enumerate() {
+                let key = (parent, name.clone());
+                let id = match known.get(&key) {
+                    Some(&id) => id,
+                    None => {
+                        let id = SubjectId(Uuid::new_v4());
+                        actions.push(LayerAction::AddSubject(Some(id), name.clone()));
+                        if parent.is_some() {
+                            actions.push(LayerAction::SetSubjectParent {
+                                subject: id,
+                                parent,
+                            });
+                        }
+                        known.insert(key, id);
+                        summary.created += 1;
+                        id
+                    }
+                };
+                if depth == path.len() - 1 && existing.contains_key(&id) && matched.insert(id) {
+                    summary.matched += 1;
+                }
+                parent = Some(id);
+            }
+        }
+        (LayerAction::Group(actions), summary)
+    }
+
+    /// Clears the done tasks in `notes` as `policy` says, in one transaction.
+    pub fn clear_done_tasks(notes: Vec<NoteId>, policy: ClearDonePolicy) -> LayerAction {
+        match policy {
+            ClearDonePolicy::DeleteNotes => LayerAction::DeleteNotes(notes),
+            ClearDonePolicy::Untask => LayerAction::EditNotes(
+                notes
+                    .into_iter()
+                    .map(|id| (id, NoteBuilder::new().task_state(TaskState::NotATask)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Deletes the subjects `ids` as a single undo step. Each one hands its
+    /// children to its parent when it's deleted, so they end up under the
+    /// closest ancestor that stays whatever the order.
+    pub fn delete_subjects(ids: &[SubjectId], policy: RemoveSubjectPolicy) -> LayerAction {
+        let deleted = ids.iter().copied().collect::<BTreeSet<_>>();
+        LayerAction::Group(
+            deleted
+                .into_iter()
+                .map(|id| LayerAction::RemoveSubject(id, policy))
+                .collect(),
+        )
+    }
+
+    /// Merges `from` into `into` as a single undo step: the notes of `from`
+    /// are tagged with `into`, its children move under `into`, and then it's
+    /// deleted.
+    pub fn merge_subject(from: &SubjectData, into: SubjectId, notes: Vec<NoteId>) -> LayerAction {
+        let mut actions = Vec::new();
+        if !notes.is_empty() {
+            actions.push(LayerAction::AddSubjectToNotes {
+                subject: into,
+                notes: notes.clone(),
+            });
+            actions.push(LayerAction::RemoveSubjectFromNotes {
+                subject: from.id,
+                notes,
+            });
+        }
+        for &child in &from.children {
+            // A child being merged into takes the place of its parent.
+            let parent = if child == into {
+                from.parent_id
+            } else {
+                Some(into)
+            };
+            actions.push(LayerAction::MoveSubtree {
+                subject: child,
+                parent,
+            });
+        }
+        actions.push(LayerAction::RemoveSubject(
+            from.id,
+            RemoveSubjectPolicy::Refuse,
+        ));
+        LayerAction::Group(actions)
+    }
+
+    /// Edits saving the new texts from [`Store::note_replacements`],
+    /// as a single undo step.
+    pub fn replace_in_notes(replacements: Vec<(Note, String)>) -> LayerAction {
+        let edits = replacements
+            .into_iter()
+            .map(|(note, text)| {
+                let builder = NoteBuilder::new().text(text).based_on(note.modified_at);
+                LayerAction::EditNote(note.id, builder)
+            })
+            .collect();
+        LayerAction::Group(edits)
+    }
+
+    /// Describes what performing the action does.
+    pub fn describe(&self) -> String {
+        match self {
+            LayerAction::CreateNote(_) => "Create note".to_string(),
+            LayerAction::DeleteNote(_) => "Delete note".to_string(),
+            LayerAction::EditNote(_, _) => "Edit note".to_string(),
+            LayerAction::BumpNote { .. } => "Bump note".to_string(),
+            LayerAction::SetPinned { pinned: true, .. } => "Pin note".to_string(),
+            LayerAction::SetPinned { pinned: false, .. } => "Unpin note".to_string(),
+            LayerAction::DeleteNotes(notes) => format!("Delete {} notes", notes.len()),
+            LayerAction::EditNotes(edits) => format!("Edit {} notes", edits.len()),
+            LayerAction::AddSubject(_, name) => format!("Create subject \"{name}\""),
+            LayerAction::RemoveSubject(..) => "Delete subject".to_string(),
+            LayerAction::SetSubjectParent { .. } => "Change subject parent".to_string(),
+            LayerAction::MoveSubtree { .. } => "Move subject".to_string(),
+            LayerAction::SetSubjectFavorite { .. } => "Change favorites".to_string(),
+            LayerAction::SetSubjectColor { .. } => "Change subject color".to_string(),
+            LayerAction::RenameSubject { name, .. } => format!("Rename subject to \"{name}\""),
+            LayerAction::AddSubjectToNotes { notes, .. } => format!("Tag {} notes", notes.len()),
+            LayerAction::RemoveSubjectFromNotes { notes, .. } => {
+                format!("Untag {} notes", notes.len())
+            }
+            LayerAction::LinkNotes { .. } => "Link notes".to_string(),
+            LayerAction::UnlinkNotes { .. } => "Unlink notes".to_string(),
+            LayerAction::MakeLinkTextOnly { .. } => "Unlink notes".to_string(),
+            LayerAction::Group(actions) => match actions.as_slice() {
+                [action] => action.describe(),
+                actions => format!("{} changes", actions.len()),
+            },
+            LayerAction::Checkpoint(label) => label.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Describes the action this one is the inverse of, as found in the undo queue.
+    pub fn describe_inverse(&self) -> String {
+        match self {
+            LayerAction::CreateNote(_) => "Delete note".to_string(),
+            LayerAction::DeleteNote(_) => "Create note".to_string(),
+            LayerAction::EditNote(_, _) => "Edit note".to_string(),
+            LayerAction::BumpNote { .. } => "Bump note".to_string(),
+            LayerAction::SetPinned { pinned: true, .. } => "Unpin note".to_string(),
+            LayerAction::SetPinned { pinned: false, .. } => "Pin note".to_string(),
+            LayerAction::DeleteNotes(notes) => format!("Create {} notes", notes.len()),
+            LayerAction::EditNotes(edits) => format!("Edit {} notes", edits.len()),
+            LayerAction::AddSubject(_, name) => format!("Delete subject \"{name}\""),
+            LayerAction::RemoveSubject(..) => "Create subject".to_string(),
+            LayerAction::SetSubjectParent { .. } => "Change subject parent".to_string(),
+            LayerAction::MoveSubtree { .. } => "Move subject".to_string(),
+            LayerAction::SetSubjectFavorite { .. } => "Change favorites".to_string(),
+            LayerAction::SetSubjectColor { .. } => "Change subject color".to_string(),
+            LayerAction::RenameSubject { .. } => "Rename subject".to_string(),
+            LayerAction::AddSubjectToNotes { notes, .. } => {
+                format!("Untag {} notes", notes.len())
+            }
+            LayerAction::RemoveSubjectFromNotes { notes, .. } => {
+                format!("Tag {} notes", notes.len())
+            }
+            LayerAction::LinkNotes { .. } => "Unlink notes".to_string(),
+            LayerAction::UnlinkNotes { .. } => "Link notes".to_string(),
+            LayerAction::MakeLinkTextOnly { .. } => "Link notes".to_string(),
+            LayerAction::Group(actions) => match actions.as_slice() {
+                [action] => action.describe_inverse(),
+                actions => format!("{} changes", actions.len()),
+            },
+            LayerAction::Checkpoint(label) => label.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Puts `second` on the line after `first`. A blank side is left out.
+fn join_lines(first: &str, second: &str) -> String {
+    if first.trim().is_empty() {
+        second.to_string()
+    } else if second.trim().is_empty() {
+        first.to_string()
+    } else if first.ends_with('\n') {
+        format!("{first}{second}")
+    } else {
+        format!("{first}\n{second}")
+    }
 }
 
 impl DbActions {
+    /// Creates the actions with the undo history saved in `store`.
     pub(crate) fn new(store: Rc<Store>) -> Self {
+        let (undo_queue, redo_queue) = store.load_undo_history().unwrap_or_else(|e| {
+            tracing::error!("Failed to load the undo history: {}", e);
+            Default::default()
+        });
         Self {
             store,
             note_cache: Cache::new(1024),
             query_cache: Cache::new(16),
             subject_cache: None,
+            ancestor_cache: RefCell::new(Cache::new(64)),
             last_added_subject: None,
-            undo_queue: VecDeque::new(),
-            redo_queue: VecDeque::new(),
+            undo_queue,
+            redo_queue,
+            history_changed: Cell::new(false),
             direction: ApplyDirection::Forward,
+            group_backwards: None,
         }
     }
 
     fn add_undo_action(&mut self, action: LayerAction) {
+        self.history_changed.set(true);
         if self.undo_queue.len() >= 64 {
             self.undo_queue.pop_front();
         }
@@ -151,6 +531,7 @@ impl DbActions {
     }
 
     fn add_redo_action(&mut self, action: LayerAction) {
+        self.history_changed.set(true);
         if self.redo_queue.len() >= 64 {
             self.redo_queue.pop_front();
         }
@@ -158,49 +539,214 @@ impl DbActions {
     }
 
     fn add_backwards(&mut self, action: LayerAction) {
+        if let Some(group) = &mut self.group_backwards {
+            group.push(action);
+            return;
+        }
         match self.direction {
             ApplyDirection::Forward => self.add_undo_action(action),
             ApplyDirection::Backward => self.add_redo_action(action),
         }
     }
 
-    pub(crate) fn undo(&mut self) -> Option<LayerEffect> {
-        let Some(action) = self.undo_queue.pop_back() else { return None };
-        let effect = self.perform_direction(action, ApplyDirection::Backward);
-        Some(effect)
+    /// Undoes the latest action. Checkpoints are moved to the redo queue
+    /// on the way, so they stay next to the action they label. If undoing
+    /// fails, the action and its checkpoints stay in the undo queue.
+    pub(crate) fn undo(&mut self) -> Option<rusqlite::Result<LayerEffect>> {
+        self.history_changed.set(true);
+        let mut checkpoints = 0;
+        loop {
+            let action = self.undo_queue.pop_back()?;
+            if let LayerAction::Checkpoint(_) = action {
+                self.add_redo_action(action);
+                checkpoints += 1;
+                continue;
+            }
+            let effect = self.perform_direction(action.clone(), ApplyDirection::Backward);
+            if effect.is_ok() {
+                while let Some(LayerAction::Checkpoint(_)) = self.undo_queue.back() {
+                    let checkpoint = self.undo_queue.pop_back().unwrap();
+                    self.add_redo_action(checkpoint);
+                }
+            } else {
+                self.undo_queue.push_back(action);
+                for _ in 0..checkpoints {
+                    let checkpoint = self.redo_queue.pop_back().unwrap();
+                    self.undo_queue.push_back(checkpoint);
+                }
+            }
+            return Some(effect);
+        }
+    }
+
+    /// Redoes the latest undone action. If redoing fails, the action and
+    /// its checkpoints stay in the redo queue.
+    pub(crate) fn redo(&mut self) -> Option<rusqlite::Result<LayerEffect>> {
+        self.history_changed.set(true);
+        let mut checkpoints = 0;
+        loop {
+            let action = self.redo_queue.pop_back()?;
+            if let LayerAction::Checkpoint(_) = action {
+                self.add_undo_action(action);
+                checkpoints += 1;
+                continue;
+            }
+            let effect = self.perform_direction(action.clone(), ApplyDirection::Forward);
+            if effect.is_err() {
+                self.redo_queue.push_back(action);
+                for _ in 0..checkpoints {
+                    let checkpoint = self.undo_queue.pop_back().unwrap();
+                    self.redo_queue.push_back(checkpoint);
+                }
+            }
+            return Some(effect);
+        }
     }
 
-    pub(crate) fn redo(&mut self) -> Option<LayerEffect> {
-        let Some(action) = self.redo_queue.pop_back() else { return None };
-        let effect = self.perform_direction(action, ApplyDirection::Forward);
-        Some(effect)
+    /// Undoes up to `count` steps, stopping at the first failure.
+    pub(crate) fn undo_n(&mut self, count: usize) -> Vec<rusqlite::Result<LayerEffect>> {
+        let mut effects = Vec::new();
+        for _ in 0..count {
+            let Some(effect) = self.undo() else { break };
+            let failed = effect.is_err();
+            effects.push(effect);
+            if failed {
+                break;
+            }
+        }
+        effects
+    }
+
+    /// Undoes the actions performed after the latest checkpoint labeled
+    /// `label`, stopping at the first failure. Does nothing if there's no
+    /// such checkpoint.
+    pub(crate) fn undo_to(&mut self, label: &str) -> Vec<rusqlite::Result<LayerEffect>> {
+        let is_target =
+            |action: &LayerAction| matches!(action, LayerAction::Checkpoint(Some(l)) if l == label);
+        let Some(target) = self.undo_queue.iter().rposition(is_target) else { return Vec::new() };
+
+        let mut effects = Vec::new();
+        // Undoing the action right after the checkpoint moves the checkpoint
+        // to the redo queue as well, which ends the loop.
+        while self
+            .undo_queue
+            .iter()
+            .skip(target + 1)
+            .any(|action| !matches!(action, LayerAction::Checkpoint(_)))
+        {
+            let Some(effect) = self.undo() else { break };
+            let failed = effect.is_err();
+            effects.push(effect);
+            if failed {
+                break;
+            }
+        }
+        effects
+    }
+
+    /// Adds a checkpoint to the undo history, labeling the next action.
+    pub(crate) fn checkpoint(&mut self, label: Option<String>) {
+        self.add_undo_action(LayerAction::Checkpoint(label));
+    }
+
+    /// Describes what the next undo will revert.
+    pub(crate) fn undo_description(&self) -> Option<String> {
+        let mut actions = self
+            .undo_queue
+            .iter()
+            .rev()
+            .skip_while(|a| matches!(a, LayerAction::Checkpoint(_)));
+        let action = actions.next()?;
+        match actions.next() {
+            Some(LayerAction::Checkpoint(Some(label))) => Some(label.clone()),
+            _ => Some(action.describe_inverse()),
+        }
+    }
+
+    /// Describes what the next redo will perform again.
+    pub(crate) fn redo_description(&self) -> Option<String> {
+        let mut label = None;
+        for action in self.redo_queue.iter().rev() {
+            match action {
+                LayerAction::Checkpoint(checkpoint) => label = checkpoint.clone(),
+                action => return Some(label.unwrap_or_else(|| action.describe())),
+            }
+        }
+        None
+    }
+
+    /// Saves the undo and redo queues to the store, if they changed since
+    /// they were last saved.
+    pub(crate) fn save_history(&self) -> rusqlite::Result<()> {
+        if !self.history_changed.replace(false) {
+            return Ok(());
+        }
+        let result = self
+            .store
+            .save_undo_history(&self.undo_queue, &self.redo_queue);
+        if result.is_err() {
+            self.history_changed.set(true);
+        }
+        result
     }
 
-    pub(crate) fn perform(&mut self, action: LayerAction) -> LayerEffect {
+    pub(crate) fn perform(&mut self, action: LayerAction) -> rusqlite::Result<LayerEffect> {
+        self.history_changed.set(true);
         self.redo_queue.clear();
         self.perform_direction(action, ApplyDirection::Forward)
     }
 
+    /// Performs the action in the given direction.
+    /// On failure nothing is added to the undo or redo queues.
     pub(crate) fn perform_direction(
         &mut self,
         action: LayerAction,
         direction: ApplyDirection,
-    ) -> LayerEffect {
+    ) -> rusqlite::Result<LayerEffect> {
         let old_direction = self.direction;
         self.direction = direction;
         let effect = match action {
             LayerAction::CreateNote(builder) => self.create_note(builder),
             LayerAction::DeleteNote(id) => self.delete_note_by_id(id),
+            LayerAction::DeleteNotes(ids) => self.delete_notes(ids),
+            LayerAction::EditNotes(edits) => self.edit_notes(edits),
             LayerAction::EditNote(id, builder) => self.edit_note_with(id, builder),
+            LayerAction::BumpNote { note, to } => self.bump_note(note, to),
+            LayerAction::SetPinned { note, pinned } => self.set_pinned(note, pinned),
             LayerAction::AddSubject(id, name) => self.add_subject(id, name),
-            LayerAction::RemoveSubject(id) => self.remove_subject(id),
+            LayerAction::RemoveSubject(id, policy) => self.remove_subject(id, policy),
             LayerAction::SetSubjectParent { subject, parent } => {
                 self.set_subject_parent(subject, parent)
             }
+            LayerAction::MoveSubtree { subject, parent } => self.move_subtree(subject, parent),
+            LayerAction::SetSubjectFavorite {
+                subject,
+                favorite,
+                order,
+            } => self.set_subject_favorite(subject, favorite, order),
+            LayerAction::SetSubjectColor { subject, color } => {
+                self.set_subject_color(subject, color)
+            }
+            LayerAction::RenameSubject { subject, name } => self.rename_subject(subject, name),
+            LayerAction::AddSubjectToNotes { subject, notes } => {
+                self.add_subject_to_notes(subject, notes)
+            }
+            LayerAction::RemoveSubjectFromNotes { subject, notes } => {
+                self.remove_subject_from_notes(subject, notes)
+            }
+            LayerAction::LinkNotes { from, to } => self.link_notes(from, to),
+            LayerAction::UnlinkNotes { from, to } => self.unlink_notes(from, to),
+            LayerAction::MakeLinkTextOnly { from, to } => self.make_link_text_only(from, to),
+            LayerAction::Group(actions) => self.perform_group(actions),
+            LayerAction::Checkpoint(label) => {
+                self.add_backwards(LayerAction::Checkpoint(label));
+                Ok(LayerEffect::Nothing)
+            }
         };
-        self.apply_effect(&effect);
         self.direction = old_direction;
-        effect
+        let effect = effect?;
+        self.apply_effect(&effect);
+        Ok(effect)
     }
 
     fn apply_effect(&mut self, eff: &LayerEffect) {
@@ -208,88 +754,461 @@ impl DbActions {
             LayerEffect::InvalidateQuery => {
                 self.invalidate_note_queries();
             }
-            &LayerEffect::InvalidateNote(id) => {
+            LayerEffect::InvalidateNote { note, subjects } => {
+                self.invalidate_queries_of_note(*note, subjects);
+                self.invalidate_note(*note);
+            }
+            LayerEffect::InvalidateNotes(ids) => {
                 self.invalidate_note_queries();
-                self.invalidate_note(id);
+                for id in ids {
+                    self.invalidate_note(*id);
+                }
             }
             LayerEffect::InvalidateSubjects => {
                 self.invalidate_subjects();
             }
+            LayerEffect::InvalidateAll => {
+                self.invalidate_note_queries();
+                self.note_cache.clear();
+                self.invalidate_subjects();
+            }
+            LayerEffect::Nothing => {}
+        }
+    }
+
+    /// Performs the actions of a group, adding their inverses as one group.
+    /// If any action fails, the ones before it are reverted.
+    fn perform_group(&mut self, actions: Vec<LayerAction>) -> rusqlite::Result<LayerEffect> {
+        let direction = self.direction;
+        let outer = self.group_backwards.replace(Vec::new());
+        let result = actions
+            .into_iter()
+            .try_for_each(|action| self.perform_direction(action, direction).map(|_| ()));
+        let mut backwards = self.group_backwards.take().unwrap_or_default();
+        backwards.reverse();
+
+        if let Err(e) = result {
+            let revert_direction = match direction {
+                ApplyDirection::Forward => ApplyDirection::Backward,
+                ApplyDirection::Backward => ApplyDirection::Forward,
+            };
+            // The inverses of the revert are thrown away with this buffer.
+            self.group_backwards = Some(Vec::new());
+            for action in backwards {
+                if let Err(e) = self.perform_direction(action, revert_direction) {
+                    tracing::error!("Reverting a failed group failed: {}", e);
+                }
+            }
+            self.group_backwards = outer;
+            return Err(e);
         }
+
+        self.group_backwards = outer;
+        self.add_backwards(LayerAction::Group(backwards));
+        Ok(LayerEffect::InvalidateAll)
     }
 
     fn invalidate_note_queries(&mut self) {
         self.query_cache.clear();
     }
 
+    /// Invalidates the queries that could list `note` before or after it
+    /// changed: those without a subject, and those on `old_subjects` or
+    /// its current subjects.
+    fn invalidate_queries_of_note(&mut self, note: NoteId, old_subjects: &[SubjectId]) {
+        let subjects = match self.store.get_note_subjects(note) {
+            Ok(subjects) => subjects,
+            Err(e) => {
+                tracing::error!("Fetching note subjects failed: {}", e);
+                self.invalidate_note_queries();
+                return;
+            }
+        };
+        let affected = |id: &SubjectId| old_subjects.contains(id) || subjects.contains(id);
+        self.query_cache.retain(|search| {
+            search.subject_id.is_some()
+                && !search
+                    .subject_id
+                    .iter()
+                    .chain(&search.also_subjects)
+                    .chain(&search.exclude_subjects)
+                    .any(affected)
+        });
+    }
+
     fn invalidate_note(&mut self, id: NoteId) {
         self.note_cache.invalidate_key(&id);
     }
 
-    fn create_note(&mut self, builder: NoteBuilder) -> LayerEffect {
-        let note = self.store.add_note(builder).unwrap();
+    fn create_note(&mut self, builder: NoteBuilder) -> rusqlite::Result<LayerEffect> {
+        let note = self.store.add_note(builder)?;
         self.add_backwards(LayerAction::DeleteNote(note.id));
-        LayerEffect::InvalidateQuery
+        Ok(LayerEffect::InvalidateQuery)
     }
 
-    fn delete_note_by_id(&mut self, id: NoteId) -> LayerEffect {
-        let note = self.store.get_note(id).unwrap();
-        self.store.delete_note(id).unwrap();
-        self.add_backwards(LayerAction::CreateNote(note.to_builder()));
-        LayerEffect::InvalidateNote(id)
+    fn delete_note_by_id(&mut self, id: NoteId) -> rusqlite::Result<LayerEffect> {
+        let note = self.store.get_note(id)?;
+        let links = self.store.get_explicit_links(id)?;
+        self.store.delete_note(id)?;
+
+        let create = LayerAction::CreateNote(note.to_builder());
+        if links.is_empty() {
+            self.add_backwards(create);
+        } else {
+            // Deleting the note deleted its explicit links, so restore them
+            // with it. The links in text come back with the text.
+            let links = links
+                .into_iter()
+                .map(|(from, to)| LayerAction::LinkNotes { from, to });
+            let restore = std::iter::once(create).chain(links);
+            self.add_backwards(LayerAction::Group(restore.collect()));
+        }
+        Ok(LayerEffect::InvalidateNote {
+            note: id,
+            subjects: note.subjects.clone(),
+        })
     }
 
-    fn edit_note_with(&mut self, id: NoteId, builder: NoteBuilder) -> LayerEffect {
+    fn edit_note_with(
+        &mut self,
+        id: NoteId,
+        builder: NoteBuilder,
+    ) -> rusqlite::Result<LayerEffect> {
         let mut builder = builder.with_id(id);
         if matches!(self.direction, ApplyDirection::Forward) && builder.modified_at.is_none() {
             builder = builder.modified_now();
         }
 
-        let old_note = self.store.get_note(id).unwrap();
+        if let Some(based_on) = builder.based_on {
+            match self.store.get_note_modified_at(id)? {
+                None => return Err(EditConflict::Deleted(id).into()),
+                Some(modified_at) if modified_at != based_on => {
+                    return Err(EditConflict::Modified(id).into())
+                }
+                Some(_) => {}
+            }
+        }
+
+        let old_note = self.store.get_note(id)?;
         let note = builder.apply_to_note(&old_note);
-        self.store.update_note(note).unwrap();
+        self.store.update_note(note)?;
 
         self.add_backwards(LayerAction::EditNote(id, old_note.to_builder()));
-        LayerEffect::InvalidateNote(id)
+        Ok(LayerEffect::InvalidateNote {
+            note: id,
+            subjects: old_note.subjects.clone(),
+        })
+    }
+
+    fn delete_notes(&mut self, ids: Vec<NoteId>) -> rusqlite::Result<LayerEffect> {
+        let mut creates = Vec::new();
+        let mut links = BTreeSet::new();
+        for &id in &ids {
+            creates.push(LayerAction::CreateNote(
+                self.store.get_note(id)?.to_builder(),
+            ));
+            links.extend(self.store.get_explicit_links(id)?);
+        }
+        self.store.delete_notes(&ids)?;
+
+        // The notes are restored before the links between them.
+        let links = links
+            .into_iter()
+            .map(|(from, to)| LayerAction::LinkNotes { from, to });
+        self.add_backwards(LayerAction::Group(
+            creates.into_iter().chain(links).collect(),
+        ));
+        Ok(LayerEffect::InvalidateNotes(ids))
     }
 
-    fn add_subject(&mut self, id: Option<SubjectId>, name: String) -> LayerEffect {
+    fn bump_note(
+        &mut self,
+        id: NoteId,
+        to: chrono::DateTime<chrono::Local>,
+    ) -> rusqlite::Result<LayerEffect> {
+        let old_note = self.store.get_note(id)?;
+        let note = NoteBuilder::new().created_at(to).apply_to_note(&old_note);
+        self.store.update_note(note)?;
+
+        self.add_backwards(LayerAction::BumpNote {
+            note: id,
+            to: old_note.created_at,
+        });
+        Ok(LayerEffect::InvalidateNote {
+            note: id,
+            subjects: old_note.subjects.clone(),
+        })
+    }
+
+    fn set_pinned(&mut self, id: NoteId, pinned: bool) -> rusqlite::Result<LayerEffect> {
+        let old_note = self.store.get_note(id)?;
+        let note = NoteBuilder::new().pinned(pinned).apply_to_note(&old_note);
+        self.store.update_note(note)?;
+
+        self.add_backwards(LayerAction::SetPinned {
+            note: id,
+            pinned: old_note.pinned,
+        });
+        Ok(LayerEffect::InvalidateNote {
+            note: id,
+            subjects: old_note.subjects.clone(),
+        })
+    }
+
+    fn edit_notes(&mut self, edits: Vec<(NoteId, NoteBuilder)>) -> rusqlite::Result<LayerEffect> {
+        let mut ids = Vec::new();
+        let mut notes = Vec::new();
+        let mut backwards = Vec::new();
+        for (id, builder) in edits {
+            let mut builder = builder.with_id(id);
+            if matches!(self.direction, ApplyDirection::Forward) && builder.modified_at.is_none() {
+                builder = builder.modified_now();
+            }
+            let old_note = self.store.get_note(id)?;
+            notes.push(builder.apply_to_note(&old_note));
+            backwards.push((id, old_note.to_builder()));
+            ids.push(id);
+        }
+        self.store.update_notes(notes)?;
+
+        self.add_backwards(LayerAction::EditNotes(backwards));
+        Ok(LayerEffect::InvalidateNotes(ids))
+    }
+
+    fn add_subject(
+        &mut self,
+        id: Option<SubjectId>,
+        name: String,
+    ) -> rusqlite::Result<LayerEffect> {
         let id = id.unwrap_or_else(|| SubjectId(Uuid::new_v4()));
-        let subject = self.store.add_subject_with_id(id, name).unwrap();
-        self.add_backwards(LayerAction::RemoveSubject(subject.id));
+        let subject = self.store.add_subject_with_id(id, name)?;
+        // Anything tagged since is untagged by undoing that first.
+        self.add_backwards(LayerAction::RemoveSubject(
+            subject.id,
+            RemoveSubjectPolicy::Refuse,
+        ));
         self.last_added_subject = Some(subject);
-        LayerEffect::InvalidateSubjects
+        Ok(LayerEffect::InvalidateSubjects)
     }
 
-    fn remove_subject(&mut self, subject_id: SubjectId) -> LayerEffect {
-        let subject = self.store.get_subject(subject_id).unwrap();
-        self.store.delete_subject(subject_id).unwrap();
+    fn remove_subject(
+        &mut self,
+        subject_id: SubjectId,
+        policy: RemoveSubjectPolicy,
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        let notes = self.store.get_subject_note_ids(subject_id)?;
+        if !notes.is_empty() || !subject.children.is_empty() {
+            if policy == RemoveSubjectPolicy::Refuse && !notes.is_empty() {
+                return Err(SubjectHasNotes(notes.len()).into());
+            }
+            // The notes and children are let go first as their own actions,
+            // so undoing the group restores them after the subject.
+            let mut actions = Vec::new();
+            if let (RemoveSubjectPolicy::MoveToParent, Some(parent)) = (policy, subject.parent_id) {
+                if !notes.is_empty() {
+                    actions.push(LayerAction::AddSubjectToNotes {
+                        subject: parent,
+                        notes: notes.clone(),
+                    });
+                }
+            }
+            if !notes.is_empty() {
+                actions.push(LayerAction::RemoveSubjectFromNotes {
+                    subject: subject_id,
+                    notes,
+                });
+            }
+            for &child in &subject.children {
+                actions.push(LayerAction::SetSubjectParent {
+                    subject: child,
+                    parent: subject.parent_id,
+                });
+            }
+            actions.push(LayerAction::RemoveSubject(
+                subject_id,
+                RemoveSubjectPolicy::Refuse,
+            ));
+            return self.perform_group(actions);
+        }
 
-        // TODO: Handle subject parent. Maybe a SubjectBuilder pattern?
-        self.add_backwards(LayerAction::AddSubject(
+        self.store.delete_subject(subject_id)?;
+        let mut restore = vec![LayerAction::AddSubject(
             Some(subject_id),
             subject.name.clone(),
-        ));
+        )];
+        if subject.parent_id.is_some() {
+            restore.push(LayerAction::SetSubjectParent {
+                subject: subject_id,
+                parent: subject.parent_id,
+            });
+        }
+        if subject.favorite {
+            restore.push(LayerAction::SetSubjectFavorite {
+                subject: subject_id,
+                favorite: true,
+                order: subject.favorite_order,
+            });
+        }
+        if subject.color.is_some() {
+            restore.push(LayerAction::SetSubjectColor {
+                subject: subject_id,
+                color: subject.color.clone(),
+            });
+        }
+        self.add_backwards(match restore.len() {
+            1 => restore.remove(0),
+            _ => LayerAction::Group(restore),
+        });
         self.last_added_subject = Some(subject);
-        LayerEffect::InvalidateSubjects
+        Ok(LayerEffect::InvalidateSubjects)
     }
 
     fn set_subject_parent(
         &mut self,
         subject_id: SubjectId,
         parent: Option<SubjectId>,
-    ) -> LayerEffect {
-        let subject = self.store.get_subject(subject_id).unwrap();
-        self.store.set_subject_parent(subject_id, parent).unwrap();
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        self.store.set_subject_parent(subject_id, parent)?;
         self.add_backwards(LayerAction::SetSubjectParent {
             subject: subject_id,
             parent: subject.parent_id,
         });
-        LayerEffect::InvalidateSubjects
+        Ok(LayerEffect::InvalidateSubjects)
+    }
+
+    fn move_subtree(
+        &mut self,
+        subject_id: SubjectId,
+        parent: Option<SubjectId>,
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        self.store.move_subtree(subject_id, parent)?;
+        self.add_backwards(LayerAction::MoveSubtree {
+            subject: subject_id,
+            parent: subject.parent_id,
+        });
+        Ok(LayerEffect::InvalidateSubjects)
+    }
+
+    fn set_subject_favorite(
+        &mut self,
+        subject_id: SubjectId,
+        favorite: bool,
+        order: i64,
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        self.store
+            .set_subject_favorite(subject_id, favorite, order)?;
+        self.add_backwards(LayerAction::SetSubjectFavorite {
+            subject: subject_id,
+            favorite: subject.favorite,
+            order: subject.favorite_order,
+        });
+        Ok(LayerEffect::InvalidateSubjects)
+    }
+
+    fn set_subject_color(
+        &mut self,
+        subject_id: SubjectId,
+        color: Option<String>,
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        self.store.set_subject_color(subject_id, color.as_deref())?;
+        self.add_backwards(LayerAction::SetSubjectColor {
+            subject: subject_id,
+            color: subject.color.clone(),
+        });
+        Ok(LayerEffect::InvalidateSubjects)
+    }
+
+    fn rename_subject(
+        &mut self,
+        subject_id: SubjectId,
+        name: String,
+    ) -> rusqlite::Result<LayerEffect> {
+        let subject = self.store.get_subject(subject_id)?;
+        self.store.rename_subject(subject_id, &name)?;
+        self.add_backwards(LayerAction::RenameSubject {
+            subject: subject_id,
+            name: subject.name.clone(),
+        });
+        Ok(LayerEffect::InvalidateSubjects)
+    }
+
+    fn add_subject_to_notes(
+        &mut self,
+        subject: SubjectId,
+        notes: Vec<NoteId>,
+    ) -> rusqlite::Result<LayerEffect> {
+        let changed = self.store.add_subject_to_notes(subject, &notes)?;
+        // Only unlink the notes that weren't already tagged.
+        self.add_backwards(LayerAction::RemoveSubjectFromNotes {
+            subject,
+            notes: changed.clone(),
+        });
+        Ok(LayerEffect::InvalidateNotes(changed))
+    }
+
+    fn remove_subject_from_notes(
+        &mut self,
+        subject: SubjectId,
+        notes: Vec<NoteId>,
+    ) -> rusqlite::Result<LayerEffect> {
+        self.store.remove_subject_from_notes(subject, &notes)?;
+        self.add_backwards(LayerAction::AddSubjectToNotes {
+            subject,
+            notes: notes.clone(),
+        });
+        Ok(LayerEffect::InvalidateNotes(notes))
+    }
+
+    fn link_notes(&mut self, from: NoteId, to: NoteId) -> rusqlite::Result<LayerEffect> {
+        // Undoing a link that already existed shouldn't remove it.
+        let backwards = match self.store.link_notes(from, to)? {
+            LinkChange::Added => LayerAction::UnlinkNotes { from, to },
+            LinkChange::MadeExplicit => LayerAction::MakeLinkTextOnly { from, to },
+            LinkChange::Unchanged => LayerAction::Group(Vec::new()),
+        };
+        self.add_backwards(backwards);
+        Ok(LayerEffect::Nothing)
+    }
+
+    fn make_link_text_only(&mut self, from: NoteId, to: NoteId) -> rusqlite::Result<LayerEffect> {
+        let backwards = if self.store.make_link_text_only(from, to)? {
+            LayerAction::LinkNotes { from, to }
+        } else {
+            LayerAction::Group(Vec::new())
+        };
+        self.add_backwards(backwards);
+        Ok(LayerEffect::Nothing)
+    }
+
+    fn unlink_notes(&mut self, from: NoteId, to: NoteId) -> rusqlite::Result<LayerEffect> {
+        let backwards = if self.store.unlink_notes(from, to)? {
+            LayerAction::LinkNotes { from, to }
+        } else {
+            LayerAction::Group(Vec::new())
+        };
+        self.add_backwards(backwards);
+        Ok(LayerEffect::Nothing)
     }
 
     fn invalidate_subjects(&mut self) {
         self.subject_cache = None;
+        self.ancestor_cache.borrow_mut().clear();
+    }
+
+    /// Cached, so it can be called on every render through a shared reference.
+    fn get_subject_ancestors(&self, id: SubjectId) -> Rc<Vec<Subject>> {
+        self.ancestor_cache.borrow_mut().get_or_insert_with(id, || {
+            let ancestors = self.store.get_subject_ancestors(id).unwrap_or_else(|e| {
+                tracing::error!("Fetching subject ancestors failed: {}", e);
+                Vec::new()
+            });
+            Rc::new(ancestors)
+        })
     }
 
     fn get_subjects(&mut self) -> Rc<BTreeMap<SubjectId, Subject>> {
@@ -309,9 +1228,15 @@ impl DbActions {
         subjects
     }
 
+    #[cfg(test)]
     fn get_note_ids_for_search(&mut self, search: NoteSearch) -> Vec<NoteId> {
-        self.query_cache
-            .get_or_insert_with(search, || self.store.find_notes(search).unwrap())
+        self.get_note_page_for_search(search).notes
+    }
+
+    fn get_note_page_for_search(&mut self, search: NoteSearch) -> NotePage {
+        self.query_cache.get_or_insert_with(search.clone(), || {
+            self.store.find_notes_page(search).unwrap()
+        })
     }
 
     fn get_note_by_id(&mut self, id: NoteId) -> Note {
@@ -320,8 +1245,22 @@ impl DbActions {
     }
 }
 
+/// Saves the history changed since the last periodic save, also when
+/// switching vaults.
+impl Drop for DbActions {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_history() {
+            tracing::error!("Failed to save the undo history: {}", e);
+        }
+    }
+}
+
+/// How often the undo history is saved, see [`use_layer_provider`].
+const HISTORY_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
 type Notes = Signal<Vec<Note>>;
 type Subjects = Signal<Rc<BTreeMap<SubjectId, Subject>>>;
+type ErrorHandler = Box<dyn Fn(String)>;
 
 /// Layer provides an abstraction layer over the store to provide a
 /// consistent interface for the rest of the application.
@@ -329,8 +1268,12 @@ pub struct Layer {
     actions: DbActions,
     event_count: usize,
     query: NoteSearch,
+    /// How many pages of `query` are listed, see [`Layer::load_more_notes`].
+    pages: usize,
+    has_more_notes: bool,
     notes: Notes,
     subjects: Subjects,
+    on_error: Option<ErrorHandler>,
 }
 
 impl Layer {
@@ -339,21 +1282,52 @@ impl Layer {
             actions: DbActions::new(store),
             event_count: 0,
             query: Default::default(),
+            pages: 1,
+            has_more_notes: false,
             notes,
             subjects,
+            on_error: None,
         }
     }
 
+    /// Sets the function called with a user-facing message when an action fails.
+    /// Without one, failures are only logged.
+    pub fn set_error_handler(&mut self, f: impl Fn(String) + 'static) {
+        self.on_error = Some(Box::new(f));
+    }
+
     pub fn perform(&mut self, action: LayerAction) {
-        self.with_action(|actions| actions.perform(action).into())
+        self.with_action(|actions| Some(actions.perform(action)))
+    }
+
+    /// Saves the undo history to the store if it changed, so that it can be
+    /// undone after a restart.
+    pub fn save_history(&self) {
+        if let Err(e) = self.actions.save_history() {
+            self.report_error(e);
+        }
     }
 
     pub fn can_undo(&self) -> bool {
-        !self.actions.undo_queue.is_empty()
+        self.undo_description().is_some()
     }
 
     pub fn can_redo(&self) -> bool {
-        !self.actions.redo_queue.is_empty()
+        self.redo_description().is_some()
+    }
+
+    /// Marks the current point in the undo history.
+    /// A label names the action performed next, e.g. "Bulk delete (12 notes)".
+    pub fn checkpoint(&mut self, label: Option<String>) {
+        self.actions.checkpoint(label);
+    }
+
+    pub fn undo_description(&self) -> Option<String> {
+        self.actions.undo_description()
+    }
+
+    pub fn redo_description(&self) -> Option<String> {
+        self.actions.redo_description()
     }
 
     pub fn undo(&mut self) {
@@ -364,66 +1338,463 @@ impl Layer {
         self.with_action(|actions| actions.redo());
     }
 
-    fn with_action(&mut self, f: impl FnOnce(&mut DbActions) -> Option<LayerEffect>) {
+    /// Undoes up to `count` steps, updating the views once at the end.
+    pub fn undo_n(&mut self, count: usize) {
+        self.with_actions(|actions| actions.undo_n(count));
+    }
+
+    /// Undoes everything done since the latest checkpoint labeled `label`,
+    /// updating the views once at the end.
+    pub fn undo_to(&mut self, label: &str) {
+        self.with_actions(|actions| actions.undo_to(label));
+    }
+
+    fn with_action(
+        &mut self,
+        f: impl FnOnce(&mut DbActions) -> Option<rusqlite::Result<LayerEffect>>,
+    ) {
+        self.with_actions(|actions| f(actions).into_iter().collect())
+    }
+
+    /// Runs any number of actions, then updates the signals once for all
+    /// of their effects.
+    fn with_actions(
+        &mut self,
+        f: impl FnOnce(&mut DbActions) -> Vec<rusqlite::Result<LayerEffect>>,
+    ) {
         self.event();
-        let Some(eff) = f(&mut self.actions) else { return };
-        match eff {
-            LayerEffect::InvalidateQuery => {
-                self.update_notes();
+        let mut update_notes = false;
+        let mut update_subjects = false;
+        for eff in f(&mut self.actions) {
+            match eff {
+                Ok(LayerEffect::InvalidateQuery) => update_notes = true,
+                Ok(LayerEffect::InvalidateNote { .. } | LayerEffect::InvalidateNotes(_)) => {
+                    update_notes = true;
+                }
+                Ok(LayerEffect::InvalidateSubjects) => update_subjects = true,
+                Ok(LayerEffect::InvalidateAll) => {
+                    update_subjects = true;
+                    update_notes = true;
+                }
+                Ok(LayerEffect::Nothing) => {}
+                Err(e) => self.report_error(e),
             }
-            LayerEffect::InvalidateNote(_) => {
-                self.update_notes();
+        }
+        if update_subjects {
+            self.update_subjects();
+        }
+        if update_notes {
+            self.update_notes();
+        }
+    }
+
+    fn report_error(&self, error: rusqlite::Error) {
+        tracing::error!("Action failed: {}", error);
+        let message = match error {
+            rusqlite::Error::UserFunctionError(e) if e.is::<EditConflict>() => {
+                format!("The edit was not saved: {e}")
             }
-            LayerEffect::InvalidateSubjects => {
-                self.update_subjects();
+            rusqlite::Error::UserFunctionError(e) if e.is::<InvalidMove>() => {
+                format!("The subject was not moved: {e}")
             }
+            rusqlite::Error::UserFunctionError(e) if e.is::<SubjectHasNotes>() => {
+                format!("The subject was not deleted: {e}")
+            }
+            rusqlite::Error::UserFunctionError(e) if e.is::<SubjectNameTaken>() => {
+                format!("The subject was not renamed: {e}")
+            }
+            rusqlite::Error::SqliteFailure(e, _)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                "That would conflict with existing data".to_string()
+            }
+            rusqlite::Error::QueryReturnedNoRows => "That item no longer exists".to_string(),
+            rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::DatabaseBusy => {
+                "The database is busy, please try again".to_string()
+            }
+            e => format!("Action failed: {e}"),
+        };
+        if let Some(on_error) = &self.on_error {
+            on_error(message);
         }
     }
 
     fn update_notes(&mut self) {
-        let search = self.query;
-        let note_ids = self.collect_notes_recursively(search);
-        let mut notes = note_ids
+        // The pages loaded so far are listed again in one go.
+        let page_size = self.query.page_size.saturating_mul(self.pages);
+        let search = self.query.clone().page_size(page_size);
+        let (note_ids, has_more) = self.collect_notes_recursively(&search);
+        let notes = note_ids
             .into_iter()
             .map(|id| self.actions.get_note_by_id(id))
             .collect::<Vec<_>>();
+        self.has_more_notes = has_more;
+        self.set_listed_notes(notes);
+    }
+
+    /// Lists the next page of the search after the notes already listed.
+    pub fn load_more_notes(&mut self) {
+        if !self.has_more_notes {
+            return;
+        }
+        let offset = self.query.page_size.saturating_mul(self.pages);
+        let search = self.query.clone().page_offset(offset);
+        let (note_ids, has_more) = self.collect_notes_recursively(&search);
+        self.pages += 1;
+        self.has_more_notes = has_more;
+
+        let mut notes = self.notes.read().clone();
+        let listed = notes.iter().map(|note| note.id).collect::<BTreeSet<_>>();
+        for id in note_ids {
+            // Notes of child subjects can be on different pages of each.
+            if !listed.contains(&id) {
+                notes.push(self.actions.get_note_by_id(id));
+            }
+        }
+        self.set_listed_notes(notes);
+    }
+
+    /// Whether the search has notes after the listed ones.
+    pub fn has_more_notes(&self) -> bool {
+        self.has_more_notes
+    }
+
+    fn set_listed_notes(&mut self, mut notes: Vec<Note>) {
         notes.sort_unstable_by(|a, b| b.created_at.cmp(&a.created_at));
-        if search.task_only {
+        if self.query.task_only && self.query.sort_by_due {
+            notes.sort_by_key(|note| (note.due_at.is_none(), note.due_at));
+        }
+        if self.query.task_only {
             notes.sort_by(|a, b| a.task_state.cmp(&b.task_state));
         }
+        debug_assert_eq!(
+            notes.len(),
+            notes
+                .iter()
+                .map(|note| note.id)
+                .collect::<BTreeSet<_>>()
+                .len()
+        );
         *self.notes.write() = notes;
     }
 
-    fn collect_notes_recursively(&mut self, search: NoteSearch) -> BTreeSet<NoteId> {
-        let mut notes = self
-            .actions
-            .get_note_ids_for_search(search)
-            .into_iter()
-            .collect::<BTreeSet<_>>();
-        if let Some(subject_id) = search.subject_id {
-            let subject = self
-                .actions
-                .get_subjects()
-                .get(&subject_id)
-                .unwrap()
-                .clone();
+    /// The notes of `search` and of the same search on the children of its
+    /// subjects, and whether any of them has more notes after this page.
+    fn collect_notes_recursively(&mut self, search: &NoteSearch) -> (BTreeSet<NoteId>, bool) {
+        let page = self.actions.get_note_page_for_search(search.clone());
+        let mut has_more = page.has_more;
+        let mut notes = page.notes.into_iter().collect::<BTreeSet<_>>();
+        let mut parents = search.subject_id.into_iter().collect::<Vec<_>>();
+        let mut child_search = search.clone();
+        if search.subject_match == SubjectMatch::Any {
+            // Notes of any subject's children match, each on their own.
+            parents.extend(&search.also_subjects);
+            child_search.also_subjects.clear();
+        }
+        for subject_id in parents {
+            // The subject may have been deleted while it was listed.
+            let Some(subject) = self.actions.get_subjects().get(&subject_id).cloned() else {
+                continue;
+            };
             for &child_id in &subject.children {
-                notes.extend(&self.collect_notes_recursively(search.subject(child_id)));
+                let child_search = child_search.clone().subject(child_id);
+                let (child_notes, child_has_more) = self.collect_notes_recursively(&child_search);
+                notes.extend(child_notes);
+                has_more |= child_has_more;
             }
         }
-        notes
+        (notes, has_more)
     }
 
     pub fn search(&self) -> SearchWorker {
         self.actions.store.search.clone()
     }
 
+    /// See [`Store::set_index_subject_names`].
+    pub fn set_index_subject_names(&self, enabled: bool) {
+        self.actions.store.set_index_subject_names(enabled)
+    }
+
+    /// Writes the database and search index out for a backup, see [`Store::flush`].
+    /// The undo history is saved first, as it's otherwise only saved now and
+    /// then. Settings and drafts are saved as they change.
+    pub fn flush(&self) -> rusqlite::Result<()> {
+        self.actions.save_history()?;
+        self.actions.store.flush()
+    }
+
+    /// The subject `step` positions away from `current`, see [`neighbor_subject`].
+    pub fn neighbor_subject(&self, current: Option<SubjectId>, step: isize) -> Option<SubjectId> {
+        neighbor_subject(&self.subjects.read(), current, step)
+    }
+
+    /// Returns the ancestors of a subject from the root down to its parent.
+    pub fn subject_ancestors(&self, id: SubjectId) -> Rc<Vec<Subject>> {
+        self.actions.get_subject_ancestors(id)
+    }
+
+    /// Whether `note` still exists. Assumes it does if that can't be checked.
+    pub fn note_exists(&self, note: NoteId) -> bool {
+        self.actions.store.note_exists(note).unwrap_or_else(|e| {
+            tracing::error!("Checking whether note {} exists failed: {}", note, e);
+            true
+        })
+    }
+
+    /// Returns the notes with the given ids in order, skipping deleted ones.
+    pub fn notes_by_id(&self, ids: &[NoteId]) -> Vec<Note> {
+        ids.iter()
+            .filter_map(|&id| match self.actions.store.get_note(id) {
+                Ok(note) => Some(note),
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(e) => {
+                    tracing::error!("Fetching note {} failed: {}", id, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Records time spent focusing on a note, see [`Store::add_focus_session`].
+    /// This isn't undoable.
+    pub fn record_focus(
+        &self,
+        note: NoteId,
+        started_at: chrono::DateTime<chrono::Local>,
+        ended_at: chrono::DateTime<chrono::Local>,
+    ) {
+        let result = self
+            .actions
+            .store
+            .add_focus_session(note, started_at, ended_at);
+        if let Err(e) = result {
+            self.report_error(e);
+        }
+    }
+
+    /// The notes with a reminder due now, see [`Store::get_due_reminders`].
+    pub fn due_reminders(&self) -> Vec<Note> {
+        self.actions
+            .store
+            .get_due_reminders(chrono::Local::now())
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching due reminders failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// Clears the reminder of `note` after it was shown, see
+    /// [`Store::clear_reminder`]. This isn't undoable.
+    pub fn clear_reminder(&mut self, note: &NoteData) {
+        let Some(at) = note.reminder_at else { return };
+        if let Err(e) = self.actions.store.clear_reminder(note.id, at) {
+            self.report_error(e);
+            return;
+        }
+        self.with_actions(|actions| {
+            let effect = LayerEffect::InvalidateNotes(vec![note.id]);
+            actions.apply_effect(&effect);
+            vec![Ok(effect)]
+        });
+    }
+
+    /// Total time spent focusing on a note in seconds, see [`Store::focus_seconds`].
+    pub fn focus_seconds(&self, note: NoteId) -> i64 {
+        self.actions.store.focus_seconds(note).unwrap_or_else(|e| {
+            tracing::error!("Fetching focus time of {} failed: {}", note, e);
+            0
+        })
+    }
+
+    /// The notes replacing `pattern` would change, see [`Store::note_replacements`].
+    pub fn note_replacements(
+        &self,
+        pattern: &FindPattern,
+        replacement: &str,
+        subject: Option<SubjectId>,
+    ) -> Vec<(Note, String)> {
+        self.actions
+            .store
+            .note_replacements(pattern, replacement, subject)
+            .unwrap_or_else(|e| {
+                tracing::error!("Finding notes to replace in failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// Subjects with `query` in their name, see [`Store::find_subjects_by_name`].
+    pub fn find_subjects_by_name(&self, query: &str) -> Vec<Subject> {
+        self.actions
+            .store
+            .find_subjects_by_name(query)
+            .unwrap_or_else(|e| {
+                tracing::error!("Searching subjects failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// All subjects in tree order with their depth, see
+    /// [`Store::get_subjects_flat_with_depth`].
+    pub fn subjects_flat_with_depth(&self) -> Vec<(Subject, usize)> {
+        self.actions
+            .store
+            .get_subjects_flat_with_depth()
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching the subject tree failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// When the first and last notes were created, see [`Store::note_date_range`].
+    pub fn note_date_range(
+        &self,
+        subject: Option<SubjectId>,
+    ) -> Option<(
+        chrono::DateTime<chrono::Local>,
+        chrono::DateTime<chrono::Local>,
+    )> {
+        self.actions
+            .store
+            .note_date_range(subject)
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching the note date range failed: {}", e);
+                None
+            })
+    }
+
+    /// Notes created on each day, see [`Store::notes_per_day`].
+    pub fn notes_per_day(
+        &self,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> BTreeMap<chrono::NaiveDate, u32> {
+        self.actions
+            .store
+            .notes_per_day(from, to)
+            .unwrap_or_else(|e| {
+                tracing::error!("Counting notes per day failed: {}", e);
+                BTreeMap::new()
+            })
+    }
+
+    /// The notes a note links to, see [`Store::get_linked_notes`].
+    pub fn linked_notes(&self, note: NoteId) -> Vec<Note> {
+        let ids = self
+            .actions
+            .store
+            .get_linked_notes(note)
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching the links of {} failed: {}", note, e);
+                Vec::new()
+            });
+        self.notes_by_id(&ids)
+    }
+
+    /// The notes linking to a note, see [`Store::get_backlinks`].
+    pub fn backlinks(&self, note: NoteId) -> Vec<Note> {
+        let ids = self.actions.store.get_backlinks(note).unwrap_or_else(|e| {
+            tracing::error!("Fetching the backlinks of {} failed: {}", note, e);
+            Vec::new()
+        });
+        self.notes_by_id(&ids)
+    }
+
+    /// The done tasks of `subject` and its descendants, see [`Store::find_done_tasks`].
+    pub fn done_tasks(&self, subject: Option<SubjectId>) -> Vec<NoteId> {
+        self.actions
+            .store
+            .find_done_tasks(subject)
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching done tasks failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// See [`Store::get_all_subjects_with_counts`].
+    pub fn subjects_with_counts(&self) -> Vec<SubjectCounts> {
+        self.actions
+            .store
+            .get_all_subjects_with_counts()
+            .unwrap_or_else(|e| {
+                tracing::error!("Counting the notes of subjects failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// See [`Store::get_subject_note_ids`].
+    pub fn subject_note_ids(&self, subject: SubjectId) -> Vec<NoteId> {
+        self.actions
+            .store
+            .get_subject_note_ids(subject)
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching the notes of {} failed: {}", subject, e);
+                Vec::new()
+            })
+    }
+
+    /// Returns every task across all subjects, see [`Store::get_all_tasks`].
+    pub fn all_tasks(&self) -> Vec<Note> {
+        self.actions.store.get_all_tasks().unwrap_or_else(|e| {
+            tracing::error!("Fetching all tasks failed: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Suggests indexed terms starting with `prefix`, see [`Store::term_suggestions`].
+    pub fn term_suggestions(&self, prefix: &str, limit: usize) -> Vec<String> {
+        self.actions
+            .store
+            .term_suggestions(prefix, limit)
+            .unwrap_or_else(|e| {
+                tracing::error!("Fetching term suggestions failed: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// The most common terms of the vault, see [`Store::top_terms`].
+    pub fn top_terms(&self, limit: usize) -> Vec<(String, i64)> {
+        self.actions.store.top_terms(limit).unwrap_or_else(|e| {
+            tracing::error!("Fetching the most common terms failed: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Replaces the underlying store with one opened from `conn`.
+    /// Undo history and caches belong to the old store and are discarded.
+    ///
+    /// If the new store can't be opened, the old one is opened again.
+    pub fn switch_store(&mut self, conn: ConnectionType) -> Result<(), OpenError> {
+        let old = self.actions.store.connection().clone();
+        // The old store keeps the search index locked until it's dropped.
+        self.actions = DbActions::new(Rc::new(Store::new(ConnectionType::InMemory)));
+        let result = match Store::open(conn) {
+            Ok(store) => {
+                self.actions = DbActions::new(Rc::new(store));
+                Ok(())
+            }
+            Err(e) => {
+                match Store::open(old) {
+                    Ok(store) => self.actions = DbActions::new(Rc::new(store)),
+                    Err(e) => tracing::error!("Reopening the previous store failed: {}", e),
+                }
+                Err(e)
+            }
+        };
+        self.event();
+        self.query = Default::default();
+        self.pages = 1;
+        self.update_subjects();
+        self.update_notes();
+        result
+    }
+
     pub fn set_search(&mut self, search: NoteSearch) {
         if self.query == search {
             return;
         }
 
         self.query = search;
+        self.pages = 1;
         self.update_notes();
     }
 
@@ -443,13 +1814,21 @@ impl Layer {
 pub fn use_layer_provider(cx: &ScopeState, conn: ConnectionType) -> Signal<Layer> {
     let notes = *use_context_provider(cx, Default::default);
     let subjects = *use_context_provider(cx, Default::default);
-    *use_context_provider(cx, || {
+    let layer = *use_context_provider(cx, || {
         let store = Store::new(conn);
         let mut layer = Layer::new(Rc::new(store), notes, subjects);
         layer.update_subjects();
         layer.update_notes();
         Signal::new(layer)
-    })
+    });
+    // Saved only now and then, as one action often follows another.
+    use_future(cx, (), move |_| async move {
+        loop {
+            tokio::time::sleep(HISTORY_SAVE_INTERVAL).await;
+            layer.read().save_history();
+        }
+    });
+    layer
 }
 
 pub fn use_layer(cx: &ScopeState) -> LayerSignal {
@@ -493,10 +1872,174 @@ impl LayerSignal {
             .perform(LayerAction::EditNote(id, builder))
     }
 
-    pub fn create_subject(self, name: impl ToString) -> Subject {
+    pub fn bump_note(self, note: NoteId, to: chrono::DateTime<chrono::Local>) {
+        self.layer
+            .write()
+            .perform(LayerAction::BumpNote { note, to })
+    }
+
+    pub fn set_pinned(self, note: NoteId, pinned: bool) {
+        self.layer
+            .write()
+            .perform(LayerAction::SetPinned { note, pinned })
+    }
+
+    /// See [`Layer::due_reminders`].
+    pub fn due_reminders(self) -> Vec<Note> {
+        self.layer.read().due_reminders()
+    }
+
+    /// See [`Layer::clear_reminder`].
+    pub fn clear_reminder(self, note: &NoteData) {
+        self.layer.write().clear_reminder(note)
+    }
+
+    /// Saves an edit from the note editor, see [`LayerAction::save_edit`].
+    /// Returns false if the edit wasn't saved.
+    pub fn save_edit(
+        self,
+        note: &NoteData,
+        text: String,
+        subjects: Vec<SubjectId>,
+        policy: EmptyEditPolicy,
+    ) -> bool {
+        match LayerAction::save_edit(note, text, subjects, policy) {
+            Some(action) => {
+                self.layer.write().perform(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds `text` to the end of a note as a new line, see [`LayerAction::append_to_note`].
+    pub fn append_to_note(self, id: NoteId, text: &str) {
+        self.edit_text_with(id, |note| LayerAction::append_to_note(note, text))
+    }
+
+    /// Adds `text` to the start of a note as a new line, see [`LayerAction::prepend_to_note`].
+    pub fn prepend_to_note(self, id: NoteId, text: &str) {
+        self.edit_text_with(id, |note| LayerAction::prepend_to_note(note, text))
+    }
+
+    fn edit_text_with(self, id: NoteId, edit: impl FnOnce(&NoteData) -> LayerAction) {
         let mut layer = self.layer.write();
+        let note = layer.actions.store.get_note(id);
+        match note {
+            Ok(note) => layer.perform(edit(&note)),
+            Err(e) => layer.report_error(e),
+        }
+    }
+
+    /// Creates a new subject, returning `None` if it couldn't be created.
+    pub fn create_subject(self, name: impl ToString) -> Option<Subject> {
+        let mut layer = self.layer.write();
+        layer.actions.last_added_subject = None;
         layer.perform(LayerAction::AddSubject(None, name.to_string()));
-        layer.actions.last_added_subject.clone().unwrap()
+        layer.actions.last_added_subject.clone()
+    }
+
+    /// Creates the subjects of an outline as a single undo step, see
+    /// [`LayerAction::create_subjects_from_outline`]. Returns `None` if
+    /// they couldn't be created.
+    pub fn create_subjects_from_outline(self, text: &str) -> Option<OutlineSummary> {
+        let mut layer = self.layer.write();
+        let subjects = layer.actions.get_subjects();
+        let (action, summary) = LayerAction::create_subjects_from_outline(text, &subjects);
+        if summary.created == 0 {
+            return Some(summary);
+        }
+        layer.actions.last_added_subject = None;
+        layer.perform(action);
+        layer.actions.last_added_subject.as_ref()?;
+        Some(summary)
+    }
+
+    /// Replaces `pattern` in the notes it matches, optionally only those with
+    /// `subject`, as a single undo step. Returns the changed notes.
+    pub fn find_replace(
+        self,
+        pattern: &FindPattern,
+        replacement: &str,
+        subject: Option<SubjectId>,
+    ) -> Vec<NoteId> {
+        let mut layer = self.layer.write();
+        let replacements =
+            match layer
+                .actions
+                .store
+                .note_replacements(pattern, replacement, subject)
+            {
+                Ok(replacements) => replacements,
+                Err(e) => {
+                    layer.report_error(e);
+                    return Vec::new();
+                }
+            };
+        if replacements.is_empty() {
+            return Vec::new();
+        }
+        let ids = replacements
+            .iter()
+            .map(|(note, _)| note.id)
+            .collect::<Vec<_>>();
+        layer.checkpoint(Some(format!("Find and replace ({} notes)", ids.len())));
+        layer.perform(LayerAction::replace_in_notes(replacements));
+        ids
+    }
+
+    /// Clears the done tasks of `subject` and its descendants, or all of them,
+    /// as a single undo step. Returns how many were cleared.
+    pub fn clear_done_tasks(self, subject: Option<SubjectId>, policy: ClearDonePolicy) -> usize {
+        let mut layer = self.layer.write();
+        let notes = layer.done_tasks(subject);
+        let count = notes.len();
+        if count > 0 {
+            layer.perform(LayerAction::clear_done_tasks(notes, policy));
+        }
+        count
+    }
+
+    /// Deletes the subjects as a single undo step, see [`LayerAction::delete_subjects`].
+    pub fn delete_subjects(self, ids: &[SubjectId], policy: RemoveSubjectPolicy) {
+        self.layer
+            .write()
+            .perform(LayerAction::delete_subjects(ids, policy))
+    }
+
+    /// Merges `from` into `into`, see [`LayerAction::merge_subject`].
+    pub fn merge_subject(self, from: SubjectId, into: SubjectId) {
+        if from == into {
+            return;
+        }
+        let mut layer = self.layer.write();
+        let Some(subject) = layer.actions.get_subjects().get(&from).cloned() else { return };
+        let notes = layer.subject_note_ids(from);
+        layer.perform(LayerAction::merge_subject(&subject, into, notes));
+    }
+
+    pub fn rename_subject(self, subject: SubjectId, name: String) {
+        self.layer
+            .write()
+            .perform(LayerAction::RenameSubject { subject, name })
+    }
+
+    pub fn add_subject_to_notes(self, subject: SubjectId, notes: Vec<NoteId>) {
+        self.layer
+            .write()
+            .perform(LayerAction::AddSubjectToNotes { subject, notes })
+    }
+
+    pub fn link_notes(self, from: NoteId, to: NoteId) {
+        self.layer
+            .write()
+            .perform(LayerAction::LinkNotes { from, to })
+    }
+
+    pub fn unlink_notes(self, from: NoteId, to: NoteId) {
+        self.layer
+            .write()
+            .perform(LayerAction::UnlinkNotes { from, to })
     }
 
     pub fn set_subject_parent(self, subject: SubjectId, parent: Option<SubjectId>) {
@@ -504,4 +2047,63 @@ impl LayerSignal {
             .write()
             .perform(LayerAction::SetSubjectParent { subject, parent })
     }
+
+    /// Moves the subject and its descendants, refusing moves that would
+    /// break the hierarchy.
+    pub fn move_subtree(self, subject: SubjectId, parent: Option<SubjectId>) {
+        self.layer
+            .write()
+            .perform(LayerAction::MoveSubtree { subject, parent })
+    }
+
+    /// Sets the chip color of a subject, `None` for the default look.
+    pub fn set_subject_color(self, subject: SubjectId, color: Option<String>) {
+        self.layer
+            .write()
+            .perform(LayerAction::SetSubjectColor { subject, color })
+    }
+
+    /// Stars or unstars a subject. New favorites go to the end of the manual order.
+    pub fn toggle_favorite(self, subject: SubjectId) {
+        let mut layer = self.layer.write();
+        let subjects = layer.actions.get_subjects();
+        let Some(current) = subjects.get(&subject) else { return };
+        let last = subjects
+            .values()
+            .filter(|s| s.favorite)
+            .map(|s| s.favorite_order)
+            .max();
+        layer.perform(LayerAction::SetSubjectFavorite {
+            subject,
+            favorite: !current.favorite,
+            order: last.map_or(0, |order| order + 1),
+        });
+    }
+
+    /// Moves a favorite `offset` steps in the manual order.
+    pub fn move_favorite(self, subject: SubjectId, offset: isize) {
+        let mut layer = self.layer.write();
+        let subjects = layer.actions.get_subjects();
+        let mut favorites = favorites(subjects.values(), FavoriteOrder::Manual);
+        let Some(from) = favorites.iter().position(|s| s.id == subject) else { return };
+        let to = from.saturating_add_signed(offset).min(favorites.len() - 1);
+        if from == to {
+            return;
+        }
+        let moved = favorites.remove(from);
+        favorites.insert(to, moved);
+
+        // Renumber everything, so favorites sharing an order can be moved too.
+        let actions = favorites
+            .iter()
+            .enumerate()
+            .filter(|(order, s)| s.favorite_order != *order as i64)
+            .map(|(order, s)| LayerAction::SetSubjectFavorite {
+                subject: s.id,
+                favorite: true,
+                order: order as i64,
+            })
+            .collect();
+        layer.perform(LayerAction::Group(actions));
+    }
 }
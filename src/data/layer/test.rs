@@ -5,14 +5,17 @@ use rand::prelude::*;
 use std::{collections::BTreeMap, rc::Rc};
 
 use crate::data::{
-    notes::{Note, NoteBuilder, NoteId, NoteSearch},
+    notes::{Note, NoteBuilder, NoteData, NoteId, NoteSearch, TaskState},
+    replace::FindPattern,
+    settings::{ClearDonePolicy, EmptyEditPolicy},
     subjects::{Subject, SubjectId},
     ConnectionType, Store,
 };
 
 use super::{
-    DbActions,
+    DbActions, Layer,
     LayerAction::{self, *},
+    RemoveSubjectPolicy,
 };
 
 fn setup() -> DbActions {
@@ -39,7 +42,7 @@ fn random_action(
             let builder = NoteBuilder::new().decide_id();
             valid_notes.push(builder.id());
             let action = LayerAction::CreateNote(builder);
-            actions.perform(action);
+            actions.perform(action).unwrap();
         }
         1 => {
             let id = *valid_notes.choose(&mut r)?;
@@ -47,26 +50,26 @@ fn random_action(
                 id,
                 NoteBuilder::new().modified_at(Local.timestamp_nanos(r.gen())),
             );
-            actions.perform(action);
+            actions.perform(action).unwrap();
         }
         2 => {
             let n: u64 = r.gen();
             let name = format!("{n}");
             let action = LayerAction::AddSubject(None, name);
-            actions.perform(action);
+            actions.perform(action).unwrap();
             valid_subjects.push(actions.last_added_subject.clone().unwrap().id);
         }
         3 => {
             let idx = r.gen_range(0..valid_notes.len());
             let id = valid_notes.remove(idx);
             let action = LayerAction::DeleteNote(id);
-            actions.perform(action);
+            actions.perform(action).unwrap();
         }
         4 => {
             let idx = r.gen_range(0..valid_subjects.len());
             let id = valid_subjects.remove(idx);
-            let action = LayerAction::RemoveSubject(id);
-            actions.perform(action);
+            let action = LayerAction::RemoveSubject(id, RemoveSubjectPolicy::Untag);
+            actions.perform(action).unwrap();
         }
         _ => unreachable!(),
     };
@@ -160,7 +163,7 @@ fn proptest_undo_redo() {
 fn create_note() {
     let mut actions = setup();
     let builder = NoteBuilder::new().text("Test Note");
-    actions.perform(CreateNote(builder.clone()));
+    actions.perform(CreateNote(builder.clone())).unwrap();
 
     let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
     assert_eq!(note_ids.len(), 1);
@@ -174,17 +177,17 @@ fn deleted_note_listing() {
     let mut actions = setup();
     let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
     let builder2 = NoteBuilder::new().text("Test Note 2").decide_id();
-    actions.perform(CreateNote(builder1.clone()));
-    actions.perform(CreateNote(builder2.clone()));
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.perform(CreateNote(builder2.clone())).unwrap();
 
     let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
     assert_eq!(note_ids, vec![builder2.id(), builder1.id()]);
 
-    actions.perform(DeleteNote(builder1.id()));
+    actions.perform(DeleteNote(builder1.id())).unwrap();
     let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
     assert_eq!(note_ids, vec![builder2.id()]);
 
-    actions.perform(DeleteNote(builder2.id()));
+    actions.perform(DeleteNote(builder2.id())).unwrap();
     let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
     assert_eq!(note_ids, vec![]);
 }
@@ -194,8 +197,8 @@ fn deleted_note_listing() {
 fn reading_deleted_note_fails() {
     let mut actions = setup();
     let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
-    actions.perform(CreateNote(builder1.clone()));
-    actions.perform(DeleteNote(builder1.id()));
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.perform(DeleteNote(builder1.id())).unwrap();
     actions.get_note_by_id(builder1.id());
 }
 
@@ -204,16 +207,124 @@ pub fn edit_note_with() {
     let mut actions = setup();
     let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
     let modify = NoteBuilder::new().text("Modified Test Note 1");
-    actions.perform(CreateNote(builder1.clone()));
-    actions.perform(EditNote(builder1.id(), modify.clone()));
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions
+        .perform(EditNote(builder1.id(), modify.clone()))
+        .unwrap();
     let note = actions.get_note_by_id(builder1.id());
     modify.assert_matches_note(&note);
 }
 
+#[test]
+pub fn edit_keeps_queries_of_other_subjects() {
+    let mut actions = setup();
+    let mut add_subject = |name: &str| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        actions.last_added_subject.clone().unwrap().id
+    };
+    let (a, b, c) = (add_subject("A"), add_subject("B"), add_subject("C"));
+
+    let builder = NoteBuilder::new().text("Note").subject(a).decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+    assert_eq!(
+        actions.store.get_note_subjects(builder.id()).unwrap(),
+        vec![a]
+    );
+
+    let searches = [a, b, c].map(|s| NoteSearch::new().subject(s));
+    for search in searches.iter().chain([&NoteSearch::new()]) {
+        actions.get_note_ids_for_search(search.clone());
+    }
+    actions
+        .perform(EditNote(builder.id(), NoteBuilder::new().subject(b)))
+        .unwrap();
+
+    let cached = |search: &NoteSearch| actions.query_cache.map.contains_key(search);
+    assert!(!cached(&searches[0]));
+    assert!(!cached(&searches[1]));
+    assert!(cached(&searches[2]));
+    assert!(!cached(&NoteSearch::new()));
+    assert_eq!(
+        actions.get_note_ids_for_search(searches[1].clone()),
+        vec![builder.id()]
+    );
+}
+
+#[test]
+pub fn clear_done_tasks_undo() {
+    let mut actions = setup();
+    let mut add_subject = |name: &str| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        actions.last_added_subject.clone().unwrap().id
+    };
+    let (parent, child) = (add_subject("Parent"), add_subject("Child"));
+    actions
+        .perform(SetSubjectParent {
+            subject: child,
+            parent: Some(parent),
+        })
+        .unwrap();
+
+    let mut create = |builder: NoteBuilder| {
+        let builder = builder.decide_id();
+        actions.perform(CreateNote(builder.clone())).unwrap();
+        builder.id()
+    };
+    let done = create(
+        NoteBuilder::new()
+            .text("Done")
+            .subject(child)
+            .task_state(TaskState::Done),
+    );
+    let todo = create(NoteBuilder::new().text("Todo").subject(child).as_task());
+    let elsewhere = create(
+        NoteBuilder::new()
+            .text("Elsewhere")
+            .task_state(TaskState::Done),
+    );
+    actions
+        .perform(LinkNotes {
+            from: todo,
+            to: done,
+        })
+        .unwrap();
+
+    let found = actions.store.find_done_tasks(Some(parent)).unwrap();
+    assert_eq!(found, vec![done]);
+    assert_eq!(
+        actions.store.find_done_tasks(None).unwrap(),
+        vec![done, elsewhere]
+    );
+
+    let state = |actions: &DbActions, id| actions.store.get_note(id).map(|n| n.task_state);
+    actions
+        .perform(LayerAction::clear_done_tasks(
+            found.clone(),
+            ClearDonePolicy::Untask,
+        ))
+        .unwrap();
+    assert_eq!(state(&actions, done).unwrap(), TaskState::NotATask);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(state(&actions, done).unwrap(), TaskState::Done);
+
+    actions
+        .perform(LayerAction::clear_done_tasks(
+            found,
+            ClearDonePolicy::DeleteNotes,
+        ))
+        .unwrap();
+    assert!(state(&actions, done).is_err());
+    actions.undo().unwrap().unwrap();
+    assert_eq!(state(&actions, done).unwrap(), TaskState::Done);
+    assert_eq!(actions.store.get_linked_notes(todo).unwrap(), vec![done]);
+}
+
 #[test]
 pub fn subject_search() {
     let mut actions = setup();
-    actions.perform(AddSubject(None, "Subject".to_string()));
+    actions
+        .perform(AddSubject(None, "Subject".to_string()))
+        .unwrap();
     let subject = actions.last_added_subject.clone().unwrap();
 
     let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
@@ -221,9 +332,852 @@ pub fn subject_search() {
         .text("Test Note 2")
         .subject(subject.id)
         .decide_id();
-    actions.perform(CreateNote(builder1.clone()));
-    actions.perform(CreateNote(builder2.clone()));
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.perform(CreateNote(builder2.clone())).unwrap();
 
     let note_ids = actions.get_note_ids_for_search(NoteSearch::default().subject(subject.id));
     assert_eq!(note_ids, vec![builder2.id()])
 }
+
+#[test]
+pub fn add_subject_to_notes_undo() {
+    let mut actions = setup();
+    actions
+        .perform(AddSubject(None, "Subject".to_string()))
+        .unwrap();
+    let subject = actions.last_added_subject.clone().unwrap();
+
+    let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
+    let builder2 = NoteBuilder::new()
+        .text("Test Note 2")
+        .subject(subject.id)
+        .decide_id();
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.perform(CreateNote(builder2.clone())).unwrap();
+
+    actions
+        .perform(AddSubjectToNotes {
+            subject: subject.id,
+            notes: vec![builder1.id(), builder2.id()],
+        })
+        .unwrap();
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default().subject(subject.id));
+    assert_eq!(note_ids, vec![builder2.id(), builder1.id()]);
+    assert_eq!(
+        actions.get_note_by_id(builder1.id()).subjects,
+        vec![subject.id]
+    );
+
+    actions.undo().unwrap().unwrap();
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default().subject(subject.id));
+    assert_eq!(note_ids, vec![builder2.id()]);
+    assert!(actions.get_note_by_id(builder1.id()).subjects.is_empty());
+    assert_eq!(
+        actions.get_note_ids_for_search(NoteSearch::default()).len(),
+        2
+    );
+}
+
+#[test]
+pub fn group_is_one_undo_step() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Write about Rust").decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+    let before = store_state(&mut actions);
+
+    let subject_id = SubjectId(uuid::Uuid::new_v4());
+    actions
+        .perform(Group(vec![
+            AddSubject(Some(subject_id), "Rust".to_string()),
+            EditNote(
+                builder.id(),
+                NoteBuilder::new()
+                    .text("Write about [[Rust]]")
+                    .subject(subject_id),
+            ),
+        ]))
+        .unwrap();
+    let note = actions.get_note_by_id(builder.id());
+    assert_eq!(note.subjects, vec![subject_id]);
+    assert_eq!(note.text, "Write about [[Rust]]");
+    let after = store_state(&mut actions);
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store_state(&mut actions), before);
+    assert_eq!(actions.redo_queue.len(), 1);
+
+    actions.redo().unwrap().unwrap();
+    assert_eq!(store_state(&mut actions), after);
+}
+
+#[test]
+pub fn failed_group_is_reverted() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Note").decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+    let before = store_state(&mut actions);
+    let undo_len = actions.undo_queue.len();
+
+    let result = actions.perform(Group(vec![
+        AddSubject(None, "Subject".to_string()),
+        EditNote(
+            NoteId(uuid::Uuid::new_v4()),
+            NoteBuilder::new().text("Missing"),
+        ),
+    ]));
+    assert!(result.is_err());
+    assert_eq!(store_state(&mut actions), before);
+    assert_eq!(actions.undo_queue.len(), undo_len);
+}
+
+#[test]
+pub fn checkpoint_labels_next_action() {
+    let mut actions = setup();
+    assert_eq!(actions.undo_description(), None);
+
+    let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
+    let builder2 = NoteBuilder::new().text("Test Note 2").decide_id();
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.checkpoint(Some("Bulk delete (1 notes)".to_string()));
+    actions.perform(CreateNote(builder2.clone())).unwrap();
+    actions.perform(DeleteNote(builder1.id())).unwrap();
+    assert_eq!(actions.undo_description().as_deref(), Some("Delete note"));
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(
+        actions.undo_description().as_deref(),
+        Some("Bulk delete (1 notes)")
+    );
+    assert_eq!(actions.redo_description().as_deref(), Some("Delete note"));
+
+    // The checkpoint is skipped over, so one undo still reverts one action.
+    actions.undo().unwrap().unwrap();
+    assert_eq!(actions.undo_description().as_deref(), Some("Create note"));
+    assert_eq!(
+        actions.redo_description().as_deref(),
+        Some("Bulk delete (1 notes)")
+    );
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
+    assert_eq!(note_ids, vec![builder1.id()]);
+
+    actions.redo().unwrap().unwrap();
+    assert_eq!(
+        actions.undo_description().as_deref(),
+        Some("Bulk delete (1 notes)")
+    );
+    actions.undo().unwrap().unwrap();
+    actions.undo().unwrap().unwrap();
+    assert!(actions.undo().is_none());
+}
+
+#[test]
+pub fn undo_to_checkpoint() {
+    let mut actions = setup();
+    let builder1 = NoteBuilder::new().text("Test Note 1").decide_id();
+    actions.perform(CreateNote(builder1.clone())).unwrap();
+    actions.checkpoint(Some("Before import".to_string()));
+    for i in 0..3 {
+        let builder = NoteBuilder::new().text(format!("Imported {i}"));
+        actions.perform(CreateNote(builder)).unwrap();
+    }
+    actions.checkpoint(Some("Unrelated".to_string()));
+
+    let effects = actions.undo_to("Before import");
+    assert_eq!(effects.len(), 3);
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
+    assert_eq!(note_ids, vec![builder1.id()]);
+    assert_eq!(actions.undo_description().as_deref(), Some("Create note"));
+
+    // The checkpoint is gone from the undo history, so this does nothing.
+    assert!(actions.undo_to("Before import").is_empty());
+
+    actions.redo().unwrap().unwrap();
+    assert_eq!(actions.undo_description().as_deref(), Some("Before import"));
+}
+
+#[test]
+pub fn undo_n_steps() {
+    let mut actions = setup();
+    for i in 0..3 {
+        let builder = NoteBuilder::new().text(format!("Note {i}"));
+        actions.perform(CreateNote(builder)).unwrap();
+    }
+
+    assert_eq!(actions.undo_n(2).len(), 2);
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
+    assert_eq!(note_ids.len(), 1);
+
+    assert_eq!(actions.undo_n(5).len(), 1);
+    assert!(actions
+        .get_note_ids_for_search(NoteSearch::default())
+        .is_empty());
+}
+
+#[test]
+pub fn blank_edit_keeps_old_text() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Keep me").decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+
+    let note = actions.get_note_by_id(builder.id());
+    let policy = EmptyEditPolicy::KeepOldText;
+    assert_eq!(
+        LayerAction::save_edit(&note, "  \n ".to_string(), vec![], policy),
+        None
+    );
+    let edit = LayerAction::save_edit(&note, "Edited".to_string(), vec![], policy);
+    actions.perform(edit.unwrap()).unwrap();
+    assert_eq!(actions.get_note_by_id(builder.id()).text, "Edited");
+}
+
+#[test]
+pub fn blank_edit_deletes_note() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Delete me").decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+
+    let note = actions.get_note_by_id(builder.id());
+    let policy = EmptyEditPolicy::DeleteNote;
+    let edit = LayerAction::save_edit(&note, " ".to_string(), vec![], policy).unwrap();
+    assert_eq!(edit, DeleteNote(builder.id()));
+    actions.perform(edit).unwrap();
+    assert!(actions
+        .get_note_ids_for_search(NoteSearch::default())
+        .is_empty());
+
+    actions.undo().unwrap().unwrap();
+    let note_ids = actions.get_note_ids_for_search(NoteSearch::default());
+    assert_eq!(note_ids, vec![builder.id()]);
+    assert_eq!(actions.get_note_by_id(builder.id()).text, "Delete me");
+}
+
+#[test]
+pub fn outdated_edit_is_refused() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Original").decide_id();
+    actions.perform(CreateNote(builder.clone())).unwrap();
+    let note = actions.get_note_by_id(builder.id());
+
+    let policy = EmptyEditPolicy::KeepOldText;
+    let first = LayerAction::save_edit(&note, "First".to_string(), vec![], policy).unwrap();
+    let second = LayerAction::save_edit(&note, "Second".to_string(), vec![], policy).unwrap();
+    actions.perform(first).unwrap();
+
+    let error = actions.perform(second.clone()).unwrap_err();
+    assert!(error.to_string().contains("changed elsewhere"), "{error}");
+    assert_eq!(actions.get_note_by_id(builder.id()).text, "First");
+    assert_eq!(actions.undo_description().as_deref(), Some("Edit note"));
+
+    actions.perform(DeleteNote(builder.id())).unwrap();
+    let error = actions.perform(second).unwrap_err();
+    assert!(error.to_string().contains("deleted"), "{error}");
+}
+
+#[test]
+pub fn move_subtree_undo() {
+    let mut actions = setup();
+    let mut add = |name: &str| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        actions.last_added_subject.clone().unwrap().id
+    };
+    let (top, middle, bottom, target) = (add("Top"), add("Middle"), add("Bottom"), add("Target"));
+    for (subject, parent) in [(middle, top), (bottom, middle)] {
+        let parent = Some(parent);
+        actions.perform(MoveSubtree { subject, parent }).unwrap();
+    }
+
+    let parent = Some(target);
+    actions
+        .perform(MoveSubtree {
+            subject: top,
+            parent,
+        })
+        .unwrap();
+    let subjects = actions.get_subjects();
+    assert_eq!(subjects[&top].parent_id, Some(target));
+    assert_eq!(subjects[&bottom].parent_id, Some(middle));
+
+    // A cycle is refused and leaves nothing to undo.
+    let parent = Some(bottom);
+    assert!(actions
+        .perform(MoveSubtree {
+            subject: target,
+            parent,
+        })
+        .is_err());
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(actions.get_subjects()[&top].parent_id, None);
+    actions.redo().unwrap().unwrap();
+    assert_eq!(actions.get_subjects()[&top].parent_id, Some(target));
+}
+
+#[test]
+pub fn text_links_stay_in_text_after_undoing_a_delete() {
+    let mut actions = setup();
+    let mut create = |text: &str| {
+        let builder = NoteBuilder::new().text(text).decide_id();
+        let id = builder.id();
+        actions.perform(CreateNote(builder)).unwrap();
+        id
+    };
+    let target = create("Target");
+    let a = create("Refers to [[Target]]");
+    let c = create(&format!("See [[{a}]]"));
+    let store = actions.store.clone();
+
+    actions.perform(DeleteNote(a)).unwrap();
+    assert_eq!(store.get_backlinks(target).unwrap(), vec![]);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![target]);
+    assert_eq!(store.get_backlinks(a).unwrap(), vec![c]);
+
+    // Editing the references out still unlinks the notes.
+    let edit = |text: &str| NoteBuilder::new().text(text);
+    actions.perform(EditNote(a, edit("No references"))).unwrap();
+    actions
+        .perform(EditNote(c, edit("Nothing either")))
+        .unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![]);
+    assert_eq!(store.get_backlinks(a).unwrap(), vec![]);
+}
+
+#[test]
+pub fn link_notes_undo() {
+    let mut actions = setup();
+    let mut create = |text: &str| {
+        let builder = NoteBuilder::new().text(text).decide_id();
+        let id = builder.id();
+        actions.perform(CreateNote(builder)).unwrap();
+        id
+    };
+    let (a, b, c) = (create("A"), create("B"), create("C"));
+    let store = actions.store.clone();
+
+    actions.perform(LinkNotes { from: a, to: b }).unwrap();
+    actions.perform(LinkNotes { from: c, to: a }).unwrap();
+    // Linking again changes nothing, so undoing it keeps the link.
+    actions.perform(LinkNotes { from: a, to: b }).unwrap();
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![b]);
+
+    // Undoing a deletion brings back the links both ways.
+    actions.perform(DeleteNote(a)).unwrap();
+    assert_eq!(store.get_backlinks(b).unwrap(), vec![]);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![b]);
+    assert_eq!(store.get_backlinks(a).unwrap(), vec![c]);
+    actions.redo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(c).unwrap(), vec![]);
+    actions.undo().unwrap().unwrap();
+
+    actions.perform(UnlinkNotes { from: a, to: b }).unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![]);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(a).unwrap(), vec![b]);
+}
+
+#[test]
+pub fn favorite_undo() {
+    let mut actions = setup();
+    actions
+        .perform(AddSubject(None, "Subject".to_string()))
+        .unwrap();
+    let subject = actions.last_added_subject.clone().unwrap().id;
+
+    actions
+        .perform(SetSubjectFavorite {
+            subject,
+            favorite: true,
+            order: 3,
+        })
+        .unwrap();
+    let favorite = actions.get_subjects()[&subject].clone();
+    assert!(favorite.favorite);
+    assert_eq!(favorite.favorite_order, 3);
+
+    actions.undo().unwrap().unwrap();
+    assert!(!actions.get_subjects()[&subject].favorite);
+}
+
+#[test]
+pub fn append_and_prepend_to_note() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().decide_id();
+    let id = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+    let text = || store.get_note(id).unwrap().text.clone();
+    let mut edit = |action: fn(&NoteData, &str) -> LayerAction, added: &str| {
+        let note = store.get_note(id).unwrap();
+        actions.perform(action(&note, added)).unwrap();
+    };
+
+    edit(LayerAction::append_to_note, "First");
+    assert_eq!(text(), "First");
+    edit(LayerAction::append_to_note, "Second");
+    assert_eq!(text(), "First\nSecond");
+    edit(LayerAction::prepend_to_note, "Zeroth");
+    assert_eq!(text(), "Zeroth\nFirst\nSecond");
+
+    // A note ending in a newline isn't given another.
+    edit(LayerAction::append_to_note, "Third\n");
+    edit(LayerAction::append_to_note, "Fourth");
+    assert_eq!(text(), "Zeroth\nFirst\nSecond\nThird\nFourth");
+
+    // Adding nothing keeps the text.
+    edit(LayerAction::append_to_note, " ");
+    assert_eq!(text(), "Zeroth\nFirst\nSecond\nThird\nFourth");
+
+    actions.undo().unwrap().unwrap();
+    actions.undo().unwrap().unwrap();
+    actions.undo().unwrap().unwrap();
+    assert_eq!(text(), "Zeroth\nFirst\nSecond");
+    actions.undo().unwrap().unwrap();
+    assert_eq!(text(), "First\nSecond");
+}
+
+#[test]
+pub fn subject_color_undo() {
+    let mut actions = setup();
+    actions
+        .perform(AddSubject(None, "Subject".to_string()))
+        .unwrap();
+    let subject = actions.last_added_subject.clone().unwrap().id;
+    let set_color = |color: &str| SetSubjectColor {
+        subject,
+        color: Some(color.to_string()),
+    };
+
+    actions.perform(set_color("#ff8000")).unwrap();
+    actions.perform(set_color("#0080ff")).unwrap();
+    assert!(actions.perform(set_color("orange")).is_err());
+    let color = |actions: &mut DbActions| actions.get_subjects()[&subject].color.clone();
+    assert_eq!(color(&mut actions).as_deref(), Some("#0080ff"));
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(color(&mut actions).as_deref(), Some("#ff8000"));
+    actions.undo().unwrap().unwrap();
+    assert_eq!(color(&mut actions), None);
+    actions.redo().unwrap().unwrap();
+    assert_eq!(color(&mut actions).as_deref(), Some("#ff8000"));
+}
+
+#[test]
+pub fn rename_subject_undo() {
+    let mut actions = setup();
+    let mut add = |name: &str| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        actions.last_added_subject.clone().unwrap().id
+    };
+    let (parent, first, second) = (add("Parent"), add("First"), add("Second"));
+    let other = add("Other");
+    for subject in [first, second] {
+        let parent = Some(parent);
+        actions.perform(MoveSubtree { subject, parent }).unwrap();
+    }
+    let rename = |name: &str| RenameSubject {
+        subject: second,
+        name: name.to_string(),
+    };
+    let name = |actions: &mut DbActions| actions.get_subjects()[&second].name.clone();
+
+    // Siblings under a parent can't share a name, and the failed rename
+    // leaves nothing to undo.
+    assert!(actions.perform(rename("First")).is_err());
+    assert_eq!(name(&mut actions), "Second");
+
+    // Neither can top-level subjects.
+    let rename_other = RenameSubject {
+        subject: other,
+        name: "Parent".to_string(),
+    };
+    assert!(actions.perform(rename_other).is_err());
+    assert_eq!(actions.get_subjects()[&other].name, "Other");
+
+    actions.perform(rename("Renamed")).unwrap();
+    assert_eq!(name(&mut actions), "Renamed");
+    actions.undo().unwrap().unwrap();
+    assert_eq!(name(&mut actions), "Second");
+    actions.redo().unwrap().unwrap();
+    assert_eq!(name(&mut actions), "Renamed");
+}
+
+#[test]
+pub fn subjects_from_outline_undo() {
+    let mut actions = setup();
+    actions
+        .perform(AddSubject(None, "Work".to_string()))
+        .unwrap();
+    let work = actions.last_added_subject.clone().unwrap().id;
+    let outline = "Work\n  Meetings\nHome/Garden\n";
+
+    let (group, summary) =
+        LayerAction::create_subjects_from_outline(outline, &actions.get_subjects());
+    assert_eq!(summary.created, 3);
+    assert_eq!(summary.matched, 1);
+    actions.perform(group).unwrap();
+
+    let subjects = actions.get_subjects();
+    let find = |name: &str| subjects.values().find(|s| s.name == name).unwrap();
+    assert_eq!(find("Meetings").parent_id, Some(work));
+    assert_eq!(find("Garden").parent_id, Some(find("Home").id));
+    assert_eq!(subjects.len(), 4);
+
+    // Everything exists now, so nothing more is created.
+    let (_, summary) = LayerAction::create_subjects_from_outline(outline, &subjects);
+    assert_eq!(summary.created, 0);
+    assert_eq!(summary.matched, 4);
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(actions.get_subjects().len(), 1);
+}
+
+#[test]
+pub fn find_replace_undo() {
+    let mut actions = setup();
+    let mut create = |text: &str| {
+        let builder = NoteBuilder::new().text(text).decide_id();
+        let id = builder.id();
+        actions.perform(CreateNote(builder)).unwrap();
+        id
+    };
+    let (a, b) = (create("Draft v1"), create("v1 is out"));
+    let store = actions.store.clone();
+
+    let pattern = FindPattern::Literal("v1".to_string());
+    let replacements = store.note_replacements(&pattern, "v2", None).unwrap();
+    assert_eq!(replacements.len(), 2);
+    actions
+        .perform(LayerAction::replace_in_notes(replacements))
+        .unwrap();
+    assert_eq!(store.get_note(a).unwrap().text, "Draft v2");
+    assert_eq!(store.get_note(b).unwrap().text, "v2 is out");
+
+    // One undo restores every note.
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_note(a).unwrap().text, "Draft v1");
+    assert_eq!(store.get_note(b).unwrap().text, "v1 is out");
+    actions.redo().unwrap().unwrap();
+    assert_eq!(store.get_note(b).unwrap().text, "v2 is out");
+}
+
+#[test]
+pub fn task_completion_undo_keeps_done_at() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new()
+        .text("Task")
+        .task_state(TaskState::Todo)
+        .decide_id();
+    let id = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+    let state = |expected: TaskState| {
+        let note = store.get_note(id).unwrap();
+        assert_eq!(note.task_state, expected);
+        note.done_at
+    };
+
+    let complete = NoteBuilder::new().task_state(TaskState::Done);
+    actions.perform(EditNote(id, complete.clone())).unwrap();
+    let done_at = state(TaskState::Done);
+    assert!(done_at.is_some());
+
+    // Undo and redo restore the same completion time every time.
+    for _ in 0..3 {
+        actions.undo().unwrap().unwrap();
+        assert_eq!(state(TaskState::Todo), None);
+        actions.redo().unwrap().unwrap();
+        assert_eq!(state(TaskState::Done), done_at);
+    }
+
+    // Reopening clears it, and undoing the reopening brings it back.
+    let reopen = NoteBuilder::new().task_state(TaskState::Todo);
+    actions.perform(EditNote(id, reopen)).unwrap();
+    assert_eq!(state(TaskState::Todo), None);
+    for _ in 0..3 {
+        actions.undo().unwrap().unwrap();
+        assert_eq!(state(TaskState::Done), done_at);
+        actions.redo().unwrap().unwrap();
+        assert_eq!(state(TaskState::Todo), None);
+    }
+
+    // Completing again is a new completion, which undo walks back through.
+    actions.perform(EditNote(id, complete)).unwrap();
+    let done_again = state(TaskState::Done);
+    assert!(done_again >= done_at);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(state(TaskState::Todo), None);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(state(TaskState::Done), done_at);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(state(TaskState::Todo), None);
+    actions.redo().unwrap().unwrap();
+    assert_eq!(state(TaskState::Done), done_at);
+}
+
+#[test]
+pub fn bump_keeps_task_state() {
+    let mut actions = setup();
+    let created_at = Local.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+    let builder = NoteBuilder::new()
+        .text("Task")
+        .created_at(created_at)
+        .task_state(TaskState::Done)
+        .decide_id();
+    let id = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+    let before = store.get_note(id).unwrap();
+
+    let to = Local.with_ymd_and_hms(2023, 8, 21, 12, 0, 0).unwrap();
+    actions.perform(BumpNote { note: id, to }).unwrap();
+    let bumped = store.get_note(id).unwrap();
+    assert_eq!(bumped.created_at, to);
+    assert_eq!(bumped.task_state, TaskState::Done);
+    assert_eq!(bumped.done_at, before.done_at);
+    assert_eq!(bumped.modified_at, before.modified_at);
+
+    actions.undo().unwrap().unwrap();
+    let restored = store.get_note(id).unwrap();
+    assert_eq!(restored.created_at, before.created_at);
+    assert_eq!(restored.done_at, before.done_at);
+}
+
+#[test]
+pub fn pin_keeps_modified_at() {
+    let mut actions = setup();
+    let builder = NoteBuilder::new().text("Pin me").decide_id();
+    let id = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+    let before = store.get_note(id).unwrap();
+
+    actions
+        .perform(SetPinned {
+            note: id,
+            pinned: true,
+        })
+        .unwrap();
+    let pinned = store.get_note(id).unwrap();
+    assert!(pinned.pinned);
+    assert_eq!(pinned.modified_at, before.modified_at);
+
+    actions.undo().unwrap().unwrap();
+    assert!(!store.get_note(id).unwrap().pinned);
+}
+
+#[test]
+pub fn remove_subject_policies() {
+    let mut actions = setup();
+    let mut add = |name: &str, parent: Option<SubjectId>| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        let id = actions.last_added_subject.clone().unwrap().id;
+        if parent.is_some() {
+            actions
+                .perform(SetSubjectParent {
+                    subject: id,
+                    parent,
+                })
+                .unwrap();
+        }
+        id
+    };
+    let garden = add("Garden", None);
+    let roses = add("Roses", Some(garden));
+    let pruning = add("Pruning", Some(roses));
+    let builder = NoteBuilder::new().text("Prune").subject(roses).decide_id();
+    let note = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+
+    // Refusing leaves everything in place, with nothing to undo.
+    let refused = actions.perform(RemoveSubject(roses, RemoveSubjectPolicy::Refuse));
+    assert!(refused.is_err());
+    assert!(store.subject_exists(roses).unwrap());
+    assert_eq!(actions.undo_description().as_deref(), Some("Create note"));
+
+    actions
+        .perform(RemoveSubject(roses, RemoveSubjectPolicy::MoveToParent))
+        .unwrap();
+    assert!(!store.subject_exists(roses).unwrap());
+    assert_eq!(store.get_note(note).unwrap().subjects, vec![garden]);
+    assert_eq!(store.get_subject(pruning).unwrap().parent_id, Some(garden));
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_subject(roses).unwrap().parent_id, Some(garden));
+    assert_eq!(store.get_note(note).unwrap().subjects, vec![roses]);
+    assert_eq!(store.get_subject(pruning).unwrap().parent_id, Some(roses));
+
+    // Deleting a subject with its parent leaves the child at the top level,
+    // whichever is deleted first.
+    let delete = LayerAction::delete_subjects(&[garden, roses], RemoveSubjectPolicy::Untag);
+    actions.perform(delete).unwrap();
+    assert_eq!(store.get_subject(pruning).unwrap().parent_id, None);
+    assert!(store.get_note(note).unwrap().subjects.is_empty());
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_subject(pruning).unwrap().parent_id, Some(roses));
+    assert_eq!(store.get_subject(roses).unwrap().parent_id, Some(garden));
+    assert_eq!(store.get_note(note).unwrap().subjects, vec![roses]);
+}
+
+#[test]
+pub fn delete_and_merge_subjects_undo() {
+    let mut actions = setup();
+    let mut add = |name: &str, parent: Option<SubjectId>| {
+        actions.perform(AddSubject(None, name.to_string())).unwrap();
+        let id = actions.last_added_subject.clone().unwrap().id;
+        if parent.is_some() {
+            actions
+                .perform(SetSubjectParent {
+                    subject: id,
+                    parent,
+                })
+                .unwrap();
+        }
+        id
+    };
+    let work = add("Work", None);
+    let meetings = add("Meetings", Some(work));
+    let standups = add("Standups", Some(meetings));
+    let jobs = add("Jobs", None);
+    let builder = NoteBuilder::new()
+        .text("Sync")
+        .subject(meetings)
+        .decide_id();
+    let note = builder.id();
+    actions.perform(CreateNote(builder)).unwrap();
+    let store = actions.store.clone();
+    let notes_of = |id| store.get_subject_note_ids(id).unwrap();
+
+    // Deleting moves the children up and untags the notes.
+    let delete = LayerAction::delete_subjects(&[meetings], RemoveSubjectPolicy::Untag);
+    actions.perform(delete).unwrap();
+    assert!(!store.subject_exists(meetings).unwrap());
+    assert_eq!(store.get_subject(standups).unwrap().parent_id, Some(work));
+    assert!(!store.get_note(note).unwrap().subjects.contains(&meetings));
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_subject(meetings).unwrap().parent_id, Some(work));
+    assert_eq!(
+        store.get_subject(standups).unwrap().parent_id,
+        Some(meetings)
+    );
+    assert_eq!(store.get_note(note).unwrap().subjects, vec![meetings]);
+
+    // Merging hands the notes and children over.
+    let from = actions.get_subjects()[&work].clone();
+    let merge = LayerAction::merge_subject(&from, jobs, notes_of(work));
+    actions.perform(merge).unwrap();
+    assert!(!store.subject_exists(work).unwrap());
+    assert_eq!(store.get_subject(meetings).unwrap().parent_id, Some(jobs));
+
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_subject(meetings).unwrap().parent_id, Some(work));
+    assert_eq!(store.get_subject(work).unwrap().name, "Work");
+}
+
+#[test]
+fn undo_history_survives_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let open = || DbActions::new(Rc::new(Store::new(ConnectionType::File(dir.path().into()))));
+    let builder = NoteBuilder::new().text("Original").decide_id();
+    let id = builder.id();
+
+    let mut actions = open();
+    for i in 0..70 {
+        let text = NoteBuilder::new().text(format!("Filler {i}"));
+        actions.perform(CreateNote(text)).unwrap();
+    }
+    actions.perform(CreateNote(builder)).unwrap();
+    let edit = NoteBuilder::new().text("Edited");
+    actions.perform(EditNote(id, edit)).unwrap();
+    actions.undo().unwrap().unwrap();
+    drop(actions);
+
+    // The queues are saved as they were, the undo queue up to its cap.
+    let mut actions = open();
+    assert_eq!(actions.undo_queue.len(), 63);
+    assert_eq!(actions.redo_queue.len(), 1);
+    actions.redo().unwrap().unwrap();
+    assert_eq!(actions.get_note_by_id(id).text, "Edited");
+    actions.undo().unwrap().unwrap();
+    actions.undo().unwrap().unwrap();
+    let notes = actions.get_note_ids_for_search(NoteSearch::default());
+    assert!(!notes.contains(&id));
+}
+
+#[test]
+fn deleting_the_listed_subject() {
+    // Signals can only be created inside a component.
+    fn app(_cx: dioxus::prelude::Scope) -> dioxus::prelude::Element {
+        let store = Store::new(ConnectionType::InMemory);
+        let subject = store.add_subject("Listed".to_string()).unwrap().id;
+        let note = NoteBuilder::new().text("Tagged").subject(subject);
+        store.add_note(note).unwrap();
+
+        let notes = dioxus_signals::Signal::new(Vec::new());
+        let subjects = dioxus_signals::Signal::new(Rc::new(BTreeMap::new()));
+        let mut layer = Layer::new(Rc::new(store), notes, subjects);
+        layer.update_subjects();
+        layer.set_search(NoteSearch::new().subject(subject));
+        assert_eq!(notes.read().len(), 1);
+
+        layer.perform(LayerAction::delete_subjects(
+            &[subject],
+            RemoveSubjectPolicy::Untag,
+        ));
+        assert!(!subjects.read().contains_key(&subject));
+        assert!(notes.read().is_empty());
+        None
+    }
+
+    let mut dom = dioxus::prelude::VirtualDom::new(app);
+    let _ = dom.rebuild();
+}
+
+#[test]
+fn failed_undo_keeps_the_step() {
+    let mut actions = setup();
+    let id = SubjectId(uuid::Uuid::new_v4());
+    actions
+        .perform(AddSubject(Some(id), "Tagged".to_string()))
+        .unwrap();
+    actions
+        .perform(Checkpoint(Some("After".to_string())))
+        .unwrap();
+    // Tagged outside the history, so undoing can't untag it first.
+    let note = NoteBuilder::new().text("Note").subject(id);
+    actions.store.add_note(note).unwrap();
+    let queue = actions.undo_queue.clone();
+
+    assert!(actions.undo().unwrap().is_err());
+    assert_eq!(actions.undo_queue, queue);
+    assert!(actions.redo_queue.is_empty());
+    assert!(actions.store.subject_exists(id).unwrap());
+}
+
+#[test]
+fn undoing_a_link_already_in_text() {
+    let mut actions = setup();
+    let target = NoteBuilder::new().text("Target").decide_id();
+    let to = target.id();
+    actions.perform(CreateNote(target)).unwrap();
+    let source = NoteBuilder::new().text(format!("See [[{to}]]")).decide_id();
+    let from = source.id();
+    actions.perform(CreateNote(source)).unwrap();
+    let store = actions.store.clone();
+
+    actions.perform(LinkNotes { from, to }).unwrap();
+    assert_eq!(actions.undo_description().as_deref(), Some("Link notes"));
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(from).unwrap(), vec![to]);
+
+    // The link is written in the text again, so it goes with the reference.
+    let edit = NoteBuilder::new().text("Nothing");
+    actions.perform(EditNote(from, edit)).unwrap();
+    assert_eq!(store.get_linked_notes(from).unwrap(), vec![]);
+    actions.undo().unwrap().unwrap();
+    assert_eq!(store.get_linked_notes(from).unwrap(), vec![to]);
+}
@@ -0,0 +1,314 @@
+//! Links from one note to another, like "see also". They're either made
+//! explicitly or written in the text of the note as `[[reference]]`.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::{debug, instrument};
+
+use super::{
+    notes::NoteId,
+    retry::{begin_write, commit_write},
+    Store,
+};
+
+/// What [`Store::link_notes`] changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkChange {
+    /// The notes weren't linked before.
+    Added,
+    /// The link was only written in the text, and now stays without it.
+    MadeExplicit,
+    /// The notes were already linked explicitly.
+    Unchanged,
+}
+
+impl Store {
+    /// Links `from` to `to`. A link written in the text of `from` is kept
+    /// when the text changes.
+    #[instrument(skip(self))]
+    pub fn link_notes(&self, from: NoteId, to: NoteId) -> rusqlite::Result<LinkChange> {
+        debug!("Linking notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let inserted = tx
+            .prepare_cached("INSERT OR IGNORE INTO note_links (from_id, to_id) VALUES (?1, ?2)")?
+            .execute(params![from, to])?;
+        let change = if inserted > 0 {
+            LinkChange::Added
+        } else {
+            let converted = tx
+                .prepare_cached(
+                    "UPDATE note_links SET in_text = 0
+                    WHERE from_id = ?1 AND to_id = ?2 AND in_text = 1",
+                )?
+                .execute(params![from, to])?;
+            if converted > 0 {
+                LinkChange::MadeExplicit
+            } else {
+                LinkChange::Unchanged
+            }
+        };
+        commit_write(tx)?;
+        Ok(change)
+    }
+
+    /// Turns the explicit link from `from` to `to` back into one written
+    /// in the text, which goes away with the reference. Returns whether
+    /// there was an explicit link.
+    #[instrument(skip(self))]
+    pub fn make_link_text_only(&self, from: NoteId, to: NoteId) -> rusqlite::Result<bool> {
+        debug!("Making link text only");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let changed = tx
+            .prepare_cached(
+                "UPDATE note_links SET in_text = 1
+                WHERE from_id = ?1 AND to_id = ?2 AND in_text = 0",
+            )?
+            .execute(params![from, to])?;
+        commit_write(tx)?;
+        Ok(changed > 0)
+    }
+
+    /// Removes the link from `from` to `to`. Returns whether there was one.
+    #[instrument(skip(self))]
+    pub fn unlink_notes(&self, from: NoteId, to: NoteId) -> rusqlite::Result<bool> {
+        debug!("Unlinking notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let deleted = tx
+            .prepare_cached("DELETE FROM note_links WHERE from_id = ?1 AND to_id = ?2")?
+            .execute(params![from, to])?;
+        commit_write(tx)?;
+        Ok(deleted > 0)
+    }
+
+    /// The notes `note` links to, oldest first.
+    pub fn get_linked_notes(&self, note: NoteId) -> rusqlite::Result<Vec<NoteId>> {
+        self.conn
+            .borrow()
+            .prepare_cached(
+                "SELECT l.to_id FROM note_links l
+                JOIN notes n ON n.id = l.to_id
+                WHERE l.from_id = ?1
+                ORDER BY n.created_at",
+            )?
+            .query_map(params![note], |row| row.get(0))?
+            .collect()
+    }
+
+    /// The notes linking to `note`, oldest first.
+    pub fn get_backlinks(&self, note: NoteId) -> rusqlite::Result<Vec<NoteId>> {
+        self.conn
+            .borrow()
+            .prepare_cached(
+                "SELECT l.from_id FROM note_links l
+                JOIN notes n ON n.id = l.from_id
+                WHERE l.to_id = ?1
+                ORDER BY n.created_at",
+            )?
+            .query_map(params![note], |row| row.get(0))?
+            .collect()
+    }
+}
+
+impl Store {
+    /// The links from and to `note` that were made explicitly, as
+    /// `(from, to)` pairs. Links written in text are left out, as they're
+    /// found from the text again.
+    pub fn get_explicit_links(&self, note: NoteId) -> rusqlite::Result<Vec<(NoteId, NoteId)>> {
+        self.conn
+            .borrow()
+            .prepare_cached(
+                "SELECT from_id, to_id FROM note_links
+                WHERE (from_id = ?1 OR to_id = ?1) AND in_text = 0",
+            )?
+            .query_map(params![note], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect()
+    }
+}
+
+/// A `[[reference]]` to another note in the text of a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteReference<'a> {
+    Id(NoteId),
+    /// The first line of the note, without a Markdown heading marker.
+    Title(&'a str),
+}
+
+/// Finds the `[[reference]]`s in `text`, in order. A reference is a note id,
+/// or otherwise the title of a note. References can't span lines.
+pub fn parse_note_references(text: &str) -> Vec<NoteReference<'_>> {
+    let mut references = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else { break };
+        let inner = &rest[..end];
+        // Skip to the next opening brackets, which may start a reference.
+        if inner.contains('\n') || inner.contains("[[") {
+            continue;
+        }
+        rest = &rest[end + 2..];
+        let inner = inner.trim();
+        if inner.is_empty() {
+            continue;
+        }
+        references.push(match inner.parse() {
+            Ok(id) => NoteReference::Id(id),
+            Err(_) => NoteReference::Title(inner),
+        });
+    }
+    references
+}
+
+/// Links `note` to the notes referenced in `text`, see [`parse_note_references`].
+/// A title refers to the newest note with that title. References to notes
+/// that don't exist yet are left unlinked.
+pub(super) fn insert_text_links(tx: &Connection, note: NoteId, text: &str) -> rusqlite::Result<()> {
+    for reference in parse_note_references(text) {
+        let target = match reference {
+            NoteReference::Id(id) => tx
+                .prepare_cached("SELECT id FROM notes WHERE id = ?1")?
+                .query_row(params![id], |row| row.get::<_, NoteId>(0))
+                .optional()?,
+            NoteReference::Title(title) => tx
+                .prepare_cached(
+                    "SELECT id FROM notes
+                    WHERE id != ?2 AND trim(ltrim(
+                        CASE WHEN instr(text, char(10)) > 0
+                            THEN substr(text, 1, instr(text, char(10)) - 1)
+                            ELSE text END,
+                        '#'
+                    )) = ?1
+                    ORDER BY created_at DESC
+                    LIMIT 1",
+                )?
+                .query_row(params![title, note], |row| row.get::<_, NoteId>(0))
+                .optional()?,
+        };
+        let Some(target) = target.filter(|&target| target != note) else { continue };
+        // A link made explicitly stays one.
+        tx.prepare_cached(
+            "INSERT OR IGNORE INTO note_links (from_id, to_id, in_text) VALUES (?1, ?2, 1)",
+        )?
+        .execute(params![note, target])?;
+    }
+    Ok(())
+}
+
+/// Deletes the links written in the text of `note`, before it's rewritten.
+pub(super) fn delete_text_links(tx: &Connection, note: NoteId) -> rusqlite::Result<()> {
+    tx.prepare_cached("DELETE FROM note_links WHERE from_id = ?1 AND in_text = 1")?
+        .execute(params![note])?;
+    Ok(())
+}
+
+/// Links every note to the notes referenced in its text again, for after an
+/// import that inserted the notes without their links.
+pub(super) fn rebuild_text_links(tx: &Connection) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM note_links WHERE in_text = 1", [])?;
+    let notes = tx
+        .prepare("SELECT id, text FROM notes WHERE text LIKE '%[[%]]%'")?
+        .query_map([], |row| {
+            Ok((row.get::<_, NoteId>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (note, text) in notes {
+        insert_text_links(tx, note, &text)?;
+    }
+    Ok(())
+}
+
+/// Deletes the links from and to a deleted note. Links written in the text
+/// of other notes are kept, so they're back if the note is restored. They're
+/// hidden until then, as the links are only listed for existing notes.
+pub(super) fn delete_note_links(tx: &Connection, note: NoteId) -> rusqlite::Result<()> {
+    tx.prepare_cached(
+        "DELETE FROM note_links
+        WHERE from_id = ?1 OR (to_id = ?1 AND in_text = 0)",
+    )?
+    .execute(params![note])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::{notes::NoteBuilder, ConnectionType};
+
+    #[test]
+    fn links_and_backlinks() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let a = store.add_note(NoteBuilder::new().text("A"))?.id;
+        let b = store.add_note(NoteBuilder::new().text("B"))?.id;
+        let c = store.add_note(NoteBuilder::new().text("C"))?.id;
+
+        assert_eq!(store.link_notes(a, b)?, LinkChange::Added);
+        assert_eq!(store.link_notes(a, b)?, LinkChange::Unchanged);
+        assert_eq!(store.link_notes(a, c)?, LinkChange::Added);
+        assert_eq!(store.link_notes(c, b)?, LinkChange::Added);
+        assert_eq!(store.get_linked_notes(a)?, vec![b, c]);
+        assert_eq!(store.get_backlinks(b)?, vec![a, c]);
+
+        // Editing a note keeps its links.
+        let note = store.get_note(a)?;
+        store.update_note(note.modify_with(|n| n.text("A edited")))?;
+        assert_eq!(store.get_linked_notes(a)?, vec![b, c]);
+
+        assert!(store.unlink_notes(a, b)?);
+        assert!(!store.unlink_notes(a, b)?);
+        assert_eq!(store.get_backlinks(b)?, vec![c]);
+
+        store.delete_note(c)?;
+        assert_eq!(store.get_linked_notes(a)?, vec![]);
+        assert_eq!(store.get_backlinks(b)?, vec![]);
+        let rows: i64 =
+            store
+                .conn
+                .borrow()
+                .query_row("SELECT COUNT(*) FROM note_links", [], |row| row.get(0))?;
+        assert_eq!(rows, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn references_are_parsed() {
+        let id = NoteId(uuid::Uuid::new_v4());
+        let text = format!("See [[ Groceries ]] and [[{id}]].\n[[Not\nthis]] [[]] [[a [[b]]");
+        assert_eq!(
+            parse_note_references(&text),
+            vec![
+                NoteReference::Title("Groceries"),
+                NoteReference::Id(id),
+                NoteReference::Title("b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_written_in_text() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let groceries = store
+            .add_note(NoteBuilder::new().text("# Groceries\nMilk"))?
+            .id;
+        let other = store.add_note(NoteBuilder::new().text("Other"))?.id;
+        let text = format!("Buy [[Groceries]], see [[{other}]] and [[Nothing]]");
+        let note = store.add_note(NoteBuilder::new().text(text))?;
+        assert_eq!(store.get_linked_notes(note.id)?, vec![groceries, other]);
+        assert_eq!(store.get_backlinks(groceries)?, vec![note.id]);
+
+        // Editing the text relinks the note, but keeps the explicit links.
+        assert_eq!(store.link_notes(note.id, other)?, LinkChange::MadeExplicit);
+        store.update_note(note.modify_with(|n| n.text("Nothing to see")))?;
+        assert_eq!(store.get_linked_notes(note.id)?, vec![other]);
+
+        let note = store.get_note(note.id)?;
+        store.update_note(note.modify_with(|n| n.text("[[Groceries]]")))?;
+        store.delete_note(note.id)?;
+        assert_eq!(store.get_backlinks(groceries)?, vec![]);
+        assert_eq!(store.get_backlinks(other)?, vec![]);
+
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
 use chrono::prelude::*;
 use const_format::formatcp;
-use rusqlite::{named_params, params, types::FromSql, Connection, Row, ToSql};
+use rusqlite::{named_params, params, types::FromSql, Connection, OptionalExtension, Row, ToSql};
+use std::collections::BTreeMap;
 use std::rc::Rc;
 use tracing::{debug, instrument, trace};
 use uuid::Uuid;
 
-use crate::data::{search, tfidf};
+use crate::data::{
+    links,
+    retry::{begin_write, commit_write},
+    search, tfidf,
+};
 
 use super::{
     subjects::{subject_list_from_db, SubjectId},
-    Store,
+    ParseIdError, Store,
 };
 
 #[derive(
@@ -30,6 +35,22 @@ impl FromSql for NoteId {
     }
 }
 
+impl std::fmt::Display for NoteId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for NoteId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(NoteId)
+            .map_err(|e| ParseIdError::new("note", s, e))
+    }
+}
+
 #[derive(
     Debug,
     Default,
@@ -50,6 +71,44 @@ pub enum TaskState {
     Done,
 }
 
+/// What a change of task state does to the completion time of the note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoneAtChange {
+    Set,
+    Clear,
+    Keep,
+}
+
+/// The outcome of [`TaskState::transition`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskStateChange {
+    pub state: TaskState,
+    pub done_at: DoneAtChange,
+}
+
+impl TaskState {
+    /// Changes the state to `to`. Becoming done records the completion
+    /// time, and stopping being done clears it.
+    pub fn transition(self, to: TaskState) -> TaskStateChange {
+        let done_at = match (self, to) {
+            (TaskState::Done, TaskState::Done) => DoneAtChange::Keep,
+            (_, TaskState::Done) => DoneAtChange::Set,
+            (TaskState::Done, _) => DoneAtChange::Clear,
+            _ => DoneAtChange::Keep,
+        };
+        TaskStateChange { state: to, done_at }
+    }
+
+    /// The state that "Make Task" and "Make Note" switch to: a note becomes
+    /// an open task and any task becomes a plain note.
+    pub fn toggle_task(self) -> TaskState {
+        match self {
+            TaskState::NotATask => TaskState::Todo,
+            TaskState::Todo | TaskState::Done => TaskState::NotATask,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct NoteData {
     pub rowid: i64,
@@ -60,6 +119,22 @@ pub struct NoteData {
     pub created_at: DateTime<Local>,
     pub modified_at: DateTime<Local>,
     pub done_at: Option<DateTime<Local>>,
+    /// When to show a notification about the note. Cleared once it has been shown.
+    #[serde(default)]
+    pub reminder_at: Option<DateTime<Local>>,
+    /// Whether the note is in the full text index and the term counts.
+    #[serde(default = "indexed_by_default")]
+    pub indexed: bool,
+    /// Listed first on its day and kept in the task list when done.
+    #[serde(default)]
+    pub pinned: bool,
+    /// When the task should be done by.
+    #[serde(default)]
+    pub due_at: Option<DateTime<Local>>,
+}
+
+fn indexed_by_default() -> bool {
+    true
 }
 
 pub type Note = Rc<NoteData>;
@@ -74,6 +149,13 @@ pub struct NoteBuilder {
     pub created_at: Option<DateTime<Local>>,
     pub modified_at: Option<DateTime<Local>>,
     pub done_at: Option<Option<DateTime<Local>>>,
+    pub reminder_at: Option<Option<DateTime<Local>>>,
+    pub indexed: Option<bool>,
+    pub pinned: Option<bool>,
+    pub due_at: Option<Option<DateTime<Local>>>,
+    /// The `modified_at` of the note the edit was made against.
+    /// If set, the edit is refused when the note has changed since.
+    pub based_on: Option<DateTime<Local>>,
 }
 
 impl NoteBuilder {
@@ -106,6 +188,14 @@ impl NoteBuilder {
         self
     }
 
+    pub fn as_task(self) -> Self {
+        self.task_state(TaskState::Todo)
+    }
+
+    pub fn as_done_task(self, done_at: DateTime<Local>) -> Self {
+        self.task_state(TaskState::Done).done_at(Some(done_at))
+    }
+
     pub fn created_at(mut self, time: DateTime<Local>) -> Self {
         self.created_at = Some(time);
         self
@@ -121,10 +211,40 @@ impl NoteBuilder {
         self
     }
 
+    pub fn reminder_at(mut self, time: Option<DateTime<Local>>) -> Self {
+        self.reminder_at = Some(time);
+        self
+    }
+
+    /// Keeps the note out of search and similar note suggestions when `false`.
+    pub fn indexed(mut self, indexed: bool) -> Self {
+        self.indexed = Some(indexed);
+        self
+    }
+
+    pub fn pinned(mut self, pinned: bool) -> Self {
+        self.pinned = Some(pinned);
+        self
+    }
+
+    pub fn due_at(mut self, time: Option<DateTime<Local>>) -> Self {
+        self.due_at = Some(time);
+        self
+    }
+
+    pub fn based_on(mut self, modified_at: DateTime<Local>) -> Self {
+        self.based_on = Some(modified_at);
+        self
+    }
+
     pub fn modified_now(self) -> Self {
         self.modified_at(Local::now())
     }
 
+    /// Applies the set fields to `note`.
+    ///
+    /// Unless `done_at` is set explicitly, it's set to now when the note
+    /// becomes done and cleared when it stops being done.
     pub fn apply_to_note_inplace(self, note: &mut NoteData) {
         note.rowid = self.rowid.unwrap_or(note.rowid);
         note.id = self.id.unwrap_or(note.id);
@@ -134,10 +254,22 @@ impl NoteBuilder {
         if let Some(subjects) = self.subjects {
             note.subjects = subjects;
         }
-        note.task_state = self.task_state.unwrap_or(note.task_state);
+        let change = note
+            .task_state
+            .transition(self.task_state.unwrap_or(note.task_state));
+        note.task_state = change.state;
         note.created_at = self.created_at.unwrap_or(note.created_at);
         note.modified_at = self.modified_at.unwrap_or(note.modified_at);
-        note.done_at = self.done_at.unwrap_or(note.done_at);
+        note.indexed = self.indexed.unwrap_or(note.indexed);
+        note.pinned = self.pinned.unwrap_or(note.pinned);
+        note.due_at = self.due_at.unwrap_or(note.due_at);
+        note.reminder_at = self.reminder_at.unwrap_or(note.reminder_at);
+        note.done_at = match (self.done_at, change.done_at) {
+            (Some(done_at), _) => done_at,
+            (None, DoneAtChange::Set) => Some(Local::now()),
+            (None, DoneAtChange::Clear) => None,
+            (None, DoneAtChange::Keep) => note.done_at,
+        };
     }
 
     pub fn apply_to_note(self, note: &NoteData) -> NoteData {
@@ -148,19 +280,56 @@ impl NoteBuilder {
 
     pub fn build(self) -> NoteData {
         let now = Local::now();
+        let change = TaskState::default().transition(self.task_state.unwrap_or_default());
+        let task_state = change.state;
+        let done_at = match (self.done_at, change.done_at) {
+            (Some(done_at), _) => done_at,
+            (None, DoneAtChange::Set) => Some(now),
+            (None, _) => None,
+        };
         NoteData {
             rowid: self.rowid.unwrap_or_default(),
             id: self.id.unwrap_or_else(|| NoteId(Uuid::new_v4())),
             text: self.text.unwrap_or_default(),
             subjects: self.subjects.unwrap_or_default(),
-            task_state: self.task_state.unwrap_or_default(),
+            task_state,
             created_at: self.created_at.unwrap_or(now),
             modified_at: self.modified_at.unwrap_or(now),
-            done_at: self.done_at.flatten(),
+            done_at,
+            reminder_at: self.reminder_at.flatten(),
+            indexed: self.indexed.unwrap_or(true),
+            pinned: self.pinned.unwrap_or_default(),
+            due_at: self.due_at.flatten(),
         }
     }
 }
 
+/// Moves `time` to `date`, keeping the time of day. Falls back to the start
+/// of the day when that time doesn't exist on the date, e.g. due to DST.
+pub fn on_date(time: DateTime<Local>, date: NaiveDate) -> DateTime<Local> {
+    let on_date = |naive: NaiveDateTime| Local.from_local_datetime(&naive).earliest();
+    on_date(date.and_time(time.time()))
+        .or_else(|| on_date(date.and_hms_opt(0, 0, 0)?))
+        .unwrap_or(time)
+}
+
+/// The quick choices for bumping a note forward from `now`.
+pub fn bump_presets(now: DateTime<Local>) -> Vec<(&'static str, DateTime<Local>)> {
+    let today = now.date_naive();
+    let days_to_monday = 7 - today.weekday().num_days_from_monday() as u64;
+    [
+        ("Today", Some(today)),
+        ("Tomorrow", today.checked_add_days(chrono::Days::new(1))),
+        (
+            "Next Monday",
+            today.checked_add_days(chrono::Days::new(days_to_monday)),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(label, date)| Some((label, on_date(now, date?))))
+    .collect()
+}
+
 #[cfg(test)]
 impl NoteBuilder {
     pub fn id(&self) -> NoteId {
@@ -178,10 +347,83 @@ impl NoteBuilder {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+/// How the subjects of a [`NoteSearch`] are combined.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum SubjectMatch {
+    /// Notes that have every one of the subjects.
+    #[default]
+    All,
+    /// Notes that have at least one of the subjects.
+    Any,
+}
+
+/// A page of notes found by [`Store::find_notes_page`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotePage {
+    pub notes: Vec<NoteId>,
+    /// Whether more notes follow the page.
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct NoteSearch {
     pub subject_id: Option<SubjectId>,
+    /// Further subjects, combined with `subject_id` as `subject_match` says.
+    /// Only applies when `subject_id` is set.
+    pub also_subjects: Vec<SubjectId>,
+    pub subject_match: SubjectMatch,
     pub task_only: bool,
+    /// Maximum number of notes returned.
+    pub page_size: usize,
+    /// Leaves out tasks completed before this time. Only applies to task searches.
+    pub hide_done_before: Option<DateTime<Local>>,
+    /// Leaves out notes with any of these subjects.
+    pub exclude_subjects: Vec<SubjectId>,
+    /// Leaves out tasks, done or not.
+    pub exclude_tasks: bool,
+    /// Leaves out done tasks.
+    pub exclude_done: bool,
+    /// Leaves out notes without subjects.
+    pub exclude_untagged: bool,
+    /// Only lists notes created before this time, for paging back from it.
+    pub created_before: Option<DateTime<Local>>,
+    /// Skips this many notes, for listing the pages after the first.
+    pub page_offset: usize,
+    /// Lists the tasks of each state by due date, soonest first and those
+    /// without one last. Only applies to task searches.
+    pub sort_by_due: bool,
+}
+
+impl Default for NoteSearch {
+    fn default() -> Self {
+        Self {
+            subject_id: None,
+            also_subjects: Vec::new(),
+            subject_match: SubjectMatch::All,
+            task_only: false,
+            page_size: DEFAULT_PAGE_SIZE,
+            hide_done_before: None,
+            exclude_subjects: Vec::new(),
+            exclude_tasks: false,
+            exclude_done: false,
+            exclude_untagged: false,
+            created_before: None,
+            page_offset: 0,
+            sort_by_due: false,
+        }
+    }
 }
 
 impl NoteSearch {
@@ -189,6 +431,17 @@ impl NoteSearch {
         Self::default()
     }
 
+    pub fn page_size(self, page_size: usize) -> Self {
+        Self { page_size, ..self }
+    }
+
+    pub fn page_offset(self, page_offset: usize) -> Self {
+        Self {
+            page_offset,
+            ..self
+        }
+    }
+
     pub fn subject(self, subject_id: SubjectId) -> Self {
         Self {
             subject_id: Some(subject_id),
@@ -200,9 +453,103 @@ impl NoteSearch {
         Self { subject_id, ..self }
     }
 
+    pub fn also_subjects(self, also_subjects: Vec<SubjectId>) -> Self {
+        Self {
+            also_subjects,
+            ..self
+        }
+    }
+
+    pub fn subject_match(self, subject_match: SubjectMatch) -> Self {
+        Self {
+            subject_match,
+            ..self
+        }
+    }
+
+    /// Only finds notes that have every one of `subjects`.
+    pub fn all_subjects(self, subjects: &[SubjectId]) -> Self {
+        self.subjects(subjects, SubjectMatch::All)
+    }
+
+    /// Only finds notes that have at least one of `subjects`.
+    pub fn any_subjects(self, subjects: &[SubjectId]) -> Self {
+        self.subjects(subjects, SubjectMatch::Any)
+    }
+
+    fn subjects(self, subjects: &[SubjectId], subject_match: SubjectMatch) -> Self {
+        let (subject_id, also_subjects) = match subjects.split_first() {
+            Some((first, rest)) => (Some(*first), rest.to_vec()),
+            None => (None, Vec::new()),
+        };
+        Self {
+            subject_id,
+            also_subjects,
+            subject_match,
+            ..self
+        }
+    }
+
     pub fn task_only(self, task_only: bool) -> Self {
         Self { task_only, ..self }
     }
+
+    pub fn hide_done_before(self, hide_done_before: Option<DateTime<Local>>) -> Self {
+        Self {
+            hide_done_before,
+            ..self
+        }
+    }
+
+    pub fn exclude_subjects(self, exclude_subjects: Vec<SubjectId>) -> Self {
+        Self {
+            exclude_subjects,
+            ..self
+        }
+    }
+
+    pub fn exclude_tasks(self, exclude_tasks: bool) -> Self {
+        Self {
+            exclude_tasks,
+            ..self
+        }
+    }
+
+    pub fn exclude_done(self, exclude_done: bool) -> Self {
+        Self {
+            exclude_done,
+            ..self
+        }
+    }
+
+    pub fn exclude_untagged(self, exclude_untagged: bool) -> Self {
+        Self {
+            exclude_untagged,
+            ..self
+        }
+    }
+
+    pub fn created_before(self, created_before: Option<DateTime<Local>>) -> Self {
+        Self {
+            created_before,
+            ..self
+        }
+    }
+
+    pub fn sort_by_due(self, sort_by_due: bool) -> Self {
+        Self {
+            sort_by_due,
+            ..self
+        }
+    }
+
+    /// Whether any of the exclusion filters are set.
+    pub fn has_exclusions(&self) -> bool {
+        !self.exclude_subjects.is_empty()
+            || self.exclude_tasks
+            || self.exclude_done
+            || self.exclude_untagged
+    }
 }
 
 impl ToSql for TaskState {
@@ -245,81 +592,208 @@ impl NoteData {
             created_at: Some(self.created_at),
             modified_at: Some(self.modified_at),
             done_at: Some(self.done_at),
+            reminder_at: Some(self.reminder_at),
+            indexed: Some(self.indexed),
+            pinned: Some(self.pinned),
+            due_at: Some(self.due_at),
+            based_on: None,
         }
     }
 
     pub fn modify_with(&self, f: impl FnOnce(NoteBuilder) -> NoteBuilder) -> NoteData {
         f(NoteBuilder::new()).apply_to_note(self)
     }
+
+    /// Whether the note is a task, done or not.
+    pub fn is_task(&self) -> bool {
+        self.task_state != TaskState::NotATask
+    }
+
+    /// Whether the note is a task that hasn't been done yet.
+    pub fn is_open_task(&self) -> bool {
+        self.task_state == TaskState::Todo
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.task_state == TaskState::Done
+    }
+
+    /// Whether the note is an open task that was due before `now`.
+    pub fn is_overdue(&self, now: DateTime<Local>) -> bool {
+        self.is_open_task() && self.due_at.is_some_and(|due_at| due_at < now)
+    }
 }
 
 impl Store {
     #[instrument(skip(self))]
     pub fn add_note(&self, note: NoteBuilder) -> rusqlite::Result<Note> {
         debug!("Adding note");
-        let mut conn = self.conn.borrow_mut();
-        let tx = conn.transaction()?;
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
         let note = self.add_note_with_tx(&tx, note.build())?;
-        tx.commit()?;
+        commit_write(tx)?;
         Ok(note)
     }
 
+    /// Like [`Store::add_note`], but if a note with the same text and subjects
+    /// was created within `window` of the new note, returns that note instead.
+    /// The flag tells whether the note was created.
+    ///
+    /// Meant for scripts, so that running one twice doesn't capture everything twice.
+    #[instrument(skip(self))]
+    pub fn add_note_dedup(
+        &self,
+        note: NoteBuilder,
+        window: chrono::Duration,
+    ) -> rusqlite::Result<(Note, bool)> {
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let note = note.build();
+
+        let mut subjects = note.subjects.clone();
+        subjects.sort();
+        let candidates = tx
+            .prepare_cached(DUPLICATE_CANDIDATES)?
+            .query_map(
+                params![
+                    note.text,
+                    (note.created_at - window).naive_utc().timestamp_nanos(),
+                    (note.created_at + window).naive_utc().timestamp_nanos(),
+                ],
+                map_row_to_note,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let duplicate = candidates.into_iter().find(|candidate| {
+            let mut candidate_subjects = candidate.subjects.clone();
+            candidate_subjects.sort();
+            candidate_subjects == subjects
+        });
+        if let Some(duplicate) = duplicate {
+            debug!("Skipping duplicate of note {}", duplicate.id.0);
+            return Ok((duplicate, false));
+        }
+
+        debug!("Adding note");
+        let note = self.add_note_with_tx(&tx, note)?;
+        commit_write(tx)?;
+        Ok((note, true))
+    }
+
     pub fn add_note_with_tx(&self, tx: &Connection, note: NoteData) -> rusqlite::Result<Note> {
-        let mut note = note;
-        tx.prepare_cached(
-            "INSERT INTO notes (
-                    id,
-                    text,
-                    task_state,
-                    created_at,
-                    modified_at,
-                    done_at
-                )
-                VALUES (
-                    :id,
-                    :text,
-                    :task_state,
-                    :created_at,
-                    :modified_at,
-                    :done_at
-                )",
-        )?
-        .execute(named_params! {
-            ":id": &note.id,
-            ":text": &note.text,
-            ":task_state": &note.task_state,
-            ":created_at": &note.created_at.naive_utc().timestamp_nanos(),
-            ":modified_at": &note.modified_at.naive_utc().timestamp_nanos(),
-            ":done_at": &note.done_at.map(|t| t.naive_utc().timestamp_nanos()),
-        })?;
+        let note = Rc::new(insert_note_rows(tx, note)?);
+        links::insert_text_links(tx, note.id, &note.text)?;
+
+        if note.indexed {
+            tfidf::insert_word_occurences(tx, &note.text)?;
+            let subject_names = self.indexed_subject_names(tx, note.id)?;
+            search::tantivy_add_note(
+                &mut self.index_writer.change(),
+                &note,
+                subject_names.as_deref(),
+            )
+            .unwrap();
+        }
 
-        note.rowid = tx.last_insert_rowid();
+        Ok(note)
+    }
 
-        let subjects = subjects_or_nil(&note.subjects);
+    /// The subject names to index with the note, if they're indexed at all.
+    fn indexed_subject_names(
+        &self,
+        conn: &Connection,
+        id: NoteId,
+    ) -> rusqlite::Result<Option<String>> {
+        if !self.index_subject_names() {
+            return Ok(None);
+        }
+        conn.prepare_cached(search::NOTE_SUBJECT_NAMES)?
+            .query_row(params![id], |row| row.get(0))
+    }
 
-        for subject in subjects {
-            tx.prepare_cached("INSERT INTO notes_subjects (note_id, subject_id) VALUES (?1, ?2)")?
-                .execute(params![note.id, subject.0])?;
+    /// Indexes `notes` again after their subjects changed, if subject names
+    /// are indexed.
+    pub(super) fn reindex_subject_names(&self, notes: &[NoteId]) -> rusqlite::Result<()> {
+        if !self.index_subject_names() {
+            return Ok(());
         }
+        let conn = self.conn.borrow();
+        let mut writer = self.index_writer.change();
+        for &id in notes {
+            let note = self.get_note(id)?;
+            if !note.indexed {
+                continue;
+            }
+            let subject_names = self.indexed_subject_names(&conn, id)?;
+            search::tantivy_remove_note(&mut writer, note.rowid).unwrap();
+            search::tantivy_add_note(&mut writer, &note, subject_names.as_deref()).unwrap();
+        }
+        Ok(())
+    }
 
-        tfidf::insert_word_occurences(tx, &note.text)?;
+    /// Inserts a note from an export, preserving its id, timestamps and task state.
+    /// Links to the note's subjects are restored from `note.subjects`.
+    ///
+    /// Does not open a transaction of its own so that an import can wrap
+    /// any number of notes in a single one.
+    #[instrument(skip(self, note), fields(id = %note.id.0))]
+    pub fn import_note(&self, note: &NoteData) -> rusqlite::Result<Note> {
+        debug!("Importing note");
+        let conn = self.conn.borrow();
+        self.add_note_with_tx(&conn, note.clone())
+    }
+
+    /// Like [`Store::import_note`], but leaves the term counts and the full
+    /// text index untouched. Meant for bulk imports, which rebuild both once
+    /// at the end with [`Store::rebuild_search_index`].
+    pub fn import_note_rows(&self, note: &NoteData) -> rusqlite::Result<()> {
+        let conn = self.conn.borrow();
+        insert_note_rows(&conn, note.clone())?;
+        Ok(())
+    }
 
-        let note = Rc::new(note);
-        search::tantivy_add_note(&mut self.index_writer.borrow_mut(), &note).unwrap();
+    /// Like [`Store::import_note_rows`], but replaces a note with the same id.
+    pub fn merge_note_rows(&self, note: &NoteData) -> rusqlite::Result<()> {
+        let conn = self.conn.borrow();
+        let exists = conn
+            .prepare_cached("SELECT 1 FROM notes WHERE id = ?1")?
+            .exists(params![note.id.0])?;
+        if exists {
+            delete_note_rows(&conn, note.id)?;
+        }
+        insert_note_rows(&conn, note.clone())?;
+        Ok(())
+    }
 
-        Ok(note)
+    /// Recounts the term occurrences and rebuilds the full text index from
+    /// all notes in the database.
+    #[instrument(skip(self))]
+    pub fn rebuild_search_index(&self) -> rusqlite::Result<()> {
+        debug!("Rebuilding search index");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        tfidf::fill_word_occurence_table(&tx)?;
+        commit_write(tx)?;
+
+        search::fill_tantivy_index(
+            &mut self.index_writer.change(),
+            &conn,
+            self.index_subject_names(),
+        );
+        Ok(())
     }
 
+    /// Replaces the stored note with `note`. The subject links are rewritten
+    /// too, so a note left without subjects is linked to the nil subject again.
     #[instrument(skip(self))]
     pub fn update_note(&self, note: NoteData) -> rusqlite::Result<()> {
         debug!("Updating note");
-        let mut conn = self.conn.borrow_mut();
-        let tx = conn.transaction()?;
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
 
         self.delete_note_with_tx(&tx, note.id)?;
         self.add_note_with_tx(&tx, note)?;
 
-        tx.commit()?;
+        commit_write(tx)?;
 
         Ok(())
     }
@@ -327,40 +801,166 @@ impl Store {
     #[instrument(skip(self))]
     pub fn delete_note(&self, note: NoteId) -> rusqlite::Result<()> {
         debug!("Deleting note");
-        let mut conn = self.conn.borrow_mut();
-        let tx = conn.transaction()?;
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
 
         self.delete_note_with_tx(&tx, note)?;
+        // Not in `delete_note_with_tx`, which edits also go through.
+        links::delete_note_links(&tx, note)?;
+
+        commit_write(tx)?;
+
+        Ok(())
+    }
 
-        tx.commit()?;
+    /// Replaces every note in `notes` with its new version in a single transaction.
+    #[instrument(skip(self, notes), fields(count = notes.len()))]
+    pub fn update_notes(&self, notes: Vec<NoteData>) -> rusqlite::Result<()> {
+        debug!("Updating notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+
+        for note in notes {
+            self.delete_note_with_tx(&tx, note.id)?;
+            self.add_note_with_tx(&tx, note)?;
+        }
 
+        commit_write(tx)?;
         Ok(())
     }
 
-    fn delete_note_with_tx(&self, tx: &Connection, note: NoteId) -> rusqlite::Result<()> {
-        let (rowid, old_text) = tx
+    /// The done tasks with `subject` or any of its descendants, like in the
+    /// task list, or all done tasks if `subject` is `None`. Oldest first.
+    pub fn find_done_tasks(&self, subject: Option<SubjectId>) -> rusqlite::Result<Vec<NoteId>> {
+        let conn = self.conn.borrow();
+        let Some(subject) = subject else {
+            return conn
+                .prepare_cached("SELECT id FROM notes WHERE task_state = ?1 ORDER BY created_at")?
+                .query_map(params![TaskState::Done], |row| row.get(0))?
+                .collect();
+        };
+        let notes = conn
             .prepare_cached(
-                "SELECT rowid, text FROM notes
-                    WHERE id = ?1",
+                "WITH RECURSIVE subtree(id) AS (
+                SELECT ?1
+                UNION SELECT s.id FROM subjects s JOIN subtree t ON s.parent_id = t.id
+            )
+            SELECT note_id FROM notes_search
+                WHERE subject_id IN subtree AND task_state = ?2
+                GROUP BY note_id
+                ORDER BY MIN(created_at)",
             )?
-            .query_row(params![note.0], |row| {
-                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
-            })?;
+            .query_map(params![subject, TaskState::Done], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(notes)
+    }
 
-        tfidf::remove_word_occurences(tx, &old_text)?;
-        search::tantivy_remove_note(&mut self.index_writer.borrow_mut(), rowid).unwrap();
+    /// Links `subject` to every note in `notes` in a single transaction.
+    /// Returns the notes that didn't already have the subject.
+    #[instrument(skip(self, notes), fields(count = notes.len()))]
+    pub fn add_subject_to_notes(
+        &self,
+        subject: SubjectId,
+        notes: &[NoteId],
+    ) -> rusqlite::Result<Vec<NoteId>> {
+        debug!("Adding subject to notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+
+        let mut changed = Vec::new();
+        for &note in notes {
+            let inserted = tx
+                .prepare_cached(
+                    "INSERT OR IGNORE INTO notes_subjects (note_id, subject_id)
+                    VALUES (?1, ?2)",
+                )?
+                .execute(params![note.0, subject.0])?;
+            if inserted == 0 {
+                continue;
+            }
+            tx.prepare_cached(
+                "DELETE FROM notes_subjects
+                    WHERE note_id = ?1 AND subject_id = ?2",
+            )?
+            .execute(params![note.0, Uuid::nil()])?;
+            changed.push(note);
+        }
 
-        tx.prepare_cached(
-            "DELETE FROM notes_subjects
-                WHERE note_id = ?1",
-        )?
-        .execute(params![note.0])?;
+        commit_write(tx)?;
+        self.reindex_subject_names(&changed)?;
+        Ok(changed)
+    }
 
-        tx.prepare_cached(
-            "DELETE FROM notes
-                WHERE id = ?1",
-        )?
-        .execute(params![note.0])?;
+    /// Unlinks `subject` from every note in `notes` in a single transaction.
+    /// Notes left without subjects are linked to the nil subject, like new notes.
+    #[instrument(skip(self, notes), fields(count = notes.len()))]
+    pub fn remove_subject_from_notes(
+        &self,
+        subject: SubjectId,
+        notes: &[NoteId],
+    ) -> rusqlite::Result<()> {
+        debug!("Removing subject from notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+
+        for &note in notes {
+            tx.prepare_cached(
+                "DELETE FROM notes_subjects
+                    WHERE note_id = ?1 AND subject_id = ?2",
+            )?
+            .execute(params![note.0, subject.0])?;
+            tx.prepare_cached(
+                "INSERT INTO notes_subjects (note_id, subject_id)
+                    SELECT ?1, ?2
+                    WHERE NOT EXISTS (SELECT 1 FROM notes_subjects WHERE note_id = ?1)",
+            )?
+            .execute(params![note.0, Uuid::nil()])?;
+        }
+
+        commit_write(tx)?;
+        self.reindex_subject_names(notes)?;
+        Ok(())
+    }
+
+    pub(super) fn delete_note_with_tx(
+        &self,
+        tx: &Connection,
+        note: NoteId,
+    ) -> rusqlite::Result<()> {
+        let (rowid, indexed_text) = delete_note_rows(tx, note)?;
+        // The links written in the text are added again if the note is rewritten.
+        links::delete_text_links(tx, note)?;
+
+        if let Some(old_text) = indexed_text {
+            tfidf::remove_word_occurences(tx, &old_text)?;
+            search::tantivy_remove_note(&mut self.index_writer.change(), rowid).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Deletes all of `notes` in a single transaction. The term counts and
+    /// the full text index are updated once for the whole batch.
+    #[instrument(skip(self, notes), fields(count = notes.len()))]
+    pub fn delete_notes(&self, notes: &[NoteId]) -> rusqlite::Result<()> {
+        debug!("Deleting notes");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+
+        let mut rowids = Vec::with_capacity(notes.len());
+        let mut texts = Vec::with_capacity(notes.len());
+        for &note in notes {
+            if let (rowid, Some(text)) = delete_note_rows(&tx, note)? {
+                rowids.push(rowid);
+                texts.push(text);
+            }
+            links::delete_note_links(&tx, note)?;
+        }
+        tfidf::remove_word_occurences_batch(&tx, texts.iter().map(String::as_str))?;
+
+        commit_write(tx)?;
+
+        search::tantivy_remove_notes(&mut self.index_writer.change(), &rowids).unwrap();
 
         Ok(())
     }
@@ -370,19 +970,43 @@ impl Store {
         debug!("Begin");
 
         let conn = self.conn.borrow();
-        let notes = match query {
-            NoteSearch {
+        let page_size = query.page_size;
+        let notes = match &query {
+            &NoteSearch {
                 subject_id: subject,
+                ref also_subjects,
+                subject_match,
                 task_only: true,
-            } => tasks_search_by_subject(&conn, subject)?,
-            NoteSearch {
+                hide_done_before,
+                ..
+            } => tasks_search_by_subject(
+                &conn,
+                subject,
+                also_subjects,
+                subject_match,
+                hide_done_before,
+                page_size,
+                &query,
+            )?,
+            &NoteSearch {
                 subject_id: Some(subject),
+                ref also_subjects,
+                subject_match,
                 task_only: false,
-            } => notes_search_by_subject(&conn, subject)?,
+                ..
+            } => notes_search_by_subject(
+                &conn,
+                subject,
+                also_subjects,
+                subject_match,
+                page_size,
+                &query,
+            )?,
             NoteSearch {
                 subject_id: None,
                 task_only: false,
-            } => notes_list_all(&conn)?,
+                ..
+            } => notes_list_all(&conn, page_size, &query)?,
         };
 
         // Assert notes are unique
@@ -395,6 +1019,16 @@ impl Store {
         Ok(notes)
     }
 
+    /// Like [`Store::find_notes`], also telling whether there are notes after
+    /// the page. Fetch them with [`NoteSearch::page_offset`].
+    pub fn find_notes_page(&self, query: NoteSearch) -> rusqlite::Result<NotePage> {
+        let page_size = query.page_size;
+        let mut notes = self.find_notes(query.page_size(page_size.saturating_add(1)))?;
+        let has_more = notes.len() > page_size;
+        notes.truncate(page_size);
+        Ok(NotePage { notes, has_more })
+    }
+
     pub fn get_note(&self, note: NoteId) -> rusqlite::Result<Note> {
         trace!("Getting note {}", note.0);
         let conn = self.conn.borrow();
@@ -409,6 +1043,68 @@ impl Store {
         Ok(notes)
     }
 
+    /// Returns when the note was last modified, or `None` if it doesn't exist.
+    pub fn get_note_modified_at(&self, note: NoteId) -> rusqlite::Result<Option<DateTime<Local>>> {
+        let conn = self.conn.borrow();
+        let modified_at = conn
+            .prepare_cached("SELECT modified_at FROM notes WHERE id = ?1")?
+            .query_row(params![note.0], |row| row.get::<_, i64>(0))
+            .optional()?;
+        Ok(modified_at.map(|ts| Local.timestamp_nanos(ts)))
+    }
+
+    /// The notes with a reminder due at `now`, earliest first. Reminders
+    /// missed while the app was closed are due too. They stay due until
+    /// cleared with [`Store::clear_reminder`] once shown.
+    pub fn get_due_reminders(&self, now: DateTime<Local>) -> rusqlite::Result<Vec<Note>> {
+        let now = now.naive_utc().timestamp_nanos();
+        self.conn
+            .borrow()
+            .prepare_cached(formatcp!(
+                r#"SELECT {columns}
+                    FROM notes n
+                    WHERE n.reminder_at <= ?1
+                    ORDER BY n.reminder_at"#,
+                columns = SINGLE_NOTE_COLUMNS
+            ))?
+            .query_map(params![now], map_row_to_note)?
+            .collect()
+    }
+
+    /// Clears the reminder of `note` due at `at`, so that it's shown once.
+    /// A reminder moved to another time in the meantime is kept.
+    /// Returns whether the reminder was cleared.
+    #[instrument(skip(self))]
+    pub fn clear_reminder(&self, note: NoteId, at: DateTime<Local>) -> rusqlite::Result<bool> {
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let cleared = tx
+            .prepare_cached(
+                "UPDATE notes SET reminder_at = NULL WHERE id = ?1 AND reminder_at = ?2",
+            )?
+            .execute(params![note, at.naive_utc().timestamp_nanos()])?;
+        commit_write(tx)?;
+        Ok(cleared > 0)
+    }
+
+    /// Whether `note` exists, without reading it.
+    pub fn note_exists(&self, note: NoteId) -> rusqlite::Result<bool> {
+        self.conn
+            .borrow()
+            .prepare_cached("SELECT 1 FROM notes WHERE id = ?1 LIMIT 1")?
+            .exists(params![note])
+    }
+
+    /// The subjects of `note`, without reading the rest of it.
+    /// Empty if the note doesn't exist.
+    pub fn get_note_subjects(&self, note: NoteId) -> rusqlite::Result<Vec<SubjectId>> {
+        self.conn
+            .borrow()
+            .prepare_cached("SELECT subject_id FROM notes_subjects WHERE note_id = ?1")?
+            .query_map(params![note], |row| row.get(0))?
+            .collect()
+    }
+
     pub fn get_notes(&self, notes: &[NoteId]) -> rusqlite::Result<Vec<Note>> {
         notes.iter().map(|note| self.get_note(*note)).collect()
     }
@@ -427,6 +1123,17 @@ impl Store {
         Ok(notes)
     }
 
+    /// Returns every task regardless of subject, undone first and newest first.
+    /// Each note appears once, even if it has several subjects.
+    pub fn get_all_tasks(&self) -> rusqlite::Result<Vec<Note>> {
+        let conn = self.conn.borrow();
+        let notes = conn
+            .prepare_cached(ALL_TASKS)?
+            .query_map(params![], map_row_to_note)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(notes)
+    }
+
     pub fn subject_note_count(&self, subject: SubjectId) -> rusqlite::Result<u64> {
         let conn = self.conn.borrow();
         let count = conn
@@ -437,9 +1144,61 @@ impl Store {
             .query_row(params![subject], |row| row.get::<_, u64>(0))?;
         Ok(count)
     }
+
+    /// When the first and last notes with `subject`, or any notes at all, were
+    /// created. Returns `None` if there are no such notes.
+    pub fn note_date_range(
+        &self,
+        subject: Option<SubjectId>,
+    ) -> rusqlite::Result<Option<(DateTime<Local>, DateTime<Local>)>> {
+        // Separate subqueries, as SQLite only reads MIN or MAX alone from an index.
+        let conn = self.conn.borrow();
+        let mut stmt = match subject {
+            Some(_) => conn.prepare_cached(SUBJECT_NOTE_DATE_RANGE)?,
+            None => conn.prepare_cached(NOTE_DATE_RANGE)?,
+        };
+        let params = match &subject {
+            Some(subject) => vec![subject as &dyn ToSql],
+            None => Vec::new(),
+        };
+        let range = stmt.query_row(params.as_slice(), |row| {
+            Ok(row.get::<_, Option<i64>>(0)?.zip(row.get(1)?))
+        })?;
+        Ok(range.map(|(first, last)| (Local.timestamp_nanos(first), Local.timestamp_nanos(last))))
+    }
+
+    /// How many notes were created on each day from `from` to `to`, both
+    /// included. Days without notes are left out.
+    pub fn notes_per_day(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> rusqlite::Result<BTreeMap<NaiveDate, u32>> {
+        let start_of = |day: NaiveDate| {
+            Local
+                .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+                .earliest()
+                .map_or(i64::MIN, |time| time.naive_utc().timestamp_nanos())
+        };
+        let end = to.succ_opt().map_or(i64::MAX, start_of);
+
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare_cached(NOTES_PER_DAY)?;
+        let rows = stmt.query_map(params![start_of(from), end], |row| {
+            Ok((row.get::<_, i32>(0)?, row.get::<_, u32>(1)?))
+        })?;
+        let mut counts = BTreeMap::new();
+        for row in rows {
+            let (day, count) = row?;
+            if let Some(day) = NaiveDate::from_num_days_from_ce_opt(day) {
+                counts.insert(day, count);
+            }
+        }
+        Ok(counts)
+    }
 }
 
-const PAGE_SIZE: usize = 200;
+const DEFAULT_PAGE_SIZE: usize = 200;
 
 pub const SINGLE_NOTE_COLUMNS: &str = "
     n.rowid,
@@ -450,85 +1209,363 @@ pub const SINGLE_NOTE_COLUMNS: &str = "
     n.task_state,
     n.created_at,
     n.modified_at,
-    n.done_at
+    n.done_at,
+    n.indexed,
+    n.reminder_at,
+    n.pinned,
+    n.due_at
 ";
 
-const NOTE_LIST_ALL: &str = formatcp!(
-    r#"SELECT DISTINCT s.note_id
+// The page size is always bound to ?1.
+const NOTE_LIST_ALL: &str = r#"SELECT DISTINCT s.note_id
     FROM notes_search s
     ORDER BY s.created_at DESC
-    LIMIT {page}"#,
-    page = PAGE_SIZE
-);
+    LIMIT ?1"#;
 
-const NOTE_SEARCH_BY_SUBJECT: &str = formatcp!(
-    r#"SELECT s.note_id
+const NOTE_SEARCH_BY_SUBJECT: &str = r#"SELECT s.note_id
     FROM notes_search s
-    WHERE s.subject_id = ?1
+    WHERE s.subject_id = ?2
     ORDER BY s.created_at DESC
-    LIMIT {page}"#,
-    page = PAGE_SIZE
+    LIMIT ?1"#;
+
+pub const ALL_TASKS: &str = formatcp!(
+    r#"SELECT {columns}
+    FROM notes n
+    WHERE n.id IN (
+        SELECT s.note_id
+        FROM notes_search s
+        WHERE s.task_state > 0
+    )
+    ORDER BY n.task_state ASC, n.created_at DESC"#,
+    columns = SINGLE_NOTE_COLUMNS
 );
 
-pub fn query_for_search(query: NoteSearch) -> String {
+/// Notes with the text ?1 created between ?2 and ?3.
+pub const DUPLICATE_CANDIDATES: &str = formatcp!(
+    r#"SELECT {columns}
+    FROM notes n
+    WHERE n.id IN (
+        SELECT s.note_id
+        FROM notes_search s
+        WHERE s.created_at BETWEEN ?2 AND ?3
+    )
+    AND n.text = ?1"#,
+    columns = SINGLE_NOTE_COLUMNS
+);
+
+pub const NOTE_DATE_RANGE: &str = "SELECT
+    (SELECT MIN(created_at) FROM notes_search),
+    (SELECT MAX(created_at) FROM notes_search)";
+
+/// Notes created from ?1 until ?2 counted by their local day, see
+/// [`Store::notes_per_day`]. Notes are listed once per subject in `notes_search`.
+const NOTES_PER_DAY: &str = "SELECT local_day(created_at) AS day, COUNT(DISTINCT note_id)
+    FROM notes_search
+    WHERE created_at >= ?1 AND created_at < ?2
+    GROUP BY day";
+
+/// Like [`NOTE_DATE_RANGE`], for the notes with the subject ?1.
+pub const SUBJECT_NOTE_DATE_RANGE: &str = "SELECT
+    (SELECT MIN(created_at) FROM notes_search WHERE subject_id = ?1),
+    (SELECT MAX(created_at) FROM notes_search WHERE subject_id = ?1)";
+
+/// Skips the notes before the page, in a query ending in its `LIMIT`.
+/// A bare `?` is numbered after every parameter before it, so the offset
+/// is always bound last.
+fn with_offset(query: String) -> String {
+    format!("{query} OFFSET ?")
+}
+
+pub fn query_for_search(query: &NoteSearch) -> String {
     match query {
         NoteSearch {
             subject_id,
+            also_subjects,
+            subject_match,
             task_only: true,
-        } => tasks_query(subject_id),
+            hide_done_before,
+            ..
+        } => tasks_query(
+            *subject_id,
+            also_subjects.len(),
+            *subject_match,
+            *hide_done_before,
+            query,
+        ),
         NoteSearch {
             subject_id: Some(_),
+            also_subjects,
+            subject_match,
             task_only: false,
-        } => NOTE_SEARCH_BY_SUBJECT.to_owned(),
+            ..
+        } => notes_by_subject_query(also_subjects.len(), *subject_match, query),
         NoteSearch {
             subject_id: None,
             task_only: false,
-        } => NOTE_LIST_ALL.to_owned(),
+            ..
+        } => notes_list_all_query(query),
     }
 }
 
-fn notes_list_all(conn: &Connection) -> rusqlite::Result<Vec<NoteId>> {
-    conn.prepare_cached(NOTE_LIST_ALL)?
-        .query_map(params![], |row| row.get(0))?
+/// A condition on `table` leaving out the notes `exclusions` excludes or
+/// that were created after its cutoff, or `None` if there is nothing to leave
+/// out. Parameters are bound from `?first_param` on, see [`exclusion_params`].
+fn exclusions_condition(
+    table: &str,
+    exclusions: &NoteSearch,
+    first_param: usize,
+) -> Option<String> {
+    if !exclusions.has_exclusions() && exclusions.created_before.is_none() {
+        return None;
+    }
+    let mut conditions = Vec::new();
+    if !exclusions.exclude_subjects.is_empty() {
+        let params = (first_param..first_param + exclusions.exclude_subjects.len())
+            .map(|param| format!("?{param}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        conditions.push(format!(
+            "{table}.note_id NOT IN (
+                SELECT note_id FROM notes_search WHERE subject_id IN ({params}))"
+        ));
+    }
+    if exclusions.exclude_tasks {
+        conditions.push(format!(
+            "{table}.task_state = {}",
+            TaskState::NotATask.to_db_value()
+        ));
+    }
+    if exclusions.exclude_done {
+        conditions.push(format!(
+            "{table}.task_state != {}",
+            TaskState::Done.to_db_value()
+        ));
+    }
+    if exclusions.exclude_untagged {
+        // Notes without subjects only have a row for the nil subject.
+        conditions.push(format!("{table}.subject_id != zeroblob(16)"));
+    }
+    if exclusions.created_before.is_some() {
+        let param = first_param + exclusions.exclude_subjects.len();
+        conditions.push(format!("{table}.created_at < ?{param}"));
+    }
+    Some(conditions.join(" AND "))
+}
+
+/// The parameters of [`exclusions_condition`] in order: the excluded subjects,
+/// then the cutoff, which the caller converts with [`created_before_param`].
+fn exclusion_params<'a>(
+    exclusions: &'a NoteSearch,
+    created_before: &'a Option<i64>,
+) -> impl Iterator<Item = &'a dyn ToSql> {
+    exclusions
+        .exclude_subjects
+        .iter()
+        .map(|s| s as &dyn ToSql)
+        .chain(created_before.as_ref().map(|t| t as &dyn ToSql))
+}
+
+fn created_before_param(exclusions: &NoteSearch) -> Option<i64> {
+    exclusions
+        .created_before
+        .map(|t| t.naive_utc().timestamp_nanos())
+}
+
+fn notes_list_all_query(exclusions: &NoteSearch) -> String {
+    let Some(condition) = exclusions_condition("s", exclusions, 2) else {
+        return with_offset(NOTE_LIST_ALL.to_owned());
+    };
+    let query = format!(
+        r#"SELECT DISTINCT s.note_id
+        FROM notes_search s
+        WHERE {condition}
+        ORDER BY s.created_at DESC
+        LIMIT ?1"#
+    );
+    with_offset(query)
+}
+
+fn notes_list_all(
+    conn: &Connection,
+    page_size: usize,
+    exclusions: &NoteSearch,
+) -> rusqlite::Result<Vec<NoteId>> {
+    let created_before = created_before_param(exclusions);
+    let mut params: Vec<&dyn ToSql> = vec![&page_size];
+    params.extend(exclusion_params(exclusions, &created_before));
+    params.push(&exclusions.page_offset);
+    conn.prepare_cached(&notes_list_all_query(exclusions))?
+        .query_map(params.as_slice(), |row| row.get(0))?
         .collect()
 }
 
-fn notes_search_by_subject(conn: &Connection, subject: SubjectId) -> rusqlite::Result<Vec<NoteId>> {
-    conn.prepare_cached(NOTE_SEARCH_BY_SUBJECT)?
-        .query_map(params![subject], |row| row.get(0))?
+fn notes_by_subject_query(
+    also_subjects: usize,
+    subject_match: SubjectMatch,
+    search: &NoteSearch,
+) -> String {
+    let exclusions = exclusions_condition("s", search, 3 + also_subjects);
+    if also_subjects == 0 && exclusions.is_none() {
+        return with_offset(NOTE_SEARCH_BY_SUBJECT.to_owned());
+    }
+    let query = format!(
+        r#"SELECT {distinct} s.note_id
+        FROM notes_search s
+        WHERE {condition} {exclusions}
+        ORDER BY s.created_at DESC
+        LIMIT ?1"#,
+        exclusions = exclusions.map_or(String::new(), |c| format!("AND {c}")),
+        // A note with several of the subjects has a row for each.
+        distinct = match subject_match {
+            SubjectMatch::All => "",
+            SubjectMatch::Any => "DISTINCT",
+        },
+        condition = subjects_condition("s", also_subjects, 3, subject_match),
+    );
+    with_offset(query)
+}
+
+/// A condition on `table` for notes with the subject bound to `?2`, combined
+/// with `also_subjects` further subjects bound from `?first_param` on.
+/// `All` intersects the notes of each subject, `Any` uses a single `IN`.
+fn subjects_condition(
+    table: &str,
+    also_subjects: usize,
+    first_param: usize,
+    subject_match: SubjectMatch,
+) -> String {
+    let params = (first_param..first_param + also_subjects).map(|param| format!("?{param}"));
+    match subject_match {
+        _ if also_subjects == 0 => format!("{table}.subject_id = ?2"),
+        SubjectMatch::All => {
+            let selects = params
+                .map(|param| format!("SELECT note_id FROM notes_search WHERE subject_id = {param}"))
+                .collect::<Vec<_>>()
+                .join(" INTERSECT ");
+            format!("{table}.subject_id = ?2 AND {table}.note_id IN ({selects})")
+        }
+        SubjectMatch::Any => {
+            let params = std::iter::once("?2".to_string())
+                .chain(params)
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{table}.subject_id IN ({params})")
+        }
+    }
+}
+
+fn notes_search_by_subject(
+    conn: &Connection,
+    subject: SubjectId,
+    also_subjects: &[SubjectId],
+    subject_match: SubjectMatch,
+    page_size: usize,
+    exclusions: &NoteSearch,
+) -> rusqlite::Result<Vec<NoteId>> {
+    let created_before = created_before_param(exclusions);
+    let mut params: Vec<&dyn ToSql> = vec![&page_size, &subject];
+    params.extend(also_subjects.iter().map(|s| s as &dyn ToSql));
+    params.extend(exclusion_params(exclusions, &created_before));
+    params.push(&exclusions.page_offset);
+    let query = notes_by_subject_query(also_subjects.len(), subject_match, exclusions);
+    conn.prepare_cached(&query)?
+        .query_map(params.as_slice(), |row| row.get(0))?
         .collect()
 }
 
 fn tasks_search_by_subject(
     conn: &Connection,
     subject: Option<SubjectId>,
+    also_subjects: &[SubjectId],
+    subject_match: SubjectMatch,
+    hide_done_before: Option<DateTime<Local>>,
+    page_size: usize,
+    exclusions: &NoteSearch,
 ) -> rusqlite::Result<Vec<NoteId>> {
-    let search = tasks_query(subject);
-    let params1 = params![subject];
-    let params2 = params![];
-    let params = if subject.is_some() { params1 } else { params2 };
+    // Extra subjects only apply together with a subject.
+    let also_subjects = if subject.is_some() {
+        also_subjects
+    } else {
+        &[]
+    };
+    let search = tasks_query(
+        subject,
+        also_subjects.len(),
+        subject_match,
+        hide_done_before,
+        exclusions,
+    );
+    let cutoff = hide_done_before.map(|t| t.naive_utc().timestamp_nanos());
+    let created_before = created_before_param(exclusions);
+    let mut params: Vec<&dyn ToSql> = vec![&page_size];
+    if let Some(subject) = &subject {
+        params.push(subject);
+    }
+    if let Some(cutoff) = &cutoff {
+        params.push(cutoff);
+    }
+    params.extend(also_subjects.iter().map(|s| s as &dyn ToSql));
+    params.extend(exclusion_params(exclusions, &created_before));
+    params.push(&exclusions.page_offset);
     conn.prepare_cached(&search)?
-        .query_map(params, |row| row.get(0))?
+        .query_map(params.as_slice(), |row| row.get(0))?
         .collect()
 }
 
-fn tasks_query(subject: Option<SubjectId>) -> String {
+/// Parameters are numbered in order: the page size, then the subject and
+/// the done cutoff if they are set, the further subjects, the exclusion parameters
+/// and the offset.
+fn tasks_query(
+    subject: Option<SubjectId>,
+    also_subjects: usize,
+    subject_match: SubjectMatch,
+    hide_done_before: Option<DateTime<Local>>,
+    exclusions: &NoteSearch,
+) -> String {
+    let cutoff_param = if subject.is_some() { "?3" } else { "?2" };
+    let also_param = 2 + usize::from(subject.is_some()) + usize::from(hide_done_before.is_some());
+    // Further subjects only apply together with a subject.
+    let also_subjects = if subject.is_some() { also_subjects } else { 0 };
+    let excluded_param = also_param + also_subjects;
     let search = format!(
         r#"SELECT DISTINCT notes_search.note_id
         FROM notes_search
         WHERE notes_search.task_state > 0 
         {subject_clause}
-        ORDER BY notes_search.task_state ASC, notes_search.created_at DESC
-        LIMIT {page}"#,
-        page = PAGE_SIZE,
-        subject_clause = if subject.is_some() {
-            "AND notes_search.subject_id = ?1"
+        {done_clause}
+        {exclusion_clause}
+        ORDER BY notes_search.task_state ASC, {due_order} notes_search.created_at DESC
+        LIMIT ?1"#,
+        due_order = if exclusions.sort_by_due {
+            "(SELECT n.due_at IS NULL FROM notes n WHERE n.id = notes_search.note_id),
+            (SELECT n.due_at FROM notes n WHERE n.id = notes_search.note_id),"
         } else {
             ""
-        }
+        },
+        subject_clause = if subject.is_some() {
+            let condition =
+                subjects_condition("notes_search", also_subjects, also_param, subject_match);
+            format!("AND {condition}")
+        } else {
+            String::new()
+        },
+        done_clause = if hide_done_before.is_some() {
+            // Tasks completed before done_at was recorded count as done
+            // when they were last modified. Pinned tasks are never hidden.
+            format!(
+                "AND (notes_search.task_state != {done}
+                    OR (SELECT n.pinned OR COALESCE(n.done_at, n.modified_at) >= {cutoff_param}
+                        FROM notes n WHERE n.id = notes_search.note_id))",
+                done = TaskState::Done.to_db_value(),
+            )
+        } else {
+            String::new()
+        },
+        exclusion_clause = exclusions_condition("notes_search", exclusions, excluded_param)
+            .map_or(String::new(), |condition| format!("AND {condition}")),
     );
 
-    search
+    with_offset(search)
 }
 
 pub(super) fn map_row_to_note(row: &Row) -> rusqlite::Result<Note> {
@@ -545,9 +1582,101 @@ pub(super) fn map_row_to_note(row: &Row) -> rusqlite::Result<Note> {
         done_at: row
             .get::<_, Option<i64>>(7)?
             .map(|ts| Local.timestamp_nanos(ts)),
+        indexed: row.get(8)?,
+        reminder_at: row
+            .get::<_, Option<i64>>(9)?
+            .map(|ts| Local.timestamp_nanos(ts)),
+        pinned: row.get(10)?,
+        due_at: row
+            .get::<_, Option<i64>>(11)?
+            .map(|ts| Local.timestamp_nanos(ts)),
     }))
 }
 
+/// Inserts the rows of `note` into the notes and notes_subjects tables,
+/// returning the note with its new row id.
+fn insert_note_rows(tx: &Connection, note: NoteData) -> rusqlite::Result<NoteData> {
+    let mut note = note;
+    tx.prepare_cached(
+        "INSERT INTO notes (
+                id,
+                text,
+                task_state,
+                created_at,
+                modified_at,
+                done_at,
+                indexed,
+                reminder_at,
+                pinned,
+                due_at
+            )
+            VALUES (
+                :id,
+                :text,
+                :task_state,
+                :created_at,
+                :modified_at,
+                :done_at,
+                :indexed,
+                :reminder_at,
+                :pinned,
+                :due_at
+            )",
+    )?
+    .execute(named_params! {
+        ":id": &note.id,
+        ":text": &note.text,
+        ":task_state": &note.task_state,
+        ":created_at": &note.created_at.naive_utc().timestamp_nanos(),
+        ":modified_at": &note.modified_at.naive_utc().timestamp_nanos(),
+        ":done_at": &note.done_at.map(|t| t.naive_utc().timestamp_nanos()),
+        ":indexed": &note.indexed,
+        ":reminder_at": &note.reminder_at.map(|t| t.naive_utc().timestamp_nanos()),
+        ":pinned": &note.pinned,
+        ":due_at": &note.due_at.map(|t| t.naive_utc().timestamp_nanos()),
+    })?;
+
+    note.rowid = tx.last_insert_rowid();
+
+    let subjects = subjects_or_nil(&note.subjects);
+
+    for subject in subjects {
+        tx.prepare_cached("INSERT INTO notes_subjects (note_id, subject_id) VALUES (?1, ?2)")?
+            .execute(params![note.id, subject.0])?;
+    }
+
+    Ok(note)
+}
+
+/// Deletes the rows of `note` from the notes and notes_subjects tables,
+/// returning its row id and, if the note was indexed, its text.
+fn delete_note_rows(tx: &Connection, note: NoteId) -> rusqlite::Result<(i64, Option<String>)> {
+    let deleted = tx
+        .prepare_cached(
+            "SELECT rowid, text, indexed FROM notes
+                WHERE id = ?1",
+        )?
+        .query_row(params![note.0], |row| {
+            let indexed = row.get::<_, bool>(2)?;
+            let text = indexed.then(|| row.get::<_, String>(1)).transpose()?;
+            Ok((row.get::<_, i64>(0)?, text))
+        })?;
+
+    tx.prepare_cached(
+        "DELETE FROM notes_subjects
+            WHERE note_id = ?1",
+    )?
+    .execute(params![note.0])?;
+
+    tx.prepare_cached(
+        "DELETE FROM notes
+            WHERE id = ?1",
+    )?
+    .execute(params![note.0])?;
+
+    Ok(deleted)
+}
+
 fn subjects_or_nil(subjects: &[SubjectId]) -> &[SubjectId] {
     const NO_SUBJECT: [SubjectId; 1] = [SubjectId(Uuid::nil())];
 
@@ -557,3 +1686,179 @@ fn subjects_or_nil(subjects: &[SubjectId]) -> &[SubjectId] {
         subjects
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn task_state_transitions() {
+        use DoneAtChange::*;
+        use TaskState::*;
+
+        let cases = [
+            (NotATask, NotATask, Keep),
+            (NotATask, Todo, Keep),
+            (NotATask, Done, Set),
+            (Todo, NotATask, Keep),
+            (Todo, Todo, Keep),
+            (Todo, Done, Set),
+            (Done, NotATask, Clear),
+            (Done, Todo, Clear),
+            (Done, Done, Keep),
+        ];
+        for (from, to, done_at) in cases {
+            let change = from.transition(to);
+            assert_eq!(change.state, to, "{from:?} -> {to:?}");
+            assert_eq!(change.done_at, done_at, "{from:?} -> {to:?}");
+        }
+
+        assert_eq!(NotATask.toggle_task(), Todo);
+        assert_eq!(Todo.toggle_task(), NotATask);
+        assert_eq!(Done.toggle_task(), NotATask);
+    }
+
+    #[test]
+    fn bump_presets_are_ahead() {
+        // A Wednesday.
+        let now = Local.with_ymd_and_hms(2023, 8, 16, 14, 30, 0).unwrap();
+        let presets = bump_presets(now);
+        let dates = presets
+            .iter()
+            .map(|(label, at)| (*label, at.date_naive().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dates,
+            [
+                ("Today", "2023-08-16".to_string()),
+                ("Tomorrow", "2023-08-17".to_string()),
+                ("Next Monday", "2023-08-21".to_string()),
+            ]
+        );
+        assert!(presets.iter().all(|(_, at)| at.time() == now.time()));
+
+        // On a Monday, next Monday is a week away.
+        let monday = Local.with_ymd_and_hms(2023, 8, 21, 9, 0, 0).unwrap();
+        let (_, next) = bump_presets(monday)[2];
+        assert_eq!(next.date_naive().to_string(), "2023-08-28");
+    }
+
+    #[test]
+    fn note_id_round_trips_through_string() {
+        let id = NoteId(Uuid::new_v4());
+        assert_eq!(id.to_string().parse::<NoteId>().unwrap(), id);
+
+        let err = "not-a-uuid".parse::<NoteId>().unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid note id \"not-a-uuid\""));
+    }
+
+    #[test]
+    fn build_done_task_sets_done_at() {
+        let note = NoteBuilder::new().as_task().build();
+        assert_eq!(note.task_state, TaskState::Todo);
+        assert_eq!(note.done_at, None);
+
+        let note = NoteBuilder::new().task_state(TaskState::Done).build();
+        assert!(note.done_at.is_some());
+
+        let done_at = Local.timestamp_nanos(1_000_000);
+        let note = NoteBuilder::new().as_done_task(done_at).build();
+        assert_eq!(note.task_state, TaskState::Done);
+        assert_eq!(note.done_at, Some(done_at));
+    }
+
+    #[test]
+    fn todo_to_done_sets_done_at() {
+        let note = NoteBuilder::new().as_task().build();
+        let before = Local::now();
+        let note = note.modify_with(|b| b.task_state(TaskState::Done));
+        assert!(note.done_at.unwrap() >= before);
+    }
+
+    #[test]
+    fn explicit_done_at_wins() {
+        let done_at = Local.timestamp_nanos(1_000_000);
+        let note = NoteBuilder::new().as_task().build();
+        let note = note.modify_with(|b| b.as_done_task(done_at));
+        assert_eq!(note.done_at, Some(done_at));
+
+        let note = note.modify_with(|b| b.task_state(TaskState::Todo).done_at(Some(done_at)));
+        assert_eq!(note.done_at, Some(done_at));
+    }
+
+    #[test]
+    fn done_to_todo_clears_done_at() {
+        let note = NoteBuilder::new().task_state(TaskState::Done).build();
+        let note = note.modify_with(|b| b.task_state(TaskState::Todo));
+        assert_eq!(note.task_state, TaskState::Todo);
+        assert_eq!(note.done_at, None);
+    }
+
+    #[test]
+    fn done_to_not_a_task_clears_done_at() {
+        let note = NoteBuilder::new().task_state(TaskState::Done).build();
+        let note = note.modify_with(|b| b.task_state(TaskState::NotATask));
+        assert_eq!(note.done_at, None);
+    }
+
+    #[test]
+    fn task_helpers() {
+        let note = NoteBuilder::new().build();
+        assert!(!note.is_task());
+        assert!(!note.is_open_task());
+        assert!(!note.is_done());
+
+        let task = NoteBuilder::new().as_task().build();
+        assert!(task.is_task());
+        assert!(task.is_open_task());
+        assert!(!task.is_done());
+
+        let done = task.modify_with(|b| b.task_state(TaskState::Done));
+        assert!(done.is_task());
+        assert!(!done.is_open_task());
+        assert!(done.is_done());
+    }
+
+    #[test]
+    fn staying_done_keeps_done_at() {
+        let done_at = Local.timestamp_nanos(1_000_000);
+        let note = NoteBuilder::new().as_done_task(done_at).build();
+
+        let note = note.modify_with(|b| b.text("Edited"));
+        assert_eq!(note.done_at, Some(done_at));
+
+        let note = note.modify_with(|b| b.task_state(TaskState::Done));
+        assert_eq!(note.done_at, Some(done_at));
+    }
+
+    #[test]
+    fn due_at_can_be_cleared() {
+        let due_at = Local.timestamp_nanos(1_000_000);
+        let note = NoteBuilder::new().as_task().due_at(Some(due_at)).build();
+        assert_eq!(note.due_at, Some(due_at));
+
+        let note = note.modify_with(|b| b.text("Edited"));
+        assert_eq!(note.due_at, Some(due_at));
+
+        let note = note.modify_with(|b| b.due_at(None));
+        assert_eq!(note.due_at, None);
+    }
+
+    #[test]
+    fn only_open_tasks_are_overdue() {
+        let due_at = Local.timestamp_nanos(1_000_000);
+        let before = due_at - chrono::Duration::hours(1);
+        let after = due_at + chrono::Duration::hours(1);
+
+        let task = NoteBuilder::new().as_task().due_at(Some(due_at)).build();
+        assert!(!task.is_overdue(before));
+        assert!(task.is_overdue(after));
+
+        let done = task.modify_with(|b| b.task_state(TaskState::Done));
+        assert!(!done.is_overdue(after));
+        let untimed = task.modify_with(|b| b.due_at(None));
+        assert!(!untimed.is_overdue(after));
+    }
+}
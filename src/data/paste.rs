@@ -0,0 +1,331 @@
+//! Converts HTML pasted into the note input, e.g. from a browser, to Markdown.
+//!
+//! Only the structure that matters in notes is kept: headings, paragraphs,
+//! lists, links, images, emphasis and code. Other tags are dropped and their
+//! text is kept.
+
+/// Converts an HTML fragment to Markdown.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut md = Converter::default();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        md.text(&decode_entities(&rest[..start]));
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            // An unclosed tag is just text.
+            md.text(&decode_entities(rest));
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        // Their contents aren't text.
+        if !closing && (name == "script" || name == "style") {
+            let close = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(end) => rest[end..].find('>').map_or("", |e| &rest[end + e + 1..]),
+                None => "",
+            };
+            continue;
+        }
+
+        if closing {
+            md.close(&name);
+        } else {
+            md.open(&name, tag);
+        }
+    }
+    md.text(&decode_entities(rest));
+    md.finish()
+}
+
+#[derive(Default)]
+struct Converter {
+    out: String,
+    /// Newlines to write before the next content, for breaks between blocks.
+    pending_newlines: usize,
+    /// Whether each open list is ordered, with the number of its next item.
+    lists: Vec<Option<usize>>,
+    /// The targets of the open links. `None` for anchors without one.
+    links: Vec<Option<String>>,
+    in_pre: bool,
+}
+
+impl Converter {
+    fn open(&mut self, name: &str, tag: &str) {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block(2);
+                let level = name[1..].parse().unwrap_or(1);
+                self.inline(&format!("{} ", "#".repeat(level)));
+            }
+            "p" | "div" | "section" | "article" | "blockquote" | "table" | "tr" => self.block(2),
+            "br" => self.block(1),
+            "hr" => {
+                self.block(2);
+                self.inline("---");
+                self.block(2);
+            }
+            "ul" | "ol" => {
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+                self.lists.push((name == "ol").then_some(1));
+            }
+            "li" => {
+                self.block(1);
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.inline(&format!("{indent}{marker}"));
+            }
+            "a" => {
+                let href = attribute(tag, "href").filter(|href| !href.is_empty());
+                if href.is_some() {
+                    self.inline("[");
+                }
+                self.links.push(href);
+            }
+            "img" => {
+                if let Some(src) = attribute(tag, "src") {
+                    let alt = attribute(tag, "alt").unwrap_or_default();
+                    self.inline(&format!("![{alt}]({src})"));
+                }
+            }
+            "strong" | "b" => self.inline("**"),
+            "em" | "i" => self.inline("*"),
+            "code" if !self.in_pre => self.inline("`"),
+            "pre" => {
+                self.block(2);
+                self.inline("```\n");
+                self.in_pre = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "p" | "div" | "section" | "article"
+            | "blockquote" | "table" | "tr" => self.block(2),
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+            }
+            "a" => {
+                if let Some(Some(href)) = self.links.pop() {
+                    self.inline(&format!("]({href})"));
+                }
+            }
+            "strong" | "b" => self.inline("**"),
+            "em" | "i" => self.inline("*"),
+            "code" if !self.in_pre => self.inline("`"),
+            "pre" => {
+                self.in_pre = false;
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("```");
+                self.block(2);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds text, collapsing whitespace like a browser does outside `pre`.
+    fn text(&mut self, text: &str) {
+        if self.in_pre {
+            self.out.push_str(text);
+            return;
+        }
+        if text.starts_with(char::is_whitespace) {
+            self.space();
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                self.space();
+            }
+            self.inline(word);
+        }
+        if text.ends_with(char::is_whitespace) {
+            self.space();
+        }
+    }
+
+    /// Separates words, unless a block break or another space already does.
+    fn space(&mut self) {
+        let at_start = self.out.is_empty() || self.out.ends_with(char::is_whitespace);
+        if self.pending_newlines == 0 && !at_start {
+            self.out.push(' ');
+        }
+    }
+
+    /// Adds Markdown syntax as is.
+    fn inline(&mut self, syntax: &str) {
+        self.flush_newlines();
+        self.out.push_str(syntax);
+    }
+
+    /// Ends the current block with at least `newlines` newlines before the next content.
+    fn block(&mut self, newlines: usize) {
+        self.pending_newlines = self.pending_newlines.max(newlines);
+    }
+
+    fn flush_newlines(&mut self) {
+        if self.pending_newlines == 0 {
+            return;
+        }
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() {
+            let existing = self.out.len() - self.out.trim_end_matches('\n').len();
+            for _ in existing..self.pending_newlines {
+                self.out.push('\n');
+            }
+        }
+        self.pending_newlines = 0;
+    }
+
+    fn finish(self) -> String {
+        self.out.trim().to_string()
+    }
+}
+
+/// The value of the attribute `name` in the inside of a tag, `a href="..."`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    // Skip the tag name.
+    let mut rest = &tag[tag.find(char::is_whitespace)?..];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+
+        let mut value = "";
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            (value, rest) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after[1..];
+                    let end = quoted.find(quote).unwrap_or(quoted.len());
+                    (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    after.split_at(end)
+                }
+            };
+        }
+        if key.eq_ignore_ascii_case(name) {
+            return Some(decode_entities(value));
+        }
+    }
+}
+
+/// Decodes the character references common in pasted HTML.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => {
+                    let number = entity.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => number.parse().ok(),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_structure_to_markdown() {
+        let html = r#"<meta charset="utf-8"><!--StartFragment-->
+            <h2>Reading   list</h2>
+            <p>See <a href="https://example.com/?a=1&amp;b=2">the <b>docs</b></a> and <code>cargo&nbsp;test</code>.</p>
+            <ul>
+                <li>First</li>
+                <li>Second
+                    <ol><li>Nested</li><li>Again</li></ol>
+                </li>
+            </ul>
+            <script>alert(1)</script><!--EndFragment-->"#;
+        assert_eq!(
+            html_to_markdown(html),
+            "## Reading list\n\n\
+             See [the **docs**](https://example.com/?a=1&b=2) and `cargo test`.\n\n\
+             - First\n\
+             - Second\n  \
+             1. Nested\n  \
+             2. Again"
+        );
+    }
+
+    #[test]
+    fn keeps_preformatted_text() {
+        let html = "<p>Run:</p><pre><code>fn main() {\n    x &lt; y\n}</code></pre>";
+        assert_eq!(
+            html_to_markdown(html),
+            "Run:\n\n```\nfn main() {\n    x < y\n}\n```"
+        );
+    }
+
+    #[test]
+    fn plain_text_passes_through() {
+        assert_eq!(html_to_markdown("Just text & more"), "Just text & more");
+        assert_eq!(html_to_markdown("a <b"), "a <b");
+    }
+}
@@ -0,0 +1,166 @@
+//! Find and replace across the text of many notes.
+
+use std::borrow::Cow;
+
+use chrono::Local;
+use const_format::formatcp;
+use regex::Regex;
+use rusqlite::params;
+use tracing::{debug, instrument};
+
+use super::{
+    notes::{map_row_to_note, Note, NoteId, SINGLE_NOTE_COLUMNS},
+    retry::{begin_write, commit_write},
+    subjects::SubjectId,
+    Store,
+};
+
+/// Notes with the subject ?1, newest first.
+pub const NOTES_WITH_SUBJECT: &str = formatcp!(
+    r#"SELECT {columns}
+    FROM notes n
+    WHERE n.id IN (
+        SELECT s.note_id
+        FROM notes_search s
+        WHERE s.subject_id = ?1
+    )
+    ORDER BY n.created_at DESC"#,
+    columns = SINGLE_NOTE_COLUMNS
+);
+
+/// What [`Store::find_replace`] looks for.
+#[derive(Debug, Clone)]
+pub enum FindPattern {
+    Literal(String),
+    /// Replacements can refer to the groups of the expression, e.g. `$1`.
+    Regex(Regex),
+}
+
+impl FindPattern {
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(FindPattern::Regex(Regex::new(pattern)?))
+    }
+
+    /// `text` with every match replaced, or `None` if that changes nothing.
+    pub fn replace(&self, text: &str, replacement: &str) -> Option<String> {
+        let replaced = match self {
+            FindPattern::Literal(literal) if literal.is_empty() || !text.contains(literal) => {
+                return None
+            }
+            FindPattern::Literal(literal) => text.replace(literal, replacement),
+            FindPattern::Regex(regex) => match regex.replace_all(text, replacement) {
+                Cow::Borrowed(_) => return None,
+                Cow::Owned(replaced) => replaced,
+            },
+        };
+        (replaced != text).then_some(replaced)
+    }
+}
+
+impl Store {
+    /// The notes that replacing `pattern` would change, newest first, along with
+    /// their new text. With `subject`, only notes with that subject are considered.
+    pub fn note_replacements(
+        &self,
+        pattern: &FindPattern,
+        replacement: &str,
+        subject: Option<SubjectId>,
+    ) -> rusqlite::Result<Vec<(Note, String)>> {
+        let notes = match subject {
+            Some(subject) => self
+                .conn
+                .borrow()
+                .prepare_cached(NOTES_WITH_SUBJECT)?
+                .query_map(params![subject], map_row_to_note)?
+                .collect::<rusqlite::Result<Vec<_>>>()?,
+            None => self.get_all_notes()?,
+        };
+        Ok(notes
+            .into_iter()
+            .filter_map(|note| {
+                let text = pattern.replace(&note.text, replacement)?;
+                Some((note, text))
+            })
+            .collect())
+    }
+
+    /// Replaces `pattern` with `replacement` in every note it matches and
+    /// returns the changed notes. With `dry_run`, only returns the notes.
+    ///
+    /// The edits happen in one transaction but can't be undone. Go through
+    /// [`super::layer::LayerAction::replace_in_notes`] for that.
+    #[instrument(skip(self, pattern))]
+    pub fn find_replace(
+        &self,
+        pattern: &FindPattern,
+        replacement: &str,
+        subject: Option<SubjectId>,
+        dry_run: bool,
+    ) -> rusqlite::Result<Vec<NoteId>> {
+        let replacements = self.note_replacements(pattern, replacement, subject)?;
+        let ids = replacements.iter().map(|(note, _)| note.id).collect();
+        if dry_run {
+            return Ok(ids);
+        }
+
+        debug!("Replacing text in {} notes", replacements.len());
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let now = Local::now();
+        for (note, text) in replacements {
+            let mut note = (*note).clone();
+            note.text = text;
+            note.modified_at = now;
+            self.delete_note_with_tx(&tx, note.id)?;
+            self.add_note_with_tx(&tx, note)?;
+        }
+        commit_write(tx)?;
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::{
+        notes::{NoteBuilder, NoteSearch},
+        ConnectionType,
+    };
+
+    #[test]
+    fn find_replace_literal_and_regex() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let project = store.add_subject("Project".to_string())?.id;
+        let tagged = store.add_note(
+            NoteBuilder::new()
+                .text("Ship v1, then v1.1")
+                .subject(project),
+        )?;
+        let untagged = store.add_note(NoteBuilder::new().text("v1 notes"))?;
+        let other = store.add_note(NoteBuilder::new().text("Nothing here"))?;
+
+        let v1 = FindPattern::Literal("v1".to_string());
+        let dry = store.find_replace(&v1, "v2", None, true)?;
+        assert_eq!(dry, vec![untagged.id, tagged.id]);
+        assert_eq!(store.get_note(tagged.id)?.text, "Ship v1, then v1.1");
+
+        assert_eq!(
+            store.find_replace(&v1, "v2", Some(project), false)?,
+            vec![tagged.id]
+        );
+        assert_eq!(store.get_note(tagged.id)?.text, "Ship v2, then v2.1");
+        assert_eq!(store.get_note(tagged.id)?.subjects, vec![project]);
+        assert_eq!(store.get_note(untagged.id)?.text, "v1 notes");
+        assert_eq!(store.get_note(other.id)?.text, "Nothing here");
+
+        let version = FindPattern::regex(r"v(\d)\.(\d)").unwrap();
+        store.find_replace(&version, "version $1.$2", None, false)?;
+        assert_eq!(store.get_note(tagged.id)?.text, "Ship v2, then version 2.1");
+        assert!(FindPattern::regex("(").is_err());
+
+        // Replacing something with itself changes nothing.
+        assert_eq!(store.find_replace(&v1, "v1", None, false)?, vec![]);
+        assert_eq!(store.find_notes(NoteSearch::new())?.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Retrying writes that fail because another connection holds the database.
+//!
+//! The search thread has its own connection, so the main connection can find
+//! the database busy or locked. This is on top of SQLite's busy timeout,
+//! which only covers waiting for a lock and not every busy error.
+
+use std::time::Duration;
+
+use rusqlite::{Connection, ErrorCode, Result, Transaction, TransactionBehavior};
+use tracing::warn;
+
+const WRITE_ATTEMPTS: u32 = 6;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Runs `f` until it doesn't fail with a busy or locked error, doubling the
+/// delay between attempts. Gives up after `WRITE_ATTEMPTS` attempts.
+fn with_retry<T>(mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..WRITE_ATTEMPTS {
+        match f() {
+            Err(e) if is_busy(&e) => {
+                warn!(
+                    "Database busy (attempt {}), retrying in {:?}",
+                    attempt, delay
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    f().map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(error, _)
+            if matches!(
+                error.code,
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked
+            ) =>
+        {
+            let message = format!("database stayed busy after {WRITE_ATTEMPTS} attempts");
+            rusqlite::Error::SqliteFailure(error, Some(message))
+        }
+        e => e,
+    })
+}
+
+/// Starts a write transaction.
+///
+/// The write lock is taken immediately, so contention fails here, before
+/// any side effects like search index updates have happened.
+pub(crate) fn begin_write(conn: &Connection) -> Result<Transaction<'_>> {
+    with_retry(|| Transaction::new_unchecked(conn, TransactionBehavior::Immediate))
+}
+
+/// Commits a transaction started with [`begin_write`].
+/// A busy `COMMIT` leaves the transaction open, so it can be retried as is.
+pub(crate) fn commit_write(tx: Transaction<'_>) -> Result<()> {
+    with_retry(|| tx.execute_batch("COMMIT"))
+    // Dropping the transaction only rolls back if the commit failed.
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use crate::data::{notes::NoteBuilder, notes::NoteSearch, ConnectionType, Store};
+
+    /// Locks the database from another thread for `hold`.
+    /// Returns once the lock is held.
+    fn lock_from_other_thread(
+        path: std::path::PathBuf,
+        hold: std::time::Duration,
+    ) -> std::thread::JoinHandle<()> {
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let conn = rusqlite::Connection::open(path.join("data.db")).unwrap();
+            conn.execute_batch("BEGIN EXCLUSIVE").unwrap();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(hold);
+            conn.execute_batch("COMMIT").unwrap();
+        });
+        locked_rx.recv().unwrap();
+        handle
+    }
+
+    #[test]
+    fn write_waits_for_other_connection() -> rusqlite::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        // Make contention surface as errors instead of waiting in SQLite.
+        store
+            .conn
+            .borrow()
+            .busy_timeout(std::time::Duration::ZERO)?;
+
+        let locker = lock_from_other_thread(
+            dir.path().to_path_buf(),
+            std::time::Duration::from_millis(50),
+        );
+        store.add_note(NoteBuilder::new().text("Written under contention"))?;
+        locker.join().unwrap();
+
+        assert_eq!(store.find_notes(NoteSearch::new())?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn write_gives_up_when_busy() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(ConnectionType::File(dir.path().to_path_buf()));
+        store
+            .conn
+            .borrow()
+            .busy_timeout(std::time::Duration::ZERO)
+            .unwrap();
+
+        let locker =
+            lock_from_other_thread(dir.path().to_path_buf(), std::time::Duration::from_secs(2));
+        let result = store.add_note(NoteBuilder::new().text("Never written"));
+        locker.join().unwrap();
+
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("stayed busy"), "{error}");
+        assert!(store.find_notes(NoteSearch::new()).unwrap().is_empty());
+    }
+}
@@ -1,27 +1,128 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use rusqlite::Connection;
-use std::sync::{Arc, Condvar, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
 use tantivy::query::QueryParserError;
 use tantivy::tokenizer::TextAnalyzer;
 use tokio::sync::oneshot;
 
-use crate::data::tfidf;
-
 use super::functions::add_functions;
 use super::notes;
 use super::{
-    notes::{Note, NoteData},
+    notes::{Note, NoteData, NoteId},
+    similarity::{SimilarityBackend, SimilarityContext, SimilarityParams},
+    subjects::SubjectId,
     ConnectionType,
 };
 
+/// Limits a search to the notes created from `after` on and before `before`.
+/// A missing end leaves the range open on that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DateRange {
+    pub after: Option<DateTime<Local>>,
+    pub before: Option<DateTime<Local>>,
+}
+
+impl DateRange {
+    /// The notes created on the days from `first` to `last`, both included.
+    pub fn days(first: Option<NaiveDate>, last: Option<NaiveDate>) -> Self {
+        let start_of = |day: NaiveDate| {
+            Local
+                .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+                .earliest()
+        };
+        DateRange {
+            after: first.and_then(start_of),
+            before: last.and_then(|day| day.succ_opt()).and_then(start_of),
+        }
+    }
+
+    /// Whether the range leaves out any notes.
+    pub fn is_bounded(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+}
+
 #[derive(Clone)]
 enum Query {
-    Search(String),
-    Similar(String),
+    Search(String, DateRange),
+    SearchWithin(String, HashSet<i64>),
+    Similar(String, SimilarityParams),
+    Count(String, Option<SubjectId>, DateRange),
+}
+
+impl Query {
+    fn kind(&self) -> &'static str {
+        match self {
+            Query::Search(_, _) => "search",
+            Query::SearchWithin(_, _) => "search_within",
+            Query::Similar(_, _) => "similar",
+            Query::Count(_, _, _) => "count",
+        }
+    }
+
+    fn term_count(&self) -> usize {
+        let (Query::Search(text, _)
+        | Query::SearchWithin(text, _)
+        | Query::Similar(text, _)
+        | Query::Count(text, _, _)) = self;
+        text.split_whitespace().count()
+    }
+}
+
+enum SearchResponse {
+    Notes(Vec<NoteData>),
+    Count(usize),
+}
+
+/// A failed search, carrying a message suitable for showing to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchError(pub String);
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<rusqlite::Error> for SearchError {
+    fn from(e: rusqlite::Error) -> Self {
+        SearchError(format!("Database error: {e}"))
+    }
+}
+
+impl From<TantivyError> for SearchError {
+    fn from(e: TantivyError) -> Self {
+        SearchError(format!("Search index error: {e}"))
+    }
 }
 
+pub type SearchResult<T> = Result<T, SearchError>;
+
+/// Hands the latest value over to a waiting thread. A value sent before
+/// the previous one was taken replaces it.
 struct Bridge<T> {
-    inner: Arc<(Mutex<Option<T>>, Condvar)>,
+    inner: Arc<(Mutex<BridgeSlot<T>>, Condvar)>,
+}
+
+struct BridgeSlot<T> {
+    value: Option<T>,
+    /// Set by [`Bridge::close`]. Values sent after it are dropped.
+    closed: bool,
+}
+
+impl<T> Default for BridgeSlot<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            closed: false,
+        }
+    }
 }
 
 impl<T> Clone for Bridge<T> {
@@ -40,32 +141,55 @@ impl<T> Bridge<T> {
     }
     fn send(&self, value: T) {
         let mut guard = self.inner.0.lock().unwrap();
-        *guard = Some(value);
+        if guard.closed {
+            return;
+        }
+        guard.value = Some(value);
         self.inner.1.notify_one();
     }
 
-    fn wait(&self) -> T {
+    /// Wakes up the waiting thread for good, dropping any value not yet taken.
+    fn close(&self) {
+        let mut guard = self.inner.0.lock().unwrap();
+        guard.closed = true;
+        guard.value = None;
+        self.inner.1.notify_all();
+    }
+
+    /// Waits for the next value, or returns `None` once the bridge is closed.
+    fn wait(&self) -> Option<T> {
         let mut guard = self
             .inner
             .1
-            .wait_while(self.inner.0.lock().unwrap(), |v| v.is_none())
+            .wait_while(self.inner.0.lock().unwrap(), |slot| {
+                slot.value.is_none() && !slot.closed
+            })
             .unwrap();
-        guard.take().unwrap()
+        guard.value.take()
     }
 }
 
 struct SearchRequest {
     query: Query,
-    send_data_to: oneshot::Sender<Vec<NoteData>>,
+    send_data_to: oneshot::Sender<SearchResult<SearchResponse>>,
 }
 
 #[derive(Clone)]
 pub struct SearchWorker {
     bridge: Bridge<SearchRequest>,
+    /// Taken and joined by [`Self::shutdown`].
+    thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
 }
 
 impl SearchWorker {
-    pub fn start_search_thread(file: ConnectionType, index: Arc<Index>) -> SearchWorker {
+    /// Searches with `reader`, which [`SearchIndexWriter`] reloads after
+    /// committing changes.
+    pub fn start_search_thread(
+        file: ConnectionType,
+        index: Arc<Index>,
+        reader: IndexReader,
+        similarity: Box<dyn SimilarityBackend>,
+    ) -> SearchWorker {
         let conn = match file {
             ConnectionType::InMemory => Connection::open_in_memory().unwrap(),
             ConnectionType::File(path) => Connection::open(path).unwrap(),
@@ -74,31 +198,142 @@ impl SearchWorker {
 
         let bridge = Bridge::<SearchRequest>::new();
 
-        let reader = index.reader().unwrap();
+        let thread = spawn_search(bridge.clone(), index, reader, conn, similarity);
+
+        SearchWorker {
+            bridge,
+            thread: Arc::new(Mutex::new(Some(thread))),
+        }
+    }
 
-        spawn_search(bridge.clone(), index, reader, conn);
+    /// Stops the search thread after the search it's running, if any, and
+    /// waits for it to exit. Searches still waiting and any made later fail.
+    pub fn shutdown(&self) {
+        self.bridge.close();
+        let thread = self.thread.lock().unwrap().take();
+        if let Some(thread) = thread {
+            if thread.join().is_err() {
+                tracing::error!("The search thread panicked");
+            }
+        }
+    }
 
-        SearchWorker { bridge }
+    pub async fn perform_search(&self, search_text: String) -> SearchResult<Vec<Note>> {
+        self.perform(Query::Search(search_text, DateRange::default()))
+            .await
+    }
+
+    /// Like [`Self::perform_search`], but only finds notes created in `range`.
+    /// Without search text, lists the notes in the range, newest first.
+    pub async fn search_in_range(
+        &self,
+        search_text: String,
+        range: DateRange,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform(Query::Search(search_text, range)).await
+    }
+
+    /// Like [`Self::perform_search`], but only finds notes among `rowids`,
+    /// e.g. the results of a previous search to narrow them down further.
+    pub async fn search_within_results(
+        &self,
+        search_text: String,
+        rowids: HashSet<i64>,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform(Query::SearchWithin(search_text, rowids)).await
+    }
+
+    /// Finds notes similar to `search_text`. The defaults of `params` come
+    /// from [`GlobalSettings::similar_notes`](super::settings::GlobalSettings::similar_notes).
+    pub async fn find_similar(
+        &self,
+        search_text: String,
+        params: SimilarityParams,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform(Query::Similar(search_text, params)).await
+    }
+
+    /// Counts the notes matching `search_text` and created in `range` without
+    /// loading them. If `subject` is given, only notes with that subject are counted.
+    pub async fn search_count(
+        &self,
+        search_text: String,
+        subject: Option<SubjectId>,
+        range: DateRange,
+    ) -> SearchResult<usize> {
+        match self
+            .request(Query::Count(search_text, subject, range))
+            .await?
+        {
+            SearchResponse::Count(count) => Ok(count),
+            SearchResponse::Notes(_) => unreachable!("count query returned notes"),
+        }
     }
 
-    pub async fn perform_search(&self, search_text: String) -> Vec<Note> {
-        self.perform(Query::Search(search_text)).await
+    /// Like [`Self::perform_search`], for callers outside of an async runtime.
+    pub fn perform_search_blocking(&self, search_text: String) -> SearchResult<Vec<Note>> {
+        self.perform_blocking(Query::Search(search_text, DateRange::default()))
     }
 
-    pub async fn find_similar(&self, search_text: String) -> Vec<Note> {
-        self.perform(Query::Similar(search_text)).await
+    /// Like [`Self::search_in_range`], for callers outside of an async runtime.
+    pub fn search_in_range_blocking(
+        &self,
+        search_text: String,
+        range: DateRange,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform_blocking(Query::Search(search_text, range))
     }
 
-    async fn perform(&self, query: Query) -> Vec<Note> {
+    /// Like [`Self::search_within_results`], for callers outside of an async runtime.
+    pub fn search_within_results_blocking(
+        &self,
+        search_text: String,
+        rowids: HashSet<i64>,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform_blocking(Query::SearchWithin(search_text, rowids))
+    }
+
+    /// Like [`Self::find_similar`], for callers outside of an async runtime.
+    pub fn find_similar_blocking(
+        &self,
+        search_text: String,
+        params: SimilarityParams,
+    ) -> SearchResult<Vec<Note>> {
+        self.perform_blocking(Query::Similar(search_text, params))
+    }
+
+    fn perform_blocking(&self, query: Query) -> SearchResult<Vec<Note>> {
+        let response = self
+            .send(query)
+            .blocking_recv()
+            .map_err(|_| SearchError("The search thread stopped".to_string()))??;
+        match response {
+            SearchResponse::Notes(notes) => Ok(notes.into_iter().map(|n| n.to_note()).collect()),
+            SearchResponse::Count(_) => unreachable!("note query returned a count"),
+        }
+    }
+
+    async fn perform(&self, query: Query) -> SearchResult<Vec<Note>> {
+        match self.request(query).await? {
+            SearchResponse::Notes(notes) => Ok(notes.into_iter().map(|n| n.to_note()).collect()),
+            SearchResponse::Count(_) => unreachable!("note query returned a count"),
+        }
+    }
+
+    async fn request(&self, query: Query) -> SearchResult<SearchResponse> {
+        self.send(query)
+            .await
+            .map_err(|_| SearchError("The search thread stopped".to_string()))?
+    }
+
+    fn send(&self, query: Query) -> oneshot::Receiver<SearchResult<SearchResponse>> {
         let (sender_to_main, receiver_to_main) = oneshot::channel();
         let query = SearchRequest {
             query,
             send_data_to: sender_to_main,
         };
         self.bridge.send(query);
-
-        let notes = receiver_to_main.await.unwrap();
-        notes.into_iter().map(|n| n.to_note()).collect()
+        receiver_to_main
     }
 }
 
@@ -107,83 +342,404 @@ fn spawn_search(
     index: Arc<Index>,
     reader: IndexReader,
     conn: Connection,
-) {
+    similarity: Box<dyn SimilarityBackend>,
+) -> thread::JoinHandle<()> {
     // Note: the handler is not allowed to crash, so unwrap is strictly forbidden.
-    let _handle = thread::spawn(move || loop {
-        let request = bridge.wait();
+    thread::spawn(move || {
+        let mut cache = HitCache::default();
+        while let Some(request) = bridge.wait() {
+            let span = tracing::debug_span!(
+                "search_request",
+                kind = request.query.kind(),
+                terms = request.query.term_count(),
+            );
+            let _entered = span.enter();
+            let start = Instant::now();
 
-        let result = match request.query {
-            Query::Search(text) => search_text(&index, &reader, &conn, vec![text], 200),
-            Query::Similar(text) => find_similar(&index, &reader, &conn, &text),
-        };
-        let result = match result {
-            Ok(result) => result,
-            Err(e) => {
-                // TODO: Tell the user that the search failed.
-                tracing::error!("Failed to search for text: {}", e);
-                Vec::new()
-            }
-        };
+            let result = handle_query(
+                &index,
+                &reader,
+                &conn,
+                &mut cache,
+                &*similarity,
+                request.query,
+            );
 
-        let _send_result = request.send_data_to.send(result);
-    });
+            let results = match &result {
+                Ok(SearchResponse::Notes(notes)) => notes.len(),
+                Ok(SearchResponse::Count(count)) => *count,
+                Err(_) => 0,
+            };
+            tracing::debug!(
+                results,
+                elapsed_ms = elapsed_ms(start),
+                "Search request handled"
+            );
+            let _send_result = request.send_data_to.send(result);
+        }
+        tracing::debug!("Search thread stopped");
+    })
 }
 
-#[tracing::instrument(skip(index, reader, conn))]
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn handle_query(
+    index: &Index,
+    reader: &IndexReader,
+    conn: &Connection,
+    cache: &mut HitCache,
+    similarity: &dyn SimilarityBackend,
+    query: Query,
+) -> SearchResult<SearchResponse> {
+    let result = match query {
+        Query::Search(text, range) if range.is_bounded() => {
+            search_text_in_range(index, reader, conn, cache, text, range, 200)
+                .map(SearchResponse::Notes)
+        }
+        Query::Search(text, _) => search_text(index, reader, conn, cache, vec![text], None, 200)
+            .map(SearchResponse::Notes),
+        Query::SearchWithin(text, rowids) => {
+            search_text(index, reader, conn, cache, vec![text], Some(&rowids), 200)
+                .map(SearchResponse::Notes)
+        }
+        Query::Similar(text, params) => {
+            find_similar(index, reader, conn, similarity, &text, params).map(SearchResponse::Notes)
+        }
+        Query::Count(text, subject, range) => {
+            count_text(index, reader, conn, &text, subject, range).map(SearchResponse::Count)
+        }
+    };
+    if let Err(e) = &result {
+        tracing::error!("Failed to search for text: {}", e);
+    }
+    result
+}
+
+const HIT_CACHE_SIZE: usize = 32;
+
+/// Ranked row ids of recent searches.
+///
+/// The hits only depend on the index, so they stay valid until the searcher
+/// is reloaded after a commit. The notes themselves are always read fresh
+/// from the database, since e.g. subject changes don't touch the index.
+#[derive(Default)]
+struct HitCache {
+    generation: u64,
+    hits: HashMap<(String, usize), Vec<u64>>,
+}
+
+impl HitCache {
+    fn get_or_search(
+        &mut self,
+        generation: u64,
+        key: (String, usize),
+        search: impl FnOnce() -> SearchResult<Vec<u64>>,
+    ) -> SearchResult<Vec<u64>> {
+        if generation != self.generation {
+            self.hits.clear();
+            self.generation = generation;
+        }
+        if let Some(hits) = self.hits.get(&key) {
+            tracing::trace!("Search cache hit");
+            return Ok(hits.clone());
+        }
+        let hits = search()?;
+        if self.hits.len() >= HIT_CACHE_SIZE {
+            self.hits.clear();
+        }
+        self.hits.insert(key, hits.clone());
+        Ok(hits)
+    }
+}
+
+/// Searches for `text` among the notes created in `range`, or lists them
+/// newest first if there is no text.
+#[tracing::instrument(skip(index, reader, conn, cache))]
+fn search_text_in_range(
+    index: &Index,
+    reader: &IndexReader,
+    conn: &Connection,
+    cache: &mut HitCache,
+    text: String,
+    range: DateRange,
+    limit: usize,
+) -> SearchResult<Vec<NoteData>> {
+    let mut in_range = rowids_in_range(conn, range)?;
+    if sanitize_text(&text).is_empty() {
+        in_range.truncate(limit);
+        return load_notes(conn, in_range.into_iter().map(|rowid| rowid as u64));
+    }
+    let in_range = in_range.into_iter().collect::<HashSet<_>>();
+    search_text(
+        index,
+        reader,
+        conn,
+        cache,
+        vec![text],
+        Some(&in_range),
+        limit,
+    )
+}
+
+#[tracing::instrument(skip(index, reader, conn, cache, within))]
 fn search_text(
     index: &Index,
     reader: &IndexReader,
     conn: &Connection,
+    cache: &mut HitCache,
     texts: Vec<String>,
+    within: Option<&HashSet<i64>>,
     limit: usize,
-) -> rusqlite::Result<Vec<NoteData>> {
+) -> SearchResult<Vec<NoteData>> {
     use itertools::Itertools;
 
     tracing::trace!("Begin");
 
-    let sanitized_texts = texts
+    let groups = texts
         .iter()
-        .map(|text| {
-            text.to_lowercase()
-                .replace(|c: char| !c.is_alphabetic(), " ")
-        })
+        .map(|text| sanitize_text(text))
         .filter(|text| !text.is_empty())
-        .collect::<Vec<_>>();
-
-    let groups = sanitized_texts
-        .iter()
-        .map(|sanitized_text| format!("({})", sanitized_text))
         .join(" OR ");
 
-    let notes = tantivy_find_notes(index, reader, conn, &groups, limit).unwrap();
+    let notes = tantivy_find_notes(index, reader, conn, cache, &groups, within, limit)?;
 
     tracing::trace!("Found {} notes", notes.len());
     Ok(notes)
 }
 
-/// Find similar notes based on the TF-IDF algorithm.
+#[tracing::instrument(skip(index, reader, conn))]
+fn count_text(
+    index: &Index,
+    reader: &IndexReader,
+    conn: &Connection,
+    text: &str,
+    subject: Option<SubjectId>,
+    range: DateRange,
+) -> SearchResult<usize> {
+    let count = tantivy_count_notes(index, reader, conn, &sanitize_text(text), subject, range)?;
+    tracing::trace!("Counted {} notes", count);
+    Ok(count)
+}
+
+pub(super) fn sanitize_text(text: &str) -> String {
+    let sanitized = text
+        .to_lowercase()
+        .replace(|c: char| !c.is_alphabetic(), " ");
+    if sanitized.trim().is_empty() {
+        String::new()
+    } else {
+        format!("({})", sanitized)
+    }
+}
+
+/// Find similar notes with the backend chosen for the store.
+#[tracing::instrument(level = "debug", skip_all, fields(results, elapsed_ms))]
 fn find_similar(
     index: &Index,
     reader: &IndexReader,
     conn: &Connection,
+    similarity: &dyn SimilarityBackend,
     text: &str,
-) -> rusqlite::Result<Vec<NoteData>> {
-    let good_word_xount = 5;
-    let best_words = tfidf::best_words(conn, text)?;
+    params: SimilarityParams,
+) -> SearchResult<Vec<NoteData>> {
+    let start = Instant::now();
+    let searcher = reader.searcher();
+    let cx = SimilarityContext {
+        conn,
+        index,
+        searcher: &searcher,
+    };
+    let similar = similarity.similar(&cx, text, params)?;
+
+    let db_query = format!(
+        "SELECT {} FROM notes n WHERE id = ?",
+        notes::SINGLE_NOTE_COLUMNS
+    );
+    let mut stmt = conn.prepare_cached(&db_query)?;
+
+    let mut notes = Vec::new();
+    for (id, _score) in similar {
+        let Ok(note) = stmt.query_row([id], notes::map_row_to_note) else { continue };
+        // SAFETY: We just created this note and the Rc is not shared
+        // with anyone else. It is safe to unwrap.
+        notes.push(std::rc::Rc::into_inner(note).unwrap());
+    }
+
+    let span = tracing::Span::current();
+    span.record("results", notes.len());
+    span.record("elapsed_ms", elapsed_ms(start));
+    Ok(notes)
+}
+
+use tantivy::{schema::*, Index, IndexReader, ReloadPolicy, TantivyError};
+
+/// How often [`SearchIndexWriter`] commits changes unless the vault says otherwise,
+/// see [`VaultSettings::index_commit_interval`](super::settings::VaultSettings::index_commit_interval).
+pub const DEFAULT_INDEX_COMMIT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The writer of the full text index, committing changes in the background.
+///
+/// Every commit writes and fsyncs a new segment, which is too slow to do for
+/// every edited note. Instead, changes made through [`Self::change`] mark the
+/// index dirty, and a thread commits it every `interval` if it is. The thread
+/// also reloads the reader of the searches, so that they see the changes.
+///
+/// Without an interval, every change is committed right away. In-memory
+/// stores do this, since they have nothing to fsync.
+pub struct SearchIndexWriter {
+    index: Arc<Index>,
+    reader: IndexReader,
+    writer: Arc<Mutex<tantivy::IndexWriter>>,
+    dirty: Arc<AtomicBool>,
+    /// Set when the store is dropped, to stop the committing thread.
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    committer: Option<thread::JoinHandle<()>>,
+}
+
+/// Changes to the index, which are committed after the guard is dropped.
+pub struct IndexChange<'a> {
+    writer: MutexGuard<'a, tantivy::IndexWriter>,
+    owner: &'a SearchIndexWriter,
+}
+
+impl std::ops::Deref for IndexChange<'_> {
+    type Target = tantivy::IndexWriter;
+
+    fn deref(&self) -> &Self::Target {
+        &self.writer
+    }
+}
+
+impl std::ops::DerefMut for IndexChange<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.writer
+    }
+}
+
+impl Drop for IndexChange<'_> {
+    fn drop(&mut self) {
+        // Marked while still holding the writer, so that the committing
+        // thread can't clear the flag in between.
+        self.owner.dirty.store(true, Ordering::Release);
+        if self.owner.committer.is_none() {
+            if let Err(e) = commit_pending(&mut self.writer, &self.owner.dirty, &self.owner.reader)
+            {
+                tracing::error!("Committing the search index failed: {}", e);
+            }
+        }
+    }
+}
+
+impl SearchIndexWriter {
+    /// Fails if the index can't be opened for writing, e.g. because another
+    /// store still has it open.
+    pub fn new(index: Arc<Index>, interval: Option<Duration>) -> tantivy::Result<Self> {
+        let writer = Arc::new(Mutex::new(index.writer(5_000_000)?));
+        // Reloaded after each commit instead of watching the index files.
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let dirty = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let committer = interval.map(|interval| {
+            let writer = writer.clone();
+            let reader = IndexReader::clone(&reader);
+            let dirty = dirty.clone();
+            let stop = stop.clone();
+            thread::spawn(move || commit_periodically(interval, &writer, &reader, &dirty, &stop))
+        });
+        Ok(Self {
+            index,
+            reader,
+            writer,
+            dirty,
+            stop,
+            committer,
+        })
+    }
 
-    let end_idx = std::cmp::min(best_words.len(), good_word_xount);
-    if end_idx == 0 {
-        return Ok(Vec::new());
+    pub fn index(&self) -> &Index {
+        &self.index
     }
 
-    let search = best_words[..end_idx].to_vec();
+    /// The reader for searches, which sees the committed changes.
+    pub fn reader(&self) -> IndexReader {
+        self.reader.clone()
+    }
 
-    tracing::debug!("Searching for: {}", search.join(" OR "));
+    /// Locks the writer for making changes, see [`IndexChange`].
+    pub fn change(&self) -> IndexChange<'_> {
+        IndexChange {
+            writer: self.writer.lock().unwrap_or_else(|e| e.into_inner()),
+            owner: self,
+        }
+    }
 
-    search_text(index, reader, conn, search, 20)
+    /// Commits the pending changes now instead of waiting for the thread.
+    pub fn commit(&self) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        commit_pending(&mut writer, &self.dirty, &self.reader)
+    }
+}
+
+impl Drop for SearchIndexWriter {
+    fn drop(&mut self) {
+        let Some(committer) = self.committer.take() else { return };
+        let (stopped, wake) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wake.notify_one();
+        // The thread commits what's left before it exits.
+        if committer.join().is_err() {
+            tracing::error!("The search index commit thread panicked");
+        }
+    }
+}
+
+/// Commits the index and reloads `reader` if there are changes.
+fn commit_pending(
+    writer: &mut tantivy::IndexWriter,
+    dirty: &AtomicBool,
+    reader: &IndexReader,
+) -> tantivy::Result<()> {
+    if !dirty.swap(false, Ordering::AcqRel) {
+        return Ok(());
+    }
+    if let Err(e) = writer.commit() {
+        // Tried again on the next round.
+        dirty.store(true, Ordering::Release);
+        return Err(e);
+    }
+    reader.reload()
 }
 
-use tantivy::{schema::*, Index, IndexReader, TantivyError};
+fn commit_periodically(
+    interval: Duration,
+    writer: &Mutex<tantivy::IndexWriter>,
+    reader: &IndexReader,
+    dirty: &AtomicBool,
+    stop: &(Mutex<bool>, Condvar),
+) {
+    let (stopped, wake) = stop;
+    loop {
+        let stopping = {
+            let stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+            let (stopped, _) = wake
+                .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+                .unwrap_or_else(|e| e.into_inner());
+            *stopped
+        };
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = commit_pending(&mut writer, dirty, reader) {
+            tracing::error!("Committing the search index failed: {}", e);
+        }
+        if stopping {
+            return;
+        }
+    }
+}
 
 fn schema() -> Schema {
     let mut schema_builder = Schema::builder();
@@ -200,21 +756,21 @@ fn schema() -> Schema {
     schema_builder.build()
 }
 
-pub fn construct_tantivy_index(path: ConnectionType) -> Index {
+pub fn construct_tantivy_index(path: ConnectionType) -> tantivy::Result<Index> {
     let schema = schema();
     let index = match path {
         ConnectionType::InMemory => Index::create_in_ram(schema.clone()),
         ConnectionType::File(path) => {
             let path = path.join("tantivy");
-            std::fs::create_dir_all(&path).unwrap();
+            std::fs::create_dir_all(&path)?;
             let index = Index::create_in_dir(&path, schema.clone());
             match index {
                 Ok(index) => index,
                 Err(TantivyError::IndexAlreadyExists) => {
                     tracing::info!("Index already exists, opening it");
-                    Index::open_in_dir(&path).unwrap()
+                    Index::open_in_dir(&path)?
                 }
-                Err(e) => panic!("Failed to create index: {}", e),
+                Err(e) => return Err(e),
             }
         }
     };
@@ -224,18 +780,40 @@ pub fn construct_tantivy_index(path: ConnectionType) -> Index {
             .filter(tantivy::tokenizer::LowerCaser)
             .build(),
     );
-    index
+    Ok(index)
 }
 
 use tantivy::doc;
 
-pub fn fill_tantivy_index(writer: &mut tantivy::IndexWriter, conn: &Connection) {
+/// The names of the subjects of the note with id ?1, separated by newlines.
+pub const NOTE_SUBJECT_NAMES: &str = "SELECT group_concat(s.name, char(10))
+    FROM notes_subjects ns
+    JOIN subjects s ON s.id = ns.subject_id
+    WHERE ns.note_id = ?1";
+
+/// The text indexed for a note, followed by its subject names if given.
+fn indexed_text(text: &str, subject_names: Option<&str>) -> String {
+    match subject_names {
+        Some(names) if !names.is_empty() => format!("{text}\n{names}"),
+        _ => text.to_string(),
+    }
+}
+
+/// Indexes all notes from scratch. With `subject_names`, the names of
+/// their subjects are indexed along with the text.
+///
+/// Nothing is committed, so that the whole rebuild lands in one commit.
+pub fn fill_tantivy_index(
+    writer: &mut tantivy::IndexWriter,
+    conn: &Connection,
+    subject_names: bool,
+) {
     writer.delete_all_documents().unwrap();
-    writer.commit().unwrap();
 
     let mut stmt = conn
-        .prepare_cached("SELECT rowid, text FROM notes")
+        .prepare_cached("SELECT rowid, id, text FROM notes WHERE indexed")
         .unwrap();
+    let mut names_stmt = conn.prepare_cached(NOTE_SUBJECT_NAMES).unwrap();
     let mut rows = stmt.query([]).unwrap();
 
     let schema = schema();
@@ -244,23 +822,34 @@ pub fn fill_tantivy_index(writer: &mut tantivy::IndexWriter, conn: &Connection)
 
     while let Some(row) = rows.next().unwrap() {
         let id: u64 = row.get(0).unwrap();
-        let text: String = row.get(1).unwrap();
+        let note_id: NoteId = row.get(1).unwrap();
+        let text: String = row.get(2).unwrap();
+        let names = subject_names
+            .then(|| {
+                names_stmt
+                    .query_row([note_id], |row| row.get::<_, Option<String>>(0))
+                    .unwrap()
+            })
+            .flatten();
         let doc = doc!(
             id_schema => id,
-            text_schema => text,
+            text_schema => indexed_text(&text, names.as_deref()),
         );
         writer.add_document(doc).unwrap();
     }
-
-    writer.commit().unwrap();
 }
 
-pub fn tantivy_add_note(writer: &mut tantivy::IndexWriter, note: &NoteData) -> tantivy::Result<()> {
+/// Indexes `note`, along with `subject_names` if given.
+pub fn tantivy_add_note(
+    writer: &mut tantivy::IndexWriter,
+    note: &NoteData,
+    subject_names: Option<&str>,
+) -> tantivy::Result<()> {
     let schema = schema();
     let id_schema = schema.get_field("id").unwrap();
     let text_schema = schema.get_field("text").unwrap();
 
-    let text = note.text.clone();
+    let text = indexed_text(&note.text, subject_names);
     let id = note.rowid as u64;
 
     let doc = doc!(
@@ -268,72 +857,184 @@ pub fn tantivy_add_note(writer: &mut tantivy::IndexWriter, note: &NoteData) -> t
         text_schema => text,
     );
     writer.add_document(doc).unwrap();
-    writer.commit().unwrap();
 
     Ok(())
 }
 
+/// Removes the note with `rowid` from the index.
 pub fn tantivy_remove_note(writer: &mut tantivy::IndexWriter, rowid: i64) -> tantivy::Result<()> {
     let schema = schema();
     let id_schema = schema.get_field("id").unwrap();
 
     writer.delete_term(Term::from_field_u64(id_schema, rowid as u64));
-    writer.commit().unwrap();
 
     Ok(())
 }
 
-#[tracing::instrument(skip(index, reader, conn))]
-fn tantivy_find_notes(
-    index: &tantivy::Index,
-    reader: &tantivy::IndexReader,
-    conn: &Connection,
-    text: &str,
-    limit: usize,
-) -> tantivy::Result<Vec<NoteData>> {
+/// Removes all of `rowids` from the index.
+pub fn tantivy_remove_notes(
+    writer: &mut tantivy::IndexWriter,
+    rowids: &[i64],
+) -> tantivy::Result<()> {
+    let schema = schema();
+    let id_schema = schema.get_field("id").unwrap();
+
+    for &rowid in rowids {
+        writer.delete_term(Term::from_field_u64(id_schema, rowid as u64));
+    }
+
+    Ok(())
+}
+
+/// Parses a search string into a tantivy query over the text field.
+/// Returns `None` if the string is empty or can't be parsed.
+fn parse_text_query(index: &tantivy::Index, text: &str) -> Option<Box<dyn tantivy::query::Query>> {
     if text.is_empty() {
         tracing::trace!("Ignoring empty search");
-        return Ok(Vec::new());
+        return None;
     }
 
     let schema = schema();
-    let id_schema = schema.get_field("id").unwrap();
     let text_schema = schema.get_field("text").unwrap();
 
-    let searcher = reader.searcher();
     let query_parser = tantivy::query::QueryParser::for_index(index, vec![text_schema]);
-    let query = query_parser.parse_query(text);
-
-    let query = match query {
-        Ok(query) => query,
+    match query_parser.parse_query(text) {
+        Ok(query) => Some(query),
         Err(QueryParserError::UnknownTokenizer { .. }) => {
             tracing::debug!("Unknown to tokenizer");
-            return Ok(Vec::new());
+            None
         }
         Err(e) => {
             tracing::error!("Failed to parse query: {}", e);
-            return Ok(Vec::new());
+            None
         }
+    }
+}
+
+#[tracing::instrument(skip(index, reader, conn))]
+fn tantivy_count_notes(
+    index: &tantivy::Index,
+    reader: &tantivy::IndexReader,
+    conn: &Connection,
+    text: &str,
+    subject: Option<SubjectId>,
+    range: DateRange,
+) -> SearchResult<usize> {
+    let in_range = if range.is_bounded() {
+        Some(rowids_in_range(conn, range)?)
+    } else {
+        None
     };
+    let rowids = match (in_range, text.is_empty()) {
+        // Without text, every note in the range matches.
+        (Some(in_range), true) => in_range.into_iter().map(|rowid| rowid as u64).collect(),
+        (in_range, _) => {
+            let Some(query) = parse_text_query(index, text) else { return Ok(0) };
+            let query = match in_range {
+                Some(in_range) => restrict_to_rowids(query, &in_range.into_iter().collect()),
+                None => query,
+            };
+            let searcher = reader.searcher();
+            if subject.is_none() {
+                return Ok(searcher.search(&query, &tantivy::collector::Count)?);
+            }
+
+            // Intersecting with a subject needs the row ids, but still not the notes themselves.
+            let schema = schema();
+            let id_schema = schema.get_field("id").unwrap();
+            let doc_addresses = searcher.search(&query, &tantivy::collector::DocSetCollector)?;
+            let mut rowids = Vec::new();
+            for doc_address in doc_addresses {
+                let retrieved_doc = searcher.doc(doc_address)?;
+                if let Some(rowid) = retrieved_doc.get_first(id_schema).and_then(|v| v.as_u64()) {
+                    rowids.push(rowid);
+                }
+            }
+            rowids
+        }
+    };
+
+    let Some(subject) = subject else { return Ok(rowids.len()) };
+    let mut stmt = conn.prepare_cached(
+        "SELECT 1 FROM notes n
+            JOIN notes_search s ON s.note_id = n.id
+            WHERE n.rowid = ?1 AND s.subject_id = ?2
+            LIMIT 1",
+    )?;
 
-    let top_docs = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(limit))?;
+    let mut count = 0;
+    for rowid in rowids {
+        if stmt.exists(rusqlite::params![rowid, subject])? {
+            count += 1;
+        }
+    }
 
+    Ok(count)
+}
+
+/// The row ids of the searchable notes created in `range`, newest first.
+fn rowids_in_range(conn: &Connection, range: DateRange) -> rusqlite::Result<Vec<i64>> {
+    let nanos = |time: Option<DateTime<Local>>, open: i64| {
+        time.map_or(open, |time| time.naive_utc().timestamp_nanos())
+    };
+    conn.prepare_cached(
+        "SELECT rowid FROM notes
+            WHERE indexed = 1 AND created_at >= ?1 AND created_at < ?2
+            ORDER BY created_at DESC",
+    )?
+    .query_map(
+        rusqlite::params![nanos(range.after, i64::MIN), nanos(range.before, i64::MAX)],
+        |row| row.get(0),
+    )?
+    .collect()
+}
+
+#[tracing::instrument(
+    level = "debug",
+    skip(index, reader, conn, cache, within),
+    fields(results, elapsed_ms)
+)]
+fn tantivy_find_notes(
+    index: &tantivy::Index,
+    reader: &tantivy::IndexReader,
+    conn: &Connection,
+    cache: &mut HitCache,
+    text: &str,
+    within: Option<&HashSet<i64>>,
+    limit: usize,
+) -> SearchResult<Vec<NoteData>> {
+    let start = Instant::now();
+    let searcher = reader.searcher();
+    let generation = searcher.generation().generation_id();
+    let rowids = match within {
+        None => cache.get_or_search(generation, (text.to_string(), limit), || {
+            tantivy_find_rowids(index, &searcher, text, None, limit)
+        })?,
+        // The candidates change with every refinement, so caching won't help.
+        Some(within) => tantivy_find_rowids(index, &searcher, text, Some(within), limit)?,
+    };
+
+    let notes = load_notes(conn, rowids)?;
+
+    let span = tracing::Span::current();
+    span.record("results", notes.len());
+    span.record("elapsed_ms", elapsed_ms(start));
+    Ok(notes)
+}
+
+/// Reads the notes in the order of `rowids`, skipping those that are gone.
+fn load_notes(
+    conn: &Connection,
+    rowids: impl IntoIterator<Item = u64>,
+) -> SearchResult<Vec<NoteData>> {
     let db_queey = format!(
         "SELECT {} FROM notes n WHERE rowid = ?",
         notes::SINGLE_NOTE_COLUMNS
     );
-    let mut stmt = conn.prepare_cached(&db_queey).unwrap();
-
-    tracing::trace!("Found {} results", top_docs.len());
+    let mut stmt = conn.prepare_cached(&db_queey)?;
 
     let mut notes = Vec::new();
-    for (_score, doc_address) in top_docs {
-        let retrieved_doc = searcher.doc(doc_address)?;
-        let rowid = retrieved_doc
-            .get_first(id_schema)
-            .unwrap()
-            .as_u64()
-            .unwrap();
+    for rowid in rowids {
         let note = stmt.query_row([rowid], notes::map_row_to_note);
         let note = match note {
             Ok(note) => note,
@@ -348,6 +1049,182 @@ fn tantivy_find_notes(
         tracing::trace!("Found note: {:?}", note);
         notes.push(note);
     }
-
     Ok(notes)
 }
+
+/// Returns the row ids of the best matching notes, best first.
+/// If `within` is given, only those notes are considered.
+fn tantivy_find_rowids(
+    index: &tantivy::Index,
+    searcher: &tantivy::Searcher,
+    text: &str,
+    within: Option<&HashSet<i64>>,
+    limit: usize,
+) -> SearchResult<Vec<u64>> {
+    let Some(query) = parse_text_query(index, text) else { return Ok(Vec::new()) };
+    let query = match within {
+        Some(rowids) => restrict_to_rowids(query, rowids),
+        None => query,
+    };
+    let hits = top_scored_rowids(searcher, &*query, limit)?;
+    Ok(hits.into_iter().map(|(rowid, _score)| rowid).collect())
+}
+
+/// Intersects `query` with the notes in `rowids`, so that the limit of a
+/// search applies after leaving the other notes out.
+fn restrict_to_rowids(
+    query: Box<dyn tantivy::query::Query>,
+    rowids: &HashSet<i64>,
+) -> Box<dyn tantivy::query::Query> {
+    let id_schema = schema().get_field("id").unwrap();
+    let terms = rowids
+        .iter()
+        .map(|&rowid| Term::from_field_u64(id_schema, rowid as u64));
+    Box::new(tantivy::query::BooleanQuery::intersection(vec![
+        query,
+        Box::new(tantivy::query::TermSetQuery::new(terms)),
+    ]))
+}
+
+/// Returns the row ids of the best matching notes with their scores, best first.
+pub(super) fn tantivy_scored_rowids(
+    index: &tantivy::Index,
+    searcher: &tantivy::Searcher,
+    text: &str,
+    limit: usize,
+) -> SearchResult<Vec<(u64, f32)>> {
+    let Some(query) = parse_text_query(index, text) else { return Ok(Vec::new()) };
+    top_scored_rowids(searcher, &*query, limit)
+}
+
+fn top_scored_rowids(
+    searcher: &tantivy::Searcher,
+    query: &dyn tantivy::query::Query,
+    limit: usize,
+) -> SearchResult<Vec<(u64, f32)>> {
+    let schema = schema();
+    let id_schema = schema.get_field("id").unwrap();
+
+    let top_docs = searcher.search(query, &tantivy::collector::TopDocs::with_limit(limit))?;
+    tracing::trace!("Found {} results", top_docs.len());
+
+    let mut rowids = Vec::new();
+    for (score, doc_address) in top_docs {
+        let retrieved_doc = searcher.doc(doc_address)?;
+        if let Some(rowid) = retrieved_doc.get_first(id_schema).and_then(|v| v.as_u64()) {
+            rowids.push((rowid, score));
+        }
+    }
+
+    Ok(rowids)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hit_cache_is_per_generation() {
+        let mut cache = HitCache::default();
+        let key = || ("(rust)".to_string(), 10);
+
+        let hits = cache.get_or_search(1, key(), || Ok(vec![1, 2])).unwrap();
+        assert_eq!(hits, vec![1, 2]);
+
+        let hits = cache
+            .get_or_search(1, key(), || panic!("should be cached"))
+            .unwrap();
+        assert_eq!(hits, vec![1, 2]);
+
+        let hits = cache.get_or_search(2, key(), || Ok(vec![3])).unwrap();
+        assert_eq!(hits, vec![3]);
+    }
+
+    #[test]
+    fn failed_search_is_not_cached() {
+        let mut cache = HitCache::default();
+        let key = || ("(rust)".to_string(), 10);
+
+        let result = cache.get_or_search(1, key(), || Err(SearchError("failed".to_string())));
+        assert!(result.is_err());
+
+        let hits = cache.get_or_search(1, key(), || Ok(vec![1])).unwrap();
+        assert_eq!(hits, vec![1]);
+    }
+
+    #[test]
+    fn index_changes_are_committed_later() {
+        let index = Arc::new(construct_tantivy_index(ConnectionType::InMemory).unwrap());
+        let id_field = schema().get_field("id").unwrap();
+        let add = |writer: &SearchIndexWriter, id: u64| {
+            writer.change().add_document(doc!(id_field => id)).unwrap();
+        };
+        let reader = index.reader().unwrap();
+        let committed = || {
+            reader.reload().unwrap();
+            reader.searcher().num_docs()
+        };
+
+        let writer =
+            SearchIndexWriter::new(index.clone(), Some(Duration::from_secs(3600))).unwrap();
+        add(&writer, 1);
+        assert_eq!(committed(), 0);
+        writer.commit().unwrap();
+        assert_eq!(committed(), 1);
+        assert_eq!(writer.reader().searcher().num_docs(), 1);
+
+        // Dropping the writer commits what's pending.
+        add(&writer, 2);
+        drop(writer);
+        assert_eq!(committed(), 2);
+
+        let writer =
+            SearchIndexWriter::new(index.clone(), Some(Duration::from_millis(10))).unwrap();
+        add(&writer, 3);
+        let start = Instant::now();
+        while writer.reader().searcher().num_docs() < 3 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(writer);
+
+        let writer = SearchIndexWriter::new(index, None).unwrap();
+        add(&writer, 4);
+        assert_eq!(writer.reader().searcher().num_docs(), 4);
+    }
+
+    #[test]
+    fn dropping_the_store_stops_the_search_thread() {
+        use crate::data::{ConnectionType, Store};
+
+        for _ in 0..100 {
+            let store = Store::new(ConnectionType::InMemory);
+            let worker = store.search.clone();
+            drop(store);
+            assert!(worker.thread.lock().unwrap().is_none());
+            assert!(worker
+                .perform_search_blocking("anything".to_string())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn bulk_changes_are_committed_once() {
+        let index = Arc::new(construct_tantivy_index(ConnectionType::InMemory).unwrap());
+        let id_field = schema().get_field("id").unwrap();
+        let writer =
+            SearchIndexWriter::new(index.clone(), Some(Duration::from_secs(3600))).unwrap();
+
+        let start = Instant::now();
+        for id in 0..2_000u64 {
+            writer.change().add_document(doc!(id_field => id)).unwrap();
+        }
+        writer.commit().unwrap();
+        let elapsed = start.elapsed();
+
+        // Every commit would have written a segment of its own.
+        assert_eq!(index.searchable_segment_ids().unwrap().len(), 1);
+        assert_eq!(writer.reader().searcher().num_docs(), 2_000);
+        assert!(elapsed < Duration::from_secs(10), "took {elapsed:?}");
+    }
+}
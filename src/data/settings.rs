@@ -0,0 +1,408 @@
+//! Settings persisted as JSON files.
+//!
+//! Global settings live in the user's config directory and are shared by all vaults.
+//! Vault settings live in the vault's data folder, next to the database.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use tracing::warn;
+
+use super::{
+    backup::BackupConfig,
+    notes::{NoteId, SubjectMatch},
+    similarity::SimilarityParams,
+    subjects::SubjectId,
+};
+
+/// How many recently opened vaults are remembered.
+const MAX_RECENT_VAULTS: usize = 10;
+
+/// What saving an edit that leaves a note without text does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum EmptyEditPolicy {
+    /// The edit isn't saved and the note keeps its old text.
+    #[default]
+    KeepOldText,
+    /// The note is deleted. Like any deletion, this can be undone.
+    DeleteNote,
+}
+
+/// How the favorite subjects are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FavoriteOrder {
+    /// In the order the user arranged them.
+    #[default]
+    Manual,
+    Alphabetical,
+}
+
+/// Which key submits the note input. The other inserts a newline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SubmitKey {
+    /// Ctrl+Enter submits, Enter inserts a newline.
+    #[default]
+    CtrlEnter,
+    /// Enter submits, Shift+Enter inserts a newline. Suits quick capture.
+    Enter,
+}
+
+/// What clearing the done tasks of a subject does with them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ClearDonePolicy {
+    /// The tasks are deleted. Like any deletion, this can be undone.
+    #[default]
+    DeleteNotes,
+    /// The tasks are kept as plain notes.
+    Untask,
+}
+
+/// The color theme of the app.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Theme {
+    /// Follows the light or dark preference of the operating system.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// The theme after this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            Theme::System => Theme::Light,
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::System,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GlobalSettings {
+    /// Recently opened data folders, most recent first.
+    pub recent_vaults: Vec<PathBuf>,
+    /// Completed tasks are hidden from the task list this many days after
+    /// they were done. `None` keeps them visible.
+    pub hide_done_after_days: Option<u32>,
+    pub empty_edit: EmptyEditPolicy,
+    pub favorite_order: FavoriteOrder,
+    /// Render `@Name` mentions of existing subjects in notes as subject chips.
+    /// Off by default, since `@` is also used for other things like emails.
+    pub subject_mentions: bool,
+    /// Start notes copied as Markdown with their date and subjects.
+    pub copy_metadata_header: bool,
+    /// Unsaved note input is written to the vault every this many seconds,
+    /// so it can be restored after a crash. 0 turns autosaving off.
+    pub draft_autosave_secs: u32,
+    /// The note input grows with its text up to this many pixels, then scrolls.
+    /// `None` lets it grow without a limit.
+    pub note_input_max_height: Option<u32>,
+    /// Keep the reading list of each vault between sessions.
+    pub persist_reading_list: bool,
+    pub submit_key: SubmitKey,
+    /// How widely the side panel looks for notes similar to the one being written.
+    pub similar_notes: SimilarityParams,
+    /// Show notes similar to the one being written in the side panel while
+    /// typing. When off, they're only looked up from the note input's button.
+    pub auto_similar_notes: bool,
+    /// Convert HTML pasted into the note input, e.g. from a browser, to Markdown.
+    /// When off, pasting inserts the plain text.
+    pub rich_paste: bool,
+    /// Edit notes in the side panel instead of in place in the list.
+    pub edit_in_side_panel: bool,
+    /// Show the ids of notes and subjects, for bug reports and scripting.
+    pub show_debug_info: bool,
+    pub clear_done: ClearDonePolicy,
+    pub theme: Theme,
+    /// Ask before closing the window while a note input has text in it.
+    pub confirm_quit_with_unsaved_text: bool,
+    /// Clients of the local API have to present this token.
+    pub api_token: Option<String>,
+    /// On launch, the vault is backed up if its latest backup is older than
+    /// this many hours. `None` turns backups off.
+    pub backup_interval_hours: Option<u32>,
+    /// How many of the latest backups are kept.
+    pub backups_kept: usize,
+}
+
+impl Default for GlobalSettings {
+    fn default() -> Self {
+        Self {
+            recent_vaults: Vec::new(),
+            hide_done_after_days: None,
+            empty_edit: EmptyEditPolicy::default(),
+            favorite_order: FavoriteOrder::default(),
+            subject_mentions: false,
+            copy_metadata_header: false,
+            draft_autosave_secs: 5,
+            note_input_max_height: Some(600),
+            persist_reading_list: false,
+            submit_key: SubmitKey::default(),
+            similar_notes: SimilarityParams::default(),
+            auto_similar_notes: true,
+            rich_paste: true,
+            edit_in_side_panel: false,
+            show_debug_info: false,
+            clear_done: ClearDonePolicy::default(),
+            theme: Theme::default(),
+            confirm_quit_with_unsaved_text: true,
+            api_token: None,
+            backup_interval_hours: Some(24),
+            backups_kept: 7,
+        }
+    }
+}
+
+impl GlobalSettings {
+    /// The global settings file, or `None` if no config directory can be determined.
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                })
+        };
+        config_dir.map(|dir| dir.join("emergence").join("settings.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::path()
+            .map(|path| load_json(&path))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = Self::path() {
+            save_json(&path, self);
+        }
+    }
+
+    /// Tasks completed before the returned time should be hidden.
+    /// Counted from the start of `today`, so the cutoff stays the same all day.
+    pub fn hide_done_before(&self, today: NaiveDate) -> Option<DateTime<Local>> {
+        let days = self.hide_done_after_days?;
+        let day = today - chrono::Duration::days(days.into());
+        Local
+            .from_local_datetime(&day.and_hms_opt(0, 0, 0)?)
+            .earliest()
+    }
+
+    /// How often drafts are autosaved, or `None` if autosaving is off.
+    pub fn draft_autosave_interval(&self) -> Option<std::time::Duration> {
+        let secs = self.draft_autosave_secs;
+        (secs > 0).then(|| std::time::Duration::from_secs(secs.into()))
+    }
+
+    /// How vaults are backed up, or `None` if backups are off.
+    pub fn backup_config(&self) -> Option<BackupConfig> {
+        Some(BackupConfig {
+            interval: chrono::Duration::hours(self.backup_interval_hours?.into()),
+            keep: self.backups_kept,
+        })
+    }
+
+    /// Returns the API token, generating and saving a new one if there is none.
+    pub fn api_token(&mut self) -> String {
+        if let Some(token) = &self.api_token {
+            return token.clone();
+        }
+        let token = format!("{:032x}", rand::random::<u128>());
+        self.api_token = Some(token.clone());
+        self.save();
+        token
+    }
+
+    /// Moves `vault` to the front of the recent vaults list.
+    pub fn add_recent_vault(&mut self, vault: &Path) {
+        let vault = std::fs::canonicalize(vault).unwrap_or_else(|_| vault.to_owned());
+        self.recent_vaults.retain(|v| *v != vault);
+        self.recent_vaults.insert(0, vault);
+        self.recent_vaults.truncate(MAX_RECENT_VAULTS);
+    }
+}
+
+/// A named set of subjects whose notes are listed together.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SavedFilter {
+    pub name: String,
+    pub subjects: Vec<SubjectId>,
+    pub subject_match: SubjectMatch,
+}
+
+/// Preferences for one subject.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SubjectSettings {
+    /// Notes added in the subject start as tasks, like in the task list.
+    pub default_new_is_task: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct VaultSettings {
+    /// Subject given to notes created in the journal.
+    /// `None` leaves them without a subject.
+    pub journal_subject: Option<SubjectId>,
+    pub saved_filters: Vec<SavedFilter>,
+    /// Only kept when [`GlobalSettings::persist_reading_list`] is set.
+    pub reading_list: Vec<NoteId>,
+    pub subjects: BTreeMap<SubjectId, SubjectSettings>,
+    /// Index the names of notes' subjects with their text, so that searching
+    /// for a subject finds its notes. Existing notes need a reindex.
+    pub index_subject_names: bool,
+    /// How often changes to the search index are committed, in milliseconds.
+    /// Searches see changes once they're committed.
+    pub index_commit_interval_ms: Option<u64>,
+}
+
+impl VaultSettings {
+    fn path(vault: &Path) -> PathBuf {
+        vault.join("settings.json")
+    }
+
+    pub fn load(vault: &Path) -> Self {
+        load_json(&Self::path(vault))
+    }
+
+    /// See [`Self::index_commit_interval_ms`], defaulting to
+    /// [`DEFAULT_INDEX_COMMIT_INTERVAL`](super::search::DEFAULT_INDEX_COMMIT_INTERVAL).
+    pub fn index_commit_interval(&self) -> std::time::Duration {
+        self.index_commit_interval_ms
+            .map_or(super::search::DEFAULT_INDEX_COMMIT_INTERVAL, |ms| {
+                std::time::Duration::from_millis(ms.max(1))
+            })
+    }
+
+    pub fn save(&self, vault: &Path) {
+        save_json(&Self::path(vault), self);
+    }
+}
+
+/// Reads settings from `path`, falling back to the defaults if the file
+/// is missing or invalid.
+pub(crate) fn load_json<T: Default + serde::de::DeserializeOwned>(path: &Path) -> T {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return T::default(),
+    };
+    match serde_json::from_reader(std::io::BufReader::new(file)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Invalid settings file {}: {}", path.display(), e);
+            T::default()
+        }
+    }
+}
+
+pub(crate) fn save_json<T: serde::Serialize>(path: &Path, settings: &T) {
+    let result = (|| {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, settings)?;
+        std::io::Result::Ok(())
+    })();
+    if let Err(e) = result {
+        warn!("Failed to save settings to {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recent_vaults_are_deduplicated_and_bounded() {
+        let mut settings = GlobalSettings::default();
+        for i in 0..MAX_RECENT_VAULTS + 2 {
+            settings.add_recent_vault(Path::new(&format!("vault-{i}")));
+        }
+        settings.add_recent_vault(Path::new("vault-5"));
+
+        assert_eq!(settings.recent_vaults.len(), MAX_RECENT_VAULTS);
+        assert_eq!(settings.recent_vaults[0], PathBuf::from("vault-5"));
+        assert_eq!(
+            settings
+                .recent_vaults
+                .iter()
+                .filter(|v| *v == Path::new("vault-5"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn hide_done_cutoff_is_start_of_day() {
+        let today = NaiveDate::from_ymd_opt(2023, 8, 10).unwrap();
+        let mut settings = GlobalSettings::default();
+        assert_eq!(settings.hide_done_before(today), None);
+
+        settings.hide_done_after_days = Some(7);
+        let cutoff = settings.hide_done_before(today).unwrap();
+        assert_eq!(
+            cutoff.naive_local(),
+            NaiveDate::from_ymd_opt(2023, 8, 3)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("settings.json");
+
+        let mut settings = GlobalSettings::default();
+        settings.add_recent_vault(Path::new("vault"));
+        settings.submit_key = SubmitKey::Enter;
+        settings.theme = Theme::Dark;
+        settings.confirm_quit_with_unsaved_text = false;
+        save_json(&path, &settings);
+
+        assert_eq!(load_json::<GlobalSettings>(&path), settings);
+        assert_eq!(
+            load_json::<GlobalSettings>(&dir.path().join("missing.json")),
+            GlobalSettings::default()
+        );
+    }
+
+    #[test]
+    fn vault_settings_are_per_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let other = dir.path().join("other");
+
+        let subject = SubjectId(uuid::Uuid::new_v4());
+        let settings = VaultSettings {
+            journal_subject: Some(subject),
+            saved_filters: vec![SavedFilter {
+                name: "Projects".to_string(),
+                subjects: vec![subject],
+                subject_match: SubjectMatch::Any,
+            }],
+            reading_list: vec![NoteId(uuid::Uuid::new_v4())],
+            subjects: BTreeMap::from([(
+                subject,
+                SubjectSettings {
+                    default_new_is_task: true,
+                },
+            )]),
+            index_subject_names: true,
+            index_commit_interval_ms: Some(500),
+        };
+        settings.save(&vault);
+
+        assert_eq!(VaultSettings::load(&vault), settings);
+        assert_eq!(VaultSettings::load(&other), VaultSettings::default());
+    }
+}
@@ -1,6 +1,28 @@
 use rusqlite::{params, Connection, Result};
 
-use super::tfidf;
+use super::{subjects, tfidf};
+
+/// The rows `notes_search` should have, as its triggers keep them.
+pub(super) const EXPECTED_NOTES_SEARCH: &str = "
+    SELECT
+        notes_subjects.note_id,
+        notes_subjects.subject_id,
+        notes.task_state,
+        notes.created_at
+    FROM notes_subjects
+    JOIN notes ON notes.id = notes_subjects.note_id
+";
+
+/// Fills `notes_search` from the note subjects. Returns how many rows were added.
+pub(super) fn fill_notes_search(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        &format!(
+            "INSERT INTO notes_search (note_id, subject_id, task_state, created_at)
+            {EXPECTED_NOTES_SEARCH}"
+        ),
+        [],
+    )
+}
 
 pub fn setup_tables(conn: &mut Connection) -> Result<()> {
     conn.execute_batch(
@@ -11,6 +33,9 @@ pub fn setup_tables(conn: &mut Connection) -> Result<()> {
             id BLOB PRIMARY KEY,
             name TEXT NOT NULL,
             parent_id BLOB,
+            favorite INTEGER NOT NULL DEFAULT 0,
+            favorite_order INTEGER NOT NULL DEFAULT 0,
+            color TEXT,
             UNIQUE (name, parent_id)
         ) WITHOUT ROWID, STRICT;
 
@@ -22,7 +47,12 @@ pub fn setup_tables(conn: &mut Connection) -> Result<()> {
             task_state INTEGER NOT NULL DEFAULT 0,
             created_at INTEGER NOT NULL,
             modified_at INTEGER NOT NULL,
-            done_at INTEGER
+            done_at INTEGER,
+            -- 0 = left out of the search index and term counts
+            indexed INTEGER NOT NULL DEFAULT 1,
+            reminder_at INTEGER,
+            pinned INTEGER NOT NULL DEFAULT 0,
+            due_at INTEGER
         ) STRICT;
 
         CREATE UNIQUE INDEX IF NOT EXISTS notes_id_index ON notes (id);
@@ -73,26 +103,54 @@ pub fn setup_tables(conn: &mut Connection) -> Result<()> {
             term TEXT PRIMARY KEY,
             count INTEGER NOT NULL
         ) WITHOUT ROWID, STRICT;
+
+        CREATE TABLE IF NOT EXISTS focus_sessions (
+            note_id BLOB NOT NULL,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER NOT NULL
+        ) STRICT;
+
+        CREATE INDEX IF NOT EXISTS focus_sessions_note_index
+            ON focus_sessions (note_id);
+
+        CREATE TABLE IF NOT EXISTS note_links (
+            from_id BLOB NOT NULL,
+            to_id BLOB NOT NULL,
+            in_text INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (from_id, to_id)
+        ) WITHOUT ROWID, STRICT;
+
+        CREATE INDEX IF NOT EXISTS note_links_to_index
+            ON note_links (to_id);
+
+        CREATE TABLE IF NOT EXISTS undo_history (
+            queue TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            action TEXT NOT NULL,
+            PRIMARY KEY (queue, position)
+        ) WITHOUT ROWID, STRICT;
     "#,
     )?;
 
+    // Needed before the term counts below are filled.
+    let indexed_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('notes')
+            WHERE name = 'indexed' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !indexed_exists {
+        conn.execute_batch("ALTER TABLE notes ADD COLUMN indexed INTEGER NOT NULL DEFAULT 1;")?;
+    }
+
     let search_index_count = conn
         .prepare_cached("SELECT COUNT(*) FROM notes_search")?
         .query_row(params![], |row| row.get::<_, i64>(0))?;
 
     if search_index_count == 0 {
-        conn.execute_batch(
-            r#"
-            INSERT INTO notes_search (
-                note_id, subject_id, task_state, created_at)
-            SELECT
-                note_id,
-                subject_id,
-                (SELECT task_state FROM notes WHERE id = note_id),
-                (SELECT created_at FROM notes WHERE id = note_id)
-            FROM notes_subjects;
-        "#,
-        )?;
+        fill_notes_search(conn)?;
     }
 
     let text_occurences_count = conn
@@ -118,6 +176,41 @@ pub fn setup_tables(conn: &mut Connection) -> Result<()> {
         conn.execute_batch("ALTER TABLE subjects ADD COLUMN parent_id BLOB;")?;
     }
 
+    // Walking the subject tree looks up children by parent.
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS subjects_parent_index ON subjects (parent_id);",
+    )?;
+    subjects::reparent_orphans(conn)?;
+
+    let favorite_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('subjects')
+            WHERE name = 'favorite' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !favorite_exists {
+        conn.execute_batch(
+            "
+            ALTER TABLE subjects ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE subjects ADD COLUMN favorite_order INTEGER NOT NULL DEFAULT 0;
+        ",
+        )?;
+    }
+
+    let color_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('subjects')
+            WHERE name = 'color' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !color_exists {
+        conn.execute_batch("ALTER TABLE subjects ADD COLUMN color TEXT;")?;
+    }
+
     let done_at_exists = conn
         .prepare_cached(
             "SELECT * FROM pragma_table_info('notes')
@@ -133,5 +226,60 @@ pub fn setup_tables(conn: &mut Connection) -> Result<()> {
         ")?;
     }
 
+    let reminder_at_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('notes')
+            WHERE name = 'reminder_at' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !reminder_at_exists {
+        conn.execute_batch("ALTER TABLE notes ADD COLUMN reminder_at INTEGER;")?;
+    }
+    // Few notes have a reminder, and the index only holds those.
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS notes_reminder_index
+            ON notes (reminder_at) WHERE reminder_at IS NOT NULL;",
+    )?;
+
+    let pinned_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('notes')
+            WHERE name = 'pinned' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !pinned_exists {
+        conn.execute_batch("ALTER TABLE notes ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;")?;
+    }
+
+    let due_at_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('notes')
+            WHERE name = 'due_at' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !due_at_exists {
+        conn.execute_batch("ALTER TABLE notes ADD COLUMN due_at INTEGER;")?;
+    }
+
+    let in_text_exists = conn
+        .prepare_cached(
+            "SELECT * FROM pragma_table_info('note_links')
+            WHERE name = 'in_text' LIMIT 1",
+        )?
+        .query_row(params![], |_| Ok(()))
+        .is_ok();
+
+    if !in_text_exists {
+        conn.execute_batch(
+            "ALTER TABLE note_links ADD COLUMN in_text INTEGER NOT NULL DEFAULT 0;",
+        )?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,214 @@
+//! Backends for finding notes similar to a piece of text.
+//!
+//! The backend is chosen when constructing the [`Store`](super::Store) and
+//! runs on the search thread. [`TfIdfBackend`] is the default.
+
+use std::collections::BTreeSet;
+
+use rusqlite::Connection;
+use tantivy::{Index, Searcher};
+
+use super::{
+    notes::NoteId,
+    search::{sanitize_text, tantivy_scored_rowids, SearchResult},
+    tfidf,
+};
+
+/// What a backend can read while looking for similar notes.
+pub struct SimilarityContext<'a> {
+    pub conn: &'a Connection,
+    pub index: &'a Index,
+    pub searcher: &'a Searcher,
+}
+
+/// How widely to look for similar notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SimilarityParams {
+    /// How many of the most distinctive words of the text are searched for.
+    /// Not every backend picks words.
+    pub words: usize,
+    /// Maximum number of notes returned.
+    pub limit: usize,
+}
+
+impl Default for SimilarityParams {
+    fn default() -> Self {
+        Self {
+            words: 5,
+            limit: 20,
+        }
+    }
+}
+
+pub trait SimilarityBackend: Send {
+    /// Returns up to `params.limit` notes similar to `text` with their
+    /// scores, most similar first.
+    fn similar(
+        &self,
+        cx: &SimilarityContext,
+        text: &str,
+        params: SimilarityParams,
+    ) -> SearchResult<Vec<(NoteId, f32)>>;
+}
+
+/// Searches the full text index for the words of `text` with the best
+/// TF-IDF scores.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TfIdfBackend;
+
+impl SimilarityBackend for TfIdfBackend {
+    fn similar(
+        &self,
+        cx: &SimilarityContext,
+        text: &str,
+        params: SimilarityParams,
+    ) -> SearchResult<Vec<(NoteId, f32)>> {
+        let best_words = tfidf::best_words(cx.conn, text)?;
+        let search = best_words
+            .iter()
+            .take(params.words)
+            .map(|word| sanitize_text(word))
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        if search.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = search.join(" OR ");
+        tracing::debug!("Searching for: {}", query);
+
+        let mut stmt = cx
+            .conn
+            .prepare_cached("SELECT id FROM notes WHERE rowid = ?")?;
+        let mut notes = Vec::new();
+        let rowids = tantivy_scored_rowids(cx.index, cx.searcher, &query, params.limit)?;
+        for (rowid, score) in rowids {
+            // The index may lag behind deletions.
+            if let Ok(id) = stmt.query_row([rowid], |row| row.get(0)) {
+                notes.push((id, score));
+            }
+        }
+        Ok(notes)
+    }
+}
+
+/// Scores every note by the share of the words of `text` it contains.
+/// Uses all of the words, regardless of [`SimilarityParams::words`].
+///
+/// Scans all notes, so it's mainly a reference for writing backends.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KeywordOverlapBackend;
+
+fn keywords(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl SimilarityBackend for KeywordOverlapBackend {
+    fn similar(
+        &self,
+        cx: &SimilarityContext,
+        text: &str,
+        params: SimilarityParams,
+    ) -> SearchResult<Vec<(NoteId, f32)>> {
+        let wanted = keywords(text);
+        if wanted.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = cx
+            .conn
+            .prepare_cached("SELECT id, text FROM notes WHERE indexed")?;
+        let mut rows = stmt.query([])?;
+        let mut scored = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: NoteId = row.get(0)?;
+            let note_text: String = row.get(1)?;
+            let overlap = keywords(&note_text).intersection(&wanted).count();
+            if overlap > 0 {
+                scored.push((id, overlap as f32 / wanted.len() as f32));
+            }
+        }
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(params.limit);
+        Ok(scored)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::{notes::NoteBuilder, ConnectionType, Store};
+
+    fn similar_texts(
+        store: &Store,
+        backend: &dyn SimilarityBackend,
+        text: &str,
+        params: SimilarityParams,
+    ) -> Vec<String> {
+        let conn = store.conn.borrow();
+        let index = store.index_writer.index();
+        let searcher = index.reader().unwrap().searcher();
+        let cx = SimilarityContext {
+            conn: &conn,
+            index,
+            searcher: &searcher,
+        };
+        let similar = backend.similar(&cx, text, params).unwrap();
+        drop(conn);
+        similar
+            .into_iter()
+            .map(|(id, _)| store.get_note(id).unwrap().text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn backends_find_similar_notes() {
+        let store = Store::new(ConnectionType::InMemory);
+        for text in [
+            "Planting tomatoes in the garden",
+            "Watering the garden tomatoes daily",
+            "Compiling rust code",
+        ] {
+            store.add_note(NoteBuilder::new().text(text)).unwrap();
+        }
+
+        let params = SimilarityParams::default();
+        let similar = similar_texts(&store, &KeywordOverlapBackend, "garden tomatoes", params);
+        assert_eq!(similar.len(), 2);
+        assert!(similar.iter().all(|text| text.contains("garden")));
+
+        let similar = similar_texts(&store, &TfIdfBackend, "compiling code", params);
+        assert_eq!(
+            similar.first().map(String::as_str),
+            Some("Compiling rust code")
+        );
+    }
+
+    #[test]
+    fn more_words_widen_the_search() {
+        let store = Store::new(ConnectionType::InMemory);
+        for text in ["Alpha stuff", "Beta things", "Beta again"] {
+            store.add_note(NoteBuilder::new().text(text)).unwrap();
+        }
+
+        // The rarer word is the more distinctive one.
+        let narrow = SimilarityParams {
+            words: 1,
+            ..Default::default()
+        };
+        let similar = similar_texts(&store, &TfIdfBackend, "alpha beta", narrow);
+        assert_eq!(similar, vec!["Alpha stuff"]);
+
+        let wide = SimilarityParams {
+            words: 2,
+            ..Default::default()
+        };
+        let similar = similar_texts(&store, &TfIdfBackend, "alpha beta", wide);
+        assert_eq!(similar.len(), 3);
+    }
+}
@@ -1,10 +1,16 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::rc::Rc;
 
-use rusqlite::{params, types::FromSql, ToSql};
-use tracing::{debug, instrument};
+use rusqlite::{params, types::FromSql, Connection, ToSql};
+use tracing::{debug, instrument, warn};
 use uuid::Uuid;
 
-use super::{notes::NoteId, Store};
+use super::{
+    notes::NoteId,
+    retry::{begin_write, commit_write},
+    settings::FavoriteOrder,
+    ParseIdError, Store,
+};
 
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
@@ -18,10 +24,33 @@ pub struct SubjectData {
     pub name: String,
     pub parent_id: Option<SubjectId>,
     pub children: Vec<SubjectId>,
+    #[serde(default)]
+    pub favorite: bool,
+    /// Manual position among the favorites, smallest first.
+    #[serde(default)]
+    pub favorite_order: i64,
+    /// Background color of the subject's chips, as `#rrggbb`.
+    #[serde(default)]
+    pub color: Option<String>,
 }
 
 pub type Subject = Rc<SubjectData>;
 
+/// How much a subject is in use, see [`Store::get_all_subjects_with_counts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubjectCounts {
+    pub subject: SubjectId,
+    pub notes: u64,
+    pub children: u64,
+}
+
+impl SubjectCounts {
+    /// Nothing would be lost by deleting the subject.
+    pub fn is_empty(&self) -> bool {
+        self.notes == 0 && self.children == 0
+    }
+}
+
 impl ToSql for SubjectId {
     fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
         self.0.to_sql()
@@ -40,6 +69,16 @@ impl std::fmt::Display for SubjectId {
     }
 }
 
+impl std::str::FromStr for SubjectId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(SubjectId)
+            .map_err(|e| ParseIdError::new("subject", s, e))
+    }
+}
+
 pub(crate) fn subject_list_from_db(
     row: &rusqlite::Row,
     idx: usize,
@@ -54,13 +93,368 @@ pub(crate) fn subject_list_from_db(
     Ok(subjects)
 }
 
+/// The favorites among `subjects`, in the order they're shown in.
+pub fn favorites<'a>(
+    subjects: impl IntoIterator<Item = &'a Subject>,
+    order: FavoriteOrder,
+) -> Vec<Subject> {
+    let mut favorites = subjects
+        .into_iter()
+        .filter(|s| s.favorite)
+        .cloned()
+        .collect::<Vec<_>>();
+    match order {
+        FavoriteOrder::Manual => {
+            favorites.sort_by(|a, b| (a.favorite_order, &a.name).cmp(&(b.favorite_order, &b.name)))
+        }
+        FavoriteOrder::Alphabetical => {
+            favorites.sort_by_key(|s| s.name.to_lowercase());
+        }
+    }
+    favorites
+}
+
+/// All subjects in depth-first tree order, siblings sorted by name.
+/// Subjects whose parent is missing are treated as roots.
+pub fn subjects_in_tree_order(subjects: &BTreeMap<SubjectId, Subject>) -> Vec<SubjectId> {
+    let by_name = |list: &mut Vec<&Subject>| list.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut roots = subjects
+        .values()
+        .filter(|s| !s.parent_id.is_some_and(|p| subjects.contains_key(&p)))
+        .collect::<Vec<_>>();
+    by_name(&mut roots);
+
+    let mut order = Vec::with_capacity(subjects.len());
+    let mut seen = HashSet::new();
+    let mut stack = roots;
+    stack.reverse();
+    while let Some(subject) = stack.pop() {
+        if !seen.insert(subject.id) {
+            continue;
+        }
+        order.push(subject.id);
+        let mut children = subject
+            .children
+            .iter()
+            .filter_map(|id| subjects.get(id))
+            .collect::<Vec<_>>();
+        by_name(&mut children);
+        stack.extend(children.into_iter().rev());
+    }
+    order
+}
+
+/// Narrows a list from [`Store::get_subjects_flat_with_depth`] to the subjects
+/// that match, along with their ancestors and descendants, keeping the order.
+pub fn filter_flat_tree(
+    flat: &[(Subject, usize)],
+    matches: impl Fn(&Subject) -> bool,
+) -> Vec<(Subject, usize)> {
+    let mut keep = vec![false; flat.len()];
+    // Indices of the ancestors of the current subject.
+    let mut ancestors: Vec<usize> = Vec::new();
+    // The depth of the matching subject whose subtree we're in.
+    let mut matched_depth: Option<usize> = None;
+    for (i, (subject, depth)) in flat.iter().enumerate() {
+        ancestors.truncate(*depth);
+        if matched_depth.is_some_and(|matched| *depth <= matched) {
+            matched_depth = None;
+        }
+        if matched_depth.is_some() {
+            keep[i] = true;
+        } else if matches(subject) {
+            keep[i] = true;
+            matched_depth = Some(*depth);
+            for &ancestor in &ancestors {
+                keep[ancestor] = true;
+            }
+        }
+        ancestors.push(i);
+    }
+    flat.iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(item, _)| item.clone())
+        .collect()
+}
+
+/// The subject `step` positions away from `current` in tree order, wrapping
+/// around. The journal, `None`, is the position before the first subject.
+pub fn neighbor_subject(
+    subjects: &BTreeMap<SubjectId, Subject>,
+    current: Option<SubjectId>,
+    step: isize,
+) -> Option<SubjectId> {
+    let positions = std::iter::once(None)
+        .chain(subjects_in_tree_order(subjects).into_iter().map(Some))
+        .collect::<Vec<_>>();
+    let index = positions.iter().position(|&p| p == current).unwrap_or(0);
+    let next = (index as isize + step).rem_euclid(positions.len() as isize);
+    positions[next as usize]
+}
+
+/// Finds `@Name` mentions of existing subjects in `text`, ignoring case.
+/// Returns the byte range of each mention, including the `@`.
+///
+/// An `@` right after a letter or digit, like in an email address, isn't a
+/// mention. When several subject names match, the longest one wins.
+pub fn find_subject_mentions<'a>(
+    text: &str,
+    subjects: impl IntoIterator<Item = &'a Subject>,
+) -> Vec<(std::ops::Range<usize>, SubjectId)> {
+    let mut names = subjects
+        .into_iter()
+        .filter(|s| !s.name.is_empty())
+        .map(|s| (s.name.to_lowercase(), s.id))
+        .collect::<Vec<_>>();
+    names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    let lowercase = text.to_lowercase();
+    // Lowercasing can change byte lengths, in which case the offsets
+    // wouldn't line up with `text`.
+    if lowercase.len() != text.len() {
+        return Vec::new();
+    }
+
+    let mut mentions = Vec::new();
+    let mut search_from = 0;
+    while let Some(offset) = text[search_from..].find('@') {
+        let at = search_from + offset;
+        search_from = at + 1;
+        let after_word = text[..at]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric);
+        if after_word {
+            continue;
+        }
+        let rest = &lowercase[at + 1..];
+        let found = names.iter().find(|(name, _)| {
+            rest.starts_with(name.as_str())
+                && !rest[name.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(char::is_alphanumeric)
+        });
+        if let Some((name, id)) = found {
+            let end = at + 1 + name.len();
+            mentions.push((at..end, *id));
+            search_from = end;
+        }
+    }
+    mentions
+}
+
+/// Reads an outline of subjects into their paths of names, root first.
+///
+/// Each line names a subject, or a `Parent/Child` path of them. A line
+/// indented deeper than the one above it goes under that one. Blank lines
+/// are skipped, and paths listed more than once are only returned once.
+pub fn parse_subject_outline(text: &str) -> Vec<Vec<String>> {
+    let mut paths: Vec<Vec<String>> = Vec::new();
+    // The indentation and path of the lines the next one may nest under.
+    let mut stack: Vec<(usize, Vec<String>)> = Vec::new();
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.trim().is_empty() {
+            continue;
+        }
+        let indent = line[..line.len() - content.len()]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>();
+        while stack.last().is_some_and(|(level, _)| *level >= indent) {
+            stack.pop();
+        }
+
+        let mut path = stack
+            .last()
+            .map(|(_, path)| path.clone())
+            .unwrap_or_default();
+        for name in content.split('/').map(str::trim).filter(|n| !n.is_empty()) {
+            path.push(name.to_string());
+            if !paths.contains(&path) {
+                paths.push(path.clone());
+            }
+        }
+        stack.push((indent, path));
+    }
+    paths
+}
+
+/// Reads a `#rrggbb` color into its red, green and blue parts.
+pub fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([part(0)?, part(2)?, part(4)?])
+}
+
+/// Black or white, whichever contrasts more with the `background` color,
+/// by the WCAG relative luminance. `None` if the color can't be read.
+pub fn contrasting_text_color(background: &str) -> Option<&'static str> {
+    let [r, g, b] = parse_hex_color(background)?.map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let against_black = (luminance + 0.05) / 0.05;
+    let against_white = 1.05 / (luminance + 0.05);
+    if against_black >= against_white {
+        Some("#000000")
+    } else {
+        Some("#ffffff")
+    }
+}
+
+/// A subject color that isn't `#rrggbb`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidColor(pub String);
+
+impl std::fmt::Display for InvalidColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid color \"{}\", expected #rrggbb", self.0)
+    }
+}
+
+impl std::error::Error for InvalidColor {}
+
+impl From<InvalidColor> for rusqlite::Error {
+    fn from(invalid: InvalidColor) -> Self {
+        rusqlite::Error::UserFunctionError(Box::new(invalid))
+    }
+}
+
+/// How many levels deep the subject hierarchy may go.
+pub const MAX_SUBJECT_DEPTH: usize = 16;
+
+/// Subjects whose name contains ?1, ignoring case.
+pub const SUBJECTS_BY_NAME: &str = "SELECT id, name, parent_id,
+        (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id),
+        favorite, favorite_order, color
+    FROM subjects s
+    WHERE case_insensitive_includes(name, ?1)
+    ORDER BY name ASC";
+
+/// Subjects in tree order with their depth, walking at most ?1 levels down.
+pub const SUBJECTS_FLAT_WITH_DEPTH: &str = "WITH RECURSIVE tree(id, depth, sort_path, id_path) AS (
+        SELECT id, 0, name, hex(id)
+        FROM subjects
+        WHERE parent_id IS NULL
+            OR parent_id NOT IN (SELECT id FROM subjects)
+        UNION ALL
+        SELECT s.id, t.depth + 1, t.sort_path || char(1) || s.name, t.id_path || hex(s.id)
+        FROM subjects s
+        JOIN tree t ON s.parent_id = t.id
+        WHERE t.depth < ?1 AND instr(t.id_path, hex(s.id)) = 0
+    )
+    SELECT s.id, s.name, s.parent_id,
+        (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id),
+        s.favorite, s.favorite_order, s.color, t.depth
+    FROM tree t
+    JOIN subjects s ON s.id = t.id
+    ORDER BY t.sort_path";
+
+/// A subject move that would break the hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidMove {
+    /// The subject would become its own parent.
+    OwnParent(SubjectId),
+    /// The new parent is a descendant of the subject, which would make a cycle.
+    IntoOwnSubtree(SubjectId),
+    /// The subtree would reach deeper than [`MAX_SUBJECT_DEPTH`].
+    TooDeep(SubjectId),
+}
+
+impl std::fmt::Display for InvalidMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidMove::OwnParent(_) => write!(f, "a subject can't be its own parent"),
+            InvalidMove::IntoOwnSubtree(_) => {
+                write!(f, "a subject can't be moved under its own descendant")
+            }
+            InvalidMove::TooDeep(_) => write!(
+                f,
+                "subjects can't be nested more than {MAX_SUBJECT_DEPTH} levels deep"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidMove {}
+
+/// Store operations fail with rusqlite errors, so invalid moves are wrapped in one.
+impl From<InvalidMove> for rusqlite::Error {
+    fn from(invalid: InvalidMove) -> Self {
+        rusqlite::Error::UserFunctionError(Box::new(invalid))
+    }
+}
+
+/// A subject that still has notes, refused with [`RemoveSubjectPolicy::Refuse`].
+///
+/// [`RemoveSubjectPolicy::Refuse`]: super::layer::RemoveSubjectPolicy::Refuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubjectHasNotes(pub usize);
+
+impl std::fmt::Display for SubjectHasNotes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            1 => write!(f, "it still has a note"),
+            n => write!(f, "it still has {n} notes"),
+        }
+    }
+}
+
+impl std::error::Error for SubjectHasNotes {}
+
+impl From<SubjectHasNotes> for rusqlite::Error {
+    fn from(error: SubjectHasNotes) -> Self {
+        rusqlite::Error::UserFunctionError(Box::new(error))
+    }
+}
+
+/// A subject under the same parent already has the name, see
+/// [`Store::rename_subject`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubjectNameTaken(pub String);
+
+impl std::fmt::Display for SubjectNameTaken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a subject next to it is already named \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for SubjectNameTaken {}
+
+impl From<SubjectNameTaken> for rusqlite::Error {
+    fn from(error: SubjectNameTaken) -> Self {
+        rusqlite::Error::UserFunctionError(Box::new(error))
+    }
+}
+
 impl Store {
+    /// Whether `id` is an existing subject, without reading it.
+    pub fn subject_exists(&self, id: SubjectId) -> rusqlite::Result<bool> {
+        self.conn
+            .borrow()
+            .prepare_cached("SELECT 1 FROM subjects WHERE id = ?1 LIMIT 1")?
+            .exists(params![id])
+    }
+
     #[instrument(skip(self))]
     pub fn get_subject(&self, id: SubjectId) -> rusqlite::Result<Subject> {
         let conn = self.conn.borrow();
         let mut stmt = conn.prepare_cached(
             "SELECT id, name, parent_id,
-                (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id)
+                (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id),
+                favorite, favorite_order, color
             FROM subjects s
             WHERE id = ?1
             ORDER BY name ASC",
@@ -71,18 +465,42 @@ impl Store {
                 name: row.get(1)?,
                 parent_id: row.get(2)?,
                 children: subject_list_from_db(row, 3)?,
+                favorite: row.get(4)?,
+                favorite_order: row.get(5)?,
+                color: row.get(6)?,
             }))
         })?;
         Ok(subject)
     }
 
+    /// Returns the ancestors of `id`, from the root down to its parent.
+    /// A cycle in the hierarchy ends the walk instead of looping forever.
+    #[instrument(skip(self))]
+    pub fn get_subject_ancestors(&self, id: SubjectId) -> rusqlite::Result<Vec<Subject>> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::from([id]);
+        let mut next = self.get_subject(id)?.parent_id;
+        while let Some(parent_id) = next {
+            if !seen.insert(parent_id) {
+                warn!("Subject hierarchy has a cycle at {}", parent_id);
+                break;
+            }
+            let parent = self.get_subject(parent_id)?;
+            next = parent.parent_id;
+            ancestors.push(parent);
+        }
+        ancestors.reverse();
+        Ok(ancestors)
+    }
+
     #[instrument(skip(self))]
     pub fn get_subjects(&self) -> rusqlite::Result<Vec<Subject>> {
         debug!("Begin");
         let conn = self.conn.borrow();
         let mut stmt = conn.prepare_cached(
             "SELECT id, name, parent_id,
-                (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id)
+                (SELECT concat_blobs(s1.id) FROM subjects s1 WHERE s1.parent_id = s.id),
+                favorite, favorite_order, color
             FROM subjects s
             ORDER BY name ASC",
         )?;
@@ -93,6 +511,9 @@ impl Store {
                     name: row.get(1)?,
                     parent_id: row.get(2)?,
                     children: subject_list_from_db(row, 3)?,
+                    favorite: row.get(4)?,
+                    favorite_order: row.get(5)?,
+                    color: row.get(6)?,
                 }))
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -100,6 +521,51 @@ impl Store {
         Ok(subjects)
     }
 
+    /// Subjects whose name contains `query`, ignoring case, sorted by name.
+    /// Unlike note search, any length of query is fine.
+    pub fn find_subjects_by_name(&self, query: &str) -> rusqlite::Result<Vec<Subject>> {
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare_cached(SUBJECTS_BY_NAME)?;
+        let subjects = stmt
+            .query_map(params![query], |row| {
+                Ok(Rc::new(SubjectData {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_id: row.get(2)?,
+                    children: subject_list_from_db(row, 3)?,
+                    favorite: row.get(4)?,
+                    favorite_order: row.get(5)?,
+                    color: row.get(6)?,
+                }))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(subjects)
+    }
+
+    /// All subjects in depth-first tree order with their depth, roots at 0.
+    /// Siblings are sorted by name, like [`subjects_in_tree_order`].
+    ///
+    /// Subjects in a cycle are left out, as they can't be reached from a root.
+    pub fn get_subjects_flat_with_depth(&self) -> rusqlite::Result<Vec<(Subject, usize)>> {
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare_cached(SUBJECTS_FLAT_WITH_DEPTH)?;
+        let subjects = stmt
+            .query_map(params![MAX_SUBJECT_DEPTH], |row| {
+                let subject = Rc::new(SubjectData {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_id: row.get(2)?,
+                    children: subject_list_from_db(row, 3)?,
+                    favorite: row.get(4)?,
+                    favorite_order: row.get(5)?,
+                    color: row.get(6)?,
+                });
+                Ok((subject, row.get(7)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(subjects)
+    }
+
     pub fn add_subject(&self, name: String) -> rusqlite::Result<Subject> {
         self.add_subject_with_id(SubjectId(Uuid::new_v4()), name)
     }
@@ -120,6 +586,9 @@ impl Store {
             name,
             parent_id: None,
             children: Vec::new(),
+            favorite: false,
+            favorite_order: 0,
+            color: None,
         }))
     }
 
@@ -132,29 +601,228 @@ impl Store {
         Ok(())
     }
 
+    /// Sets the parent of `subject`. Its children keep it as their parent,
+    /// so this is [`Store::move_subtree`] without the count.
     pub fn set_subject_parent(
         &self,
         subject: SubjectId,
         parent: Option<SubjectId>,
+    ) -> rusqlite::Result<()> {
+        self.move_subtree(subject, parent)?;
+        Ok(())
+    }
+
+    /// Moves subjects whose parent doesn't exist to the top level. The parent
+    /// may have been deleted outside the app. Returns how many were moved.
+    #[instrument(skip(self))]
+    pub fn reparent_orphans(&self) -> rusqlite::Result<usize> {
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let moved = reparent_orphans(&tx)?;
+        commit_write(tx)?;
+        Ok(moved)
+    }
+
+    /// Moves `subject` with all of its descendants under `new_parent`,
+    /// or to the top level if it's `None`. Returns how many subjects moved.
+    ///
+    /// Fails with [`InvalidMove`] if the move would create a cycle or nest
+    /// subjects deeper than [`MAX_SUBJECT_DEPTH`], and with
+    /// [`rusqlite::Error::QueryReturnedNoRows`] if either subject is missing.
+    #[instrument(skip(self))]
+    pub fn move_subtree(
+        &self,
+        subject: SubjectId,
+        new_parent: Option<SubjectId>,
+    ) -> rusqlite::Result<usize> {
+        debug!("Moving subtree");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+
+        if new_parent == Some(subject) {
+            return Err(InvalidMove::OwnParent(subject).into());
+        }
+
+        // Walk the subtree breadth first, remembering how deep it goes.
+        let mut children_stmt =
+            tx.prepare_cached("SELECT id FROM subjects WHERE parent_id = ?1")?;
+        let mut subtree = HashSet::from([subject]);
+        let mut queue = VecDeque::from([(subject, 1)]);
+        let mut height = 1;
+        while let Some((id, level)) = queue.pop_front() {
+            height = height.max(level);
+            let children = children_stmt
+                .query_map(params![id], |row| row.get::<_, SubjectId>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            for child in children {
+                if subtree.insert(child) {
+                    queue.push_back((child, level + 1));
+                }
+            }
+        }
+        drop(children_stmt);
+
+        let parent_depth = match new_parent {
+            Some(parent) if subtree.contains(&parent) => {
+                return Err(InvalidMove::IntoOwnSubtree(subject).into());
+            }
+            Some(parent) => self.get_subject_ancestors(parent)?.len() + 1,
+            None => 0,
+        };
+        if parent_depth + height > MAX_SUBJECT_DEPTH {
+            return Err(InvalidMove::TooDeep(subject).into());
+        }
+
+        let moved = tx
+            .prepare_cached("UPDATE subjects SET parent_id = ?1 WHERE id = ?2")?
+            .execute(params![new_parent, subject])?;
+        if moved == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        commit_write(tx)?;
+        Ok(subtree.len())
+    }
+
+    pub fn set_subject_favorite(
+        &self,
+        subject: SubjectId,
+        favorite: bool,
+        order: i64,
     ) -> rusqlite::Result<()> {
         self.conn
             .borrow()
-            .prepare_cached("UPDATE subjects SET parent_id = ?1 WHERE id = ?2")?
-            .execute(params![parent, subject.0])?;
+            .prepare_cached("UPDATE subjects SET favorite = ?1, favorite_order = ?2 WHERE id = ?3")?
+            .execute(params![favorite, order, subject.0])?;
 
         Ok(())
     }
 
+    /// Sets the chip color of `subject`, `None` for the default look.
+    /// Fails with [`InvalidColor`] unless the color is `#rrggbb`.
+    pub fn set_subject_color(
+        &self,
+        subject: SubjectId,
+        color: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        if let Some(color) = color {
+            parse_hex_color(color).ok_or_else(|| InvalidColor(color.to_string()))?;
+        }
+        self.conn
+            .borrow()
+            .prepare_cached("UPDATE subjects SET color = ?1 WHERE id = ?2")?
+            .execute(params![color, subject.0])?;
+
+        Ok(())
+    }
+
+    /// Renames `subject`, indexing its notes again if subject names are indexed.
+    /// Fails with [`SubjectNameTaken`] if a sibling already has the name,
+    /// top-level subjects included.
+    #[instrument(skip(self))]
+    pub fn rename_subject(&self, subject: SubjectId, name: &str) -> rusqlite::Result<()> {
+        debug!("Renaming subject");
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        // The unique constraint doesn't cover top-level subjects, as their
+        // parent is NULL.
+        let taken = tx
+            .prepare_cached(
+                "SELECT 1 FROM subjects s
+                WHERE s.name = ?1 AND s.id != ?2
+                    AND s.parent_id IS (SELECT parent_id FROM subjects WHERE id = ?2)",
+            )?
+            .exists(params![name, subject.0])?;
+        if taken {
+            return Err(SubjectNameTaken(name.to_string()).into());
+        }
+        tx.prepare_cached("UPDATE subjects SET name = ?1 WHERE id = ?2")?
+            .execute(params![name, subject.0])?;
+        commit_write(tx)?;
+        // The search index isn't part of the transaction, so it only
+        // follows a rename that was saved.
+        self.reindex_subject_names(&self.get_subject_note_ids(subject)?)
+    }
+
+    /// The notes tagged with `subject`, in no particular order.
+    pub fn get_subject_note_ids(&self, subject: SubjectId) -> rusqlite::Result<Vec<NoteId>> {
+        let conn = self.conn.borrow();
+        let notes = conn
+            .prepare_cached("SELECT note_id FROM notes_subjects WHERE subject_id = ?1")?
+            .query_map(params![subject], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(notes)
+    }
+
+    /// The note and child counts of every subject in one query, sorted by name.
+    #[instrument(skip(self))]
+    pub fn get_all_subjects_with_counts(&self) -> rusqlite::Result<Vec<SubjectCounts>> {
+        let conn = self.conn.borrow();
+        let counts = conn
+            .prepare_cached(
+                "SELECT s.id,
+                    COUNT(ns.note_id),
+                    (SELECT COUNT(*) FROM subjects c WHERE c.parent_id = s.id)
+                FROM subjects s
+                LEFT JOIN notes_subjects ns ON ns.subject_id = s.id
+                GROUP BY s.id
+                ORDER BY s.name ASC",
+            )?
+            .query_map(params![], |row| {
+                Ok(SubjectCounts {
+                    subject: row.get(0)?,
+                    notes: row.get(1)?,
+                    children: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
     pub fn import_subject(&self, subject: &SubjectData) -> rusqlite::Result<()> {
         self.conn
             .borrow()
             .prepare_cached(
                 "
-                INSERT INTO subjects (id, name, parent_id)
-                VALUES (?1, ?2, ?3)
+                INSERT INTO subjects (id, name, parent_id, favorite, favorite_order, color)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ",
+            )?
+            .execute(params![
+                subject.id.0,
+                subject.name,
+                subject.parent_id,
+                subject.favorite,
+                subject.favorite_order,
+                subject.color
+            ])?;
+        Ok(())
+    }
+
+    /// Like [`Store::import_subject`], but replaces a subject with the same id.
+    pub fn merge_subject(&self, subject: &SubjectData) -> rusqlite::Result<()> {
+        self.conn
+            .borrow()
+            .prepare_cached(
+                "
+                INSERT INTO subjects (id, name, parent_id, favorite, favorite_order, color)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (id) DO UPDATE SET
+                    name = excluded.name,
+                    parent_id = excluded.parent_id,
+                    favorite = excluded.favorite,
+                    favorite_order = excluded.favorite_order,
+                    color = excluded.color
                 ",
             )?
-            .execute(params![subject.id.0, subject.name, subject.parent_id])?;
+            .execute(params![
+                subject.id.0,
+                subject.name,
+                subject.parent_id,
+                subject.favorite,
+                subject.favorite_order,
+                subject.color
+            ])?;
         Ok(())
     }
 
@@ -180,3 +848,127 @@ impl Store {
         Ok(())
     }
 }
+
+/// See [`Store::reparent_orphans`]. Also run when the database is opened.
+pub(super) fn reparent_orphans(conn: &Connection) -> rusqlite::Result<usize> {
+    let moved = conn
+        .prepare_cached(
+            "UPDATE subjects SET parent_id = NULL
+            WHERE parent_id IS NOT NULL
+                AND parent_id NOT IN (SELECT id FROM subjects)",
+        )?
+        .execute([])?;
+    if moved > 0 {
+        warn!(
+            "Moved {} subjects with a missing parent to the top level",
+            moved
+        );
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn subject(name: &str, parent: Option<SubjectId>) -> SubjectData {
+        SubjectData {
+            id: SubjectId(Uuid::new_v4()),
+            name: name.to_string(),
+            parent_id: parent,
+            children: Vec::new(),
+            favorite: false,
+            favorite_order: 0,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn subject_id_round_trips_through_string() {
+        let id = SubjectId(Uuid::new_v4());
+        assert_eq!(id.to_string().parse::<SubjectId>().unwrap(), id);
+
+        let err = "".parse::<SubjectId>().unwrap_err();
+        assert!(err.to_string().starts_with("Invalid subject id \"\""));
+    }
+
+    #[test]
+    fn finds_subject_mentions() {
+        let work = Rc::new(subject("Work", None));
+        let project = Rc::new(subject("Work Project", None));
+        let subjects = [work.clone(), project.clone()];
+
+        let text = "@work and @Work Project, not me@work.com or @workshop";
+        let mentions = find_subject_mentions(text, &subjects);
+        assert_eq!(mentions, vec![(0..5, work.id), (10..23, project.id)]);
+        assert_eq!(&text[10..23], "@Work Project");
+    }
+
+    #[test]
+    fn neighbors_follow_tree_order() {
+        let work = subject("Work", None);
+        let home = subject("Home", None);
+        let project = subject("Project", Some(work.id));
+        let meetings = subject("Meetings", Some(work.id));
+        let mut work = work;
+        work.children = vec![project.id, meetings.id];
+
+        let subjects = [&work, &home, &project, &meetings]
+            .into_iter()
+            .map(|s| (s.id, Rc::new(s.clone())))
+            .collect::<BTreeMap<_, _>>();
+
+        assert_eq!(
+            subjects_in_tree_order(&subjects),
+            vec![home.id, work.id, meetings.id, project.id]
+        );
+
+        assert_eq!(neighbor_subject(&subjects, None, 1), Some(home.id));
+        assert_eq!(
+            neighbor_subject(&subjects, Some(work.id), 1),
+            Some(meetings.id)
+        );
+        assert_eq!(neighbor_subject(&subjects, Some(project.id), 1), None);
+        assert_eq!(neighbor_subject(&subjects, None, -1), Some(project.id));
+        assert_eq!(neighbor_subject(&subjects, Some(home.id), -1), None);
+    }
+
+    #[test]
+    fn text_color_contrasts_with_background() {
+        assert_eq!(parse_hex_color("#ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_hex_color("ff8000"), None);
+        assert_eq!(parse_hex_color("#ff80"), None);
+        assert_eq!(parse_hex_color("#gg8000"), None);
+
+        assert_eq!(contrasting_text_color("#ffffff"), Some("#000000"));
+        assert_eq!(contrasting_text_color("#ffff00"), Some("#000000"));
+        assert_eq!(contrasting_text_color("#000000"), Some("#ffffff"));
+        assert_eq!(contrasting_text_color("#0000ff"), Some("#ffffff"));
+        assert_eq!(contrasting_text_color("red"), None);
+    }
+
+    #[test]
+    fn outline_nests_by_indentation_and_slashes() {
+        let outline = "
+Work
+    Meetings
+	Projects/Emergence
+        Bugs
+Home/Garden
+
+Work /  Meetings
+Home
+";
+        let paths = parse_subject_outline(outline);
+        let expected: Vec<Vec<&str>> = vec![
+            vec!["Work"],
+            vec!["Work", "Meetings"],
+            vec!["Work", "Projects"],
+            vec!["Work", "Projects", "Emergence"],
+            vec!["Work", "Projects", "Emergence", "Bugs"],
+            vec!["Home"],
+            vec!["Home", "Garden"],
+        ];
+        assert_eq!(paths, expected);
+    }
+}
@@ -5,6 +5,40 @@
 
 use std::collections::BTreeMap;
 
+use super::{
+    retry::{begin_write, commit_write},
+    Store,
+};
+
+/// The ?3 most common terms between ?1 and ?2, i.e. with a prefix.
+pub const TERM_SUGGESTIONS: &str = "SELECT term
+    FROM term_occurrences
+    WHERE term >= ?1 AND term < ?2 AND count > 0
+    ORDER BY count DESC, term
+    LIMIT ?3;";
+
+/// Every term by how many notes it occurs in, most common first.
+const TOP_TERMS: &str = "SELECT term, count
+    FROM term_occurrences
+    WHERE count > 0
+    ORDER BY count DESC, term;";
+
+/// Words too common to tell notes apart, left out of [`Store::top_terms`].
+/// They're normalized like the terms before comparing.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "because", "been", "before", "being",
+    "but", "can", "could", "did", "does", "each", "for", "from", "had", "has", "have", "her",
+    "here", "him", "his", "how", "into", "its", "just", "like", "many", "more", "much", "not",
+    "one", "only", "other", "our", "out", "over", "she", "should", "some", "such", "than", "that",
+    "the", "their", "them", "then", "there", "these", "they", "this", "those", "very", "was",
+    "were", "what", "when", "where", "which", "while", "who", "will", "with", "would", "you",
+    "your",
+];
+
+fn is_stopword(term: &str) -> bool {
+    STOPWORDS.iter().any(|word| normalize_word(word) == term)
+}
+
 /// Trims punctuation from the beginning and end of a word.
 /// Matches against the Alphabetic Unicode character property.
 /// Returns an empty string if the word has no alphabetic characters.
@@ -163,11 +197,38 @@ pub fn remove_word_occurences(conn: &rusqlite::Connection, text: &str) -> rusqli
     Ok(())
 }
 
+/// Like [`remove_word_occurences`] for many texts at once, with a single
+/// update per distinct word.
+pub fn remove_word_occurences_batch<'a>(
+    conn: &rusqlite::Connection,
+    texts: impl IntoIterator<Item = &'a str>,
+) -> rusqlite::Result<()> {
+    let mut totals = BTreeMap::<String, usize>::new();
+    for text in texts {
+        let text = normalize_text(text);
+        for (word, count) in count_word_occurrences(&text) {
+            if count > 0 {
+                *totals.entry(word.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO term_occurrences (term, count) VALUES (?1, 0)
+        ON CONFLICT(term) DO UPDATE SET count = count - ?2;",
+    )?;
+    for (word, notes) in totals {
+        stmt.execute((word, notes))?;
+    }
+
+    Ok(())
+}
+
 /// Fills the term_occurrences table with the word counts from the notes table.
 /// This is used to perform a full reindex of the notes.
 pub fn fill_word_occurence_table(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
     tracing::info!("Filling word occurence table");
-    let mut read_stmt = conn.prepare_cached("SELECT text FROM notes")?;
+    let mut read_stmt = conn.prepare_cached("SELECT text FROM notes WHERE indexed")?;
 
     // drop existing occurences
     conn.execute_batch("DELETE FROM term_occurrences;")?;
@@ -181,6 +242,64 @@ pub fn fill_word_occurence_table(conn: &rusqlite::Connection) -> rusqlite::Resul
     Ok(())
 }
 
+impl Store {
+    /// Returns up to `limit` indexed terms starting with `prefix`,
+    /// most common first.
+    pub fn term_suggestions(&self, prefix: &str, limit: usize) -> rusqlite::Result<Vec<String>> {
+        let prefix = normalize_text(prefix);
+        let prefix = prefix.trim();
+        if prefix.len() < 3 {
+            return Ok(Vec::new());
+        }
+        // Equivalent to `term LIKE 'prefix%'`, but LIKE can't use the
+        // primary key index on a BINARY collated column.
+        let upper_bound = format!("{prefix}{}", char::MAX);
+
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare_cached(TERM_SUGGESTIONS)?;
+        let terms = stmt
+            .query_map((prefix, upper_bound, limit), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(terms)
+    }
+
+    /// Returns up to `limit` of the most common terms with the number of
+    /// notes they occur in, most common first. Stopwords are left out.
+    pub fn top_terms(&self, limit: usize) -> rusqlite::Result<Vec<(String, i64)>> {
+        let conn = self.conn.borrow();
+        let mut stmt = conn.prepare_cached(TOP_TERMS)?;
+        let mut rows = stmt.query([])?;
+        let mut terms = Vec::new();
+        while terms.len() < limit {
+            let Some(row) = rows.next()? else { break };
+            let term: String = row.get(0)?;
+            if !is_stopword(&term) {
+                terms.push((term, row.get(1)?));
+            }
+        }
+        Ok(terms)
+    }
+
+    /// How many terms no longer occur in any note but still have a row.
+    pub fn zero_count_terms(&self) -> rusqlite::Result<u64> {
+        self.conn
+            .borrow()
+            .prepare_cached("SELECT COUNT(*) FROM term_occurrences WHERE count <= 0")?
+            .query_row([], |row| row.get(0))
+    }
+
+    /// Deletes the terms that no longer occur in any note. Removing text
+    /// only decrements the counts, so these pile up with edits and deletions.
+    /// Returns how many terms were deleted.
+    pub fn prune_term_occurrences(&self) -> rusqlite::Result<usize> {
+        let conn = self.conn.borrow();
+        let tx = begin_write(&conn)?;
+        let pruned = tx.execute("DELETE FROM term_occurrences WHERE count <= 0", [])?;
+        commit_write(tx)?;
+        Ok(pruned)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::data::{notes::NoteBuilder, ConnectionType, Store};
@@ -211,4 +330,79 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_term_suggestions() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        store.add_note(NoteBuilder::new().text("Programming in Rust"))?;
+        store.add_note(NoteBuilder::new().text("Program the robot"))?;
+        store.add_note(NoteBuilder::new().text("A progress report"))?;
+        store.add_note(NoteBuilder::new().text("Another program"))?;
+
+        assert_eq!(
+            store.term_suggestions("Pro", 10)?,
+            vec!["program", "programm", "progres"]
+        );
+        assert_eq!(store.term_suggestions("prog", 1)?, vec!["program"]);
+        assert_eq!(store.term_suggestions("progr", 10)?.len(), 3);
+        assert!(store.term_suggestions("pr", 10)?.is_empty());
+        assert!(store.term_suggestions("xyz", 10)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_terms() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        store.add_note(NoteBuilder::new().text("The garden needs water"))?;
+        store.add_note(NoteBuilder::new().text("Water the plants in the garden"))?;
+        let removed = store.add_note(NoteBuilder::new().text("Garden party with cakes"))?;
+        store.delete_note(removed.id)?;
+
+        assert_eq!(
+            store.top_terms(3)?,
+            vec![
+                ("garden".to_string(), 2),
+                ("water".to_string(), 2),
+                ("need".to_string(), 1)
+            ]
+        );
+        let terms = store.top_terms(10)?;
+        assert_eq!(terms.len(), 4);
+        assert!(terms
+            .iter()
+            .all(|(term, _)| term != "the" && term != "cake"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_term_occurrences() -> rusqlite::Result<()> {
+        let store = Store::new(ConnectionType::InMemory);
+        let kept = store.add_note(NoteBuilder::new().text("Shared words stay"))?;
+        let deleted = store.add_note(NoteBuilder::new().text("Shared words vanish"))?;
+        let edited = store.add_note(NoteBuilder::new().text("Temporary wording"))?;
+        store.delete_note(deleted.id)?;
+        store.update_note(edited.modify_with(|b| b.text("Shared")))?;
+        assert!(store.zero_count_terms()? > 0);
+        let live_terms = || -> rusqlite::Result<Vec<(String, i64)>> {
+            let conn = store.conn.borrow();
+            let mut stmt = conn.prepare(
+                "SELECT term, count FROM term_occurrences WHERE count > 0 ORDER BY term",
+            )?;
+            let terms = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            terms.collect()
+        };
+        let before = live_terms()?;
+
+        assert!(store.prune_term_occurrences()? > 0);
+        assert_eq!(store.zero_count_terms()?, 0);
+        assert_eq!(store.prune_term_occurrences()?, 0);
+        assert_eq!(live_terms()?, before);
+
+        let conn = store.conn.borrow();
+        assert_eq!(best_words(&conn, &kept.text)?.len(), 3);
+
+        Ok(())
+    }
 }
@@ -1 +1,3 @@
+#[cfg(feature = "api")]
+pub mod api;
 pub mod data;
@@ -4,7 +4,12 @@ mod views;
 
 use std::path::PathBuf;
 
-use dioxus_desktop::use_window;
+use chrono::{DateTime, Local};
+use dioxus_desktop::{
+    use_window, use_wry_event_handler,
+    wry::application::event::{Event as WryEvent, WindowEvent},
+    WindowCloseBehaviour,
+};
 use dioxus_signals::*;
 pub use emergence::data;
 
@@ -16,8 +21,19 @@ use dioxus::{
 use emergence::data::layer::use_layer_provider;
 use sir::AppStyle;
 use tracing::{info, metadata::LevelFilter};
-
-use crate::views::{journal::Journal, side_panel::SidePanel, ViewState};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use crate::views::{
+    command_palette::CommandPalette,
+    confirm_dialog::ConfirmDialog,
+    journal::Journal,
+    note_input::use_unsaved_text,
+    reminders::use_reminders,
+    side_panel::SidePanel,
+    theme::theme_css,
+    toasts::{push_toast, ToastLevel, Toasts},
+    ViewState,
+};
 
 use clap::{Parser, ValueEnum};
 
@@ -34,6 +50,10 @@ struct Args {
     #[arg(long)]
     reindex: bool,
 
+    /// Rebuild the table listing notes by subject and task state
+    #[arg(long)]
+    repair_search_index: bool,
+
     /// Construct a sample database.
     ///
     /// This will NOT overwrite an existing database.
@@ -44,13 +64,46 @@ struct Args {
     #[arg(long, value_name = "FILE", conflicts_with = "import")]
     export: Option<PathBuf>,
 
+    /// Export each note to a Markdown file in this folder
+    #[arg(long, value_name = "FOLDER", conflicts_with_all = ["export", "import"])]
+    export_md: Option<PathBuf>,
+
+    /// Only export notes changed after this time, e.g. 2023-08-01T00:00:00+03:00
+    #[arg(long, value_name = "TIME", requires = "export", value_parser = parse_time)]
+    since: Option<DateTime<Local>>,
+
     /// Import from JSON file
     #[arg(long, value_name = "FILE", conflicts_with = "export")]
     import: Option<PathBuf>,
 
+    /// Merge the import into the existing database instead of replacing it
+    #[arg(long, requires = "import")]
+    merge: bool,
+
     /// Explain database query plans
     #[arg(long)]
     explain: bool,
+
+    /// Drop unused search terms and compact the database file
+    #[arg(long)]
+    vacuum: bool,
+
+    /// Check the database for problems
+    #[arg(long)]
+    check: bool,
+
+    /// Write out pending changes so the data folder can be copied for a backup
+    #[arg(long)]
+    flush: bool,
+
+    /// Time searches on the vault and report their latencies
+    #[arg(long, value_name = "KIND")]
+    benchmark: Option<BenchmarkKind>,
+
+    /// Serve the local HTTP API on this port instead of opening the app
+    #[cfg(feature = "api")]
+    #[arg(long, value_name = "PORT")]
+    serve: Option<u16>,
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy)]
@@ -62,6 +115,18 @@ enum LogLevel {
     Error,
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum BenchmarkKind {
+    Search,
+}
+
+/// How many notes `--benchmark` takes its queries from.
+const BENCHMARK_QUERIES: usize = 200;
+
+fn parse_time(time: &str) -> Result<DateTime<Local>, chrono::ParseError> {
+    Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Local))
+}
+
 impl LogLevel {
     fn to_level_filter(self) -> LevelFilter {
         match self {
@@ -78,9 +143,16 @@ fn main() {
     let args = Args::parse();
     let data_path = args.data.unwrap_or_else(|| PathBuf::from("data"));
 
+    // At trace level, also log when spans close so that their timings and
+    // recorded fields (e.g. search result counts) show up.
+    let span_events = match args.verbosity {
+        LogLevel::Trace => FmtSpan::CLOSE,
+        _ => FmtSpan::NONE,
+    };
     tracing::subscriber::set_global_default(
         tracing_subscriber::FmtSubscriber::builder()
             .with_max_level(args.verbosity.to_level_filter())
+            .with_span_events(span_events)
             .finish(),
     )
     .unwrap();
@@ -91,6 +163,62 @@ fn main() {
         return;
     }
 
+    if args.vacuum {
+        info!("Vacuuming database");
+        let store = Store::new(data::ConnectionType::File(data_path));
+        store.vacuum().unwrap();
+        info!("Finished vacuuming");
+        return;
+    }
+
+    if args.flush {
+        let store = Store::new(data::ConnectionType::File(data_path));
+        store.flush().unwrap();
+        info!("Flushed");
+        return;
+    }
+
+    if args.check {
+        let store = Store::new(data::ConnectionType::File(data_path));
+        let errors = store.integrity_errors().unwrap();
+        if errors.is_empty() {
+            println!("Integrity check: ok");
+        }
+        for error in errors {
+            println!("Integrity check: {error}");
+        }
+        let drift = store.notes_search_drift().unwrap();
+        if drift == 0 {
+            println!("Note listing table: ok");
+        } else {
+            println!(
+                "Note listing table: {drift} rows out of sync (fixed by --repair-search-index)"
+            );
+        }
+        let zero_terms = store.zero_count_terms().unwrap();
+        println!("Unused search terms: {zero_terms} (removed by --vacuum and on exit)");
+        return;
+    }
+
+    if let Some(BenchmarkKind::Search) = args.benchmark {
+        let store = Store::new(data::ConnectionType::File(data_path));
+        let texts = data::benchmark::sample_texts(&store, BENCHMARK_QUERIES).unwrap();
+        let params = data::settings::GlobalSettings::load().similar_notes;
+        let result = data::benchmark::benchmark_search(&store, &texts, params).unwrap();
+        println!("Notes: {}", result.notes);
+        println!("Full text: {}", result.search);
+        println!("Similar notes: {}", result.similar);
+        return;
+    }
+
+    if args.repair_search_index {
+        info!("Rebuilding the note listing table");
+        let store = Store::new(data::ConnectionType::File(data_path));
+        let rows = store.repair_notes_search().unwrap();
+        info!("Finished rebuilding, {} rows", rows);
+        return;
+    }
+
     if args.reindex {
         info!("Reindexing search engine");
         let tantivy_dir = data_path.join("tantivy");
@@ -99,9 +227,10 @@ fn main() {
         let conn = store.conn.borrow();
 
         let _ = std::fs::remove_dir_all(tantivy_dir.clone());
-        let index = data::search::construct_tantivy_index(data_path);
+        let index = data::search::construct_tantivy_index(data_path).unwrap();
         let mut writer = index.writer(50_000_000).unwrap();
-        data::search::fill_tantivy_index(&mut writer, &conn);
+        data::search::fill_tantivy_index(&mut writer, &conn, store.index_subject_names());
+        writer.commit().unwrap();
 
         info!("Finished reindexing");
         return;
@@ -112,8 +241,23 @@ fn main() {
             "Exporting to {}, this may take a long time",
             export_file.display()
         );
-        data::export::export(data_path, export_file);
+        let until = match args.since {
+            Some(since) => data::export::export_since(data_path, since, export_file),
+            None => data::export::export(data_path, export_file),
+        };
         info!("Finished exporting");
+        if let Some(until) = until {
+            println!("Continue with: --since {}", until.to_rfc3339());
+        }
+        return;
+    }
+
+    if let Some(out_dir) = args.export_md {
+        info!("Exporting Markdown to {}", out_dir.display());
+        match data::export::export_markdown(data_path, out_dir) {
+            Ok(count) => info!("Finished exporting {} notes", count),
+            Err(e) => tracing::error!("Exporting Markdown failed: {}", e),
+        }
         return;
     }
 
@@ -122,8 +266,41 @@ fn main() {
             "Importing from {}, this may take a long time",
             import_file.display()
         );
-        data::export::import(data_path, import_file);
-        info!("Finished importing");
+        let on_progress = |progress: data::export::ImportProgress| {
+            info!(
+                "Imported {}/{} subjects, {}/{} notes",
+                progress.subjects, progress.total_subjects, progress.notes, progress.total_notes
+            );
+        };
+        let summary = if args.merge {
+            Some(data::export::import_merge(
+                data_path,
+                import_file,
+                on_progress,
+            ))
+        } else {
+            data::export::import(data_path, import_file, on_progress)
+        };
+        if let Some(summary) = summary {
+            info!(
+                "Finished importing: imported {} notes, {} subjects",
+                summary.notes, summary.subjects
+            );
+        }
+        return;
+    }
+
+    #[cfg(feature = "api")]
+    if let Some(port) = args.serve {
+        let mut global_settings = data::settings::GlobalSettings::load();
+        let token = global_settings.api_token();
+        if let Some(path) = data::settings::GlobalSettings::path() {
+            info!("The API token is stored in {}", path.display());
+        }
+        let store = Store::new(data::ConnectionType::File(data_path));
+        if let Err(e) = emergence::api::serve(&store, port, &token) {
+            tracing::error!("Serving the API failed: {}", e);
+        }
         return;
     }
 
@@ -142,6 +319,10 @@ fn main() {
 
     info!("Starting app");
 
+    let mut global_settings = data::settings::GlobalSettings::load();
+    global_settings.add_recent_vault(&data_path);
+    global_settings.save();
+
     let disable_context_menu = !cfg!(debug_assertions);
 
     // launch the dioxus app in a webview
@@ -150,6 +331,8 @@ fn main() {
         AppProps { db_file: data_path },
         dioxus_desktop::Config::new()
             .with_disable_context_menu(disable_context_menu)
+            // Closing only hides the window, so the app can ask before quitting.
+            .with_close_behaviour(WindowCloseBehaviour::LastWindowHides)
             .with_window(
                 dioxus_desktop::WindowBuilder::new()
                     .with_title("Emergence Notes")
@@ -166,12 +349,66 @@ struct AppProps {
 }
 
 fn App(cx: Scope<'_, AppProps>) -> Element<'_> {
+    // Before the layer opens the vault, since only one store at a time can
+    // write to its search index.
+    let backup_error = cx
+        .use_hook(|| {
+            let config = data::settings::GlobalSettings::load().backup_config()?;
+            let result = data::backup::maybe_backup(&cx.props.db_file, &config);
+            result.err().map(|e| e.to_string())
+        })
+        .clone();
     let layer = use_layer_provider(cx, data::ConnectionType::File(cx.props.db_file.clone()));
-    let view_state = *use_context_provider(cx, || Signal::new(ViewState::new(layer)));
+    let view_state = *use_context_provider(cx, || {
+        Signal::new(ViewState::new(layer, cx.props.db_file.clone()))
+    });
+
+    cx.use_hook(|| {
+        let toasts = view_state.read().toasts;
+        layer.write().set_error_handler(move |message| {
+            push_toast(toasts, ToastLevel::Error, message);
+        });
+        if let Some(e) = backup_error {
+            tracing::error!("Backing up the vault failed: {}", e);
+            push_toast(toasts, ToastLevel::Error, format!("Backing up failed: {e}"));
+        }
+    });
+
+    use_reminders(cx);
 
     let window = use_window(cx);
     let zoom_level = use_state(cx, || 100);
 
+    let unsaved_text = use_unsaved_text(cx);
+    let confirm_quit = use_state(cx, || false);
+    {
+        let window = window.clone();
+        let confirm_quit = confirm_quit.clone();
+        use_wry_event_handler(cx, move |event, _| {
+            let WryEvent::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+                ..
+            } = event
+            else {
+                return;
+            };
+            if *window_id != window.id() {
+                return;
+            }
+            let ask = view_state
+                .read()
+                .global_settings
+                .confirm_quit_with_unsaved_text;
+            if ask && unsaved_text.any(&layer.read()) {
+                window.set_visible(true);
+                confirm_quit.set(true);
+            } else {
+                window.close();
+            }
+        });
+    }
+
     // Workaround for not being able to attach event listeners to the document.
     let js = r#"
         if (!window.eventsRegistered) {
@@ -179,10 +416,24 @@ fn App(cx: Scope<'_, AppProps>) -> Element<'_> {
                 // Shift+Tab is recognized ss Unidentified key, so we have to check for code
                 if (e.code === 'Tab') return;
                 if (e.target.className === 'magic-capture') return;
+                // Enter belongs to the note input, which handles it itself.
+                // Forwarding it would let Ctrl+Enter start another note.
+                if (e.key === 'Enter' && e.target.classList.contains('note-textarea')) {
+                    if (e.target.dataset.enterSubmits === 'true' && !e.shiftKey) {
+                        e.preventDefault();
+                    }
+                    return;
+                }
                 document
                     .querySelector('.magic-capture')
                     .dispatchEvent(new KeyboardEvent('keydown', e));
             });
+            // Remember which subject chip a click landed on; the Markdown
+            // component asks for it in its own click handler.
+            document.addEventListener('click', (e) => {
+                const chip = e.target.closest('.subject-chip');
+                window.clickedSubjectChip = chip ? chip.dataset.subject : null;
+            }, true);
             window.eventsRegistered = true;
         }
     "#;
@@ -202,6 +453,15 @@ fn App(cx: Scope<'_, AppProps>) -> Element<'_> {
             "f" => {
                 view_state.write().show_search();
             }
+            "k" => {
+                view_state.write().command_palette = true;
+            }
+            "]" => {
+                view_state.write().step_subject(1);
+            }
+            "[" => {
+                view_state.write().step_subject(-1);
+            }
             "z" => {
                 let view = view_state.read();
                 if view.show_input {
@@ -235,8 +495,27 @@ fn App(cx: Scope<'_, AppProps>) -> Element<'_> {
         _ => {}
     };
 
+    let theme = theme_css(view_state.read().global_settings.theme);
+
+    let quit_dialog = (*confirm_quit.get()).then(|| {
+        rsx! {
+            ConfirmDialog {
+                title: "Quit",
+                message: "A note you're writing hasn't been saved. Quit anyway?",
+                on_confirm: move |_| window.close(),
+                on_cancel: |_| confirm_quit.set(false),
+            }
+        }
+    });
+
+    let command_palette = view_state
+        .read()
+        .command_palette
+        .then(|| rsx! { CommandPalette { } });
+
     render! {
         style { include_str!("style.css") },
+        style { "{theme}" },
         AppStyle { },
         div {
             class: "magic-capture",
@@ -246,6 +525,10 @@ fn App(cx: Scope<'_, AppProps>) -> Element<'_> {
             class: "app",
             Journal { },
             SidePanel { },
+            // Inside the app so that it gets the theme colors.
+            Toasts { }
+            command_palette
+            quit_dialog
         }
     }
 }
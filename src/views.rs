@@ -1,95 +1,532 @@
+pub mod clickable;
 pub mod command_palette;
 pub mod confirm_dialog;
 pub mod journal;
 pub mod list_notes;
 pub mod markdown;
 pub mod note_input;
+pub mod reminders;
 pub mod scroll_to;
 pub mod search_view;
 pub mod select_subject;
 pub mod side_panel;
+pub mod subject_manager;
+pub mod theme;
+pub mod toasts;
 pub mod view_note;
+pub mod windowing;
 
+use std::{collections::BTreeSet, path::PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
 use dioxus::prelude::{use_context, ScopeState};
 use dioxus_signals::Signal;
 use emergence::data::{
+    drafts::Drafts,
     layer::Layer,
-    notes::{NoteId, NoteSearch},
+    notes::{NoteData, NoteId, NoteSearch, SubjectMatch},
+    settings::{FavoriteOrder, GlobalSettings, SavedFilter, SubjectSettings, Theme, VaultSettings},
     subjects::SubjectId,
+    ConnectionType,
+};
+
+use self::{
+    search_view::SearchSort,
+    side_panel::SidePanelState,
+    toasts::{push_toast, Toast, ToastLevel},
+    windowing::ListScroll,
 };
 
-use self::side_panel::SidePanelState;
+/// A running focus session on a note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FocusTimer {
+    pub note: NoteId,
+    pub started_at: DateTime<Local>,
+}
 
 pub struct ViewState {
     pub layer: Signal<Layer>,
     pub show_input: bool,
     pub show_search: bool,
     pub search_text: String,
+    pub search_sort: SearchSort,
+    /// Only searches notes created on or after this day.
+    pub search_from: Option<NaiveDate>,
+    /// Only searches notes created on or before this day.
+    pub search_to: Option<NaiveDate>,
     pub tasks_only: bool,
+    /// Shows old completed tasks despite `hide_done_after_days`.
+    pub show_completed: bool,
+    /// Lists the tasks by due date instead of by creation time.
+    pub sort_by_due: bool,
+    /// Shows the tasks of every subject instead of the journal.
+    pub all_tasks: bool,
     pub scroll_to_note: Option<NoteId>,
     pub selected_subject: Option<SubjectId>,
+    /// Further subjects the listed notes must have besides `selected_subject`.
+    pub also_subjects: Vec<SubjectId>,
+    /// Whether the listed notes need all or any of the selected subjects.
+    pub subject_match: SubjectMatch,
     pub side_panel: SidePanelState,
     pub command_palette: bool,
+    /// Keys of the date groups folded in the note list, kept for the session.
+    pub collapsed_groups: BTreeSet<String>,
+    /// Notes collected to revisit, shown in the side panel.
+    pub reading_list: Vec<NoteId>,
+    pub focus_timer: Option<FocusTimer>,
+    /// A note waiting for the note to link it to be picked.
+    pub linking_from: Option<NoteId>,
+    /// Lists the notes from before this time instead of the latest ones.
+    pub showing_before: Option<DateTime<Local>>,
+    pub toasts: Signal<Vec<Toast>>,
+    /// Where the note list is scrolled, read only by the list itself.
+    pub note_list_scroll: Signal<ListScroll>,
+    pub global_settings: GlobalSettings,
+    /// Data folder of the open vault.
+    pub vault: PathBuf,
+    pub vault_settings: VaultSettings,
 }
 
 impl ViewState {
-    pub fn new(layer: Signal<Layer>) -> Self {
+    pub fn new(layer: Signal<Layer>, vault: PathBuf) -> Self {
+        let global_settings = GlobalSettings::load();
+        let vault_settings = VaultSettings::load(&vault);
         Self {
             layer,
-            show_input: false,
+            // Reopen the input if a new note was left unsaved.
+            show_input: Drafts::load(&vault).new_note.is_some(),
             show_search: false,
             search_text: String::new(),
+            search_sort: SearchSort::default(),
+            search_from: None,
+            search_to: None,
             tasks_only: false,
+            show_completed: false,
+            sort_by_due: false,
+            all_tasks: false,
             scroll_to_note: None,
             selected_subject: None,
+            also_subjects: Vec::new(),
+            subject_match: SubjectMatch::All,
             side_panel: SidePanelState::Nothing,
+            command_palette: false,
+            collapsed_groups: BTreeSet::new(),
+            reading_list: saved_reading_list(&layer.read(), &global_settings, &vault_settings),
+            focus_timer: None,
+            linking_from: None,
+            showing_before: None,
+            toasts: Signal::new(Vec::new()),
+            note_list_scroll: Signal::new(ListScroll::default()),
+            global_settings,
+            vault_settings,
+            vault,
         }
     }
 
+    /// Shows a toast notification to the user.
+    pub fn notify(&self, level: ToastLevel, message: impl ToString) {
+        push_toast(self.toasts, level, message.to_string());
+    }
+
     pub fn go_to_subject(&mut self, subject: SubjectId) {
         self.selected_subject = Some(subject);
+        self.also_subjects.clear();
+        self.subject_match = SubjectMatch::All;
         self.scroll_to_note = None;
+        self.showing_before = None;
         self.side_panel = SidePanelState::SubjectDetails(subject);
         self.show_search = false;
+        self.all_tasks = false;
         self.update_notes();
     }
 
+    /// Moves `step` subjects forward or back in the subject tree,
+    /// passing through the journal at the ends.
+    pub fn step_subject(&mut self, step: isize) {
+        let next = self
+            .layer
+            .read()
+            .neighbor_subject(self.selected_subject, step);
+        match next {
+            Some(subject) => self.go_to_subject(subject),
+            None => self.go_to_journal(),
+        }
+    }
+
     pub fn go_to_note(&mut self, note: NoteId, subject: SubjectId) {
         self.go_to_subject(subject);
         self.scroll_to_note = Some(note);
     }
 
+    /// Scrolls to `note` among the notes of its first subject, or in the
+    /// journal if it has none.
+    pub fn show_note(&mut self, note: &NoteData) {
+        match note.subjects.first() {
+            Some(&subject) => self.go_to_note(note.id, subject),
+            None => {
+                self.go_to_journal();
+                self.show_search = false;
+                self.all_tasks = false;
+                self.scroll_to_note = Some(note.id);
+            }
+        }
+    }
+
+    /// Lists the notes that have every one of `subjects`.
+    pub fn go_to_common_subjects(&mut self, subjects: &[SubjectId]) {
+        let Some((&first, rest)) = subjects.split_first() else { return self.go_to_journal() };
+        self.go_to_subject(first);
+        self.also_subjects = rest.to_vec();
+        self.update_notes();
+    }
+
+    /// Lists the notes matching the subjects of a saved filter.
+    pub fn apply_filter(&mut self, filter: &SavedFilter) {
+        let Some((&first, rest)) = filter.subjects.split_first() else {
+            return self.go_to_journal();
+        };
+        self.go_to_subject(first);
+        self.also_subjects = rest.to_vec();
+        self.subject_match = filter.subject_match;
+        self.side_panel = SidePanelState::Nothing;
+        self.update_notes();
+    }
+
+    /// Stores `filter` in the vault, replacing any filter of the same name.
+    pub fn save_filter(&mut self, filter: SavedFilter) {
+        let filters = &mut self.vault_settings.saved_filters;
+        match filters.iter_mut().find(|f| f.name == filter.name) {
+            Some(existing) => *existing = filter,
+            None => filters.push(filter),
+        }
+        self.vault_settings.save(&self.vault);
+    }
+
+    pub fn delete_filter(&mut self, name: &str) {
+        self.vault_settings.saved_filters.retain(|f| f.name != name);
+        self.vault_settings.save(&self.vault);
+    }
+
     fn update_notes(&self) {
-        self.layer.write().set_search(NoteSearch {
-            subject_id: self.selected_subject,
-            task_only: self.tasks_only,
-        })
+        let hide_done_before = if self.show_completed {
+            None
+        } else {
+            let today = chrono::Local::now().date_naive();
+            self.global_settings.hide_done_before(today)
+        };
+        self.layer.write().set_search(
+            NoteSearch::new()
+                .subject_opt(self.selected_subject)
+                .also_subjects(self.also_subjects.clone())
+                .subject_match(self.subject_match)
+                .task_only(self.tasks_only)
+                .hide_done_before(hide_done_before)
+                .created_before(self.showing_before)
+                .sort_by_due(self.sort_by_due),
+        )
     }
 
     pub fn show_search(&mut self) {
         self.show_search = true;
         self.tasks_only = false;
+        self.all_tasks = false;
     }
 
     pub fn show_tasks_only(&mut self) {
         self.tasks_only = true;
         self.show_search = false;
+        self.all_tasks = false;
         self.update_notes();
     }
 
     pub fn show_notes_only(&mut self) {
         self.tasks_only = false;
         self.show_search = false;
+        self.all_tasks = false;
         self.update_notes();
     }
 
+    pub fn toggle_show_completed(&mut self) {
+        self.show_completed = !self.show_completed;
+        self.update_notes();
+    }
+
+    pub fn toggle_sort_by_due(&mut self) {
+        self.sort_by_due = !self.sort_by_due;
+        self.update_notes();
+    }
+
+    pub fn set_hide_done_after_days(&mut self, days: Option<u32>) {
+        self.global_settings.hide_done_after_days = days;
+        self.global_settings.save();
+        self.update_notes();
+    }
+
+    pub fn set_favorite_order(&mut self, order: FavoriteOrder) {
+        self.global_settings.favorite_order = order;
+        self.global_settings.save();
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.global_settings.theme = theme;
+        self.global_settings.save();
+    }
+
+    pub fn set_journal_subject(&mut self, subject: Option<SubjectId>) {
+        self.vault_settings.journal_subject = subject;
+        self.vault_settings.save(&self.vault);
+    }
+
+    /// Whether notes added in `subject` start as tasks.
+    pub fn default_new_is_task(&self, subject: SubjectId) -> bool {
+        self.vault_settings
+            .subjects
+            .get(&subject)
+            .is_some_and(|settings| settings.default_new_is_task)
+    }
+
+    pub fn set_default_new_is_task(&mut self, subject: SubjectId, is_task: bool) {
+        let settings = self.vault_settings.subjects.entry(subject).or_default();
+        settings.default_new_is_task = is_task;
+        if *settings == SubjectSettings::default() {
+            self.vault_settings.subjects.remove(&subject);
+        }
+        self.vault_settings.save(&self.vault);
+    }
+
+    /// Starts or stops indexing subject names for search. Notes that are
+    /// already indexed only change after `--reindex`.
+    pub fn set_index_subject_names(&mut self, enabled: bool) {
+        self.vault_settings.index_subject_names = enabled;
+        self.vault_settings.save(&self.vault);
+        self.layer.read().set_index_subject_names(enabled);
+        self.notify(
+            ToastLevel::Info,
+            "Run with --reindex to apply this to existing notes",
+        );
+    }
+
+    /// Whether the note input starts in task mode: in the task list, or in a
+    /// subject that adds tasks by default.
+    pub fn new_note_is_task(&self) -> bool {
+        self.tasks_only
+            || self
+                .selected_subject
+                .is_some_and(|subject| self.default_new_is_task(subject))
+    }
+
+    pub fn show_all_tasks(&mut self) {
+        self.all_tasks = true;
+        self.tasks_only = false;
+        self.show_search = false;
+    }
+
+    /// Closes the current vault and opens the one in `path` instead.
+    /// Does nothing if the vault is already open.
+    pub fn switch_vault(&mut self, path: PathBuf) {
+        if path == self.vault {
+            return;
+        }
+        tracing::info!("Switching to vault {}", path.display());
+        // The session belongs to the old vault.
+        self.stop_focus();
+        let switched = self
+            .layer
+            .write()
+            .switch_store(ConnectionType::File(path.clone()));
+        if let Err(e) = switched {
+            self.notify(
+                ToastLevel::Error,
+                format!("Could not open the vault {}: {e}", path.display()),
+            );
+            return;
+        }
+
+        self.global_settings.add_recent_vault(&path);
+        self.global_settings.save();
+        self.vault_settings = VaultSettings::load(&path);
+        self.vault = path;
+        self.reading_list = saved_reading_list(
+            &self.layer.read(),
+            &self.global_settings,
+            &self.vault_settings,
+        );
+
+        self.show_input = false;
+        self.show_search = false;
+        self.search_text.clear();
+        self.search_from = None;
+        self.search_to = None;
+        self.tasks_only = false;
+        self.all_tasks = false;
+        self.scroll_to_note = None;
+        self.showing_before = None;
+        self.linking_from = None;
+        self.selected_subject = None;
+        self.also_subjects.clear();
+        self.subject_match = SubjectMatch::All;
+        self.side_panel = SidePanelState::Nothing;
+        self.collapsed_groups.clear();
+    }
+
+    pub fn add_to_reading_list(&mut self, note: NoteId) {
+        if !self.reading_list.contains(&note) {
+            self.reading_list.push(note);
+            self.save_reading_list();
+        }
+    }
+
+    pub fn remove_from_reading_list(&mut self, note: NoteId) {
+        self.reading_list.retain(|n| *n != note);
+        self.save_reading_list();
+    }
+
+    /// Moves a note `step` places down the reading list, or up if negative.
+    pub fn move_in_reading_list(&mut self, note: NoteId, step: isize) {
+        let Some(from) = self.reading_list.iter().position(|n| *n == note) else { return };
+        let to = from
+            .saturating_add_signed(step)
+            .min(self.reading_list.len() - 1);
+        let note = self.reading_list.remove(from);
+        self.reading_list.insert(to, note);
+        self.save_reading_list();
+    }
+
+    fn save_reading_list(&mut self) {
+        if self.global_settings.persist_reading_list {
+            self.vault_settings.reading_list = self.reading_list.clone();
+            self.vault_settings.save(&self.vault);
+        }
+    }
+
+    /// Shows the reading list in the side panel, or hides it if it's shown.
+    pub fn toggle_reading_list(&mut self) {
+        self.side_panel = match (&self.side_panel, self.selected_subject) {
+            (SidePanelState::ReadingList, Some(subject)) => SidePanelState::SubjectDetails(subject),
+            (SidePanelState::ReadingList, None) => SidePanelState::Nothing,
+            _ => SidePanelState::ReadingList,
+        };
+    }
+
+    /// Shows the subject manager in the side panel, or hides it if it's shown.
+    pub fn toggle_subject_manager(&mut self) {
+        self.side_panel = match (&self.side_panel, self.selected_subject) {
+            (SidePanelState::ManageSubjects, Some(subject)) => {
+                SidePanelState::SubjectDetails(subject)
+            }
+            (SidePanelState::ManageSubjects, None) => SidePanelState::Nothing,
+            _ => SidePanelState::ManageSubjects,
+        };
+    }
+
+    /// Shows the most common words in the side panel, or hides them if they're shown.
+    pub fn toggle_word_cloud(&mut self) {
+        self.side_panel = match (&self.side_panel, self.selected_subject) {
+            (SidePanelState::WordCloud, Some(subject)) => SidePanelState::SubjectDetails(subject),
+            (SidePanelState::WordCloud, None) => SidePanelState::Nothing,
+            _ => SidePanelState::WordCloud,
+        };
+    }
+
+    /// Shows the notes created per day in the side panel, or hides them if they're shown.
+    pub fn toggle_activity(&mut self) {
+        self.side_panel = match (&self.side_panel, self.selected_subject) {
+            (SidePanelState::Activity, Some(subject)) => SidePanelState::SubjectDetails(subject),
+            (SidePanelState::Activity, None) => SidePanelState::Nothing,
+            _ => SidePanelState::Activity,
+        };
+    }
+
+    /// Lists the journal from the end of `day`, so that its notes come first.
+    pub fn show_day(&mut self, day: chrono::NaiveDate) {
+        self.selected_subject = None;
+        self.also_subjects.clear();
+        self.subject_match = SubjectMatch::All;
+        self.show_search = false;
+        self.tasks_only = false;
+        let end = day
+            .succ_opt()
+            .and_then(|next| next.and_hms_opt(0, 0, 0))
+            .and_then(|next| next.and_local_timezone(Local).earliest());
+        self.show_notes_before(end);
+    }
+
+    /// Shows the details of a subject in the side panel without leaving the current view.
+    pub fn show_subject_details(&mut self, subject: SubjectId) {
+        self.side_panel = SidePanelState::SubjectDetails(subject);
+    }
+
+    /// Opens the editor of a note in the side panel.
+    pub fn edit_in_side_panel(&mut self, note: NoteId) {
+        self.side_panel = SidePanelState::EditNote(note);
+    }
+
+    /// Closes the note editor of the side panel, going back to the subject.
+    pub fn close_side_panel_editor(&mut self) {
+        if let SidePanelState::EditNote(_) = self.side_panel {
+            self.side_panel = match self.selected_subject {
+                Some(subject) => SidePanelState::SubjectDetails(subject),
+                None => SidePanelState::Nothing,
+            };
+        }
+    }
+
+    /// Shows the links of a note in the side panel.
+    pub fn show_note_links(&mut self, note: NoteId) {
+        self.side_panel = SidePanelState::NoteLinks(note);
+    }
+
+    /// Waits for the user to pick a note to link `note` to.
+    pub fn start_linking(&mut self, note: NoteId) {
+        self.linking_from = Some(note);
+        self.notify(ToastLevel::Info, "Pick the note to link to from its menu");
+    }
+
+    /// Starts timing focus on a note, stopping any running timer first.
+    pub fn start_focus(&mut self, note: NoteId) {
+        self.stop_focus();
+        self.focus_timer = Some(FocusTimer {
+            note,
+            started_at: Local::now(),
+        });
+    }
+
+    /// Stops the running timer and adds the time to its note.
+    pub fn stop_focus(&mut self) {
+        if let Some(timer) = self.focus_timer.take() {
+            self.layer
+                .read()
+                .record_focus(timer.note, timer.started_at, Local::now());
+        }
+    }
+
     pub fn go_to_journal(&mut self) {
         self.selected_subject = None;
+        self.also_subjects.clear();
+        self.subject_match = SubjectMatch::All;
         self.scroll_to_note = None;
+        self.showing_before = None;
         self.side_panel = SidePanelState::Nothing;
         self.update_notes();
     }
 
+    /// Stops listing the notes of `subjects` after they were deleted or
+    /// merged away, going back to the journal if one of them was selected.
+    pub fn forget_subjects(&mut self, subjects: &[SubjectId]) {
+        if self
+            .selected_subject
+            .is_some_and(|id| subjects.contains(&id))
+        {
+            self.go_to_journal();
+            return;
+        }
+        let listed = self.also_subjects.len();
+        self.also_subjects.retain(|id| !subjects.contains(id));
+        if self.also_subjects.len() != listed {
+            self.update_notes();
+        }
+    }
+
     pub fn start_note_input(&mut self) {
         self.show_input = true;
     }
@@ -104,6 +541,44 @@ impl ViewState {
     pub fn set_search_text(&mut self, text: String) {
         self.search_text = text;
     }
+
+    /// Opens the search view searching for `text`.
+    pub fn search_for(&mut self, text: String) {
+        self.set_search_text(text);
+        self.show_search();
+    }
+
+    /// Lists the notes from before `time`, or the latest notes if it's `None`.
+    pub fn show_notes_before(&mut self, time: Option<DateTime<Local>>) {
+        self.showing_before = time;
+        self.scroll_to_note = None;
+        self.update_notes();
+    }
+
+    pub fn toggle_group(&mut self, key: String) {
+        if !self.collapsed_groups.remove(&key) {
+            self.collapsed_groups.insert(key);
+        }
+    }
+
+    pub fn collapse_groups(&mut self, keys: impl IntoIterator<Item = String>) {
+        self.collapsed_groups.extend(keys);
+    }
+}
+
+/// The reading list saved in the vault, if reading lists are kept between sessions.
+/// Notes deleted since are left out.
+fn saved_reading_list(
+    layer: &Layer,
+    global: &GlobalSettings,
+    vault: &VaultSettings,
+) -> Vec<NoteId> {
+    if !global.persist_reading_list {
+        return Vec::new();
+    }
+    let mut notes = vault.reading_list.clone();
+    notes.retain(|&note| layer.note_exists(note));
+    notes
 }
 
 pub fn use_view_state(cx: &ScopeState) -> Signal<ViewState> {
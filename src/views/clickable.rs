@@ -0,0 +1,58 @@
+//! Clickable elements that can also be used with the keyboard and screen readers.
+
+use dioxus::{html::input_data::keyboard_types::Key, prelude::*};
+
+#[derive(Props)]
+pub struct ClickableProps<'a> {
+    class: &'a str,
+    /// Read by screen readers instead of the content, for icon-only buttons.
+    label: Option<&'a str>,
+    title: Option<&'a str>,
+    #[props(default)]
+    style: &'a str,
+    onclick: EventHandler<'a, ()>,
+    /// Right click, or the context menu key when focused.
+    oncontextmenu: Option<EventHandler<'a, ()>>,
+    children: Element<'a>,
+}
+
+/// A `div` acting as a button: it can be focused with Tab and activated
+/// with Enter or Space as well as by clicking.
+pub fn Clickable<'a>(cx: Scope<'a, ClickableProps<'a>>) -> Element<'a> {
+    let onkeydown = move |e: KeyboardEvent| {
+        let activates = match e.key() {
+            Key::Enter => true,
+            Key::Character(c) => c == " ",
+            _ => false,
+        };
+        // Modified keys are left for the global shortcuts, e.g. Ctrl+Enter.
+        if activates && e.modifiers().is_empty() {
+            cx.props.onclick.call(());
+        }
+    };
+    let prevent_default = if cx.props.oncontextmenu.is_some() {
+        "oncontextmenu"
+    } else {
+        ""
+    };
+
+    render! {
+        div {
+            class: "{cx.props.class}",
+            role: "button",
+            tabindex: 0,
+            "aria-label": cx.props.label,
+            title: cx.props.title,
+            style: "{cx.props.style}",
+            prevent_default: "{prevent_default}",
+            onclick: move |_| cx.props.onclick.call(()),
+            onkeydown: onkeydown,
+            oncontextmenu: move |_| {
+                if let Some(oncontextmenu) = &cx.props.oncontextmenu {
+                    oncontextmenu.call(());
+                }
+            },
+            &cx.props.children
+        }
+    }
+}
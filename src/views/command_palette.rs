@@ -0,0 +1,268 @@
+//! A list of commands to run from the keyboard, opened with Ctrl+K.
+//! Going to a favorite subject is offered first.
+//!
+//! Commands that need arguments ask for them one step at a time, each with
+//! the same typeahead list.
+
+use std::collections::BTreeMap;
+
+use dioxus::{html::input_data::keyboard_types::Key, prelude::*};
+use emergence::data::{
+    layer::{use_layer, use_subjects},
+    settings::FavoriteOrder,
+    subjects::{favorites, Subject, SubjectId},
+};
+use sir::css;
+
+use crate::views::use_view_state;
+
+/// What the palette is asking for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Command,
+    /// The subject whose parent to set.
+    SetParentOf,
+    /// The new parent of the subject.
+    SetParentTo(SubjectId),
+}
+
+/// What picking an item of the list does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pick {
+    Go(Step),
+    GoToSubject(SubjectId),
+    SetParent {
+        subject: SubjectId,
+        parent: Option<SubjectId>,
+    },
+}
+
+/// Whether `id` is `root` or one of its descendants.
+fn is_in_subtree(subjects: &BTreeMap<SubjectId, Subject>, id: SubjectId, root: SubjectId) -> bool {
+    let mut next = Some(id);
+    while let Some(current) = next {
+        if current == root {
+            return true;
+        }
+        next = subjects.get(&current).and_then(|s| s.parent_id);
+    }
+    false
+}
+
+/// Subjects whose name contains `search`, sorted by name.
+fn matching_subjects<'a>(
+    subjects: &'a BTreeMap<SubjectId, Subject>,
+    search: &str,
+) -> Vec<&'a Subject> {
+    let search = search.to_lowercase();
+    let mut matches = subjects
+        .values()
+        .filter(|s| s.name.to_lowercase().contains(&search))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|s| s.name.to_lowercase());
+    matches
+}
+
+fn items(
+    step: Step,
+    search: &str,
+    subjects: &BTreeMap<SubjectId, Subject>,
+    current: Option<&Subject>,
+    favorite_order: FavoriteOrder,
+) -> Vec<(String, Pick)> {
+    match step {
+        Step::Command => {
+            let mut commands = favorites(subjects.values(), favorite_order)
+                .into_iter()
+                .map(|s| (format!("Go to {}", s.name), Pick::GoToSubject(s.id)))
+                .collect::<Vec<_>>();
+            if let Some(current) = current {
+                commands.push((
+                    format!("Set parent of {} to…", current.name),
+                    Pick::Go(Step::SetParentTo(current.id)),
+                ));
+            }
+            commands.push((
+                "Set parent of subject…".to_string(),
+                Pick::Go(Step::SetParentOf),
+            ));
+            let search = search.to_lowercase();
+            commands.retain(|(label, _)| label.to_lowercase().contains(&search));
+            commands
+        }
+        Step::SetParentOf => matching_subjects(subjects, search)
+            .into_iter()
+            .map(|s| (s.name.clone(), Pick::Go(Step::SetParentTo(s.id))))
+            .collect(),
+        Step::SetParentTo(subject) => {
+            let mut parents = Vec::new();
+            if "top level".contains(&search.to_lowercase()) {
+                parents.push((
+                    "Top level".to_string(),
+                    Pick::SetParent {
+                        subject,
+                        parent: None,
+                    },
+                ));
+            }
+            // A subject can't go under itself or its descendants.
+            parents.extend(
+                matching_subjects(subjects, search)
+                    .into_iter()
+                    .filter(|s| !is_in_subtree(subjects, s.id, subject))
+                    .map(|s| {
+                        let pick = Pick::SetParent {
+                            subject,
+                            parent: Some(s.id),
+                        };
+                        (s.name.clone(), pick)
+                    }),
+            );
+            parents
+        }
+    }
+}
+
+fn prompt(step: Step, subjects: &BTreeMap<SubjectId, Subject>) -> String {
+    match step {
+        Step::Command => "Run a command".to_string(),
+        Step::SetParentOf => "Set the parent of".to_string(),
+        Step::SetParentTo(subject) => match subjects.get(&subject) {
+            Some(subject) => format!("Set the parent of {} to", subject.name),
+            None => "Set the parent to".to_string(),
+        },
+    }
+}
+
+pub fn CommandPalette(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let all_subjects = use_subjects(cx);
+    let step = use_state(cx, || Step::Command);
+    let search = use_state(cx, String::new);
+    let highlighted = use_state(cx, || 0usize);
+
+    let subjects = all_subjects.read();
+    let current = view_state
+        .read()
+        .selected_subject
+        .and_then(|id| subjects.get(&id));
+    let favorite_order = view_state.read().global_settings.favorite_order;
+    let items = items(
+        *step.get(),
+        search.get(),
+        &subjects,
+        current,
+        favorite_order,
+    );
+    let prompt = prompt(*step.get(), &subjects);
+
+    let pick = move |pick: Pick| match pick {
+        Pick::Go(next) => {
+            step.set(next);
+            search.set(String::new());
+            highlighted.set(0);
+        }
+        Pick::GoToSubject(subject) => {
+            let mut view_state = view_state.write();
+            view_state.go_to_subject(subject);
+            view_state.command_palette = false;
+        }
+        // Moving the subtree refuses to create cycles, and can be undone.
+        Pick::SetParent { subject, parent } => {
+            layer.move_subtree(subject, parent);
+            view_state.write().command_palette = false;
+        }
+    };
+
+    let picks = items.iter().map(|(_, pick)| *pick).collect::<Vec<_>>();
+    let onkeydown = move |e: KeyboardEvent| {
+        let count = picks.len();
+        match e.key() {
+            Key::ArrowDown if count > 0 => highlighted.set((*highlighted.get() + 1) % count),
+            Key::ArrowUp if count > 0 => highlighted.set((*highlighted.get() + count - 1) % count),
+            Key::Enter => {
+                if let Some(&chosen) = picks.get(*highlighted.get()) {
+                    pick(chosen);
+                }
+            }
+            Key::Escape => view_state.write().command_palette = false,
+            _ => {}
+        }
+    };
+
+    let style = css!(
+        "
+        position: absolute;
+        top: 15%;
+        left: 50%;
+        transform: translateX(-50%);
+        width: 400px;
+        display: flex;
+        flex-direction: column;
+        gap: 5px;
+        padding: 10px;
+        border: 1px solid var(--border);
+        background-color: var(--panel);
+
+        .palette-prompt {
+            color: var(--muted);
+        }
+
+        .palette-items {
+            display: flex;
+            flex-direction: column;
+            max-height: 300px;
+            overflow-y: auto;
+        }
+
+        .palette-item {
+            padding: 5px;
+            cursor: pointer;
+
+            &:hover, &.highlighted {
+                background-color: var(--chip-hover);
+            }
+        }
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "confirm-dialog-container",
+            onclick: move |_| view_state.write().command_palette = false,
+            div {
+                class: "{style}",
+                onclick: |e| e.stop_propagation(),
+                div { class: "palette-prompt", "{prompt}" }
+                input {
+                    value: "{search}",
+                    oninput: |e| {
+                        search.set(e.value.clone());
+                        highlighted.set(0);
+                    },
+                    onkeydown: onkeydown,
+                    onmounted: |e| { e.inner().set_focus(true); },
+                }
+                div {
+                    class: "palette-items",
+                    items.into_iter().enumerate().map(|(i, (label, chosen))| {
+                        let class = if *highlighted.get() == i {
+                            "palette-item highlighted"
+                        } else {
+                            "palette-item"
+                        };
+                        rsx! {
+                            div {
+                                key: "{i}",
+                                class: class,
+                                onclick: move |_| pick(chosen),
+                                "{label}"
+                            }
+                        }
+                    })
+                }
+            }
+        }
+    })
+}
@@ -1,18 +1,28 @@
 use dioxus::prelude::*;
+use emergence::data::{layer::use_subjects, settings::FavoriteOrder, subjects::favorites};
 use sir::css;
 
-use crate::views::{list_notes::ListNotes, search_view::Search, use_view_state, ViewState};
+use crate::views::{
+    clickable::Clickable,
+    list_notes::{ListAllTasks, ListNotes},
+    search_view::Search,
+    use_view_state, ViewState,
+};
 
 pub fn Journal(cx: Scope) -> Element {
     let view_state = use_view_state(cx);
 
-    let &ViewState { show_search, .. } = &*view_state.read();
+    let &ViewState {
+        show_search,
+        all_tasks,
+        ..
+    } = &*view_state.read();
 
     let style = css!(
         "
         display: grid;
         grid-template-columns: 1fr;
-        grid-template-rows: auto 1fr;
+        grid-template-rows: auto auto 1fr;
         max-width: 800px;
         position: relative;
 
@@ -27,11 +37,19 @@ pub fn Journal(cx: Scope) -> Element {
     render! {
         div {
             class: "{style}",
+            FavoritesBar { },
             Tabs { },
             if show_search {
                 rsx! {
                     Search { }
                 }
+            } else if all_tasks {
+                rsx! {
+                    div {
+                        class: "notes",
+                        ListAllTasks { }
+                    }
+                }
             } else {
                 rsx! {
                     div {
@@ -50,15 +68,16 @@ fn Tabs(cx: Scope) -> Element {
     let ViewState {
         tasks_only,
         show_search,
+        all_tasks,
         ..
     } = &*view_state.read();
 
-    let notes_obly = !*tasks_only && !*show_search;
+    let notes_obly = !*tasks_only && !*show_search && !*all_tasks;
 
     let style = css!(
         "
         display: grid;
-        grid-template-columns: repeat(3, 1fr);
+        grid-template-columns: repeat(4, 1fr);
         grid-template-rows: 1fr;
         height: fit-content;
         gap: 0px;
@@ -75,11 +94,11 @@ fn Tabs(cx: Scope) -> Element {
             cursor: pointer;
 
             &:hover {
-                border-bottom: 2px solid rgba(0, 0, 0, 0.5);
+                border-bottom: 2px solid var(--faint);
             }
 
             &.selected {
-                border-bottom: 2px solid black;
+                border-bottom: 2px solid var(--text);
             }
         }
     "
@@ -96,21 +115,29 @@ fn Tabs(cx: Scope) -> Element {
     render! {
         div {
             class: "{style}",
-            div {
+            Clickable {
                 class: tab_class(notes_obly),
                 onclick: move |_| {
                     view_state.write().show_notes_only();
                 },
                 "Notes"
             }
-            div {
+            Clickable {
                 class: tab_class(*tasks_only),
                 onclick: move |_| {
                     view_state.write().show_tasks_only();
                 },
                 "Tasks"
             }
-            div {
+            Clickable {
+                class: tab_class(*all_tasks),
+                title: "Tasks from every subject",
+                onclick: move |_| {
+                    view_state.write().show_all_tasks();
+                },
+                "Inbox"
+            }
+            Clickable {
                 class: tab_class(*show_search),
                 onclick: move |_| {
                     view_state.write().show_search();
@@ -120,3 +147,82 @@ fn Tabs(cx: Scope) -> Element {
         }
     }
 }
+
+/// Quick access to the favorite subjects.
+fn FavoritesBar(cx: Scope) -> Element {
+    let view_state = use_view_state(cx);
+    let subjects = use_subjects(cx).read().clone();
+
+    let ViewState {
+        selected_subject,
+        global_settings,
+        ..
+    } = &*view_state.read();
+    let order = global_settings.favorite_order;
+    let favorites = favorites(subjects.values(), order);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: row;
+        flex-wrap: wrap;
+        align-items: center;
+        gap: 5px;
+
+        &:not(:empty) {
+            padding: 5px 0;
+        }
+
+        .subject-card.selected {
+            font-weight: bold;
+        }
+
+        .order {
+            margin-left: auto;
+            font-size: 0.8em;
+            color: var(--muted);
+            cursor: pointer;
+
+            &:hover {
+                text-decoration: underline;
+            }
+        }
+    "
+    );
+
+    if favorites.is_empty() {
+        return render! { div { class: "{style}" } };
+    }
+
+    let (order_label, next_order) = match order {
+        FavoriteOrder::Manual => ("Manual order", FavoriteOrder::Alphabetical),
+        FavoriteOrder::Alphabetical => ("A–Z", FavoriteOrder::Manual),
+    };
+
+    render! {
+        div {
+            class: "{style}",
+            favorites.into_iter().map(|subject| {
+                let class = if Some(subject.id) == *selected_subject {
+                    "subject-card selected"
+                } else {
+                    "subject-card"
+                };
+                rsx! {
+                    Clickable {
+                        key: "{subject.id.0}",
+                        class: class,
+                        onclick: move |_| view_state.write().go_to_subject(subject.id),
+                        "{subject.name}"
+                    }
+                }
+            })
+            Clickable {
+                class: "order",
+                title: "Change how favorites are ordered",
+                onclick: move |_| view_state.write().set_favorite_order(next_order),
+                order_label
+            }
+        }
+    }
+}
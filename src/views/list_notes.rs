@@ -1,17 +1,28 @@
 use crate::views::{
+    confirm_dialog::ConfirmDialog,
     note_input::CreateNote,
     scroll_to::ScrollTo,
+    search_view::{ExportResults, SearchSort},
+    select_subject::SelectSubject,
+    toasts::ToastLevel,
     use_view_state,
     view_note::{OnSubjectSelect, ViewNote},
+    windowing::{mounted_notes, WATCH_SCROLL_JS, WINDOWING_THRESHOLD},
     ViewState,
 };
+use chrono::Datelike;
 use dioxus::prelude::*;
 use emergence::data::{
-    layer::{use_layer, use_notes},
-    notes::{Note, TaskState},
+    layer::{use_layer, use_notes, use_subjects},
+    notes::Note,
+    search::DateRange,
+    subjects::{Subject, SubjectId},
 };
 use std::collections::BTreeMap;
 
+/// Choices for how many days completed tasks stay in the task list.
+const HIDE_DONE_OPTIONS: [Option<u32>; 4] = [None, Some(1), Some(7), Some(30)];
+
 type NoteGroup<T> = (chrono::NaiveDate, String, Vec<T>);
 
 fn group_by_date(query: &[Note]) -> Vec<NoteGroup<Note>> {
@@ -32,6 +43,80 @@ fn group_by_date(query: &[Note]) -> Vec<NoteGroup<Note>> {
     groups
 }
 
+/// The first days of the months from `first` to `last`, newest first.
+fn months_between(first: chrono::NaiveDate, last: chrono::NaiveDate) -> Vec<chrono::NaiveDate> {
+    let first = first.with_day(1).unwrap_or(first);
+    let mut month = last.with_day(1).unwrap_or(last);
+    let mut months = vec![];
+    while month >= first {
+        months.push(month);
+        let Some(previous) = month.checked_sub_months(chrono::Months::new(1)) else { break };
+        month = previous;
+    }
+    months
+}
+
+/// The time the notes of `month` are listed before when jumping to it,
+/// the start of the next month.
+fn month_cutoff(month: chrono::NaiveDate) -> Option<chrono::DateTime<chrono::Local>> {
+    month
+        .checked_add_months(chrono::Months::new(1))?
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(chrono::Local)
+        .earliest()
+}
+
+/// Lists the months with notes for jumping back in a long list.
+fn Timeline(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let subject = view_state.read().selected_subject;
+    let showing_before = view_state.read().showing_before;
+
+    let event_count = layer.read().event_count();
+    let range = use_memo(cx, (&subject, &event_count), |(subject, _)| {
+        layer.read().note_date_range(subject)
+    });
+    let Some((first, last)) = *range else { return None };
+    let months = months_between(first.date_naive(), last.date_naive());
+    if months.len() < 2 {
+        return None;
+    }
+
+    let latest_class = if showing_before.is_none() {
+        "timeline-month selected"
+    } else {
+        "timeline-month"
+    };
+    render! {
+        div {
+            class: "timeline",
+            div {
+                class: latest_class,
+                onclick: move |_| view_state.write().show_notes_before(None),
+                "Latest"
+            }
+            months.into_iter().map(|month| {
+                let cutoff = month_cutoff(month);
+                let label = month.format("%Y-%m");
+                let class = if showing_before.is_some() && cutoff == showing_before {
+                    "timeline-month selected"
+                } else {
+                    "timeline-month"
+                };
+                rsx! {
+                    div {
+                        key: "{label}",
+                        class: class,
+                        onclick: move |_| view_state.write().show_notes_before(cutoff),
+                        "{label}"
+                    }
+                }
+            })
+        }
+    }
+}
+
 /// Reverse the order of the groups and the notes in each group.
 /// This exists temporarily to test if chronological order is better.
 fn reverse_groups<T>(groups: &mut [NoteGroup<T>]) {
@@ -41,7 +126,15 @@ fn reverse_groups<T>(groups: &mut [NoteGroup<T>]) {
     }
 }
 
+/// Move pinned notes to the top of their group, keeping the order otherwise.
+fn pinned_first(groups: &mut [NoteGroup<Note>]) {
+    for (_, _, notes) in groups.iter_mut() {
+        notes.sort_by_key(|note| !note.pinned);
+    }
+}
+
 pub fn ListNotes(cx: Scope) -> Element {
+    let layer = use_layer(cx);
     let view_state = use_view_state(cx);
 
     let &ViewState {
@@ -52,6 +145,19 @@ pub fn ListNotes(cx: Scope) -> Element {
         ..
     } = &*view_state.read();
 
+    let collapsed_groups = view_state.read().collapsed_groups.clone();
+    let new_note_is_task = view_state.read().new_note_is_task();
+    let show_completed = view_state.read().show_completed;
+    let sort_by_due = view_state.read().sort_by_due;
+    let hide_done_after_days = view_state.read().global_settings.hide_done_after_days;
+    let all_subjects = use_subjects(cx);
+    // Ignore the journal subject if it has been deleted since.
+    let journal_subject = view_state
+        .read()
+        .vault_settings
+        .journal_subject
+        .filter(|id| all_subjects.read().contains_key(id));
+
     let subject_id_key = selected_subject.map_or_else(|| "none".to_string(), |id| id.0.to_string());
     let query = use_notes(cx);
 
@@ -65,15 +171,15 @@ pub fn ListNotes(cx: Scope) -> Element {
         while let Some(first) = query.peek() {
             let mut group = vec![];
             let first_date = first.created_at.naive_local().date();
-            let state = first.task_state;
+            let is_done = first.is_done();
             while let Some(node) = query.peek() {
                 let date = node.created_at.naive_local().date();
-                if date != first_date || node.task_state != state {
+                if date != first_date || node.is_done() != is_done {
                     break;
                 }
                 group.push(query.next().unwrap().clone());
             }
-            if state == TaskState::Done {
+            if is_done {
                 let key = first_date.format("%Y-%m-%d-done").to_string();
                 done.push((first_date, key, group));
             } else {
@@ -86,6 +192,63 @@ pub fn ListNotes(cx: Scope) -> Element {
     };
 
     reverse_groups(&mut groups);
+    pinned_first(&mut groups);
+
+    let scroll = view_state.read().note_list_scroll;
+    let list_key = format!("{subject_id_key}-{tasks_only}");
+    let js_eval = use_eval(cx).clone();
+    use_future(cx, (&list_key,), move |(list_key,)| {
+        let eval = js_eval(WATCH_SCROLL_JS);
+        async move {
+            let Ok(eval) = eval else { return };
+            while let Ok(report) = eval.recv().await {
+                let mut next = scroll.read().clone();
+                if next.update(&list_key, &report) {
+                    *scroll.write() = next;
+                }
+            }
+        }
+    });
+
+    // Groups holding the note we're scrolling to are always expanded.
+    let collapsed = groups
+        .iter()
+        .map(|(_, key, nodes)| {
+            let has_target =
+                scroll_to_note.is_some_and(|target| nodes.iter().any(|note| note.id == target));
+            collapsed_groups.contains(key) && !has_target
+        })
+        .collect::<Vec<_>>();
+
+    let note_count = groups
+        .iter()
+        .map(|(_, _, nodes)| nodes.len())
+        .sum::<usize>();
+    let mounted = (note_count >= WINDOWING_THRESHOLD).then(|| {
+        let layout = groups
+            .iter()
+            .zip(&collapsed)
+            .map(|((_, key, nodes), &collapsed)| {
+                (
+                    key.clone(),
+                    collapsed,
+                    nodes.iter().map(|note| note.id).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let last = groups
+            .last()
+            .and_then(|(_, _, nodes)| nodes.last())
+            .map(|note| note.id);
+        mounted_notes(&layout, &scroll.read(), &list_key, scroll_to_note, last)
+    });
+
+    let week_ago = chrono::Local::now().date_naive() - chrono::Duration::days(7);
+    let old_group_keys = groups
+        .iter()
+        .filter(|(date, _, _)| *date < week_ago)
+        .map(|(_, key, _)| key.clone())
+        .collect::<Vec<_>>();
 
     let mut groups = groups
         .into_iter()
@@ -97,15 +260,34 @@ pub fn ListNotes(cx: Scope) -> Element {
                     .into_iter()
                     .map(|note| {
                         let id = note.id;
-                        (
-                            id,
-                            rsx! { ViewNote {
-                                key: "{note.id.0}",
-                                note: note.clone(),
-                                hide_subject: selected_subject,
-                                subject_select: OnSubjectSelect::Switch
-                            } },
-                        )
+                        let is_mounted = match &mounted {
+                            Some(mounted) => mounted.contains(&id),
+                            None => true,
+                        };
+                        let node = if is_mounted {
+                            rsx! {
+                                div {
+                                    key: "{id.0}",
+                                    class: "note-slot",
+                                    "data-note": "{id}",
+                                    ViewNote {
+                                        note: note.clone(),
+                                        hide_subject: selected_subject,
+                                        subject_select: OnSubjectSelect::Switch
+                                    }
+                                }
+                            }
+                        } else {
+                            let height = scroll.read().height_of(id);
+                            rsx! {
+                                div {
+                                    key: "{id.0}",
+                                    class: "note-slot",
+                                    style: "height: {height}px;",
+                                }
+                            }
+                        };
+                        (id, node)
                     })
                     .collect::<Vec<_>>(),
             )
@@ -146,8 +328,8 @@ pub fn ListNotes(cx: Scope) -> Element {
         rsx! {
             CreateNote {
                 key: "input",
-                subject: selected_subject,
-                task: tasks_only,
+                subject: selected_subject.or(journal_subject),
+                task: new_note_is_task,
                 on_create_note: move |_| view_state.write().finish_note_input(true),
                 on_cancel: move |_| view_state.write().finish_note_input(false),
             }
@@ -163,43 +345,176 @@ pub fn ListNotes(cx: Scope) -> Element {
         }
     };
 
+    let collapse_old = if old_group_keys.is_empty() {
+        None
+    } else {
+        Some(rsx! {
+            div {
+                class: "collapse-old",
+                title: "Collapse all days older than a week",
+                onclick: move |_| view_state.write().collapse_groups(old_group_keys.clone()),
+                "Fold old"
+            }
+        })
+    };
+
+    // The list is oldest first, so older pages are loaded at the top.
+    let load_more = layer.read().has_more_notes().then(|| {
+        rsx! {
+            div {
+                class: "collapse-old",
+                title: "The list shows a page of notes at a time",
+                onclick: move |_| layer.write().load_more_notes(),
+                "Load older notes"
+            }
+        }
+    });
+
+    let done_filter = tasks_only.then(|| {
+        let toggle = hide_done_after_days.map(|_| {
+            let label = if show_completed {
+                "Hide old done"
+            } else {
+                "Show all done"
+            };
+            rsx! {
+                div {
+                    class: "collapse-old",
+                    onclick: move |_| view_state.write().toggle_show_completed(),
+                    "{label}"
+                }
+            }
+        });
+        let sort_label = if sort_by_due {
+            "Sort by created"
+        } else {
+            "Sort by due"
+        };
+        rsx! {
+            select {
+                class: "hide-done-days",
+                title: "Hide tasks completed more than this many days ago",
+                onchange: move |e| {
+                    let days = e.value.parse().ok();
+                    view_state.write().set_hide_done_after_days(days);
+                },
+                HIDE_DONE_OPTIONS.iter().map(|&days| {
+                    let (value, label) = match days {
+                        Some(days) => (days.to_string(), format!("Hide after {days}d")),
+                        None => ("never".to_string(), "Keep done".to_string()),
+                    };
+                    rsx! {
+                        option {
+                            key: "{value}",
+                            value: "{value}",
+                            selected: days == hide_done_after_days,
+                            "{label}"
+                        }
+                    }
+                })
+            }
+            toggle
+            div {
+                class: "collapse-old",
+                onclick: move |_| view_state.write().toggle_sort_by_due(),
+                "{sort_label}"
+            }
+        }
+    });
+
+    let journal_subject_select = selected_subject.is_none().then(|| {
+        let mut subjects = all_subjects.read().values().cloned().collect::<Vec<_>>();
+        subjects.sort_by(|a, b| a.name.cmp(&b.name));
+        rsx! {
+            select {
+                class: "journal-subject",
+                title: "Subject of new notes added in the journal",
+                onchange: move |e| {
+                    let subject = e.value.parse().ok();
+                    view_state.write().set_journal_subject(subject);
+                },
+                option {
+                    value: "none",
+                    selected: journal_subject.is_none(),
+                    "No subject"
+                }
+                subjects.into_iter().map(|subject| {
+                    rsx! {
+                        option {
+                            key: "{subject.id.0}",
+                            value: "{subject.id}",
+                            selected: journal_subject == Some(subject.id),
+                            "{subject.name}"
+                        }
+                    }
+                })
+            }
+        }
+    });
+
     render! {
         div {
             class: "note-grid-wrapper",
             div {
-                class: "note-grid-scroll",
+                class: "note-list-with-timeline",
                 div {
-                    class: "place-at-end",
+                    class: "note-grid-scroll note-list-scroll",
                     div {
-                        class: "note-grid",
-                        groups.into_iter().map(|(date, key, nodes)| {
-                            let date_string = date.format("%Y-%m-%d");
-                            rsx! {
-                                div {
-                                    key: "{key}",
-                                    class: "group-wrapper",
-                                    div {
-                                        class: "date-wrapper",
+                        class: "place-at-end",
+                        div {
+                            class: "note-grid",
+                            load_more
+                            groups.into_iter().zip(collapsed).map(|((date, key, nodes), collapsed)| {
+                                let date_string = date.format("%Y-%m-%d");
+                                let note_count = nodes.len();
+                                let toggle_key = key.clone();
+                                let expand_key = key.clone();
+                                let content = if collapsed {
+                                    rsx! {
+                                        div {
+                                            class: "group-collapsed",
+                                            onclick: move |_| view_state.write().toggle_group(expand_key.clone()),
+                                            "{note_count} notes hidden"
+                                        }
+                                    }
+                                } else {
+                                    rsx! {
                                         div {
-                                            class: "date",
-                                            "{date_string}"
+                                            class: "group",
+                                            nodes.into_iter().map(|(_, node)| node)
                                         }
-                                    },
+                                    }
+                                };
+                                rsx! {
                                     div {
-                                        class: "group",
-                                        nodes.into_iter().map(|(_, node)| node)
+                                        key: "{key}",
+                                        class: "group-wrapper",
+                                        "data-group": "{key}",
+                                        div {
+                                            class: "date-wrapper",
+                                            div {
+                                                class: "date collapsible",
+                                                onclick: move |_| view_state.write().toggle_group(toggle_key.clone()),
+                                                "{date_string}"
+                                            }
+                                        },
+                                        content
                                     }
                                 }
-                            }
-                        })
+                            })
+                        }
                     }
                 }
+                Timeline {}
             }
             div {
                 class: "group-wrapper",
                 style: "margin-bottom: 10px;",
                 div {
                     class: "date-wrapper",
+                    collapse_old,
+                    done_filter,
+                    journal_subject_select
                 },
                 div {
                     class: "group",
@@ -210,28 +525,279 @@ pub fn ListNotes(cx: Scope) -> Element {
     }
 }
 
+/// Groups tasks by subject, then by state. A task with several subjects is
+/// only listed under the one whose name sorts first. Tasks without a subject
+/// come last.
+fn group_tasks_by_subject(
+    tasks: &[Note],
+    subjects: &BTreeMap<SubjectId, Subject>,
+) -> Vec<(String, String, Vec<Note>)> {
+    let mut groups = BTreeMap::new();
+    for task in tasks {
+        let subject = task
+            .subjects
+            .iter()
+            .filter_map(|id| subjects.get(id))
+            .min_by(|a, b| a.name.cmp(&b.name));
+        let subject_key = subject.map(|s| (s.name.clone(), s.id));
+        groups
+            .entry((subject_key.is_none(), subject_key, task.is_done()))
+            .or_insert_with(Vec::new)
+            .push(task.clone());
+    }
+
+    groups
+        .into_iter()
+        .map(|((_, subject, is_done), notes)| {
+            let (name, id) = match subject {
+                Some((name, id)) => (name, id.0.to_string()),
+                None => ("No subject".to_string(), "none".to_string()),
+            };
+            let (label, state_key) = if is_done {
+                (format!("{name} (done)"), "done")
+            } else {
+                (name, "undone")
+            };
+            (label, format!("inbox-{id}-{state_key}"), notes)
+        })
+        .collect()
+}
+
+/// Lists the tasks of every subject, for triaging them in one place.
+pub fn ListAllTasks(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let subjects = use_subjects(cx).read().clone();
+
+    let event_count = layer.read().event_count();
+    let tasks = use_memo(cx, (&event_count,), |_| layer.read().all_tasks());
+    let collapsed_groups = view_state.read().collapsed_groups.clone();
+
+    let groups = group_tasks_by_subject(tasks, &subjects);
+    if groups.is_empty() {
+        return render! {
+            div { "No tasks anywhere" }
+        };
+    }
+
+    render! {
+        div {
+            class: "note-grid-wrapper",
+            div {
+                class: "note-grid-scroll",
+                div {
+                    class: "note-grid",
+                    groups.into_iter().map(|(label, key, notes)| {
+                        let collapsed = collapsed_groups.contains(&key);
+                        let note_count = notes.len();
+                        let toggle_key = key.clone();
+                        let expand_key = key.clone();
+                        let content = if collapsed {
+                            rsx! {
+                                div {
+                                    class: "group-collapsed",
+                                    onclick: move |_| view_state.write().toggle_group(expand_key.clone()),
+                                    "{note_count} tasks hidden"
+                                }
+                            }
+                        } else {
+                            rsx! {
+                                div {
+                                    class: "group",
+                                    notes.into_iter().map(|note| rsx! {
+                                        ViewNote {
+                                            key: "{note.id.0}",
+                                            note: note.clone(),
+                                            hide_subject: None,
+                                            subject_select: OnSubjectSelect::Switch,
+                                        }
+                                    })
+                                }
+                            }
+                        };
+                        rsx! {
+                            div {
+                                key: "{key}",
+                                class: "group-wrapper",
+                                div {
+                                    class: "date-wrapper",
+                                    div {
+                                        class: "date collapsible",
+                                        onclick: move |_| view_state.write().toggle_group(toggle_key.clone()),
+                                        "{label}"
+                                    }
+                                },
+                                content
+                            }
+                        }
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Orders search results for display and groups them by the date being
+/// sorted on. Relevance keeps the search ranking as a single ungrouped list.
+fn sort_search_results(query: &[Note], sort: SearchSort) -> Vec<(String, String, Vec<Note>)> {
+    let mut notes = query.to_vec();
+    let date_of: fn(&Note) -> chrono::DateTime<chrono::Local> = match sort {
+        SearchSort::Relevance => return vec![(String::new(), "ranked".to_string(), notes)],
+        SearchSort::Newest => {
+            notes.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            |note| note.created_at
+        }
+        SearchSort::Oldest => {
+            notes.sort_by_key(|note| note.created_at);
+            |note| note.created_at
+        }
+        SearchSort::RecentlyEdited => {
+            notes.sort_by(|a, b| b.modified_at.cmp(&a.modified_at));
+            |note| note.modified_at
+        }
+    };
+
+    let mut groups: Vec<(String, String, Vec<Note>)> = Vec::new();
+    for note in notes {
+        let date = date_of(&note)
+            .naive_local()
+            .date()
+            .format("%Y-%m-%d")
+            .to_string();
+        match groups.last_mut() {
+            Some((label, _, group)) if *label == date => group.push(note),
+            _ => groups.push((date.clone(), date, vec![note])),
+        }
+    }
+    groups
+}
+
+#[derive(Clone, PartialEq)]
+enum TagAll {
+    Hidden,
+    SelectSubject,
+    Confirm(Subject),
+}
+
 #[inline_props]
-pub fn ListSearchResult(cx: Scope, search_text: String) -> Element {
+pub fn ListSearchResult(
+    cx: Scope,
+    search_text: String,
+    sort: SearchSort,
+    range: DateRange,
+) -> Element {
     let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let tag_all = use_state(cx, || TagAll::Hidden);
+    let exporting = use_state(cx, || false);
 
     let event_count = layer.read().event_count();
-    let query_fut = use_future(cx, (search_text, &event_count), move |(search_text, _)| {
+    let deps = (search_text, range, &event_count);
+    let count_fut = use_future(cx, deps, move |(search_text, range, _)| {
+        let search_text = search_text.trim().to_string();
+        let search = layer.read().search();
+        async move { search.search_count(search_text, None, range).await.ok() }
+    });
+    let query_fut = use_future(cx, deps, move |(search_text, range, _)| {
         let search_text = search_text.trim().to_string();
         let search = layer.read().search();
-        async move { search.perform_search(search_text).await }
+        async move {
+            let result = search.search_in_range(search_text, range).await;
+            result.unwrap_or_else(|e| {
+                view_state
+                    .read()
+                    .notify(ToastLevel::Error, format!("Search failed: {e}"));
+                Vec::new()
+            })
+        }
+    });
+
+    let result_count = count_fut.value().copied().flatten().map(|count| {
+        let plural = if count == 1 { "result" } else { "results" };
+        rsx! {
+            div {
+                class: "search-result-count",
+                "{count} {plural}"
+            }
+        }
     });
+
     let query = match query_fut.value() {
         Some(query) => query,
-        _ => return render! { div { "Loading..." } },
+        _ => {
+            return render! {
+                div { class: "search-result-actions", result_count }
+                div { "Loading..." }
+            }
+        }
     };
 
-    let groups = group_by_date(query);
+    let note_ids = query.iter().map(|note| note.id).collect::<Vec<_>>();
+    let tag_all_popup = match tag_all.get() {
+        TagAll::Hidden => None,
+        TagAll::SelectSubject => Some(rsx! {
+            SelectSubject {
+                on_select: move |subject: Subject| tag_all.set(TagAll::Confirm(subject)),
+                on_cancel: |_| tag_all.set(TagAll::Hidden),
+                ignore_subjects: vec![],
+            }
+        }),
+        TagAll::Confirm(subject) => {
+            let plural = if note_ids.len() == 1 { "note" } else { "notes" };
+            let message = cx.bump().alloc_str(&format!(
+                "Add the subject \"{}\" to {} {plural}?",
+                subject.name,
+                note_ids.len()
+            ));
+            let label = format!("Tag {} search results", note_ids.len());
+            let subject_id = subject.id;
+            Some(rsx! {
+                ConfirmDialog {
+                    title: "Tag All Results",
+                    message: message,
+                    on_confirm: move |_| {
+                        layer.write().checkpoint(Some(label.clone()));
+                        layer.add_subject_to_notes(subject_id, note_ids.clone());
+                        tag_all.set(TagAll::Hidden);
+                    },
+                    on_cancel: |_| tag_all.set(TagAll::Hidden),
+                }
+            })
+        }
+    };
+    let export_popup = (*exporting.get()).then(|| {
+        rsx! {
+            ExportResults {
+                notes: query.clone(),
+                query: search_text.clone(),
+                on_done: |_| exporting.set(false),
+            }
+        }
+    });
+    let tag_all_button = (!query.is_empty()).then(|| {
+        rsx! {
+            div {
+                class: "tag-all-results",
+                onclick: move |_| tag_all.set(TagAll::SelectSubject),
+                "Tag all results"
+            }
+            div {
+                class: "tag-all-results",
+                onclick: move |_| exporting.set(true),
+                "Export results"
+            }
+            tag_all_popup
+            export_popup
+        }
+    });
+
+    let groups = sort_search_results(query, *sort);
 
     let groups = groups
         .into_iter()
-        .map(|(date, key, nodes)| {
+        .map(|(label, key, nodes)| {
             (
-                date,
+                label,
                 key,
                 nodes
                     .into_iter()
@@ -249,14 +815,18 @@ pub fn ListSearchResult(cx: Scope, search_text: String) -> Element {
         .collect::<Vec<_>>();
 
     render! {
+        div {
+            class: "search-result-actions",
+            result_count,
+            tag_all_button
+        }
         div {
             class: "note-grid-wrapper",
             div {
                 class: "note-grid-scroll",
                 div {
                     class: "note-grid",
-                    groups.into_iter().map(|(date, key, nodes)| {
-                        let date_string = date.format("%Y-%m-%d");
+                    groups.into_iter().map(|(label, key, nodes)| {
                         rsx! {
                             div {
                                 key: "{key}",
@@ -265,7 +835,7 @@ pub fn ListSearchResult(cx: Scope, search_text: String) -> Element {
                                     class: "date-wrapper",
                                     div {
                                         class: "date",
-                                        "{date_string}"
+                                        "{label}"
                                     }
                                 },
                                 div {
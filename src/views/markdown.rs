@@ -1,15 +1,56 @@
 use dioxus::prelude::*;
+use emergence::data::{
+    layer::use_subjects,
+    subjects::{find_subject_mentions, Subject},
+};
+
+use crate::views::use_view_state;
+
+/// Returns and clears the subject chip the current click landed on.
+/// The listener setting it is registered in `main.rs`.
+const CLICKED_CHIP_JS: &str = r#"
+    const subject = window.clickedSubjectChip;
+    window.clickedSubjectChip = null;
+    return subject;
+"#;
 
 #[inline_props]
 pub fn Markdown(cx: Scope, source: String) -> Element {
+    let view_state = use_view_state(cx);
+    let subjects = use_subjects(cx);
+    let js_eval = use_eval(cx);
+
+    let subject_mentions = view_state.read().global_settings.subject_mentions;
+
     let html = markdown::to_html_with_options(source, &markdown::Options::gfm());
     let body = match html {
-        Ok(html) => rsx! {
-            div {
-                class: "markdown allow-select",
-                dangerous_inner_html: "{html}"
+        Ok(html) => {
+            let html = if subject_mentions {
+                link_subject_mentions(&html, subjects.read().values())
+            } else {
+                html
+            };
+            let onclick = move |_| {
+                if !subject_mentions {
+                    return;
+                }
+                let Ok(eval) = js_eval(CLICKED_CHIP_JS) else { return };
+                cx.spawn(async move {
+                    let clicked = eval.join().await.ok();
+                    let clicked = clicked.as_ref().and_then(|v| v.as_str());
+                    if let Some(id) = clicked.and_then(|id| id.parse().ok()) {
+                        view_state.write().go_to_subject(id);
+                    }
+                });
+            };
+            rsx! {
+                div {
+                    class: "markdown allow-select",
+                    onclick: onclick,
+                    dangerous_inner_html: "{html}"
+                }
             }
-        },
+        }
         Err(e) => {
             tracing::warn!("Markdown error: {}", e);
             rsx! {
@@ -23,3 +64,55 @@ pub fn Markdown(cx: Scope, source: String) -> Element {
 
     cx.render(body)
 }
+
+/// Wraps `@Name` mentions of existing subjects in the rendered `html` into
+/// subject chips. Text inside code blocks and links is left alone.
+fn link_subject_mentions<'a>(html: &str, subjects: impl Iterator<Item = &'a Subject>) -> String {
+    let subjects = subjects.collect::<Vec<_>>();
+    let mut out = String::with_capacity(html.len());
+    // How many `code`, `pre` or `a` elements we are inside of.
+    let mut skip_depth = 0usize;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let (text, tail) = rest.split_at(text_end);
+        if skip_depth == 0 {
+            let mut last = 0;
+            for (range, id) in find_subject_mentions(text, subjects.iter().copied()) {
+                out.push_str(&text[last..range.start]);
+                out.push_str(&format!(
+                    r#"<span class="subject-chip" data-subject="{}">{}</span>"#,
+                    id.0,
+                    &text[range.clone()]
+                ));
+                last = range.end;
+            }
+            out.push_str(&text[last..]);
+        } else {
+            out.push_str(text);
+        }
+
+        if tail.is_empty() {
+            break;
+        }
+        let tag_end = tail.find('>').map_or(tail.len(), |i| i + 1);
+        let (tag, tail) = tail.split_at(tag_end);
+        let closing = tag.starts_with("</");
+        let name = tag
+            .trim_start_matches(['<', '/'])
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default();
+        if matches!(name, "code" | "pre" | "a") {
+            if closing {
+                skip_depth = skip_depth.saturating_sub(1);
+            } else {
+                skip_depth += 1;
+            }
+        }
+        out.push_str(tag);
+        rest = tail;
+    }
+    out
+}
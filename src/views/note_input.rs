@@ -1,8 +1,11 @@
-use std::{hash::Hash, rc::Rc};
+use std::{hash::Hash, rc::Rc, time::Duration};
 
 use crate::{
     data::subjects::{Subject, SubjectId},
-    views::{select_subject::SelectSubject, use_view_state, view_note::SubjectCards},
+    views::{
+        clickable::Clickable, select_subject::SelectSubject, toasts::ToastLevel, use_view_state,
+        view_note::SubjectCards,
+    },
 };
 use ahash::HashMap;
 use dioxus::{
@@ -11,8 +14,11 @@ use dioxus::{
 };
 use dioxus_signals::*;
 use emergence::data::{
-    layer::use_layer,
+    drafts::Drafts,
+    layer::{use_layer, Layer},
     notes::{Note, NoteBuilder, NoteId, TaskState},
+    paste::html_to_markdown,
+    settings::SubmitKey,
 };
 
 struct SignalCache<K, V: 'static> {
@@ -97,6 +103,9 @@ impl<K, V> std::ops::Deref for CachedSignalOwner<K, V> {
     }
 }
 
+#[derive(Default, Clone)]
+struct TextInputSignals(Signal<SignalCache<Option<NoteId>, String>>);
+
 /// Gives a signal for the note editor's text.
 /// `note_id` *must not* change for the lifetime of the component.
 fn use_text_input_state(
@@ -104,9 +113,6 @@ fn use_text_input_state(
     note_id: Option<NoteId>,
     initial_text: Option<&str>,
 ) -> CachedSignalOwner<Option<NoteId>, String> {
-    #[derive(Default, Clone)]
-    struct TextInputSignals(Signal<SignalCache<Option<NoteId>, String>>);
-
     let cache = use_root_context(cx, TextInputSignals::default).0;
     let scope = cache.origin_scope();
     *cx.use_hook(|| {
@@ -124,6 +130,31 @@ fn use_text_input_state(
     })
 }
 
+/// The text of the open note inputs, to check whether any of them has text
+/// that would be lost. Can be used outside of rendering.
+#[derive(Clone, Copy)]
+pub struct UnsavedText(Signal<SignalCache<Option<NoteId>, String>>);
+
+impl UnsavedText {
+    /// Whether a new note has text, or an edit has changed the text of its note.
+    pub fn any(&self, layer: &Layer) -> bool {
+        self.0.read().items.iter().any(|(note, text)| {
+            let text = text.read();
+            match note {
+                None => !text.is_empty(),
+                Some(id) => match layer.notes_by_id(&[*id]).pop() {
+                    Some(note) => note.text != *text,
+                    None => !text.is_empty(),
+                },
+            }
+        })
+    }
+}
+
+pub fn use_unsaved_text(cx: &ScopeState) -> UnsavedText {
+    UnsavedText(use_root_context(cx, TextInputSignals::default).0)
+}
+
 #[derive(Props)]
 pub struct CreateNoteProps<'a> {
     #[props(!optional)]
@@ -164,10 +195,15 @@ pub fn CreateNote<'a>(cx: Scope<'a, CreateNoteProps<'a>>) -> Element<'a> {
 pub struct EditNoteProps<'a> {
     note: Note,
     on_done: EventHandler<'a, ()>,
+    /// Whether the editor is in the side panel, which can't list similar
+    /// notes without closing it.
+    #[props(default)]
+    in_side_panel: bool,
 }
 
 pub fn EditNote<'a>(cx: Scope<'a, EditNoteProps<'a>>) -> Element<'a> {
     let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
     let note_id = cx.props.note.id;
 
     let on_done = move |_| {
@@ -175,7 +211,13 @@ pub fn EditNote<'a>(cx: Scope<'a, EditNoteProps<'a>>) -> Element<'a> {
     };
 
     let on_create_note = move |(text, subjects): (String, Vec<SubjectId>)| {
-        layer.edit_note(note_id, NoteBuilder::new().text(text).subjects(subjects));
+        let policy = view_state.read().global_settings.empty_edit;
+        if !layer.save_edit(&cx.props.note, text, subjects, policy) {
+            view_state.read().notify(
+                ToastLevel::Info,
+                "A note can't be left empty, so the old text was kept",
+            );
+        }
         cx.props.on_done.call(());
     };
 
@@ -186,6 +228,7 @@ pub fn EditNote<'a>(cx: Scope<'a, EditNoteProps<'a>>) -> Element<'a> {
             initial_text: cx.props.note.text.clone(),
             initial_subjects: cx.props.note.subjects.clone(),
             note_id: note_id,
+            in_side_panel: cx.props.in_side_panel,
         }
     })
 }
@@ -197,24 +240,160 @@ struct NoteInputProps<'a> {
     note_id: Option<NoteId>,
     initial_text: Option<String>,
     initial_subjects: Vec<SubjectId>,
+    /// See [`EditNoteProps::in_side_panel`].
+    #[props(default)]
+    in_side_panel: bool,
+}
+
+/// Grows the note textareas to fit their content, up to `max_height` pixels.
+/// Taller content scrolls inside the textarea.
+fn textarea_hack_js(max_height: Option<u32>) -> String {
+    let max_height = max_height.map_or("Infinity".to_string(), |h| h.to_string());
+    format!(
+        r#"
+        const maxHeight = {max_height};
+        const tx = document.getElementsByClassName("note-textarea");
+        for (let i = 0; i < tx.length; i++) {{
+            const parent = tx[i].parentElement;
+            const parentStyle = window.getComputedStyle(parent, null);
+            const parentPadding =
+                parseInt(parentStyle.getPropertyValue('padding-bottom'))
+                + parseInt(parentStyle.getPropertyValue('padding-top'));
+            const oldHeight = Math.min(tx[i].scrollHeight, maxHeight);
+            tx[i].parentElement.setAttribute("style",
+                "height:" + (oldHeight + parentPadding) + "px;");
+            tx[i].setAttribute("style", "height:0;");
+            const height = Math.min(tx[i].scrollHeight, maxHeight);
+            const overflow = tx[i].scrollHeight > maxHeight ? "auto" : "hidden";
+            tx[i].setAttribute("style",
+                "height:" + height + "px; overflow-y:" + overflow + ";");
+            tx[i].parentElement.setAttribute("style", "height: fit-content;");
+            tx[i].scrollIntoView({{ block: "nearest" }});
+        }}
+        "#
+    )
+}
+
+/// Hands HTML pasted into the note textarea marked with `paste_id` over with
+/// the text around the selection it replaces, instead of pasting it.
+fn rich_paste_js(paste_id: usize) -> String {
+    format!(
+        r#"
+        const watch = () => {{
+            const tx = document.querySelector('.note-textarea[data-paste-id="{paste_id}"]');
+            if (!tx) {{
+                requestAnimationFrame(watch);
+                return;
+            }}
+            tx.addEventListener("paste", (e) => {{
+                const html = e.clipboardData.getData("text/html");
+                if (!html) return;
+                e.preventDefault();
+                dioxus.send({{
+                    html: html,
+                    before: tx.value.slice(0, tx.selectionStart),
+                    after: tx.value.slice(tx.selectionEnd),
+                }});
+            }});
+        }};
+        watch();
+        "#
+    )
+}
+
+/// Puts the cursor at `position`, in UTF-16 code units like the DOM counts them,
+/// once the pasted text has been rendered.
+fn set_cursor_js(paste_id: usize, position: usize) -> String {
+    format!(
+        r#"
+        requestAnimationFrame(() => {{
+            const tx = document.querySelector('.note-textarea[data-paste-id="{paste_id}"]');
+            if (tx) {{
+                tx.selectionStart = tx.selectionEnd = {position};
+            }}
+        }});
+        "#
+    )
 }
 
-const TEXTAREA_HACK: &str = r#"
-const tx = document.getElementsByClassName("note-textarea");
-for (let i = 0; i < tx.length; i++) {
-    const parent = tx[i].parentElement;
-    const parentStyle = window.getComputedStyle(parent, null);
-    const parentPadding =
-        parseInt(parentStyle.getPropertyValue('padding-bottom'))
-        + parseInt(parentStyle.getPropertyValue('padding-top'));
-    tx[i].parentElement.setAttribute("style",
-        "height:" + (tx[i].scrollHeight + parentPadding) + "px;");
-    tx[i].setAttribute("style", "height:0;");
-    tx[i].setAttribute("style", "height:" + (tx[i].scrollHeight) + "px;");
-    tx[i].parentElement.setAttribute("style", "height: fit-content;");
-    tx[i].scrollIntoView({ block: "nearest" });
+/// Whether Enter with `modifiers` submits the note input.
+fn submits(submit_key: SubmitKey, modifiers: Modifiers) -> bool {
+    match submit_key {
+        SubmitKey::CtrlEnter => modifiers.contains(Modifiers::CONTROL),
+        SubmitKey::Enter => !modifiers.contains(Modifiers::SHIFT),
+    }
+}
+
+/// Where the `@` that opened the subject selection is in the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mention {
+    None,
+    /// `@` was pressed but the input with it hasn't arrived yet.
+    Typed,
+    /// A byte offset into the text.
+    At(usize),
+}
+
+impl Mention {
+    /// Follows the `@` through an edit of the text from `before` to `after`.
+    fn after_edit(self, before: &str, after: &str) -> Self {
+        let at = match self {
+            Mention::None => return Mention::None,
+            Mention::Typed => typed_mention_at(before, after),
+            Mention::At(at) => shift_mention(at, before, after),
+        };
+        at.map_or(Mention::None, Mention::At)
+    }
+}
+
+/// The bytes `before` and `after` start and end with, not overlapping.
+fn common_affixes(before: &str, after: &str) -> (usize, usize) {
+    let (before, after) = (before.as_bytes(), after.as_bytes());
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix, suffix)
+}
+
+/// Where the `@` typed to go from `before` to `after` is in `after`.
+fn typed_mention_at(before: &str, after: &str) -> Option<usize> {
+    let (prefix, _) = common_affixes(before, after);
+    // Right after a `@`, typing another one looks like it went after both.
+    // Either can be removed for the same result.
+    (after.as_bytes().get(prefix) == Some(&b'@')).then_some(prefix)
+}
+
+/// Where the `@` at `at` in `before` is in `after`, unless the edit touched it.
+fn shift_mention(at: usize, before: &str, after: &str) -> Option<usize> {
+    let (prefix, suffix) = common_affixes(before, after);
+    let moved = if prefix > at {
+        at
+    } else if before.len() - suffix <= at {
+        at + after.len() - before.len()
+    } else {
+        return None;
+    };
+    (after.as_bytes().get(moved) == Some(&b'@')).then_some(moved)
+}
+
+/// `text` without the `@` at `at`, if it's there.
+fn remove_mention(text: &str, at: usize) -> Option<String> {
+    if text.as_bytes().get(at) != Some(&b'@') {
+        return None;
+    }
+    let mut text = text.to_string();
+    text.remove(at);
+    Some(text)
 }
-"#;
+
+/// Notes longer than this are resized once typing pauses for
+/// [`RESIZE_DEBOUNCE`] instead of on every keystroke.
+const RESIZE_DEBOUNCE_CHARS: usize = 5_000;
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(150);
 
 fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
     #[derive(PartialEq, Eq, Clone, Copy)]
@@ -224,24 +403,75 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
         YesMouse,
     }
 
+    let view_state = use_view_state(cx);
+
     // Resize trick
     // Adapted from https://stackoverflow.com/a/25621277
     let js_eval = use_eval(cx);
-    let size_textareas = move || {
-        js_eval(TEXTAREA_HACK).unwrap();
+    let resize_js = textarea_hack_js(view_state.read().global_settings.note_input_max_height);
+    let paste_resize_js = resize_js.clone();
+    let size_textareas = {
+        let js_eval = js_eval.clone();
+        let resize_js = resize_js.clone();
+        move || {
+            js_eval(&resize_js).unwrap();
+        }
     };
-
-    let text = use_text_input_state(cx, cx.props.note_id, cx.props.initial_text.as_deref());
+    // Bumped on every input, so a pending resize can tell it has been superseded.
+    let resize_generation = use_ref(cx, || 0u64);
+    let schedule_resize = move |text_len: usize| {
+        if text_len < RESIZE_DEBOUNCE_CHARS {
+            js_eval(&resize_js).unwrap();
+            return;
+        }
+        let generation = {
+            let mut generation = resize_generation.write_silent();
+            *generation += 1;
+            *generation
+        };
+        let resize_generation = resize_generation.clone();
+        let js_eval = js_eval.clone();
+        let resize_js = resize_js.clone();
+        cx.spawn(async move {
+            tokio::time::sleep(RESIZE_DEBOUNCE).await;
+            if *resize_generation.read() == generation {
+                js_eval(&resize_js).unwrap();
+            }
+        });
+    };
+    let vault = view_state.read().vault.clone();
+    let note_id = cx.props.note_id;
+
+    // A draft left behind by a crash takes precedence over the note's text.
+    let draft = cx.use_hook(|| Drafts::load(&vault).get(note_id).map(str::to_owned));
+    let initial_text = draft.as_deref().or(cx.props.initial_text.as_deref());
+    let text = use_text_input_state(cx, note_id, initial_text);
+
+    let autosave_interval = view_state.read().global_settings.draft_autosave_interval();
+    use_future(cx, (), |_| {
+        let vault = vault.clone();
+        let initial_text = cx.props.initial_text.clone();
+        async move {
+            let Some(interval) = autosave_interval else { return };
+            loop {
+                tokio::time::sleep(interval).await;
+                let text = text.read().clone();
+                // An edit that hasn't changed anything isn't worth keeping.
+                let unchanged = initial_text.as_deref() == Some(text.as_str());
+                Drafts::update(&vault, note_id, if unchanged { "" } else { &text });
+            }
+        }
+    });
 
     // TODO: Combine these states.
     let subjects = use_ref(cx, || cx.props.initial_subjects.clone());
     let show_subjects = use_state(cx, || ShowSubjects::No);
+    let mention = use_ref(cx, || Mention::None);
     let textarea = use_ref(cx, || None::<Rc<MountedData>>);
 
-    let view_state = use_view_state(cx);
-
     let cleanup = move || {
         view_state.write().side_panel.back();
+        Drafts::update(&view_state.read().vault, note_id, "");
     };
 
     let submit = move || {
@@ -261,14 +491,50 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
         cx.props.on_cancel.call(());
     };
 
-    let onkeypress = move |e: KeyboardEvent| match e.key() {
-        Key::Enter if e.modifiers().contains(Modifiers::CONTROL) => {
+    // With `SubmitKey::Enter`, the newline of a submitting Enter is
+    // suppressed by the document listener in `main.rs`, which looks at
+    // `data-enter-submits`. That listener also keeps Enter in the input from
+    // reaching the global Ctrl+Enter shortcut, which starts a new note.
+    let submit_key = view_state.read().global_settings.submit_key;
+    let enter_submits = submit_key == SubmitKey::Enter;
+    let show_similar = !cx.props.in_side_panel;
+    let auto_similar = show_similar && view_state.read().global_settings.auto_similar_notes;
+
+    // Pasted HTML is converted to Markdown. Without HTML, or with the
+    // setting off, the textarea pastes plain text as usual.
+    let paste_id = cx.scope_id().0;
+    let rich_paste = view_state.read().global_settings.rich_paste;
+    let paste_eval = use_eval(cx).clone();
+    use_future(cx, (), move |_| {
+        let eval = rich_paste.then(|| paste_eval(&rich_paste_js(paste_id)));
+        async move {
+            let Some(Ok(eval)) = eval else { return };
+            while let Ok(paste) = eval.recv().await {
+                let markdown = html_to_markdown(paste["html"].as_str().unwrap_or_default());
+                let before = paste["before"].as_str().unwrap_or_default();
+                let after = paste["after"].as_str().unwrap_or_default();
+                let new_text = format!("{before}{markdown}{after}");
+                let cursor = before.encode_utf16().count() + markdown.encode_utf16().count();
+                text.set(new_text.clone());
+                let js = format!("{}{}", set_cursor_js(paste_id, cursor), paste_resize_js);
+                if let Err(e) = paste_eval(&js) {
+                    tracing::warn!("Placing the cursor after a paste failed: {:?}", e);
+                }
+                if auto_similar {
+                    view_state.write().side_panel.list_similar(new_text);
+                }
+            }
+        }
+    });
+    let onkeydown = move |e: KeyboardEvent| match e.key() {
+        Key::Enter if submits(submit_key, e.modifiers()) => {
             submit();
         }
         Key::Escape => {
             cancel();
         }
         Key::Character(c) if c == "@" && *show_subjects.get() == ShowSubjects::No => {
+            *mention.write_silent() = Mention::Typed;
             show_subjects.set(ShowSubjects::YesKeyboard);
         }
         _ => {}
@@ -278,10 +544,12 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
         subjects.write().push(subject.id);
         show_subjects.set(ShowSubjects::No);
         textarea.read().as_ref().unwrap().set_focus(true);
-        let t = text.read().clone();
-        if *show_subjects.get() == ShowSubjects::YesKeyboard && t.ends_with('@') {
-            // remove the @
-            text.set(t[0..t.len() - 1].to_string());
+        let mention = std::mem::replace(&mut *mention.write_silent(), Mention::None);
+        if let Mention::At(at) = mention {
+            let removed = remove_mention(&text.read(), at);
+            if let Some(t) = removed {
+                text.set(t);
+            }
         }
     };
 
@@ -290,7 +558,10 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
             class: "note-row",
             SubjectCards {
                 sids: subjects.read().clone(),
-                on_add_subject: |_| show_subjects.set(ShowSubjects::YesMouse),
+                on_add_subject: |_| {
+                    *mention.write_silent() = Mention::None;
+                    show_subjects.set(ShowSubjects::YesMouse);
+                },
                 on_click_subject: move |subject: Subject| {
                     subjects.write().retain(|s| *s != subject.id);
                     textarea.read().as_ref().unwrap().set_focus(true);
@@ -303,17 +574,28 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
                     value: "{*text}",
                     rows: 2,
                     onmounted: move |e| {
-                        view_state.write().side_panel.list_similar(text.read().clone());
+                        if auto_similar {
+                            view_state.write().side_panel.list_similar(text.read().clone());
+                        }
                         *textarea.write_silent() = Some(e.inner().clone());
                         e.inner().set_focus(true);
                         size_textareas();
                     },
                     oninput: move |e| {
+                        let current = *mention.read();
+                        if current != Mention::None {
+                            let moved = current.after_edit(&text.read(), &e.value);
+                            *mention.write_silent() = moved;
+                        }
                         text.set(e.value.clone());
-                        size_textareas();
-                        view_state.write().side_panel.list_similar(e.value.clone())
+                        schedule_resize(e.value.len());
+                        if auto_similar {
+                            view_state.write().side_panel.list_similar(e.value.clone());
+                        }
                     },
-                    onkeypress: onkeypress,
+                    "data-enter-submits": "{enter_submits}",
+                    "data-paste-id": "{paste_id}",
+                    onkeydown: onkeydown,
                 }
             },
             div {
@@ -331,6 +613,18 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
                         onclick: move |_| cancel(),
                         "Cancel"
                     }
+                    if show_similar && !auto_similar {
+                        rsx! {
+                            Clickable {
+                                class: "note-action",
+                                title: "Show similar notes in the side panel",
+                                onclick: move |_| {
+                                    view_state.write().side_panel.list_similar(text.read().clone());
+                                },
+                                "Similar"
+                            }
+                        }
+                    }
                 }
             }
             if *show_subjects.get() != ShowSubjects::No {
@@ -338,6 +632,7 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
                     SelectSubject {
                         on_select: on_select_subject,
                         on_cancel: move |_| {
+                            *mention.write_silent() = Mention::None;
                             show_subjects.set(ShowSubjects::No);
                             textarea.read().as_ref().unwrap().set_focus(true);
                         },
@@ -349,3 +644,62 @@ fn NoteInput<'a>(cx: Scope<'a, NoteInputProps<'a>>) -> Element<'a> {
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Types `@` at `at` and picks a subject after `edit`.
+    fn pick_subject(text: &str, at: usize, edit: impl Fn(&str) -> String) -> Option<String> {
+        let typed = format!("{}@{}", &text[..at], &text[at..]);
+        let mention = Mention::Typed.after_edit(text, &typed);
+        let edited = edit(&typed);
+        match mention.after_edit(&typed, &edited) {
+            Mention::At(at) => remove_mention(&edited, at),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn mention_at_the_end() {
+        assert_eq!(
+            pick_subject("Call mom", 8, |t| t.to_string()),
+            Some("Call mom".to_string())
+        );
+    }
+
+    #[test]
+    fn mention_in_the_middle() {
+        assert_eq!(
+            pick_subject("Call mom about @ things", 5, |t| t.to_string()),
+            Some("Call mom about @ things".to_string())
+        );
+        assert_eq!(
+            pick_subject("Äiti ja isä", 5, |t| t.to_string()),
+            Some("Äiti ja isä".to_string())
+        );
+    }
+
+    #[test]
+    fn mention_follows_edits() {
+        assert_eq!(
+            pick_subject("Call mom", 8, |t| format!("Please {t}")),
+            Some("Please Call mom".to_string())
+        );
+        assert_eq!(
+            pick_subject("Call mom", 4, |t| format!("{t} today")),
+            Some("Call mom today".to_string())
+        );
+        assert_eq!(pick_subject("Call mom", 4, |t| t.replace('@', "")), None);
+    }
+
+    #[test]
+    fn mention_next_to_another() {
+        assert_eq!(
+            pick_subject("mail@", 5, |t| t.to_string()),
+            Some("mail@".to_string())
+        );
+        assert_eq!(remove_mention("no mention", 3), None);
+        assert_eq!(remove_mention("short", 10), None);
+    }
+}
@@ -0,0 +1,61 @@
+//! Notifications for note reminders.
+
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use emergence::data::layer::use_layer;
+
+use super::{toasts::ToastLevel, use_view_state};
+
+/// How often due reminders are looked for.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Notifications show at most this many characters of the note.
+const MAX_BODY_CHARS: usize = 120;
+
+/// Shows a native desktop notification.
+fn desktop_notification(title: &str, body: &str) -> Result<(), notify_rust::error::Error> {
+    notify_rust::Notification::new()
+        .appname("Emergence")
+        .summary(title)
+        .body(body)
+        .show()?;
+    Ok(())
+}
+
+/// The first line of the note, shortened to fit a notification.
+fn notification_body(text: &str) -> String {
+    let line = text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or_default();
+    let mut body = line.trim().chars().take(MAX_BODY_CHARS).collect::<String>();
+    if line.trim().chars().count() > MAX_BODY_CHARS {
+        body.push('…');
+    }
+    body
+}
+
+/// Shows a desktop notification for every reminder as it comes due, or a
+/// toast where notifications can't be shown. A reminder is cleared once it
+/// has been shown. The first check happens right away, so reminders that
+/// came due while the app was closed are shown on launch.
+pub fn use_reminders(cx: &ScopeState) {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    use_future(cx, (), move |_| async move {
+        loop {
+            for note in layer.due_reminders() {
+                let body = notification_body(&note.text);
+                if let Err(e) = desktop_notification("Reminder", &body) {
+                    tracing::warn!("Could not show a desktop notification: {}", e);
+                    view_state
+                        .read()
+                        .notify(ToastLevel::Info, format!("Reminder: {body}"));
+                }
+                layer.clear_reminder(&note);
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
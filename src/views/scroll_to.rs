@@ -21,7 +21,7 @@ pub fn ScrollTo<'a>(cx: Scope<'a, ScrollToProps<'a>>) -> Element<'a> {
             }
 
             25% {
-                box-shadow: 0px 0px 5px 5px #c29232;
+                box-shadow: 0px 0px 5px 5px var(--highlight);
             }
 
             100% {
@@ -1,15 +1,131 @@
+use chrono::NaiveDate;
 use dioxus::prelude::*;
+use emergence::data::{
+    export::{export_to_writer, ExportFormat},
+    layer::{use_layer, use_subjects},
+    notes::Note,
+    replace::FindPattern,
+    search::DateRange,
+};
+use sir::css;
 
-use crate::views::list_notes::ListSearchResult;
+use crate::views::{
+    clickable::Clickable, confirm_dialog::ConfirmDialog, list_notes::ListSearchResult,
+    toasts::ToastLevel,
+};
 
 use super::use_view_state;
 
+const MAX_SUGGESTIONS: usize = 8;
+const MAX_SUBJECT_RESULTS: usize = 20;
+
+/// How search results are ordered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Newest,
+    Oldest,
+    RecentlyEdited,
+}
+
+impl SearchSort {
+    const ALL: [SearchSort; 4] = [
+        SearchSort::Relevance,
+        SearchSort::Newest,
+        SearchSort::Oldest,
+        SearchSort::RecentlyEdited,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "Relevance",
+            SearchSort::Newest => "Newest",
+            SearchSort::Oldest => "Oldest",
+            SearchSort::RecentlyEdited => "Recently edited",
+        }
+    }
+}
+
 pub fn Search(cx: Scope) -> Element {
     let view_state = use_view_state(cx);
+    let layer = use_layer(cx);
     let text = view_state.read().search_text.clone();
+    let sort = view_state.read().search_sort;
+    let search_from = view_state.read().search_from;
+    let search_to = view_state.read().search_to;
+    let range = DateRange::days(search_from, search_to);
+    let date_value = |day: Option<NaiveDate>| day.map(|day| day.to_string()).unwrap_or_default();
+    let (from_value, to_value) = (date_value(search_from), date_value(search_to));
 
     let has_too_short_word = text.split_whitespace().any(|word| word.len() < 3);
 
+    // Suggest completions for the word being typed, not for finished ones.
+    let last_word = if text.ends_with(char::is_whitespace) {
+        String::new()
+    } else {
+        text.split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    let suggestions = layer.read().term_suggestions(&last_word, MAX_SUGGESTIONS);
+    let suggestions = suggestions
+        .into_iter()
+        .filter(|term| *term != last_word)
+        .collect::<Vec<_>>();
+
+    let sort_style = css!(
+        "
+        display: flex;
+        flex-flow: row;
+        gap: 5px;
+
+        .sort-option {
+            padding: 2px 5px;
+            background-color: var(--panel);
+            font-size: 0.8em;
+            cursor: pointer;
+
+            &.selected {
+                background-color: var(--selected);
+            }
+        }
+
+        .search-date {
+            font-size: 0.8em;
+        }
+        "
+    );
+
+    let suggestion_style = css!(
+        "
+        position: relative;
+        display: grid;
+
+        .term-suggestions {
+            position: absolute;
+            z-index: 10;
+            top: 100%;
+            left: 0;
+            min-width: 200px;
+            border: 1px solid var(--border);
+            background-color: var(--panel);
+            display: grid;
+            grid-template-columns: 1fr;
+
+            .term-suggestion {
+                padding: 5px;
+                cursor: pointer;
+            }
+
+            .term-suggestion:hover {
+                background-color: var(--chip-hover);
+            }
+        }
+        "
+    );
+
     cx.render(rsx! {
         div {
             class: "search",
@@ -22,20 +138,82 @@ pub fn Search(cx: Scope) -> Element {
                         "Search"
                     }
                 }
-                textarea {
-                    class: "search-input",
-                    value: "{text}",
-                    rows: 1,
-                    onmounted: |e| {
-                        e.inner().set_focus(true);
-                    },
-                    oninput: move |e| {
-                        view_state.write().set_search_text(e.value.clone());
+                div {
+                    class: "{suggestion_style}",
+                    textarea {
+                        class: "search-input",
+                        value: "{text}",
+                        rows: 1,
+                        onmounted: |e| {
+                            e.inner().set_focus(true);
+                        },
+                        oninput: move |e| {
+                            view_state.write().set_search_text(e.value.clone());
+                        },
                     },
-                },
+                    if !suggestions.is_empty() {
+                        rsx! {
+                            div {
+                                class: "term-suggestions",
+                                suggestions.into_iter().map(|term| {
+                                    let completed = complete_last_word(&text, &term);
+                                    rsx! {
+                                        div {
+                                            key: "{term}",
+                                            class: "term-suggestion",
+                                            onclick: move |_| {
+                                                view_state.write().set_search_text(completed.clone());
+                                            },
+                                            "{term}"
+                                        }
+                                    }
+                                })
+                            }
+                        }
+                    }
+                }
+            }
+            div {
+                class: "group-wrapper",
+                div {
+                    class: "date-wrapper",
+                }
+                div {
+                    class: "{sort_style}",
+                    SearchSort::ALL.into_iter().map(|option| {
+                        let selected = if option == sort { "selected" } else { "" };
+                        rsx! {
+                            div {
+                                key: "{option.label()}",
+                                class: "sort-option {selected}",
+                                onclick: move |_| view_state.write().search_sort = option,
+                                "{option.label()}"
+                            }
+                        }
+                    })
+                    // Dates left empty don't limit the search.
+                    input {
+                        class: "search-date",
+                        r#type: "date",
+                        title: "Only notes created on or after this day",
+                        value: "{from_value}",
+                        oninput: move |e| view_state.write().search_from = e.value.parse().ok(),
+                    }
+                    input {
+                        class: "search-date",
+                        r#type: "date",
+                        title: "Only notes created on or before this day",
+                        value: "{to_value}",
+                        oninput: move |e| view_state.write().search_to = e.value.parse().ok(),
+                    }
+                }
             }
+            FindReplace {}
             div {
                 style: "overflow-y: scroll; max-height: 100%;",
+                SubjectResults {
+                    search_text: text.clone(),
+                }
                 if has_too_short_word {
                     rsx! {
                         div {
@@ -47,7 +225,7 @@ pub fn Search(cx: Scope) -> Element {
                                 class: "group",
                                 div {
                                     class: "note",
-                                    "Search terms must be at least 3 characters long"
+                                    "Note search terms must be at least 3 characters long"
                                 }
                             }
                         }
@@ -55,8 +233,355 @@ pub fn Search(cx: Scope) -> Element {
                 }
                 ListSearchResult {
                     search_text: text.clone(),
+                    sort: sort,
+                    range: range,
+                }
+            }
+        }
+    })
+}
+
+/// Subjects whose name matches the search, listed before the notes.
+/// Names are matched as they're typed, without the length limit of note search.
+#[inline_props]
+fn SubjectResults(cx: Scope, search_text: String) -> Element {
+    let view_state = use_view_state(cx);
+    let layer = use_layer(cx);
+
+    let query = search_text.trim();
+    if query.is_empty() {
+        return None;
+    }
+    let subjects = layer.read().find_subjects_by_name(query);
+    if subjects.is_empty() {
+        return None;
+    }
+    let more = subjects.len().saturating_sub(MAX_SUBJECT_RESULTS);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-flow: row wrap;
+        gap: 5px;
+
+        .subject-result {
+            padding: 2px 8px;
+            background-color: var(--chip);
+            border-radius: 3px;
+            cursor: pointer;
+
+            &:hover {
+                background-color: var(--chip-hover);
+            }
+        }
+
+        .more-subjects {
+            padding: 2px 8px;
+            color: var(--muted);
+        }
+        "
+    );
+
+    render! {
+        div {
+            class: "group-wrapper",
+            div {
+                class: "date-wrapper",
+                div {
+                    class: "date",
+                    "Subjects"
+                }
+            }
+            div {
+                class: "{style}",
+                subjects.into_iter().take(MAX_SUBJECT_RESULTS).map(|subject| {
+                    let subject_id = subject.id;
+                    rsx! {
+                        Clickable {
+                            key: "{subject_id.0}",
+                            class: "subject-result",
+                            onclick: move |_| view_state.write().go_to_subject(subject_id),
+                            "{subject.name}"
+                        }
+                    }
+                })
+                if more > 0 {
+                    rsx! {
+                        div {
+                            class: "more-subjects",
+                            "and {more} more"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replaces text in every note, or every note with the selected subject.
+fn FindReplace(cx: Scope) -> Element {
+    let view_state = use_view_state(cx);
+    let layer = use_layer(cx);
+    let open = use_state(cx, || false);
+    let find = use_state(cx, String::new);
+    let replacement = use_state(cx, String::new);
+    let use_regex = use_state(cx, || false);
+    let in_subject = use_state(cx, || false);
+    let confirm = use_state(cx, || false);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-flow: row wrap;
+        align-items: center;
+        gap: 5px;
+        font-size: 0.8em;
+
+        .toggle {
+            color: var(--muted);
+            cursor: pointer;
+
+            &:hover {
+                text-decoration: underline;
+            }
+        }
+
+        textarea {
+            border: 1px solid var(--border);
+            background-color: var(--surface);
+            color: inherit;
+            padding: 2px 5px;
+            resize: none;
+            outline: none;
+        }
+
+        .replace-button {
+            padding: 2px 5px;
+            background-color: var(--button);
+            cursor: pointer;
+
+            &:hover {
+                background-color: var(--button-hover);
+            }
+        }
+
+        .invalid {
+            color: var(--muted);
+            font-style: italic;
+        }
+    "
+    );
+
+    if !*open.get() {
+        return render! {
+            div {
+                class: "group-wrapper",
+                div { class: "date-wrapper" }
+                div {
+                    class: "{style}",
+                    div {
+                        class: "toggle",
+                        onclick: move |_| open.set(true),
+                        "Find and replace in notes"
+                    }
+                }
+            }
+        };
+    }
+
+    let selected_subject = view_state.read().selected_subject;
+    let subject = selected_subject.filter(|_| *in_subject.get());
+    let pattern = if *use_regex.get() {
+        FindPattern::regex(find.get()).ok()
+    } else {
+        Some(FindPattern::Literal(find.get().clone()))
+    };
+    let invalid = pattern.is_none() && !find.get().is_empty();
+    let count = pattern.as_ref().map_or(0, |pattern| {
+        layer
+            .read()
+            .note_replacements(pattern, replacement.get(), subject)
+            .len()
+    });
+    let plural = if count == 1 { "note" } else { "notes" };
+
+    let regex_box = if *use_regex.get() { "☑" } else { "☐" };
+    let subject_box = if *in_subject.get() { "☑" } else { "☐" };
+    let subject_toggle = selected_subject.is_some().then(|| {
+        rsx! {
+            div {
+                class: "toggle",
+                onclick: move |_| in_subject.set(!*in_subject.get()),
+                "{subject_box} Only in this subject"
+            }
+        }
+    });
+    let status = if invalid {
+        rsx! { div { class: "invalid", "Invalid regular expression" } }
+    } else {
+        rsx! {
+            div {
+                class: "replace-button",
+                onclick: move |_| {
+                    if count > 0 {
+                        confirm.set(true);
+                    }
+                },
+                "Replace in {count} {plural}"
+            }
+        }
+    };
+
+    let confirm_dialog = (*confirm.get()).then(|| {
+        let message = cx.bump().alloc_str(&format!(
+            "Replace \"{}\" with \"{}\" in {count} {plural}? This can be undone.",
+            find.get(),
+            replacement.get()
+        ));
+        rsx! {
+            ConfirmDialog {
+                title: "Find and Replace",
+                message: message,
+                on_confirm: move |_| {
+                    if let Some(pattern) = &pattern {
+                        layer.find_replace(pattern, replacement.get(), subject);
+                    }
+                    confirm.set(false);
+                },
+                on_cancel: |_| confirm.set(false),
+            }
+        }
+    });
+
+    render! {
+        div {
+            class: "group-wrapper",
+            div { class: "date-wrapper" }
+            div {
+                class: "{style}",
+                textarea {
+                    rows: 1,
+                    placeholder: "Find",
+                    value: "{find}",
+                    oninput: |e| find.set(e.value.clone()),
+                }
+                textarea {
+                    rows: 1,
+                    placeholder: "Replace with",
+                    value: "{replacement}",
+                    oninput: |e| replacement.set(e.value.clone()),
+                }
+                div {
+                    class: "toggle",
+                    onclick: move |_| use_regex.set(!*use_regex.get()),
+                    "{regex_box} Regex"
+                }
+                subject_toggle,
+                status,
+                div {
+                    class: "toggle",
+                    onclick: move |_| open.set(false),
+                    "Close"
+                }
+                confirm_dialog
+            }
+        }
+    }
+}
+
+#[derive(Props)]
+pub struct ExportResultsProps<'a> {
+    notes: Vec<Note>,
+    /// The search the notes were found with, recorded in the export.
+    query: String,
+    on_done: EventHandler<'a, ()>,
+}
+
+/// Exports the search results to a file, in a format of the user's choice.
+pub fn ExportResults<'a>(cx: Scope<'a, ExportResultsProps<'a>>) -> Element<'a> {
+    let view_state = use_view_state(cx);
+    let subjects = use_subjects(cx);
+    let format = use_state(cx, ExportFormat::default);
+    let path = use_state(cx, || None::<String>);
+
+    let default_path = view_state
+        .read()
+        .vault
+        .join(format!("search-results.{}", format.get().extension()))
+        .display()
+        .to_string();
+    let path_text = path.get().clone().unwrap_or(default_path);
+
+    let export = {
+        let path_text = path_text.clone();
+        move |_| {
+            let count = cx.props.notes.len();
+            let result = std::fs::File::create(&path_text).and_then(|file| {
+                export_to_writer(
+                    std::io::BufWriter::new(file),
+                    cx.props.notes.iter().map(|note| &**note),
+                    &subjects.read(),
+                    *format.get(),
+                    Some(cx.props.query.trim()),
+                )
+            });
+            match result {
+                Ok(()) => view_state.read().notify(
+                    ToastLevel::Info,
+                    format!("Exported {count} notes to {path_text}"),
+                ),
+                Err(e) => view_state
+                    .read()
+                    .notify(ToastLevel::Error, format!("Export failed: {e}")),
+            }
+            cx.props.on_done.call(());
+        }
+    };
+
+    cx.render(rsx! {
+        div {
+            class: "confirm-dialog-container",
+            div {
+                class: "confirm-dialog",
+                div {
+                    class: "confirm-dialog-title",
+                    "Export {cx.props.notes.len()} Results"
+                }
+                div {
+                    class: "confirm-dialog-message",
+                    ExportFormat::ALL.into_iter().map(|option| {
+                        let selected = if option == *format.get() { "☑" } else { "☐" };
+                        rsx! {
+                            Clickable {
+                                key: "{option.label()}",
+                                onclick: move |_| format.set(option),
+                                "{selected} {option.label()} "
+                            }
+                        }
+                    })
+                    textarea {
+                        rows: 1,
+                        value: "{path_text}",
+                        oninput: |e| path.set(Some(e.value.clone())),
+                    }
+                }
+                button {
+                    class: "confirm-dialog-button",
+                    onclick: export,
+                    "Export"
+                }
+                button {
+                    class: "confirm-dialog-button",
+                    onclick: |_| cx.props.on_done.call(()),
+                    "Cancel"
                 }
             }
         }
     })
 }
+
+/// Replaces the last word of `text` with `term`, ready for the next word.
+fn complete_last_word(text: &str, term: &str) -> String {
+    let rest = text.trim_end_matches(|c: char| !c.is_whitespace());
+    format!("{rest}{term} ")
+}
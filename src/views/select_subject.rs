@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
-use crate::data::subjects::{Subject, SubjectId};
+use crate::data::subjects::{filter_flat_tree, Subject, SubjectId};
+use crate::views::view_note::subject_chip_style;
 use dioxus::html::input_data::keyboard_types::Key;
 use dioxus::prelude::*;
 use emergence::data::layer::{use_layer, use_subjects};
@@ -9,6 +10,10 @@ use sir::css;
 
 const FOLDER_ICON: &str = "▼";
 
+/// With more subjects than this, they're listed flat with indentation instead
+/// of as nested components, and the tree order comes from the database.
+const FLAT_LIST_THRESHOLD: usize = 200;
+
 #[derive(Props)]
 pub struct Props<'a> {
     on_select: EventHandler<'a, Subject>,
@@ -16,6 +21,8 @@ pub struct Props<'a> {
     ignore_subjects: Vec<SubjectId>,
     #[props(default = false)]
     show_above: bool,
+    /// Shows a checkbox in front of every subject, ticked for these.
+    ticked: Option<BTreeSet<SubjectId>>,
 }
 
 pub fn SelectSubject<'a>(cx: Scope<'a, Props<'a>>) -> Element<'a> {
@@ -23,24 +30,40 @@ pub fn SelectSubject<'a>(cx: Scope<'a, Props<'a>>) -> Element<'a> {
     let all_subjects = use_subjects(cx);
     let search = use_state(cx, String::new);
 
+    let flat_subjects = use_memo(cx, (&*all_subjects.read(),), |(all_subjects,)| {
+        (all_subjects.len() > FLAT_LIST_THRESHOLD)
+            .then(|| Rc::new(layer.read().subjects_flat_with_depth()))
+    });
+
     // TODO: Add semantic sorting
-    let (filtered_subjects, subject_tree) = use_memo(
+    let (filtered_subjects, subject_list) = use_memo(
         cx,
-        (&cx.props.ignore_subjects, &*all_subjects.read(), search),
-        |(ignore_subjects, all_subjects, search)| {
+        (
+            &cx.props.ignore_subjects,
+            &*all_subjects.read(),
+            search,
+            flat_subjects,
+        ),
+        |(ignore_subjects, all_subjects, search, flat_subjects)| {
+            let search = search.to_lowercase();
+            let matches = |s: &Subject| {
+                !ignore_subjects.contains(&s.id) && s.name.to_lowercase().contains(&search)
+            };
             let mut subjects = all_subjects
                 .values()
-                .filter(|s| !ignore_subjects.contains(&s.id))
-                .filter(|s| s.name.to_lowercase().contains(&search.to_lowercase()))
+                .filter(|s| matches(s))
                 .cloned()
                 .collect::<Vec<_>>();
             subjects.sort_unstable_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
 
-            let subject_tree = construct_subject_tree(
-                &all_subjects,
-                &subjects.iter().map(|s| s.id).collect::<Vec<_>>(),
-            );
-            (std::rc::Rc::new(subjects), subject_tree)
+            let subject_list = match flat_subjects {
+                Some(flat) => SubjectList::Flat(Rc::new(filter_flat_tree(&flat, matches))),
+                None => SubjectList::Tree(construct_subject_tree(
+                    &all_subjects,
+                    &subjects.iter().map(|s| s.id).collect::<Vec<_>>(),
+                )),
+            };
+            (Rc::new(subjects), subject_list)
         },
     );
 
@@ -57,7 +80,11 @@ pub fn SelectSubject<'a>(cx: Scope<'a, Props<'a>>) -> Element<'a> {
                     Some(subject) if subject.name.to_lowercase() == search.to_lowercase() => {
                         subject.clone()
                     }
-                    _ => layer.create_subject(search.clone()),
+                    _ => match layer.create_subject(search.clone()) {
+                        Some(subject) => subject,
+                        // The failure has already been reported to the user.
+                        None => return,
+                    },
                 };
                 cx.props.on_select.call(subject);
             }
@@ -93,23 +120,44 @@ pub fn SelectSubject<'a>(cx: Scope<'a, Props<'a>>) -> Element<'a> {
     );
 
     let all_subjects = all_subjects.read();
-    let tree_view = rsx! {
-        div {
-            class: "{tree_view_style}",
-            subject_tree.roots.iter().cloned().map(|root| {
-                let all_subjects = all_subjects.clone();
-                let tree = subject_tree.children.clone();
-                rsx! {
-                    SubjectTreeView {
-                        key: "{root.0}",
-                        on_select: |s| cx.props.on_select.call(s),
-                        subjects: all_subjects,
-                        tree: tree,
-                        node: root,
+    let tree_view = match subject_list {
+        SubjectList::Tree(subject_tree) => rsx! {
+            div {
+                class: "{tree_view_style}",
+                subject_tree.roots.iter().cloned().map(|root| {
+                    let all_subjects = all_subjects.clone();
+                    let tree = subject_tree.children.clone();
+                    rsx! {
+                        SubjectTreeView {
+                            key: "{root.0}",
+                            on_select: |s| cx.props.on_select.call(s),
+                            subjects: all_subjects,
+                            tree: tree,
+                            node: root,
+                            ticked: cx.props.ticked.clone(),
+                        }
                     }
-                }
-            })
-        }
+                })
+            }
+        },
+        SubjectList::Flat(flat) => rsx! {
+            div {
+                class: "{tree_view_style}",
+                flat.iter().enumerate().map(|(i, (subject, depth))| {
+                    let has_children = flat.get(i + 1).is_some_and(|(_, next)| next > depth);
+                    rsx! {
+                        SubjectRow {
+                            key: "{subject.id.0}",
+                            on_select: |s| cx.props.on_select.call(s),
+                            subject: subject.clone(),
+                            has_children: has_children,
+                            ticked: cx.props.ticked.as_ref().map(|t| t.contains(&subject.id)),
+                            depth: *depth,
+                        }
+                    }
+                })
+            }
+        },
     };
 
     let wrapper = css!(
@@ -124,8 +172,8 @@ pub fn SelectSubject<'a>(cx: Scope<'a, Props<'a>>) -> Element<'a> {
             z-index: 10;
             min-width: 200px;
             overflow: hidden;
-            border: 1px solid #ccc;
-            background-color: #ccd;
+            border: 1px solid var(--border);
+            background-color: var(--chip-hover);
             display: grid;
             grid-template-columns: 1fr;
             grid-gap: 5px;
@@ -160,6 +208,8 @@ struct SubjectTreeProps<'a> {
     subjects: Rc<BTreeMap<SubjectId, Subject>>,
     tree: Rc<BTreeMap<SubjectId, Vec<SubjectId>>>,
     node: SubjectId,
+    #[props(!optional)]
+    ticked: Option<BTreeSet<SubjectId>>,
 }
 
 fn SubjectTreeView<'a>(cx: Scope<'a, SubjectTreeProps<'a>>) -> Element {
@@ -168,20 +218,80 @@ fn SubjectTreeView<'a>(cx: Scope<'a, SubjectTreeProps<'a>>) -> Element {
     let tree = cx.props.tree.clone();
 
     let my_subject = subjects.get(&node).unwrap().clone();
-    let on_select_me = {
-        to_owned![my_subject];
-        move |_| {
-            cx.props.on_select.call(my_subject.clone());
+
+    static STATIC_VEC: Vec<SubjectId> = Vec::new();
+    let children = tree.get(&node).unwrap_or(&STATIC_VEC);
+
+    let card = rsx! {
+        SubjectRow {
+            on_select: |s| cx.props.on_select.call(s),
+            subject: my_subject,
+            has_children: !children.is_empty(),
+            ticked: cx.props.ticked.as_ref().map(|t| t.contains(&node)),
         }
     };
 
-    let on_keydown = {
-        to_owned![my_subject];
-        move |e: KeyboardEvent| {
-            if e.key() == Key::Enter || e.key() == Key::Character(" ".to_string()) {
-                cx.props.on_select.call(my_subject.clone());
+    let children_container = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        /* padding + margin + border = 15px */
+        padding-left: 7px;
+        margin-left: 7px;
+        border-left: 1px solid var(--faint);
+    "
+    );
+
+    let subjects = subjects.clone();
+    let tree = tree.clone();
+    render! {
+        div {
+            card,
+            if !children.is_empty() {
+                rsx! {
+                    div {
+                        class: "{children_container}",
+                        children.iter().cloned().map(|child| {
+                            let subjects = subjects.clone();
+                            let tree = tree.clone();
+                            rsx! {
+                                SubjectTreeView {
+                                    key: "{child.0}",
+                                    on_select: |s| cx.props.on_select.call(s),
+                                    subjects: subjects,
+                                    tree: tree,
+                                    node: child,
+                                    ticked: cx.props.ticked.clone(),
+                                }
+                            }
+                        })
+                    }
+                }
             }
         }
+    }
+}
+
+#[derive(Props)]
+struct SubjectRowProps<'a> {
+    on_select: EventHandler<'a, Subject>,
+    subject: Subject,
+    has_children: bool,
+    /// Whether the checkbox is ticked, if there is one.
+    #[props(!optional)]
+    ticked: Option<bool>,
+    /// Indentation in the flat list. The tree indents rows by nesting them.
+    #[props(default)]
+    depth: usize,
+}
+
+fn SubjectRow<'a>(cx: Scope<'a, SubjectRowProps<'a>>) -> Element {
+    let subject = &cx.props.subject;
+    let on_select_me = move |_| cx.props.on_select.call(cx.props.subject.clone());
+    let on_keydown = move |e: KeyboardEvent| {
+        if e.key() == Key::Enter || e.key() == Key::Character(" ".to_string()) {
+            cx.props.on_select.call(cx.props.subject.clone());
+        }
     };
 
     let container = css!(
@@ -195,85 +305,67 @@ fn SubjectTreeView<'a>(cx: Scope<'a, SubjectTreeProps<'a>>) -> Element {
             align-items: center;
             justify-content: center;
             width: 15px;
+            flex-shrink: 0;
             cursor: pointer;
         }
 
         .name {
             flex-grow: 1;
+
+            .chip {
+                padding: 0 4px;
+            }
         }
 
         &:hover, &:focus {
-            background-color: #eee;
+            background-color: var(--surface);
             cursor: pointer;
         }
     "
     );
 
-    static STATIC_VEC: Vec<SubjectId> = Vec::new();
-    let children = tree.get(&node).unwrap_or(&STATIC_VEC);
+    let checkbox = cx
+        .props
+        .ticked
+        .map(|ticked| if ticked { "☑ " } else { "☐ " });
+    // Matches the indentation of the nested tree.
+    let indent = cx.props.depth * 15;
+    let chip_style = subject_chip_style(subject);
 
-    let card = rsx! {
+    render! {
         div {
-            key: "{my_subject.id}",
             class: "{container}",
+            style: "padding-left: {indent}px;",
             tabindex: 101,
             onkeydown: on_keydown,
             div {
                 class: "fold-button",
-                if children.is_empty() {
-                    ""
-                } else {
+                if cx.props.has_children {
                     FOLDER_ICON
+                } else {
+                    ""
                 }
             }
             div {
                 class: "name",
                 onclick: on_select_me,
-                "{my_subject.name}"
-            }
-        }
-    };
-
-    let children_container = css!(
-        "
-        display: flex;
-        flex-direction: column;
-        /* padding + margin + border = 15px */
-        padding-left: 7px;
-        margin-left: 7px;
-        border-left: 1px solid #888;
-    "
-    );
-
-    let subjects = subjects.clone();
-    let tree = tree.clone();
-    render! {
-        div {
-            card,
-            if !children.is_empty() {
-                rsx! {
-                    div {
-                        class: "{children_container}",
-                        children.iter().cloned().map(|child| {
-                            let subjects = subjects.clone();
-                            let tree = tree.clone();
-                            rsx! {
-                                SubjectTreeView {
-                                    key: "{child.0}",
-                                    on_select: |s| cx.props.on_select.call(s),
-                                    subjects: subjects,
-                                    tree: tree,
-                                    node: child,
-                                }
-                            }
-                        })
-                    }
+                checkbox,
+                span {
+                    class: "chip",
+                    style: "{chip_style}",
+                    "{subject.name}"
                 }
             }
         }
     }
 }
 
+enum SubjectList {
+    Tree(SubjectTree),
+    /// Subjects in tree order with their depth, see [`FLAT_LIST_THRESHOLD`].
+    Flat(Rc<Vec<(Subject, usize)>>),
+}
+
 struct SubjectTree {
     roots: Vec<SubjectId>,
     children: Rc<BTreeMap<SubjectId, Vec<SubjectId>>>,
@@ -293,9 +385,13 @@ fn construct_subject_tree(
     let mut roots = Vec::new();
 
     while let Some(subject_id) = queue.pop_front() {
-        let subject = all_subjects.get(&subject_id).unwrap();
+        let Some(subject) = all_subjects.get(&subject_id) else { continue };
         tree.entry(subject_id).or_insert_with(Vec::new);
-        if let Some(parent_id) = subject.parent_id {
+        // A subject whose parent is missing is shown at the top level.
+        let parent_id = subject
+            .parent_id
+            .filter(|parent_id| all_subjects.contains_key(parent_id));
+        if let Some(parent_id) = parent_id {
             let entry = tree.entry(parent_id).or_insert_with(Vec::new);
             if entry.is_empty() && !filtered.contains(&parent_id) {
                 queue.push_back(parent_id);
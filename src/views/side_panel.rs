@@ -1,13 +1,24 @@
-use dioxus::prelude::*;
+use std::collections::BTreeSet;
+
+use chrono::Datelike;
+use dioxus::{html::input_data::keyboard_types::Key, prelude::*};
 use emergence::data::{
     layer::{use_layer, use_subjects},
+    notes::{Note, NoteId, SubjectMatch},
+    settings::{ClearDonePolicy, FavoriteOrder, SavedFilter},
     subjects::{Subject, SubjectId},
 };
 use sir::css;
 
 use crate::views::{
+    clickable::Clickable,
+    confirm_dialog::ConfirmDialog,
+    note_input::EditNote,
     select_subject::SelectSubject,
-    view_note::{OnSubjectSelect, ViewNote},
+    subject_manager::SubjectManager,
+    theme::theme_label,
+    toasts::ToastLevel,
+    view_note::{copy_to_clipboard, subject_chip_style, OnSubjectSelect, ViewNote},
 };
 
 use super::{use_view_state, ViewState};
@@ -17,6 +28,12 @@ pub enum SidePanelState {
     #[default]
     Nothing,
     SubjectDetails(SubjectId),
+    ReadingList,
+    NoteLinks(NoteId),
+    EditNote(NoteId),
+    ManageSubjects,
+    WordCloud,
+    Activity,
     ListSimilar {
         text: String,
         previous: Box<SidePanelState>,
@@ -24,7 +41,13 @@ pub enum SidePanelState {
 }
 
 impl SidePanelState {
+    /// Lists the notes similar to `text`, going back to the current state
+    /// afterwards. Does nothing while a note is edited in the side panel,
+    /// which would close the editor.
     pub fn list_similar(&mut self, text: String) {
+        if let Self::EditNote(_) = self {
+            return;
+        }
         let old = std::mem::replace(self, Self::Nothing);
         *self = match old {
             Self::ListSimilar { text: _, previous } => Self::ListSimilar {
@@ -54,13 +77,28 @@ pub fn SidePanel(cx: Scope) -> Element {
 
     let view_state_read = view_state.read();
     let ViewState {
-        selected_subject, ..
+        selected_subject,
+        also_subjects,
+        subject_match,
+        ..
     } = &*view_state_read;
 
-    let subject_name = selected_subject
-        .and_then(|id| subjects.get(&id))
-        .map(|s| s.name.clone())
-        .unwrap_or_else(|| "Journal".to_string());
+    let separator = match subject_match {
+        SubjectMatch::All => " & ",
+        SubjectMatch::Any => " | ",
+    };
+
+    let subject_name = if selected_subject.is_some() {
+        selected_subject
+            .iter()
+            .chain(also_subjects)
+            .filter_map(|id| subjects.get(id))
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>()
+            .join(separator)
+    } else {
+        "Journal".to_string()
+    };
 
     let content = match &view_state_read.side_panel {
         SidePanelState::Nothing => rsx! {
@@ -71,6 +109,29 @@ pub fn SidePanel(cx: Scope) -> Element {
                 subject_id: *subject,
             }
         },
+        SidePanelState::ReadingList => rsx! {
+            ReadingList {}
+        },
+        SidePanelState::NoteLinks(note) => rsx! {
+            NoteLinks {
+                note_id: *note,
+            }
+        },
+        SidePanelState::EditNote(note) => rsx! {
+            NoteEditor {
+                key: "{note}",
+                note_id: *note,
+            }
+        },
+        SidePanelState::ManageSubjects => rsx! {
+            SubjectManager {}
+        },
+        SidePanelState::WordCloud => rsx! {
+            WordCloud {}
+        },
+        SidePanelState::Activity => rsx! {
+            ActivityHeatmap {}
+        },
         SidePanelState::ListSimilar { text, .. } => rsx! {
             FindSimilar {
                 text: text.clone(),
@@ -78,10 +139,31 @@ pub fn SidePanel(cx: Scope) -> Element {
         },
     };
 
+    let reading_class = if let SidePanelState::ReadingList = view_state_read.side_panel {
+        "select-button selected"
+    } else {
+        "select-button"
+    };
+    let manage_class = if let SidePanelState::ManageSubjects = view_state_read.side_panel {
+        "select-button selected"
+    } else {
+        "select-button"
+    };
+    let words_class = if let SidePanelState::WordCloud = view_state_read.side_panel {
+        "select-button selected"
+    } else {
+        "select-button"
+    };
+    let activity_class = if let SidePanelState::Activity = view_state_read.side_panel {
+        "select-button selected"
+    } else {
+        "select-button"
+    };
+
     let style = css!(
         "
-        background-color: #ddd;
-        border-left: 1px solid #ccc;
+        background-color: var(--panel);
+        border-left: 1px solid var(--border);
         overflow: hidden;
 
         display: grid;
@@ -102,7 +184,7 @@ pub fn SidePanel(cx: Scope) -> Element {
         padding: 10px;
         display: flex;
         flex-direction: column;
-        border-bottom: 1px solid #bbb;
+        border-bottom: 1px solid var(--border-strong);
 
         .row {
             display: flex;
@@ -117,19 +199,24 @@ pub fn SidePanel(cx: Scope) -> Element {
             .select-button {
                 flex-grow: 0;
                 padding: 5px;
-                background-color: #bbb;
+                background-color: var(--button);
                 font-weight: bold;
                 cursor: pointer;
     
                 &.selected {
-                    background-color: rgb(180, 200, 230);
+                    background-color: var(--selected);
                 }
             }
         }
     "
     );
 
+    let theme = view_state_read.global_settings.theme;
+    let theme_label = theme_label(theme);
+    let theme_title = cx.bump().alloc_str(&format!("Theme: {theme:?}"));
+
     let show_subject_select = use_state(cx, || false);
+    let show_vaults = use_state(cx, || false);
 
     cx.render(rsx! {
         div {
@@ -144,7 +231,7 @@ pub fn SidePanel(cx: Scope) -> Element {
                     }
                     if selected_subject.is_some() {
                         rsx! {
-                            div {
+                            Clickable {
                                 class: "select-button",
                                 onclick: move |_| {
                                     view_state.write().go_to_journal();
@@ -153,14 +240,71 @@ pub fn SidePanel(cx: Scope) -> Element {
                             }
                         }
                     }
-                    div {
+                    Clickable {
+                        class: reading_class,
+                        title: "Reading list",
+                        onclick: move |_| {
+                            view_state.write().toggle_reading_list();
+                        },
+                        "Reading ({view_state_read.reading_list.len()})"
+                    }
+                    Clickable {
+                        class: manage_class,
+                        title: "Manage subjects",
+                        onclick: move |_| {
+                            view_state.write().toggle_subject_manager();
+                        },
+                        "Subjects"
+                    }
+                    Clickable {
+                        class: words_class,
+                        title: "Most common words",
+                        onclick: move |_| {
+                            view_state.write().toggle_word_cloud();
+                        },
+                        "Words"
+                    }
+                    Clickable {
+                        class: activity_class,
+                        title: "Notes written per day",
+                        onclick: move |_| {
+                            view_state.write().toggle_activity();
+                        },
+                        "Activity"
+                    }
+                    Clickable {
+                        class: "select-button",
+                        title: "Switch vault",
+                        onclick: move |_| {
+                            show_vaults.set(!*show_vaults.get());
+                        },
+                        "Vaults"
+                    }
+                    Clickable {
+                        class: "select-button",
+                        label: theme_title,
+                        title: theme_title,
+                        onclick: move |_| {
+                            view_state.write().set_theme(theme.next());
+                        },
+                        "{theme_label}"
+                    }
+                    Clickable {
                         class: "select-button",
+                        label: "Choose subject",
                         onclick: move |_| {
                             show_subject_select.set(!*show_subject_select.get());
                         },
                         "▼"
                     }
                 }
+                if *show_vaults.get() {
+                    rsx! {
+                        VaultSwitcher {
+                            on_done: |_| show_vaults.set(false),
+                        }
+                    }
+                }
                 if *show_subject_select.get() {
                     rsx! {
                         SelectSubject {
@@ -179,6 +323,85 @@ pub fn SidePanel(cx: Scope) -> Element {
     })
 }
 
+#[derive(Props)]
+struct VaultSwitcherProps<'a> {
+    on_done: EventHandler<'a, ()>,
+}
+
+fn VaultSwitcher<'a>(cx: Scope<'a, VaultSwitcherProps<'a>>) -> Element<'a> {
+    let view_state = use_view_state(cx);
+    let new_path = use_state(cx, String::new);
+
+    let recent_vaults = view_state.read().global_settings.recent_vaults.clone();
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 5px;
+        padding-top: 10px;
+
+        .vault {
+            padding: 2px 5px;
+            overflow: hidden;
+            text-overflow: ellipsis;
+            white-space: nowrap;
+            cursor: pointer;
+
+            &:hover {
+                background-color: var(--button);
+            }
+        }
+
+        .vault-path {
+            resize: none;
+        }
+    "
+    );
+
+    let open = move |path: std::path::PathBuf| {
+        view_state.write().switch_vault(path);
+        cx.props.on_done.call(());
+    };
+
+    let onkeydown = move |e: KeyboardEvent| match e.key() {
+        Key::Enter => {
+            let path = new_path.get().trim().to_string();
+            if !path.is_empty() {
+                open(path.into());
+            }
+        }
+        Key::Escape => cx.props.on_done.call(()),
+        _ => {}
+    };
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            recent_vaults.into_iter().map(|vault| {
+                let display: &str = cx.bump().alloc_str(&vault.display().to_string());
+                rsx! {
+                    Clickable {
+                        key: "{display}",
+                        class: "vault",
+                        title: display,
+                        onclick: move |_| open(vault.clone()),
+                        "{display}"
+                    }
+                }
+            })
+            textarea {
+                class: "vault-path",
+                rows: 1,
+                placeholder: "Open folder...",
+                value: "{new_path}",
+                oninput: |e| new_path.set(e.value.clone()),
+                onkeydown: onkeydown,
+            }
+        }
+    })
+}
+
 #[inline_props]
 fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
     let subject_id = *subject_id;
@@ -194,10 +417,12 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
         .iter()
         .map(|id| {
             let subject = subjects.get(id).unwrap().clone();
+            let style = cx.bump().alloc_str(&subject_chip_style(&subject));
             rsx! {
-                div {
+                Clickable {
                     key: "{subject.id.0}",
                     class: "subject-card",
+                    style: style,
                     onclick: move |_| {
                         view_state.write().go_to_subject(subject.id);
                     },
@@ -207,8 +432,87 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
         })
         .collect::<Vec<_>>();
 
+    let ancestors = layer.read().subject_ancestors(subject_id);
+    let breadcrumb = ancestors.iter().cloned().map(|ancestor| {
+        let style = cx.bump().alloc_str(&subject_chip_style(&ancestor));
+        rsx! {
+            Clickable {
+                key: "{ancestor.id.0}",
+                class: "subject-card",
+                style: style,
+                onclick: move |_| {
+                    view_state.write().go_to_subject(ancestor.id);
+                },
+                "{ancestor.name}"
+            }
+            div {
+                class: "separator",
+                "›"
+            }
+        }
+    });
+
     let show_parent_select = use_state(cx, || false);
-    let set_parent = move |parent: Option<SubjectId>| layer.set_subject_parent(subject_id, parent);
+    let set_parent = move |parent: Option<SubjectId>| layer.move_subtree(subject_id, parent);
+
+    let (star, star_title) = if my_subject.favorite {
+        ("★", "Remove from favorites")
+    } else {
+        ("☆", "Add to favorites")
+    };
+    let manual_order = view_state.read().global_settings.favorite_order == FavoriteOrder::Manual;
+
+    let color = my_subject.color.clone();
+    let clear_color = color.is_some().then(|| {
+        rsx! {
+            Clickable {
+                class: "clear",
+                label: "Clear color",
+                title: "Clear color",
+                onclick: move |_| layer.set_subject_color(subject_id, None),
+                "✖"
+            }
+        }
+    });
+    // The picker needs some color to start from.
+    let color_value = color.unwrap_or_else(|| "#cccccc".to_string());
+
+    let new_is_task = view_state.read().default_new_is_task(subject_id);
+    let new_is_task_box = if new_is_task { "☑" } else { "☐" };
+    let move_buttons = (my_subject.favorite && manual_order).then(|| {
+        rsx! {
+            Clickable {
+                class: "favorite-button",
+                label: "Move left in favorites",
+                title: "Move left in favorites",
+                onclick: move |_| layer.move_favorite(subject_id, -1),
+                "◀"
+            }
+            Clickable {
+                class: "favorite-button",
+                label: "Move right in favorites",
+                title: "Move right in favorites",
+                onclick: move |_| layer.move_favorite(subject_id, 1),
+                "▶"
+            }
+        }
+    });
+
+    let js_eval = use_eval(cx);
+    let show_debug_info = view_state.read().global_settings.show_debug_info;
+    let copy_id = show_debug_info.then(|| {
+        let id_title = cx.bump().alloc_str(&format!("Copy ID {subject_id}"));
+        rsx! {
+            Clickable {
+                class: "copy-id-button",
+                title: id_title,
+                onclick: move |_| {
+                    copy_to_clipboard(cx, js_eval, view_state, &subject_id.to_string());
+                },
+                "Copy ID"
+            }
+        }
+    });
 
     let style = css!(
         "
@@ -233,7 +537,59 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
 
                 &:hover {
                     color: red;
-                    background-color: #bbb;
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .color-row {
+            display: flex;
+            flex-direction: row;
+            align-items: center;
+            gap: 10px;
+
+            .clear {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    color: red;
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .breadcrumb {
+            display: flex;
+            flex-direction: row;
+            flex-wrap: wrap;
+            align-items: center;
+            gap: 5px;
+
+            .separator {
+                color: var(--muted);
+            }
+
+            .current {
+                font-weight: bold;
+            }
+
+            .favorite-button {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--button);
+                }
+            }
+
+            .copy-id-button {
+                padding: 0 5px;
+                cursor: pointer;
+                color: var(--muted);
+
+                &:hover {
+                    background-color: var(--button);
                 }
             }
         }
@@ -243,19 +599,28 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
             flex-direction: column;
             gap: 5px;
         }
+
+        .subject-option {
+            cursor: pointer;
+        }
     "
     );
 
-    let parent = if let Some(parent_id) = my_subject.parent_id {
-        let parent = subjects.get(&parent_id).unwrap().clone();
+    let parent = my_subject
+        .parent_id
+        .and_then(|parent_id| subjects.get(&parent_id))
+        .cloned();
+    let parent = if let Some(parent) = parent {
+        let parent_style = cx.bump().alloc_str(&subject_chip_style(&parent));
         rsx! {
             div {
                 class: "parent-row",
                 div {
                     "Parent:"
                 }
-                div {
+                Clickable {
                     class: "subject-card",
+                    style: parent_style,
                     onclick: move |_| {
                         view_state.write().go_to_subject(parent.id);
                     },
@@ -263,8 +628,10 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
                         "{parent.name}"
                     }
                 }
-                div {
+                Clickable {
                     class: "clear",
+                    label: "Clear parent",
+                    title: "Clear parent",
                     onclick: move |_| {
                         set_parent(None);
                     },
@@ -281,7 +648,7 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
                 div {
                     "Parent:"
                 }
-                div {
+                Clickable {
                     class: "subject-card",
                     onclick: move |_| {
                         show_parent_select.set(true);
@@ -313,9 +680,50 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
         div {
             class: "{style}",
             UndoRedo {},
+            div {
+                class: "breadcrumb",
+                breadcrumb,
+                div {
+                    class: "current",
+                    "{my_subject.name}"
+                }
+                Clickable {
+                    class: "favorite-button",
+                    label: star_title,
+                    title: star_title,
+                    onclick: move |_| layer.toggle_favorite(subject_id),
+                    star
+                }
+                move_buttons
+                copy_id
+            }
             div {
                 parent
             }
+            div {
+                class: "color-row",
+                div {
+                    "Color:"
+                }
+                input {
+                    r#type: "color",
+                    value: "{color_value}",
+                    onchange: move |e| layer.set_subject_color(subject_id, Some(e.value.clone())),
+                }
+                clear_color
+            }
+            Clickable {
+                class: "subject-option",
+                onclick: move |_| {
+                    view_state
+                        .write()
+                        .set_default_new_is_task(subject_id, !new_is_task);
+                },
+                "{new_is_task_box} New notes are tasks"
+            }
+            ClearDoneTasks {
+                subject: subject_id,
+            }
             div {
                 class: "children",
                 children.into_iter()
@@ -325,116 +733,1018 @@ fn SubjectDetails(cx: Scope, subject_id: SubjectId) -> Element {
 }
 
 fn NoSubject(cx: Scope) -> Element {
+    let view_state = use_view_state(cx);
+    let index_subject_names = view_state.read().vault_settings.index_subject_names;
+    let index_box = if index_subject_names { "☑" } else { "☐" };
+
     let style = css!(
         "
         padding: 10px;
         display: flex;
         flex-direction: column;
         gap: 10px;
+
+        .option {
+            cursor: pointer;
+        }
         "
     );
     cx.render(rsx! {
         div {
             class: "{style}",
             UndoRedo {}
+            SavedFilters {}
+            SubjectOutline {}
+            ClearDoneTasks {}
+            Clickable {
+                class: "option",
+                title: "Lets searching for a subject's name find its notes",
+                onclick: move |_| {
+                    view_state.write().set_index_subject_names(!index_subject_names);
+                },
+                "{index_box} Search subject names"
+            }
         }
     })
 }
 
-fn UndoRedo(cx: Scope) -> Element {
+/// Clears the done tasks of `subject` and its descendants, or of all subjects,
+/// after confirming how many there are.
+#[inline_props]
+fn ClearDoneTasks(cx: Scope, subject: Option<SubjectId>) -> Element {
     let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let confirm = use_state(cx, || None::<usize>);
+    let subject = *subject;
+    let policy = view_state.read().global_settings.clear_done;
+
     let style = css!(
         "
-        psdding: 10px;
-        display: flex;
-        flex-direction: row;
-        align-items: center;
-        gap: 10px;
-
-        .button {
-            padding: 0 5px;
+        .clear-done {
             cursor: pointer;
+            color: var(--muted);
 
             &:hover {
-                background-color: #bbb;
-            }
-            &.disabled {
-                opacity: 0.5;
+                background-color: var(--button);
             }
         }
     "
     );
-    let button_style = |enabled: bool| match enabled {
-        true => "button",
-        false => "button disabled",
+
+    let open = move |_| {
+        let count = layer.read().done_tasks(subject).len();
+        if count == 0 {
+            view_state
+                .read()
+                .notify(ToastLevel::Info, "There are no done tasks to clear");
+        } else {
+            confirm.set(Some(count));
+        }
     };
+
+    let dialog = confirm.get().map(|count| {
+        let plural = if count == 1 { "task" } else { "tasks" };
+        let message = cx.bump().alloc_str(&match policy {
+            ClearDonePolicy::DeleteNotes => format!("Delete {count} done {plural}?"),
+            ClearDonePolicy::Untask => format!("Turn {count} done {plural} into plain notes?"),
+        });
+        rsx! {
+            ConfirmDialog {
+                title: "Clear Done Tasks",
+                message: message,
+                on_confirm: move |_| {
+                    layer.clear_done_tasks(subject, policy);
+                    confirm.set(None);
+                },
+                on_cancel: |_| confirm.set(None),
+            }
+        }
+    });
+
     cx.render(rsx! {
         div {
             class: "{style}",
-            rsx! {
-                div {
-                    class: button_style(layer.read().can_undo()),
-                    onclick: move |_| {
-                        layer.write().undo();
-                    },
-                    div {
-                        "Undo"
-                    }
-                }
-            }
-            rsx! {
-                div {
-                    class: button_style(layer.read().can_redo()),
-                    onclick: move |_| {
-                        layer.write().redo();
-                    },
-                    div {
-                        "Redo"
-                    }
-                }
+            Clickable {
+                class: "clear-done",
+                title: "Can be undone",
+                onclick: open,
+                "Clear done tasks"
             }
+            dialog
         }
     })
 }
 
-#[inline_props]
-fn FindSimilar(cx: Scope, text: String) -> Element {
+/// Creates subjects from a pasted outline, one per line, nested by indentation
+/// or `Parent/Child` paths.
+fn SubjectOutline(cx: Scope) -> Element {
     let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let open = use_state(cx, || false);
+    let outline = use_state(cx, String::new);
 
-    let counter = layer.read().event_count();
-
-    let similar = use_future(cx, (text, &counter), |(text, _)| {
-        let search = layer.read().search();
-        async move { search.find_similar(text).await }
-    });
-
-    let notes = similar.value()?;
+    let create = move |_| {
+        let Some(summary) = layer.create_subjects_from_outline(outline.get()) else {
+            // The failure has already been reported to the user.
+            return;
+        };
+        view_state.read().notify(
+            ToastLevel::Info,
+            format!(
+                "Created {} subjects, {} already existed",
+                summary.created, summary.matched
+            ),
+        );
+        outline.set(String::new());
+        open.set(false);
+    };
 
     let style = css!(
         "
         display: flex;
         flex-direction: column;
-        gap: 10px;
-        overflow: hidden;
-        padding: 10px 0px 0px 10px;
+        gap: 5px;
 
-        .similar-notes {
-            display: grid;
-            gap: 10px;
-            overflow-y: scroll;
+        .outline {
+            resize: vertical;
+        }
 
-            .wrapper {
-                max-height: 100px;
-                height: fit-content;
-                padding-top: 5px;
-                overflow: hidden;
-            }
+        .row {
+            display: flex;
+            flex-direction: row;
+            gap: 5px;
         }
-    "
-    );
 
-    let elems = notes
-        .iter()
+        .select-button {
+            padding: 2px 5px;
+            background-color: var(--button);
+            cursor: pointer;
+        }
+
+        .open-outline {
+            padding: 2px 5px;
+            cursor: pointer;
+            color: var(--muted);
+
+            &:hover {
+                background-color: var(--button);
+            }
+        }
+    "
+    );
+
+    if !*open.get() {
+        return cx.render(rsx! {
+            div {
+                class: "{style}",
+                Clickable {
+                    class: "open-outline",
+                    title: "Create subjects from a list, nested by indentation or Parent/Child",
+                    onclick: |_| open.set(true),
+                    "+ Subjects from a list"
+                }
+            }
+        });
+    }
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            textarea {
+                class: "outline",
+                rows: 6,
+                placeholder: "Work\n    Meetings\nHome/Garden",
+                value: "{outline}",
+                oninput: |e| outline.set(e.value.clone()),
+            }
+            div {
+                class: "row",
+                Clickable {
+                    class: "select-button",
+                    onclick: create,
+                    "Create subjects"
+                }
+                Clickable {
+                    class: "select-button",
+                    onclick: |_| open.set(false),
+                    "Cancel"
+                }
+            }
+        }
+    })
+}
+
+fn SavedFilters(cx: Scope) -> Element {
+    let view_state = use_view_state(cx);
+    let show_builder = use_state(cx, || false);
+
+    let filters = view_state.read().vault_settings.saved_filters.clone();
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 5px;
+
+        .filter {
+            display: flex;
+            flex-direction: row;
+            align-items: center;
+
+            .name {
+                flex-grow: 1;
+                padding: 2px 5px;
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--button);
+                }
+            }
+
+            .delete {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    color: red;
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .new-filter {
+            padding: 2px 5px;
+            cursor: pointer;
+            color: var(--muted);
+
+            &:hover {
+                background-color: var(--button);
+            }
+        }
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div {
+                class: "side-panel-header",
+                "Filters"
+            }
+            filters.into_iter().map(|filter| {
+                let name = filter.name.clone();
+                rsx! {
+                    div {
+                        key: "{filter.name}",
+                        class: "filter",
+                        Clickable {
+                            class: "name",
+                            onclick: move |_| view_state.write().apply_filter(&filter),
+                            "{filter.name}"
+                        }
+                        Clickable {
+                            class: "delete",
+                            label: "Delete filter",
+                            title: "Delete filter",
+                            onclick: move |_| view_state.write().delete_filter(&name),
+                            "✖"
+                        }
+                    }
+                }
+            })
+            if *show_builder.get() {
+                rsx! {
+                    FilterBuilder {
+                        on_done: |_| show_builder.set(false),
+                    }
+                }
+            } else {
+                rsx! {
+                    Clickable {
+                        class: "new-filter",
+                        onclick: |_| show_builder.set(true),
+                        "+ New filter"
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[derive(Props)]
+struct FilterBuilderProps<'a> {
+    on_done: EventHandler<'a, ()>,
+}
+
+/// Builds a saved filter by ticking subjects in the subject selector.
+fn FilterBuilder<'a>(cx: Scope<'a, FilterBuilderProps<'a>>) -> Element<'a> {
+    let view_state = use_view_state(cx);
+    let name = use_state(cx, String::new);
+    let subject_match = use_state(cx, || SubjectMatch::Any);
+    let ticked = use_state(cx, BTreeSet::<SubjectId>::new);
+
+    let toggle = move |subject: Subject| {
+        ticked.with_mut(|ticked| {
+            if !ticked.remove(&subject.id) {
+                ticked.insert(subject.id);
+            }
+        });
+    };
+
+    let save = move |_| {
+        let filter_name = name.get().trim().to_string();
+        if filter_name.is_empty() || ticked.get().is_empty() {
+            return;
+        }
+        view_state.write().save_filter(SavedFilter {
+            name: filter_name,
+            subjects: ticked.get().iter().copied().collect(),
+            subject_match: *subject_match.get(),
+        });
+        cx.props.on_done.call(());
+    };
+
+    let match_class = |mode: SubjectMatch| {
+        if *subject_match.get() == mode {
+            "select-button selected"
+        } else {
+            "select-button"
+        }
+    };
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 5px;
+
+        .filter-name {
+            resize: none;
+        }
+
+        .row {
+            display: flex;
+            flex-direction: row;
+            gap: 5px;
+        }
+
+        .select-button {
+            padding: 2px 5px;
+            background-color: var(--button);
+            cursor: pointer;
+
+            &.selected {
+                background-color: var(--selected);
+            }
+        }
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            textarea {
+                class: "filter-name",
+                rows: 1,
+                placeholder: "Filter name",
+                value: "{name}",
+                oninput: |e| name.set(e.value.clone()),
+            }
+            div {
+                class: "row",
+                Clickable {
+                    class: match_class(SubjectMatch::Any),
+                    title: "Notes with any of the subjects",
+                    onclick: |_| subject_match.set(SubjectMatch::Any),
+                    "Any"
+                }
+                Clickable {
+                    class: match_class(SubjectMatch::All),
+                    title: "Notes with all of the subjects",
+                    onclick: |_| subject_match.set(SubjectMatch::All),
+                    "All"
+                }
+            }
+            div {
+                class: "row",
+                Clickable {
+                    class: "select-button",
+                    onclick: save,
+                    "Save"
+                }
+                Clickable {
+                    class: "select-button",
+                    onclick: |_| cx.props.on_done.call(()),
+                    "Cancel"
+                }
+            }
+            SelectSubject {
+                on_select: toggle,
+                on_cancel: |_| cx.props.on_done.call(()),
+                ignore_subjects: vec![],
+                show_above: false,
+                ticked: ticked.get().clone(),
+            }
+        }
+    })
+}
+
+fn UndoRedo(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let style = css!(
+        "
+        psdding: 10px;
+        display: flex;
+        flex-direction: row;
+        align-items: center;
+        gap: 10px;
+
+        .button {
+            padding: 0 5px;
+            cursor: pointer;
+
+            &:hover {
+                background-color: var(--button);
+            }
+            &.disabled {
+                opacity: 0.5;
+            }
+        }
+    "
+    );
+    let button_style = |enabled: bool| match enabled {
+        true => "button",
+        false => "button disabled",
+    };
+    let undo_title: &str = match layer.read().undo_description() {
+        Some(description) => cx.bump().alloc_str(&format!("Undo: {description}")),
+        None => "Nothing to undo",
+    };
+    let redo_title: &str = match layer.read().redo_description() {
+        Some(description) => cx.bump().alloc_str(&format!("Redo: {description}")),
+        None => "Nothing to redo",
+    };
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            rsx! {
+                Clickable {
+                    class: button_style(layer.read().can_undo()),
+                    title: undo_title,
+                    onclick: move |_| {
+                        layer.write().undo();
+                    },
+                    div {
+                        "Undo"
+                    }
+                }
+            }
+            rsx! {
+                Clickable {
+                    class: button_style(layer.read().can_redo()),
+                    title: redo_title,
+                    onclick: move |_| {
+                        layer.write().redo();
+                    },
+                    div {
+                        "Redo"
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn ReadingList(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+
+    let reading_list = view_state.read().reading_list.clone();
+    let notes = layer.read().notes_by_id(&reading_list);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 10px;
+        overflow: hidden;
+        padding: 10px 0px 0px 10px;
+
+        .reading-notes {
+            display: grid;
+            gap: 10px;
+            overflow-y: scroll;
+
+            .wrapper {
+                max-height: 100px;
+                height: fit-content;
+                padding-top: 5px;
+                overflow: hidden;
+            }
+        }
+
+        .controls {
+            display: flex;
+            flex-direction: row;
+            justify-content: flex-end;
+            gap: 5px;
+
+            .button {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .empty {
+            color: var(--muted);
+        }
+    "
+    );
+
+    let elems = notes
+        .iter()
+        .map(|note| {
+            let id = note.id;
+            rsx! {
+                div {
+                    key: "{note.id.0}",
+                    div {
+                        class: "controls",
+                        Clickable {
+                            class: "button",
+                            label: "Move up",
+                            title: "Move up",
+                            onclick: move |_| view_state.write().move_in_reading_list(id, -1),
+                            "▲"
+                        }
+                        Clickable {
+                            class: "button",
+                            label: "Move down",
+                            title: "Move down",
+                            onclick: move |_| view_state.write().move_in_reading_list(id, 1),
+                            "▼"
+                        }
+                        Clickable {
+                            class: "button",
+                            label: "Remove from reading list",
+                            title: "Remove from reading list",
+                            onclick: move |_| view_state.write().remove_from_reading_list(id),
+                            "✖"
+                        }
+                    }
+                    div {
+                        class: "wrapper",
+                        ViewNote {
+                            note: note.clone(),
+                            subject_select: OnSubjectSelect::Switch,
+                            hide_subject: None,
+                        }
+                    }
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div {
+                class: "side-panel-header",
+                "Reading List"
+            }
+            if elems.is_empty() {
+                rsx! {
+                    div {
+                        class: "empty",
+                        "Add notes here from their menu."
+                    }
+                }
+            }
+            div {
+                class: "reading-notes",
+                elems.into_iter()
+            }
+        }
+    })
+}
+
+/// How many words the word cloud shows.
+const WORD_CLOUD_SIZE: usize = 100;
+
+/// The most common words of the vault, sized by how many notes they're in.
+/// Clicking one searches for it.
+fn WordCloud(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+
+    let event_count = layer.read().event_count();
+    let terms = use_memo(cx, (&event_count,), |_| {
+        let mut terms = layer.read().top_terms(WORD_CLOUD_SIZE);
+        terms.sort();
+        terms
+    });
+    // Sizes scale by the log of the count, so that a few very common words
+    // don't dwarf the rest.
+    let most = terms.iter().map(|(_, count)| *count).max().unwrap_or(1);
+    let least = terms.iter().map(|(_, count)| *count).min().unwrap_or(1);
+    let least_ln = (least as f64).ln();
+    let spread = ((most as f64).ln() - least_ln).max(f64::EPSILON);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        overflow: hidden;
+        padding: 10px;
+
+        .words {
+            display: flex;
+            flex-wrap: wrap;
+            align-items: baseline;
+            gap: 5px 10px;
+            overflow-y: auto;
+
+            .word {
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--chip-hover);
+                }
+            }
+        }
+
+        .empty {
+            color: var(--muted);
+        }
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div { class: "side-panel-header", "Words" }
+            if terms.is_empty() {
+                rsx! {
+                    div {
+                        class: "empty",
+                        "Words show up here as notes are written."
+                    }
+                }
+            }
+            div {
+                class: "words",
+                terms.iter().map(|(term, count)| {
+                    let weight = ((*count as f64).ln() - least_ln) / spread;
+                    let size = 0.8 + 1.2 * weight;
+                    let size_style = cx.bump().alloc_str(&format!("font-size: {size:.2}em;"));
+                    let title = cx.bump().alloc_str(&format!("{term}: {count} notes"));
+                    let text = term.clone();
+                    rsx! {
+                        Clickable {
+                            key: "{term}",
+                            class: "word",
+                            title: title,
+                            style: size_style,
+                            onclick: move |_| view_state.write().search_for(text.clone()),
+                            "{term}"
+                        }
+                    }
+                })
+            }
+        }
+    })
+}
+
+/// How many weeks the activity heatmap covers.
+const HEATMAP_WEEKS: i64 = 53;
+
+/// How many notes were written each day over the last year, a column per
+/// week. Clicking a day lists the journal from that day.
+fn ActivityHeatmap(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+
+    let today = chrono::Local::now().date_naive();
+    // Columns start on Monday.
+    let first = today
+        - chrono::Duration::days(today.weekday().num_days_from_monday().into())
+        - chrono::Duration::weeks(HEATMAP_WEEKS - 1);
+    let event_count = layer.read().event_count();
+    let counts = use_memo(cx, (&first, &today, &event_count), |(first, today, _)| {
+        layer.read().notes_per_day(first, today)
+    });
+    let total = counts.values().sum::<u32>();
+    let most = counts.values().copied().max().unwrap_or(1);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 10px;
+        overflow: hidden;
+        padding: 10px;
+
+        .days {
+            display: grid;
+            grid-auto-flow: column;
+            grid-template-rows: repeat(7, 10px);
+            grid-auto-columns: 10px;
+            gap: 2px;
+            overflow-x: auto;
+
+            .day {
+                cursor: pointer;
+                background-color: var(--todo);
+
+                &.level-0 {
+                    background-color: var(--chip);
+                }
+                &.level-1 {
+                    opacity: 0.4;
+                }
+                &.level-2 {
+                    opacity: 0.6;
+                }
+                &.level-3 {
+                    opacity: 0.8;
+                }
+                &:hover {
+                    outline: 1px solid var(--border);
+                }
+            }
+        }
+
+        .summary {
+            color: var(--muted);
+        }
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div { class: "side-panel-header", "Activity" }
+            div {
+                class: "days",
+                first.iter_days().take_while(|day| *day <= today).map(|day| {
+                    let count = counts.get(&day).copied().unwrap_or(0);
+                    // Levels 1 to 4 split the busiest day's count in quarters.
+                    let level = ((count * 4 + most - 1) / most).min(4);
+                    let class = cx.bump().alloc_str(&format!("day level-{level}"));
+                    let plural = if count == 1 { "note" } else { "notes" };
+                    let title = cx.bump().alloc_str(&format!("{day}: {count} {plural}"));
+                    rsx! {
+                        Clickable {
+                            key: "{day}",
+                            class: class,
+                            title: title,
+                            onclick: move |_| view_state.write().show_day(day),
+                        }
+                    }
+                })
+            }
+            div { class: "summary", "{total} notes in the last year" }
+        }
+    })
+}
+
+/// Edits a note in the side panel, leaving the list as it is until saved.
+#[inline_props]
+fn NoteEditor(cx: Scope, note_id: NoteId) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+
+    let Some(note) = layer.read().notes_by_id(&[*note_id]).pop() else {
+        return render! {
+            div { class: "side-panel-header", "The note was deleted" }
+        };
+    };
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        overflow-y: auto;
+        padding: 10px;
+    "
+    );
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div { class: "side-panel-header", "Edit Note" }
+            EditNote {
+                note: note,
+                on_done: move |_| view_state.write().close_side_panel_editor(),
+                in_side_panel: true,
+            }
+        }
+    })
+}
+
+#[inline_props]
+fn NoteLinks(cx: Scope, note_id: NoteId) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let note_id = *note_id;
+
+    let Some(note) = layer.read().notes_by_id(&[note_id]).pop() else {
+        return render! {
+            div { class: "side-panel-header", "The note was deleted" }
+        };
+    };
+    let links = layer.read().linked_notes(note_id);
+    let backlinks = layer.read().backlinks(note_id);
+    let linking = view_state.read().linking_from == Some(note_id);
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 10px;
+        overflow: hidden;
+        padding: 10px 0px 0px 10px;
+
+        .linked-notes {
+            display: grid;
+            gap: 10px;
+            overflow-y: scroll;
+
+            .wrapper {
+                max-height: 100px;
+                height: fit-content;
+                padding-top: 5px;
+                overflow: hidden;
+            }
+        }
+
+        .section {
+            font-weight: bold;
+        }
+
+        .controls {
+            display: flex;
+            flex-direction: row;
+            justify-content: flex-end;
+            gap: 5px;
+
+            .button {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .empty {
+            color: var(--muted);
+        }
+    "
+    );
+
+    let link_button = if linking {
+        rsx! {
+            Clickable {
+                class: "button",
+                onclick: move |_| view_state.write().linking_from = None,
+                "Cancel linking"
+            }
+        }
+    } else {
+        rsx! {
+            Clickable {
+                class: "button",
+                title: "Pick the note to link to from its menu",
+                onclick: move |_| view_state.write().start_linking(note_id),
+                "+ Link a note"
+            }
+        }
+    };
+
+    let view = |note: &Note| {
+        rsx! {
+            div {
+                class: "wrapper",
+                ViewNote {
+                    note: note.clone(),
+                    subject_select: OnSubjectSelect::Switch,
+                    hide_subject: None,
+                }
+            }
+        }
+    };
+    let link_elems = links.iter().map(|linked| {
+        let to = linked.id;
+        rsx! {
+            div {
+                key: "{to.0}",
+                div {
+                    class: "controls",
+                    Clickable {
+                        class: "button",
+                        label: "Remove the link",
+                        title: "Remove the link",
+                        onclick: move |_| layer.unlink_notes(note_id, to),
+                        "✖"
+                    }
+                }
+                view(linked)
+            }
+        }
+    });
+    let backlink_elems = backlinks.iter().map(|linking| {
+        rsx! {
+            div {
+                key: "{linking.id.0}",
+                view(linking)
+            }
+        }
+    });
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div {
+                class: "side-panel-header",
+                "Linked Notes"
+            }
+            div {
+                class: "controls",
+                link_button
+            }
+            div {
+                class: "linked-notes",
+                view(&note),
+                div { class: "section", "Links" }
+                if links.is_empty() {
+                    rsx! { div { class: "empty", "No links yet. Write [[the first line of a note]] to link to it." } }
+                }
+                link_elems,
+                div { class: "section", "Backlinks" }
+                if backlinks.is_empty() {
+                    rsx! { div { class: "empty", "No notes link here." } }
+                }
+                backlink_elems
+            }
+        }
+    })
+}
+
+#[inline_props]
+fn FindSimilar(cx: Scope, text: String) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+
+    let counter = layer.read().event_count();
+
+    let params = view_state.read().global_settings.similar_notes;
+    let similar = use_future(cx, (text, &counter), move |(text, _)| {
+        let search = layer.read().search();
+        async move {
+            let result = search.find_similar(text, params).await;
+            result.unwrap_or_else(|e| {
+                view_state.read().notify(
+                    ToastLevel::Error,
+                    format!("Finding similar notes failed: {e}"),
+                );
+                Vec::new()
+            })
+        }
+    });
+
+    let notes = similar.value()?;
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 10px;
+        overflow: hidden;
+        padding: 10px 0px 0px 10px;
+
+        .similar-notes {
+            display: grid;
+            gap: 10px;
+            overflow-y: scroll;
+
+            .wrapper {
+                max-height: 100px;
+                height: fit-content;
+                padding-top: 5px;
+                overflow: hidden;
+            }
+        }
+    "
+    );
+
+    let elems = notes
+        .iter()
         .map(|note| {
             rsx! {
                 div {
@@ -464,3 +1774,23 @@ fn FindSimilar(cx: Scope, text: String) -> Element {
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn similar_notes_keep_the_side_panel_editor() {
+        let note = NoteId(uuid::Uuid::new_v4());
+        let mut state = SidePanelState::EditNote(note);
+        state.list_similar("Text being edited".to_string());
+        assert!(matches!(state, SidePanelState::EditNote(id) if id == note));
+
+        let mut state = SidePanelState::ReadingList;
+        state.list_similar("First".to_string());
+        state.list_similar("Second".to_string());
+        assert!(matches!(&state, SidePanelState::ListSimilar { text, .. } if text == "Second"));
+        state.back();
+        assert!(matches!(state, SidePanelState::ReadingList));
+    }
+}
@@ -0,0 +1,337 @@
+//! A screen for cleaning up the subjects of a vault.
+
+use std::collections::BTreeSet;
+
+use dioxus::{html::input_data::keyboard_types::Key, prelude::*};
+use emergence::data::{
+    layer::{use_layer, use_subjects, RemoveSubjectPolicy},
+    subjects::{Subject, SubjectCounts, SubjectId},
+};
+use sir::css;
+
+use crate::views::{
+    clickable::Clickable, confirm_dialog::ConfirmDialog, select_subject::SelectSubject,
+    use_view_state, view_note::subject_chip_style,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+    Name,
+    Notes,
+    Children,
+}
+
+/// What the row of a subject is in the middle of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowAction {
+    Rename(SubjectId),
+    Merge(SubjectId),
+    Move(SubjectId),
+}
+
+/// Lists every subject with how much it's used, for renaming, merging,
+/// moving and deleting them. Everything can be undone.
+pub fn SubjectManager(cx: Scope) -> Element {
+    let layer = use_layer(cx);
+    let view_state = use_view_state(cx);
+    let subjects = use_subjects(cx).read().clone();
+
+    let event_count = layer.read().event_count();
+    let counts = use_memo(cx, (&event_count,), |_| layer.read().subjects_with_counts());
+
+    let sort_by = use_state(cx, || SortBy::Name);
+    let selected = use_ref(cx, BTreeSet::<SubjectId>::new);
+    let row_action = use_state(cx, || None::<RowAction>);
+    let new_name = use_state(cx, String::new);
+    let confirm_delete = use_state(cx, || false);
+
+    let mut rows = counts
+        .iter()
+        .filter_map(|counts| Some((subjects.get(&counts.subject)?.clone(), *counts)))
+        .collect::<Vec<(Subject, SubjectCounts)>>();
+    match sort_by.get() {
+        SortBy::Name => {}
+        SortBy::Notes => rows.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.notes)),
+        SortBy::Children => rows.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.children)),
+    }
+    let empty = rows
+        .iter()
+        .filter(|(_, counts)| counts.is_empty())
+        .map(|(subject, _)| subject.id)
+        .collect::<BTreeSet<_>>();
+    let empty_count = empty.len();
+
+    // Subjects deleted meanwhile, e.g. by undoing, can't stay selected.
+    selected
+        .write_silent()
+        .retain(|id| subjects.contains_key(id));
+    let selected_count = selected.read().len();
+
+    let style = css!(
+        "
+        display: flex;
+        flex-direction: column;
+        gap: 10px;
+        overflow: hidden;
+        padding: 10px;
+
+        .controls {
+            display: flex;
+            flex-direction: row;
+            flex-wrap: wrap;
+            gap: 5px;
+            align-items: center;
+
+            .button {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover, &.selected {
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .subjects {
+            display: grid;
+            grid-template-columns: auto 1fr auto auto auto;
+            align-items: center;
+            gap: 5px 10px;
+            overflow-y: auto;
+
+            .subject-row {
+                display: contents;
+            }
+        }
+
+        .count {
+            text-align: right;
+            color: var(--muted);
+        }
+
+        .empty {
+            font-style: italic;
+            color: var(--muted);
+        }
+
+        .row-actions {
+            display: flex;
+            gap: 5px;
+
+            .button {
+                padding: 0 5px;
+                cursor: pointer;
+
+                &:hover {
+                    background-color: var(--button);
+                }
+            }
+        }
+
+        .row-editor {
+            grid-column: 1 / -1;
+        }
+    "
+    );
+
+    let sort_class = |sort: SortBy| {
+        if *sort_by.get() == sort {
+            "button selected"
+        } else {
+            "button"
+        }
+    };
+
+    let delete_message = cx.bump().alloc_str(&format!(
+        "Delete {selected_count} subjects? Their notes are kept. This can be undone."
+    ));
+    let delete_dialog = (*confirm_delete.get()).then(|| {
+        rsx! {
+            ConfirmDialog {
+                title: "Delete Subjects",
+                message: delete_message,
+                on_confirm: move |_| {
+                    let ids = std::mem::take(&mut *selected.write())
+                        .into_iter()
+                        .collect::<Vec<_>>();
+                    layer.delete_subjects(&ids, RemoveSubjectPolicy::Untag);
+                    view_state.write().forget_subjects(&ids);
+                    confirm_delete.set(false);
+                },
+                on_cancel: |_| confirm_delete.set(false),
+            }
+        }
+    });
+
+    let row_editor = |subject: &Subject| {
+        let id = subject.id;
+        match *row_action.get() {
+            Some(RowAction::Rename(of)) if of == id => Some(rsx! {
+                input {
+                    class: "row-editor",
+                    value: "{new_name}",
+                    autofocus: true,
+                    oninput: |e| new_name.set(e.value.clone()),
+                    onkeydown: move |e: KeyboardEvent| match e.key() {
+                        Key::Enter => {
+                            let name = new_name.get().trim().to_string();
+                            if !name.is_empty() {
+                                layer.rename_subject(id, name);
+                            }
+                            row_action.set(None);
+                        }
+                        Key::Escape => row_action.set(None),
+                        _ => {}
+                    },
+                }
+            }),
+            Some(RowAction::Merge(of)) if of == id => Some(rsx! {
+                div {
+                    class: "row-editor",
+                    "Merge into:"
+                    SelectSubject {
+                        on_select: move |into: Subject| {
+                            layer.merge_subject(id, into.id);
+                            view_state.write().forget_subjects(&[id]);
+                            row_action.set(None);
+                        },
+                        on_cancel: |_| row_action.set(None),
+                        ignore_subjects: vec![id],
+                    }
+                }
+            }),
+            Some(RowAction::Move(of)) if of == id => Some(rsx! {
+                div {
+                    class: "row-editor",
+                    "Move under:"
+                    Clickable {
+                        class: "button",
+                        onclick: move |_| {
+                            layer.move_subtree(id, None);
+                            row_action.set(None);
+                        },
+                        "Top level"
+                    }
+                    SelectSubject {
+                        on_select: move |parent: Subject| {
+                            layer.move_subtree(id, Some(parent.id));
+                            row_action.set(None);
+                        },
+                        on_cancel: |_| row_action.set(None),
+                        ignore_subjects: vec![id],
+                    }
+                }
+            }),
+            _ => None,
+        }
+    };
+
+    cx.render(rsx! {
+        div {
+            class: "{style}",
+            div { class: "side-panel-header", "Subjects" }
+            div {
+                class: "controls",
+                "Sort by:"
+                Clickable {
+                    class: sort_class(SortBy::Name),
+                    onclick: |_| sort_by.set(SortBy::Name),
+                    "Name"
+                }
+                Clickable {
+                    class: sort_class(SortBy::Notes),
+                    onclick: |_| sort_by.set(SortBy::Notes),
+                    "Notes"
+                }
+                Clickable {
+                    class: sort_class(SortBy::Children),
+                    onclick: |_| sort_by.set(SortBy::Children),
+                    "Children"
+                }
+            }
+            div {
+                class: "controls",
+                Clickable {
+                    class: "button",
+                    title: "Select the subjects without notes or children",
+                    onclick: move |_| *selected.write() = empty.clone(),
+                    "Select empty ({empty_count})"
+                }
+                Clickable {
+                    class: "button",
+                    onclick: |_| selected.write().clear(),
+                    "Select none"
+                }
+                if selected_count > 0 {
+                    rsx! {
+                        Clickable {
+                            class: "button",
+                            onclick: |_| confirm_delete.set(true),
+                            "Delete selected ({selected_count})"
+                        }
+                    }
+                }
+            }
+            div {
+                class: "subjects",
+                rows.iter().map(|(subject, counts)| {
+                    let id = subject.id;
+                    let checked = selected.read().contains(&id);
+                    let chip_style = cx.bump().alloc_str(&subject_chip_style(subject));
+                    let name = subject.name.clone();
+                    rsx! {
+                        div {
+                            key: "{id}",
+                            class: "subject-row",
+                            input {
+                                r#type: "checkbox",
+                                checked: checked,
+                                onclick: move |_| {
+                                    let mut selected = selected.write();
+                                    if !selected.remove(&id) {
+                                        selected.insert(id);
+                                    }
+                                },
+                            }
+                            div {
+                                span {
+                                    class: "subject-card",
+                                    style: chip_style,
+                                    "{subject.name}"
+                                }
+                                if counts.is_empty() {
+                                    rsx! { span { class: "empty", " empty" } }
+                                }
+                            }
+                            div { class: "count", title: "Notes", "{counts.notes}" }
+                            div { class: "count", title: "Children", "{counts.children}" }
+                            div {
+                                class: "row-actions",
+                                Clickable {
+                                    class: "button",
+                                    onclick: move |_| {
+                                        new_name.set(name.clone());
+                                        row_action.set(Some(RowAction::Rename(id)));
+                                    },
+                                    "Rename"
+                                }
+                                Clickable {
+                                    class: "button",
+                                    onclick: move |_| row_action.set(Some(RowAction::Merge(id))),
+                                    "Merge"
+                                }
+                                Clickable {
+                                    class: "button",
+                                    onclick: move |_| row_action.set(Some(RowAction::Move(id))),
+                                    "Move"
+                                }
+                            }
+                            row_editor(subject)
+                        }
+                    }
+                })
+            }
+            delete_dialog
+        }
+    })
+}
@@ -0,0 +1,89 @@
+//! The color palettes of the themes.
+//!
+//! Styles refer to colors through CSS custom properties, which are set on the
+//! `.app` root according to the chosen theme.
+
+use emergence::data::settings::Theme;
+
+type Palette = [(&'static str, &'static str)];
+
+const LIGHT: &Palette = &[
+    ("color-scheme", "light"),
+    ("--background", "#fbfbfb"),
+    ("--panel", "#ddd"),
+    ("--surface", "#eee"),
+    ("--border", "#ccc"),
+    ("--border-strong", "#bbb"),
+    ("--text", "#000"),
+    ("--muted", "#666"),
+    ("--faint", "#888"),
+    ("--button", "#bbb"),
+    ("--button-hover", "#aaa"),
+    ("--chip", "#bbd"),
+    ("--chip-hover", "#ccd"),
+    ("--selected", "rgb(180, 200, 230)"),
+    ("--todo", "rgb(130, 200, 255)"),
+    ("--done", "#aaa"),
+    ("--warning", "rgb(255, 230, 170)"),
+    ("--error", "rgb(255, 190, 190)"),
+    ("--highlight", "#c29232"),
+    ("--done-overlay", "rgba(255, 255, 255, 0.5)"),
+    ("--active-overlay", "rgba(200, 200, 255, 0.3)"),
+    ("--backdrop", "rgba(0, 0, 0, 0.2)"),
+    ("--scrollbar", "rgba(0, 0, 0, 0.5)"),
+];
+
+const DARK: &Palette = &[
+    ("color-scheme", "dark"),
+    ("--background", "#1e1f22"),
+    ("--panel", "#2b2d31"),
+    ("--surface", "#313338"),
+    ("--border", "#3f4147"),
+    ("--border-strong", "#4e5058"),
+    ("--text", "#dcdde0"),
+    ("--muted", "#a0a3a8"),
+    ("--faint", "#7f838a"),
+    ("--button", "#404249"),
+    ("--button-hover", "#4e5058"),
+    ("--chip", "#3c4470"),
+    ("--chip-hover", "#4a5490"),
+    ("--selected", "rgb(59, 90, 128)"),
+    ("--todo", "rgb(47, 127, 191)"),
+    ("--done", "#5a5d63"),
+    ("--warning", "rgb(107, 85, 32)"),
+    ("--error", "rgb(110, 46, 46)"),
+    ("--highlight", "#c29232"),
+    ("--done-overlay", "rgba(0, 0, 0, 0.4)"),
+    ("--active-overlay", "rgba(120, 120, 255, 0.2)"),
+    ("--backdrop", "rgba(0, 0, 0, 0.5)"),
+    ("--scrollbar", "rgba(255, 255, 255, 0.3)"),
+];
+
+fn declarations(palette: &Palette) -> String {
+    palette
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}; "))
+        .collect()
+}
+
+/// The stylesheet setting the palette of `theme` on the app.
+pub fn theme_css(theme: Theme) -> String {
+    match theme {
+        Theme::Light => format!(".app {{ {} }}", declarations(LIGHT)),
+        Theme::Dark => format!(".app {{ {} }}", declarations(DARK)),
+        Theme::System => format!(
+            ".app {{ {} }} @media (prefers-color-scheme: dark) {{ .app {{ {} }} }}",
+            declarations(LIGHT),
+            declarations(DARK)
+        ),
+    }
+}
+
+/// A short label for the theme toggle.
+pub fn theme_label(theme: Theme) -> &'static str {
+    match theme {
+        Theme::System => "◐",
+        Theme::Light => "☀",
+        Theme::Dark => "☾",
+    }
+}
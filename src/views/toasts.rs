@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dioxus::prelude::*;
+use dioxus_signals::Signal;
+use sir::css;
+
+use super::use_view_state;
+
+/// Older toasts are dropped once there are more than this many.
+const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toast {
+    pub id: usize,
+    pub level: ToastLevel,
+    pub message: String,
+}
+
+pub fn push_toast(toasts: Signal<Vec<Toast>>, level: ToastLevel, message: String) {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let mut toasts = toasts.write();
+    toasts.push(Toast { id, level, message });
+    if toasts.len() > MAX_TOASTS {
+        toasts.remove(0);
+    }
+}
+
+pub fn Toasts(cx: Scope) -> Element {
+    let toasts = use_view_state(cx).read().toasts;
+
+    let style = css!(
+        "
+        position: fixed;
+        right: 10px;
+        bottom: 10px;
+        z-index: 50;
+        display: flex;
+        flex-direction: column;
+        gap: 5px;
+        max-width: 300px;
+
+        .toast {
+            padding: 5px 10px;
+            border: 1px solid var(--border);
+            background-color: var(--surface);
+            cursor: pointer;
+
+            &.warning {
+                background-color: var(--warning);
+            }
+            &.error {
+                background-color: var(--error);
+            }
+        }
+    "
+    );
+
+    let class = |level: ToastLevel| match level {
+        ToastLevel::Info => "toast",
+        ToastLevel::Warning => "toast warning",
+        ToastLevel::Error => "toast error",
+    };
+
+    render! {
+        div {
+            class: "{style}",
+            toasts.read().iter().map(|toast| {
+                let id = toast.id;
+                rsx! {
+                    div {
+                        key: "{id}",
+                        class: class(toast.level),
+                        title: "Click to dismiss",
+                        onclick: move |_| toasts.write().retain(|t| t.id != id),
+                        "{toast.message}"
+                    }
+                }
+            })
+        }
+    }
+}
@@ -1,14 +1,73 @@
-use dioxus::{html::input_data::MouseButton, prelude::*};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use dioxus::{
+    html::input_data::{keyboard_types::Key, MouseButton},
+    prelude::*,
+};
+use dioxus_signals::Signal;
 use emergence::data::{
-    layer::{use_layer, use_subjects},
-    notes::{Note, NoteBuilder, TaskState},
-    subjects::{Subject, SubjectId},
+    export::notes_to_markdown,
+    focus::format_focus_time,
+    layer::{use_layer, use_subjects, LayerAction, LayerSignal},
+    notes::{bump_presets, on_date, Note, NoteBuilder, NoteId, TaskState},
+    subjects::{contrasting_text_color, Subject, SubjectData, SubjectId},
 };
+use uuid::Uuid;
 
 use crate::views::{
-    confirm_dialog::ConfirmDialog, markdown::Markdown, note_input::EditNote, use_view_state,
+    clickable::Clickable, confirm_dialog::ConfirmDialog, markdown::Markdown, note_input::EditNote,
+    toasts::ToastLevel, use_view_state, ViewState,
 };
 
+const SELECTION_JS: &str = "return window.getSelection().toString();";
+
+/// The format of `datetime-local` inputs.
+const DATETIME_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// Copies `text` to the clipboard through a temporary text area.
+/// The script returns whether copying succeeded.
+pub fn copy_to_clipboard_js(text: &str) -> String {
+    let text = serde_json::to_string(text).unwrap_or_default();
+    format!(
+        r#"
+        const area = document.createElement('textarea');
+        area.value = {text};
+        document.body.appendChild(area);
+        area.select();
+        let copied = false;
+        try {{
+            copied = document.execCommand('copy');
+        }} catch (e) {{}}
+        area.remove();
+        return copied;
+        "#
+    )
+}
+
+/// Copies `text` to the clipboard, showing an error toast if it fails.
+pub fn copy_to_clipboard(
+    cx: &ScopeState,
+    js_eval: &dyn Fn(&str) -> Result<UseEval, EvalError>,
+    view_state: Signal<ViewState>,
+    text: &str,
+) {
+    let Ok(eval) = js_eval(&copy_to_clipboard_js(text)) else {
+        view_state
+            .read()
+            .notify(ToastLevel::Error, "Could not copy to the clipboard");
+        return;
+    };
+    cx.spawn(async move {
+        let copied = eval.join().await.ok().and_then(|v| v.as_bool());
+        if copied != Some(true) {
+            view_state
+                .read()
+                .notify(ToastLevel::Error, "Could not copy to the clipboard");
+        }
+    });
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OnSubjectSelect {
     Switch,
@@ -30,19 +89,39 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
         Dropdown(f64, f64),
         Edit,
         ConfirmDelete,
+        Reminder,
+        DueDate,
+        Bump,
     }
 
     let layer = use_layer(cx);
     let view_state = use_view_state(cx);
+    let all_subjects = use_subjects(cx);
 
     let state = use_state(cx, || State::Normal);
+    let selection = use_state(cx, String::new);
+    let js_eval = use_eval(cx);
 
     let note = &cx.props.note;
-    let time_text = note
+    let mut time_text = note
         .created_at
         .naive_local()
         .format("%Y-%m-%d %H:%M")
         .to_string();
+    let focus_seconds = layer.read().focus_seconds(note.id);
+    if focus_seconds > 0 {
+        time_text += &format!("\nFocused for {}", format_focus_time(focus_seconds));
+    }
+    if let Some(reminder_at) = note.reminder_at {
+        time_text += &format!("\nReminder at {}", reminder_at.format("%Y-%m-%d %H:%M"));
+    }
+    if let Some(due_at) = note.due_at {
+        time_text += &format!("\nDue {}", due_at.format("%Y-%m-%d %H:%M"));
+    }
+    let show_debug_info = view_state.read().global_settings.show_debug_info;
+    if show_debug_info {
+        time_text += &format!("\nId: {}\nRow: {}", note.id, note.rowid);
+    }
 
     // TODO: This probably should use oncontextmenu
     // See https://developer.mozilla.org/en-US/docs/Web/API/Element/contextmenu_event
@@ -54,7 +133,16 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
             }
             let coord = e.page_coordinates();
             match state.get() {
-                State::Normal => state.set(State::Dropdown(coord.x, coord.y)),
+                State::Normal => {
+                    state.set(State::Dropdown(coord.x, coord.y));
+                    let Ok(eval) = js_eval(SELECTION_JS) else { return };
+                    let selection = selection.clone();
+                    cx.spawn(async move {
+                        let text = eval.join().await.ok();
+                        let text = text.as_ref().and_then(|v| v.as_str()).unwrap_or_default();
+                        selection.set(text.trim().to_string());
+                    });
+                }
                 State::Dropdown(_, _) => state.set(State::Normal),
                 _ => {}
             }
@@ -71,31 +159,102 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
     let make_task = {
         let note = note.clone();
         move |_| {
-            let new_state = match note.task_state {
-                TaskState::NotATask => TaskState::Todo,
-                TaskState::Todo => TaskState::NotATask,
-                TaskState::Done => TaskState::NotATask,
-            };
+            let new_state = note.task_state.toggle_task();
             layer.edit_note(note.id, NoteBuilder::new().task_state(new_state));
             state.set(State::Normal);
         }
     };
 
+    let copy = move |text: &str| copy_to_clipboard(cx, js_eval, view_state, text);
+
     let on_dropdown_action = move |action: DropdownAction| match action {
-        DropdownAction::Edit => state.set(State::Edit),
+        DropdownAction::Edit => {
+            if view_state.read().global_settings.edit_in_side_panel {
+                view_state.write().edit_in_side_panel(note.id);
+                state.set(State::Normal);
+            } else {
+                state.set(State::Edit);
+            }
+        }
         DropdownAction::Delete => state.set(State::ConfirmDelete),
         DropdownAction::MakeTask => make_task(()),
+        DropdownAction::CommonSubjects => {
+            view_state.write().go_to_common_subjects(&note.subjects);
+            state.set(State::Normal);
+        }
+        DropdownAction::ShowInList => {
+            view_state.write().show_note(note);
+            state.set(State::Normal);
+        }
         DropdownAction::Bump => {
-            layer.edit_note(note.id, NoteBuilder::new().created_at(chrono::Local::now()));
+            layer.bump_note(note.id, Local::now());
+            state.set(State::Normal);
+        }
+        DropdownAction::TogglePinned => {
+            layer.set_pinned(note.id, !note.pinned);
+            state.set(State::Normal);
+        }
+        DropdownAction::BumpTo => state.set(State::Bump),
+        DropdownAction::ToggleReadingList => {
+            let mut view_state = view_state.write();
+            if view_state.reading_list.contains(&note.id) {
+                view_state.remove_from_reading_list(note.id);
+            } else {
+                view_state.add_to_reading_list(note.id);
+            }
+            state.set(State::Normal);
+        }
+        DropdownAction::ShowLinks => {
+            view_state.write().show_note_links(note.id);
+            state.set(State::Normal);
+        }
+        DropdownAction::StartLinking => {
+            view_state.write().start_linking(note.id);
+            state.set(State::Normal);
+        }
+        DropdownAction::LinkHere(from) => {
+            layer.link_notes(from, note.id);
+            let mut view_state = view_state.write();
+            view_state.linking_from = None;
+            view_state.show_note_links(from);
+            state.set(State::Normal);
+        }
+        DropdownAction::SetReminder => state.set(State::Reminder),
+        DropdownAction::SetDueDate => state.set(State::DueDate),
+        DropdownAction::ToggleIndexed => {
+            layer.edit_note(note.id, NoteBuilder::new().indexed(!note.indexed));
+            state.set(State::Normal);
+        }
+        DropdownAction::CopyAsMarkdown => {
+            state.set(State::Normal);
+            let header = view_state.read().global_settings.copy_metadata_header;
+            copy(&notes_to_markdown([&**note], &all_subjects.read(), header));
+        }
+        DropdownAction::CopyId => {
+            state.set(State::Normal);
+            copy(&note.id.to_string());
+        }
+        DropdownAction::SubjectFromSelection { link } => {
+            let subjects = all_subjects.read().clone();
+            subject_from_selection(layer, &subjects, note, selection.get(), link);
             state.set(State::Normal);
         }
     };
 
+    // Only offer selection actions for text selected in this note.
+    let note_selection = Some(selection.get().clone())
+        .filter(|selected| !selected.is_empty() && note.text.contains(selected.as_str()));
+
     let dropdown = if let State::Dropdown(x, y) = *state.get() {
         Some(rsx! {
             Dropdown {
                 pos: (x, y),
                 note: note.clone(),
+                in_reading_list: view_state.read().reading_list.contains(&note.id),
+                linking_from: view_state.read().linking_from.filter(|from| *from != note.id),
+                selection: note_selection,
+                in_search: view_state.read().show_search,
+                show_debug_info: show_debug_info,
                 on_action: on_dropdown_action,
                 on_close: |_| state.set(State::Normal),
             }
@@ -110,59 +269,138 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
         &cx.props.note.text
     };
 
-    let task_button = match cx.props.note.task_state {
-        TaskState::NotATask => rsx! { div { class: "task-button-place" } },
-        TaskState::Todo => {
-            let onclick = move |_| {
-                layer.edit_note(
-                    cx.props.note.id,
-                    NoteBuilder::new()
-                        .task_state(TaskState::Done)
-                        .done_at(Some(chrono::Local::now())),
-                );
-            };
-            rsx! {
-                div {
-                    class: "task-button todo",
-                    onclick: onclick,
-                    title: "TODO"
-                }
+    let task_button = if cx.props.note.is_open_task() {
+        let onclick = move |_| {
+            layer.edit_note(
+                cx.props.note.id,
+                NoteBuilder::new().task_state(TaskState::Done),
+            );
+        };
+        rsx! {
+            Clickable {
+                class: "task-button todo",
+                label: "Mark as done",
+                title: "TODO",
+                onclick: onclick,
             }
         }
-        TaskState::Done => {
-            let onclick = move |_| {
-                layer.edit_note(
-                    cx.props.note.id,
-                    NoteBuilder::new().task_state(TaskState::Todo).done_at(None),
-                );
-            };
-            rsx! {
-                div {
-                    class: "task-button done",
-                    onclick: onclick,
-                    title: "DONE"
+    } else if cx.props.note.is_done() {
+        let onclick = move |_| {
+            layer.edit_note(cx.props.note.id, NoteBuilder::new().as_task());
+        };
+        rsx! {
+            Clickable {
+                class: "task-button done",
+                label: "Mark as not done",
+                title: "DONE",
+                onclick: onclick,
+            }
+        }
+    } else {
+        rsx! { div { class: "task-button-place" } }
+    };
+
+    let focus_timer = view_state
+        .read()
+        .focus_timer
+        .filter(|timer| timer.note == note.id);
+    let focus_button = match (cx.props.note.is_open_task(), focus_timer) {
+        (true, None) => Some(rsx! {
+            Clickable {
+                class: "focus-button",
+                label: "Start focusing",
+                title: "Start focusing",
+                onclick: move |_| view_state.write().start_focus(cx.props.note.id),
+                "▶"
+            }
+        }),
+        (_, Some(timer)) => {
+            let title = cx.bump().alloc_str(&format!(
+                "Focusing since {}, click to stop",
+                timer.started_at.format("%H:%M")
+            ));
+            Some(rsx! {
+                Clickable {
+                    class: "focus-button running",
+                    label: "Stop focusing",
+                    title: title,
+                    onclick: move |_| view_state.write().stop_focus(),
+                    "■"
                 }
+            })
+        }
+        _ => None,
+    };
+
+    let pin_button = note.pinned.then(|| {
+        rsx! {
+            Clickable {
+                class: "pin-button",
+                label: "Unpin",
+                title: "Pinned, click to unpin",
+                onclick: move |_| layer.set_pinned(cx.props.note.id, false),
+                "📌"
             }
         }
+    });
+
+    // Open tasks past their due date stand out until done.
+    let due_date = note.due_at.filter(|_| note.is_task()).map(|due_at| {
+        let class = if note.is_overdue(Local::now()) {
+            "due-date overdue"
+        } else {
+            "due-date"
+        };
+        let label = cx
+            .bump()
+            .alloc_str(&format!("Due {}", due_at.format("%b %-d")));
+        let title = cx.bump().alloc_str(&format!(
+            "Due {}, click to change",
+            due_at.format("%Y-%m-%d %H:%M")
+        ));
+        rsx! {
+            Clickable {
+                class: class,
+                title: title,
+                onclick: move |_| state.set(State::DueDate),
+                "{label}"
+            }
+        }
+    });
+
+    // Pins the note with P while it has the focus, e.g. after clicking it.
+    let on_keydown = move |e: KeyboardEvent| {
+        let pin_key = matches!(e.key(), Key::Character(c) if c == "p" || c == "P");
+        if pin_key && e.modifiers().is_empty() && matches!(state.get(), State::Normal) {
+            layer.set_pinned(cx.props.note.id, !cx.props.note.pinned);
+        }
     };
 
     // Overlay for done notes
-    let overlay = if cx.props.note.task_state == TaskState::Done {
+    let overlay = if cx.props.note.is_done() {
         Some(rsx! {
             div {
                 class: "note-overlay",
-                style: "background-color: rgba(255, 255, 255, 0.5);"
+                style: "background-color: var(--done-overlay);"
             }
         })
     } else {
         None
     };
-    let overlay = if let State::Dropdown { .. } | State::ConfirmDelete = *state.get() {
+    let active = matches!(
+        *state.get(),
+        State::Dropdown { .. }
+            | State::ConfirmDelete
+            | State::Reminder
+            | State::DueDate
+            | State::Bump
+    );
+    let overlay = if active {
         Some(rsx! {
             overlay,
             div {
                 class: "note-overlay",
-                style: "background-color: rgba(200, 200, 255, 0.3);"
+                style: "background-color: var(--active-overlay);"
             }
         })
     } else {
@@ -182,6 +420,52 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
         None
     };
 
+    let reminder_dialog = if let State::Reminder = *state.get() {
+        Some(rsx! {
+            TimeDialog {
+                title: "Reminder",
+                at: note.reminder_at,
+                on_set: move |reminder_at| {
+                    layer.edit_note(note.id, NoteBuilder::new().reminder_at(reminder_at));
+                    state.set(State::Normal);
+                },
+                on_cancel: |_| state.set(State::Normal),
+            }
+        })
+    } else {
+        None
+    };
+
+    let due_date_dialog = if let State::DueDate = *state.get() {
+        Some(rsx! {
+            TimeDialog {
+                title: "Due Date",
+                at: note.due_at,
+                on_set: move |due_at| {
+                    layer.edit_note(note.id, NoteBuilder::new().due_at(due_at));
+                    state.set(State::Normal);
+                },
+                on_cancel: |_| state.set(State::Normal),
+            }
+        })
+    } else {
+        None
+    };
+
+    let bump_dialog = if let State::Bump = *state.get() {
+        Some(rsx! {
+            BumpDialog {
+                on_bump: move |to| {
+                    layer.bump_note(note.id, to);
+                    state.set(State::Normal);
+                },
+                on_cancel: |_| state.set(State::Normal),
+            }
+        })
+    } else {
+        None
+    };
+
     let subjects = note
         .subjects
         .iter()
@@ -212,9 +496,14 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
                     on_click_subject: on_click_subject,
                 },
                 task_button,
+                due_date,
+                focus_button,
+                pin_button,
                 div {
                     class: "note",
+                    tabindex: 0,
                     onmousedown: on_mousedown,
+                    onkeydown: on_keydown,
                     overlay,
                     div {
                         class: "note-content",
@@ -226,6 +515,9 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
                 },
                 dropdown,
                 confirm_delete,
+                reminder_dialog,
+                due_date_dialog,
+                bump_dialog,
             }
         }
     };
@@ -233,59 +525,354 @@ pub fn ViewNote(cx: Scope<'_, ViewNoteProps>) -> Element<'_> {
     cx.render(content)
 }
 
+/// Tags the note with a subject named after `selection`, creating the subject
+/// unless one with that name exists. With `link`, the first occurrence of the
+/// selection in the text is also replaced with a `[[link]]`.
+/// Everything happens as a single undo step.
+fn subject_from_selection(
+    layer: LayerSignal,
+    subjects: &BTreeMap<SubjectId, Subject>,
+    note: &Note,
+    selection: &str,
+    link: bool,
+) {
+    let mut actions = Vec::new();
+    let existing = subjects
+        .values()
+        .find(|s| s.name.to_lowercase() == selection.to_lowercase());
+    let subject_id = match existing {
+        Some(subject) => subject.id,
+        None => {
+            let id = SubjectId(Uuid::new_v4());
+            actions.push(LayerAction::AddSubject(Some(id), selection.to_string()));
+            id
+        }
+    };
+
+    let mut builder = NoteBuilder::new();
+    if !note.subjects.contains(&subject_id) {
+        let mut note_subjects = note.subjects.clone();
+        note_subjects.push(subject_id);
+        builder = builder.subjects(note_subjects);
+    }
+    if link {
+        let text = note
+            .text
+            .replacen(selection, &format!("[[{selection}]]"), 1);
+        builder = builder.text(text);
+    }
+    actions.push(LayerAction::EditNote(note.id, builder));
+
+    layer.write().perform(LayerAction::Group(actions));
+}
+
+#[derive(Props)]
+struct TimeDialogProps<'a> {
+    title: &'a str,
+    #[props(!optional)]
+    at: Option<DateTime<Local>>,
+    /// Called with the new time, or `None` to clear it.
+    on_set: EventHandler<'a, Option<DateTime<Local>>>,
+    on_cancel: EventHandler<'a, ()>,
+}
+
+/// Picks the time of a reminder or due date.
+fn TimeDialog<'a>(cx: Scope<'a, TimeDialogProps<'a>>) -> Element<'a> {
+    let value = use_state(cx, || {
+        cx.props
+            .at
+            .map(|at| at.format(DATETIME_INPUT_FORMAT).to_string())
+            .unwrap_or_default()
+    });
+    let parsed = NaiveDateTime::parse_from_str(value.get(), DATETIME_INPUT_FORMAT)
+        .ok()
+        .and_then(|at| Local.from_local_datetime(&at).earliest());
+
+    cx.render(rsx! {
+        div {
+            class: "confirm-dialog-container",
+            div {
+                class: "confirm-dialog",
+                div {
+                    class: "confirm-dialog-title",
+                    "{cx.props.title}"
+                }
+                div {
+                    class: "confirm-dialog-message",
+                    input {
+                        r#type: "datetime-local",
+                        value: "{value}",
+                        oninput: |e| value.set(e.value.clone()),
+                    }
+                },
+                button {
+                    class: "confirm-dialog-button",
+                    disabled: parsed.is_none(),
+                    onclick: move |_| {
+                        if parsed.is_some() {
+                            cx.props.on_set.call(parsed);
+                        }
+                    },
+                    "Save"
+                },
+                if cx.props.at.is_some() {
+                    rsx! {
+                        button {
+                            class: "confirm-dialog-button",
+                            onclick: |_| cx.props.on_set.call(None),
+                            "Clear"
+                        }
+                    }
+                }
+                button {
+                    class: "confirm-dialog-button",
+                    onclick: |_| cx.props.on_cancel.call(()),
+                    "Cancel"
+                }
+            }
+        }
+    })
+}
+
+#[derive(Props)]
+struct BumpDialogProps<'a> {
+    on_bump: EventHandler<'a, DateTime<Local>>,
+    on_cancel: EventHandler<'a, ()>,
+}
+
+/// Picks a date to move a note to, keeping its time of day.
+fn BumpDialog<'a>(cx: Scope<'a, BumpDialogProps<'a>>) -> Element<'a> {
+    let date = use_state(cx, String::new);
+    let now = Local::now();
+    let custom = NaiveDate::parse_from_str(date.get(), "%Y-%m-%d")
+        .ok()
+        .map(|date| on_date(now, date));
+
+    cx.render(rsx! {
+        div {
+            class: "confirm-dialog-container",
+            div {
+                class: "confirm-dialog",
+                div {
+                    class: "confirm-dialog-title",
+                    "Bump to Date"
+                }
+                bump_presets(now).into_iter().map(|(label, to)| rsx! {
+                    button {
+                        key: "{label}",
+                        class: "confirm-dialog-button",
+                        onclick: move |_| cx.props.on_bump.call(to),
+                        "{label}"
+                    }
+                })
+                div {
+                    class: "confirm-dialog-message",
+                    input {
+                        r#type: "date",
+                        value: "{date}",
+                        oninput: |e| date.set(e.value.clone()),
+                    }
+                },
+                button {
+                    class: "confirm-dialog-button",
+                    disabled: custom.is_none(),
+                    onclick: move |_| {
+                        if let Some(to) = custom {
+                            cx.props.on_bump.call(to);
+                        }
+                    },
+                    "Bump"
+                },
+                button {
+                    class: "confirm-dialog-button",
+                    onclick: |_| cx.props.on_cancel.call(()),
+                    "Cancel"
+                }
+            }
+        }
+    })
+}
+
+#[derive(Clone, Copy)]
 enum DropdownAction {
     Edit,
     Delete,
     MakeTask,
     Bump,
+    BumpTo,
+    TogglePinned,
+    ToggleReadingList,
+    ShowLinks,
+    StartLinking,
+    LinkHere(NoteId),
+    SetReminder,
+    SetDueDate,
+    ToggleIndexed,
+    CommonSubjects,
+    ShowInList,
+    CopyAsMarkdown,
+    CopyId,
+    SubjectFromSelection { link: bool },
 }
 
 #[derive(Props)]
 struct DropdownProps<'a> {
     pos: (f64, f64),
     note: Note,
+    in_reading_list: bool,
+    /// A note waiting to be linked to this one.
+    #[props(!optional)]
+    linking_from: Option<NoteId>,
+    selection: Option<String>,
+    /// Offers to show the note among the others, for search results.
+    in_search: bool,
+    /// Offers to copy the id of the note.
+    show_debug_info: bool,
     on_action: EventHandler<'a, DropdownAction>,
     on_close: EventHandler<'a, ()>,
 }
 
 fn Dropdown<'a>(cx: Scope<'a, DropdownProps<'a>>) -> Element<'a> {
+    // The menu keeps the focus, so that it closes when the focus leaves.
+    // The arrow keys pick an item instead.
+    let highlighted = use_state(cx, || None::<usize>);
+    let note = &cx.props.note;
+
+    let mut items = vec![
+        (
+            if !note.is_task() {
+                "Make Task"
+            } else {
+                "Make Note"
+            },
+            DropdownAction::MakeTask,
+        ),
+        ("Bump to Today", DropdownAction::Bump),
+        ("Bump to Date", DropdownAction::BumpTo),
+        (
+            if note.pinned { "Unpin" } else { "Pin" },
+            DropdownAction::TogglePinned,
+        ),
+    ];
+    if cx.props.in_search {
+        items.push(("Show in List", DropdownAction::ShowInList));
+    }
+    if note.subjects.len() > 1 {
+        items.push(("Notes with These Subjects", DropdownAction::CommonSubjects));
+    }
+    items.push(("Copy as Markdown", DropdownAction::CopyAsMarkdown));
+    if cx.props.show_debug_info {
+        items.push(("Copy ID", DropdownAction::CopyId));
+    }
+    items.push((
+        if cx.props.in_reading_list {
+            "Remove from Reading List"
+        } else {
+            "Add to Reading List"
+        },
+        DropdownAction::ToggleReadingList,
+    ));
+    if let Some(from) = cx.props.linking_from {
+        items.push(("Link Here", DropdownAction::LinkHere(from)));
+    }
+    items.push((
+        if note.reminder_at.is_some() {
+            "Change Reminder"
+        } else {
+            "Set Reminder"
+        },
+        DropdownAction::SetReminder,
+    ));
+    if note.is_task() {
+        items.push((
+            if note.due_at.is_some() {
+                "Change Due Date"
+            } else {
+                "Set Due Date"
+            },
+            DropdownAction::SetDueDate,
+        ));
+    }
+    items.push(("Linked Notes", DropdownAction::ShowLinks));
+    items.push(("Link to Another Note", DropdownAction::StartLinking));
+    items.push((
+        if note.indexed {
+            "Exclude from Search"
+        } else {
+            "Include in Search"
+        },
+        DropdownAction::ToggleIndexed,
+    ));
+    if cx.props.selection.is_some() {
+        items.push((
+            "Make Subject from Selection",
+            DropdownAction::SubjectFromSelection { link: false },
+        ));
+        items.push((
+            "Link Selection as Subject",
+            DropdownAction::SubjectFromSelection { link: true },
+        ));
+    }
+    items.push(("Edit", DropdownAction::Edit));
+    items.push(("Delete", DropdownAction::Delete));
+
+    let actions = items.iter().map(|(_, action)| *action).collect::<Vec<_>>();
+    let onkeydown = move |e: KeyboardEvent| {
+        let count = actions.len();
+        let current = *highlighted.get();
+        match e.key() {
+            Key::ArrowDown => highlighted.set(Some(current.map_or(0, |i| (i + 1) % count))),
+            Key::ArrowUp => {
+                highlighted.set(Some(current.map_or(count - 1, |i| (i + count - 1) % count)))
+            }
+            Key::Enter => {
+                if let Some(i) = current {
+                    cx.props.on_action.call(actions[i]);
+                }
+            }
+            Key::Escape => cx.props.on_close.call(()),
+            _ => {}
+        }
+    };
+
     cx.render(rsx! {
         div {
             style: "left: {cx.props.pos.0}px; top: {cx.props.pos.1}px;",
             class: "note-dropdown",
+            role: "menu",
             tabindex: 0,
             onmounted: |e| {
                 e.inner().set_focus(true);
             },
             onblur: |_| cx.props.on_close.call(()),
-            div {
-                class: "note-dropdown-item",
-                onclick: |_| cx.props.on_action.call(DropdownAction::MakeTask),
-                if cx.props.note.task_state == TaskState::NotATask {
-                    "Make Task"
+            onkeydown: onkeydown,
+            items.into_iter().enumerate().map(|(i, (label, action))| {
+                let class = if *highlighted.get() == Some(i) {
+                    "note-dropdown-item highlighted"
                 } else {
-                    "Make Note"
+                    "note-dropdown-item"
+                };
+                rsx! {
+                    div {
+                        key: "{label}",
+                        class: class,
+                        role: "menuitem",
+                        onclick: move |_| cx.props.on_action.call(action),
+                        "{label}"
+                    }
                 }
-            },
-            div {
-                class: "note-dropdown-item",
-                onclick: |_| cx.props.on_action.call(DropdownAction::Bump),
-                "Bump to Today"
-            },
-            div {
-                class: "note-dropdown-item",
-                onclick: |_| cx.props.on_action.call(DropdownAction::Edit),
-                "Edit"
-            },
-            div {
-                class: "note-dropdown-item",
-                onclick: |_| cx.props.on_action.call(DropdownAction::Delete),
-                "Delete"
-            },
+            })
         }
     })
 }
 
+/// Inline style coloring a chip of `subject`, empty if it has no color.
+pub fn subject_chip_style(subject: &SubjectData) -> String {
+    let Some(color) = &subject.color else { return String::new() };
+    let Some(text) = contrasting_text_color(color) else { return String::new() };
+    format!("background-color: {color}; color: {text};")
+}
+
 #[derive(Props)]
 pub struct SubjectCardsProps<'a> {
     sids: Vec<SubjectId>,
@@ -293,7 +880,9 @@ pub struct SubjectCardsProps<'a> {
     on_click_subject: Option<EventHandler<'a, Subject>>,
 }
 
+/// Right-clicking a card shows its subject's details in the side panel.
 pub fn SubjectCards<'a>(cx: Scope<'a, SubjectCardsProps<'a>>) -> Element<'a> {
+    let view_state = use_view_state(cx);
     let subjects = use_subjects(cx).read().clone();
 
     let mut cards = cx
@@ -303,25 +892,34 @@ pub fn SubjectCards<'a>(cx: Scope<'a, SubjectCardsProps<'a>>) -> Element<'a> {
         .map(|sid| {
             let s = subjects.get(sid).unwrap().clone();
             let on_click_subject = &cx.props.on_click_subject;
+            let subject_id = s.id;
+            let name = s.name.clone();
+            let style = cx.bump().alloc_str(&subject_chip_style(&s));
             rsx! {
-                div {
-                    key: "{s.id.0}",
+                Clickable {
+                    key: "{subject_id.0}",
                     class: "subject-card",
+                    title: "Right-click for details",
+                    style: style,
                     onclick: move |_| {
                         if let Some(on_click_subject) = on_click_subject {
                             on_click_subject.call(s.clone());
                         }
                     },
-                    "{s.name}"
+                    oncontextmenu: move |_| {
+                        view_state.write().show_subject_details(subject_id);
+                    },
+                    "{name}"
                 }
             }
         })
         .collect::<Vec<_>>();
     if let Some(on_add_subject) = &cx.props.on_add_subject {
         cards.push(rsx! {
-            div {
+            Clickable {
                 key: "add-subject",
                 class: "subject-card",
+                label: "Add subject",
                 onclick: |_| on_add_subject.call(()),
                 "+"
             }
@@ -0,0 +1,359 @@
+//! Mounts only the notes near the viewport of long note lists.
+//! The rest are replaced by empty slots of their last measured height,
+//! so the scroll position stays where it was.
+
+use emergence::data::notes::NoteId;
+use std::collections::{HashMap, HashSet};
+
+/// Lists with fewer notes than this are mounted in full.
+pub const WINDOWING_THRESHOLD: usize = 100;
+/// Height of a note that hasn't been measured yet.
+const ESTIMATED_NOTE_HEIGHT: f64 = 60.0;
+/// How far above and below the viewport notes stay mounted.
+const OVERSCAN: f64 = 1500.0;
+/// Viewport height assumed before the first scroll report.
+const DEFAULT_VIEWPORT: f64 = 1000.0;
+/// Gap between the notes of a group.
+const NOTE_GAP: f64 = 15.0;
+/// Gap between the groups of the list.
+const GROUP_GAP: f64 = 10.0;
+/// Height of a folded group.
+const COLLAPSED_GROUP_HEIGHT: f64 = 30.0;
+/// Height of a date header that hasn't been measured yet.
+const ESTIMATED_HEADER_HEIGHT: f64 = 30.0;
+/// Scrolling less than this doesn't move the window.
+const SCROLL_STEP: f64 = 200.0;
+
+/// Reports the scroll position of the note list and the heights of the
+/// mounted notes and date headers through `dioxus.send` whenever either
+/// changes.
+pub const WATCH_SCROLL_JS: &str = r#"
+    const watch = () => {
+        const list = document.querySelector('.note-list-scroll');
+        if (!list) {
+            requestAnimationFrame(watch);
+            return;
+        }
+        if (list.stopWatching) {
+            list.stopWatching();
+        }
+        let pending = false;
+        const report = () => {
+            if (pending) return;
+            pending = true;
+            requestAnimationFrame(() => {
+                pending = false;
+                const heights = [];
+                for (const slot of list.querySelectorAll('.note-slot[data-note]')) {
+                    heights.push([slot.dataset.note, slot.offsetHeight]);
+                }
+                // The date column is padded by 5px on both sides.
+                const headers = [];
+                for (const group of list.querySelectorAll('.group-wrapper[data-group]')) {
+                    const date = group.querySelector('.date');
+                    if (date) headers.push([group.dataset.group, date.offsetHeight + 10]);
+                }
+                dioxus.send({
+                    top: list.scrollTop,
+                    height: list.clientHeight,
+                    heights: heights,
+                    headers: headers,
+                });
+            });
+        };
+        const observer = new ResizeObserver(report);
+        for (const grid of list.getElementsByClassName('note-grid')) {
+            observer.observe(grid);
+        }
+        list.addEventListener('scroll', report);
+        list.stopWatching = () => {
+            observer.disconnect();
+            list.removeEventListener('scroll', report);
+        };
+        report();
+    };
+    watch();
+"#;
+
+/// The last reported scroll position of the note list and the measured
+/// heights of its notes and date headers.
+#[derive(Clone, Default, PartialEq)]
+pub struct ListScroll {
+    /// Which list the position belongs to.
+    list: String,
+    top: f64,
+    height: f64,
+    heights: HashMap<NoteId, f64>,
+    /// Header heights by group key.
+    headers: HashMap<String, f64>,
+}
+
+impl ListScroll {
+    /// The measured height of a note, or an estimate if it hasn't been
+    /// mounted yet.
+    pub fn height_of(&self, id: NoteId) -> f64 {
+        self.heights
+            .get(&id)
+            .copied()
+            .unwrap_or(ESTIMATED_NOTE_HEIGHT)
+    }
+
+    /// The measured height of the date header of a group, or an estimate
+    /// if it hasn't been rendered yet.
+    pub fn header_height(&self, group: &str) -> f64 {
+        self.headers
+            .get(group)
+            .copied()
+            .unwrap_or(ESTIMATED_HEADER_HEIGHT)
+    }
+
+    /// Applies a report from [`WATCH_SCROLL_JS`]. Returns whether the
+    /// change is big enough to move the window.
+    pub fn update(&mut self, list: &str, report: &serde_json::Value) -> bool {
+        let top = report["top"].as_f64().unwrap_or_default();
+        let height = report["height"].as_f64().unwrap_or_default();
+
+        let mut changed = self.list != list
+            || (self.top - top).abs() > SCROLL_STEP
+            || (self.height - height).abs() > 1.0;
+        if changed {
+            self.list = list.to_string();
+            self.top = top;
+            self.height = height;
+        }
+
+        let reported = report["heights"].as_array().into_iter().flatten();
+        for entry in reported {
+            let id = entry[0].as_str().and_then(|id| id.parse::<NoteId>().ok());
+            let (Some(id), Some(height)) = (id, entry[1].as_f64()) else { continue };
+            let known = self.heights.insert(id, height);
+            let moved = match known {
+                Some(known) => (known - height).abs() > 1.0,
+                None => true,
+            };
+            changed |= moved;
+        }
+
+        let reported = report["headers"].as_array().into_iter().flatten();
+        for entry in reported {
+            let (Some(group), Some(height)) = (entry[0].as_str(), entry[1].as_f64()) else {
+                continue;
+            };
+            let known = self.headers.insert(group.to_string(), height);
+            let moved = match known {
+                Some(known) => (known - height).abs() > 1.0,
+                None => true,
+            };
+            changed |= moved;
+        }
+        changed
+    }
+}
+
+/// Picks the notes of `groups` to mount, given as the group key, whether
+/// the group is collapsed and the notes in display order. A group is at
+/// least as tall as its date header, which sits beside the notes. The
+/// `target` note is centered on when given, and it and the `last` note
+/// are always mounted so that scrolling to them keeps working.
+pub fn mounted_notes(
+    groups: &[(String, bool, Vec<NoteId>)],
+    scroll: &ListScroll,
+    list: &str,
+    target: Option<NoteId>,
+    last: Option<NoteId>,
+) -> HashSet<NoteId> {
+    let mut offsets = Vec::new();
+    let mut offset = 0.0;
+    for (key, collapsed, notes) in groups {
+        offset += GROUP_GAP;
+        let group_top = offset;
+        let mut group_bottom = group_top;
+        if *collapsed {
+            group_bottom += COLLAPSED_GROUP_HEIGHT;
+        } else {
+            for (i, &id) in notes.iter().enumerate() {
+                let top = if i == 0 {
+                    group_top
+                } else {
+                    group_bottom + NOTE_GAP
+                };
+                let height = scroll.height_of(id);
+                offsets.push((id, top, height));
+                group_bottom = top + height;
+            }
+        }
+        offset = group_bottom.max(group_top + scroll.header_height(key));
+    }
+    let total = offset;
+
+    let known = scroll.list == list && scroll.height > 0.0;
+    let viewport = if known {
+        scroll.height
+    } else {
+        DEFAULT_VIEWPORT
+    };
+    let target_offset = target.and_then(|target| {
+        offsets
+            .iter()
+            .find(|(id, _, _)| *id == target)
+            .map(|(_, offset, height)| offset + height / 2.0)
+    });
+    let top = match target_offset {
+        Some(center) => center - viewport / 2.0,
+        None if known => scroll.top,
+        // Lists start scrolled to the newest notes at the bottom.
+        None => total - viewport,
+    };
+
+    let start = top - OVERSCAN;
+    let end = top + viewport + OVERSCAN;
+    let mut mounted = offsets
+        .into_iter()
+        .filter(|(_, offset, height)| offset + height >= start && *offset <= end)
+        .map(|(id, _, _)| id)
+        .collect::<HashSet<_>>();
+    mounted.extend(target);
+    mounted.extend(last);
+    mounted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    const LIST: &str = "list";
+
+    /// Ten groups of thirty notes each.
+    fn layout() -> Vec<(String, bool, Vec<NoteId>)> {
+        (0..10)
+            .map(|group| {
+                let notes = (0..30)
+                    .map(|i| NoteId(Uuid::from_u128(group * 30 + i)))
+                    .collect();
+                (format!("group-{group}"), false, notes)
+            })
+            .collect()
+    }
+
+    /// Reports every note of `groups` as 100px tall.
+    fn scrolled(groups: &[(String, bool, Vec<NoteId>)], top: f64, height: f64) -> ListScroll {
+        let heights = groups
+            .iter()
+            .flat_map(|(_, _, notes)| notes)
+            .map(|id| json!([id.to_string(), 100.0]))
+            .collect::<Vec<_>>();
+        let mut scroll = ListScroll::default();
+        scroll.update(
+            LIST,
+            &json!({ "top": top, "height": height, "heights": heights }),
+        );
+        scroll
+    }
+
+    #[test]
+    fn mounts_around_the_top() {
+        let groups = layout();
+        let first = groups[0].2[0];
+        let middle = groups[5].2[0];
+        let last = *groups[9].2.last().unwrap();
+        let scroll = scrolled(&groups, 0.0, 1000.0);
+
+        let mounted = mounted_notes(&groups, &scroll, LIST, None, Some(last));
+        assert!(mounted.contains(&first));
+        assert!(!mounted.contains(&middle));
+        assert!(mounted.contains(&last));
+        assert!(mounted.len() < 40);
+    }
+
+    #[test]
+    fn mounts_around_the_middle() {
+        let groups = layout();
+        let first = groups[0].2[0];
+        let middle = groups[5].2[0];
+        // Each group is 30 notes of 100px with 15px gaps, plus a 10px gap.
+        let middle_top = 5.0 * (10.0 + 30.0 * 100.0 + 29.0 * 15.0) + 10.0;
+        let scroll = scrolled(&groups, middle_top, 1000.0);
+
+        let mounted = mounted_notes(&groups, &scroll, LIST, None, None);
+        assert!(!mounted.contains(&first));
+        assert!(mounted.contains(&middle));
+        assert!(mounted.contains(&groups[4].2[29]));
+        assert!(!mounted.contains(&groups[3].2[0]));
+    }
+
+    #[test]
+    fn mounts_around_the_bottom_before_scrolling() {
+        let groups = layout();
+        let first = groups[0].2[0];
+        let last = *groups[9].2.last().unwrap();
+        // Nothing has been reported for this list yet.
+        let scroll = ListScroll::default();
+
+        let mounted = mounted_notes(&groups, &scroll, LIST, None, Some(last));
+        assert!(!mounted.contains(&first));
+        assert!(mounted.contains(&last));
+        assert!(mounted.contains(&groups[9].2[0]));
+    }
+
+    #[test]
+    fn mounts_the_target_note() {
+        let groups = layout();
+        let target = groups[2].2[15];
+        let scroll = scrolled(&groups, 0.0, 1000.0);
+
+        let mounted = mounted_notes(&groups, &scroll, LIST, Some(target), None);
+        assert!(mounted.contains(&target));
+        assert!(mounted.contains(&groups[2].2[0]));
+        assert!(!mounted.contains(&groups[0].2[0]));
+    }
+
+    #[test]
+    fn resizing_moves_the_window() {
+        let groups = layout();
+        let below = groups[1].2[5];
+        let mut scroll = scrolled(&groups, 0.0, 1000.0);
+        assert!(!mounted_notes(&groups, &scroll, LIST, None, None).contains(&below));
+
+        let resized = scroll.update(LIST, &json!({ "top": 0.0, "height": 3000.0 }));
+        assert!(resized);
+        assert!(mounted_notes(&groups, &scroll, LIST, None, None).contains(&below));
+    }
+
+    #[test]
+    fn tall_date_headers_push_the_notes_down() {
+        // Groups of one short note each, so the headers decide their height.
+        let groups = (0..100)
+            .map(|i| {
+                let notes = vec![NoteId(Uuid::from_u128(i))];
+                (format!("group-{i}"), false, notes)
+            })
+            .collect::<Vec<_>>();
+        let heights = groups
+            .iter()
+            .map(|(_, _, notes)| json!([notes[0].to_string(), 20.0]))
+            .collect::<Vec<_>>();
+        let headers = groups
+            .iter()
+            .map(|(key, _, _)| json!([key, 100.0]))
+            .collect::<Vec<_>>();
+        let mut scroll = ListScroll::default();
+        scroll.update(
+            LIST,
+            &json!({ "top": 0.0, "height": 1000.0, "heights": heights }),
+        );
+        let beyond_headers = groups[40].2[0];
+        assert!(mounted_notes(&groups, &scroll, LIST, None, None).contains(&beyond_headers));
+
+        let moved = scroll.update(
+            LIST,
+            &json!({ "top": 0.0, "height": 1000.0, "headers": headers }),
+        );
+        assert!(moved);
+        // Each group now takes 110px, so the window ends around group 22.
+        let mounted = mounted_notes(&groups, &scroll, LIST, None, None);
+        assert!(mounted.contains(&groups[20].2[0]));
+        assert!(!mounted.contains(&beyond_headers));
+    }
+}